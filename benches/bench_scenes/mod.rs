@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use raytacer::background::Sky;
+use raytacer::material::{Diffusor, Material, Reflector, Refractor};
+use raytacer::ray_tracing::{Camera, Quad, Ray, Scene, Sphere};
+use raytacer::renderer::RenderSettings;
+use raytacer::vec_math::{Color, Point3, Vec3};
+
+// Scenes of a given size for the benchmarks, kept out of the library.
+// Everything is generated from SEED, so the same benchmark measures the same
+// work on any machine and at any commit. None of them come with a BVH; call
+// `Scene::build_bvh` before rendering.
+pub const SEED: u64 = 0x6265_6e63_6873_6565;
+
+// Objects of every scene fit in this cube around the origin.
+pub const EXTENT: f64 = 10.0;
+
+fn materials(rng: &mut StdRng) -> Vec<Arc<dyn Material>> {
+    let mut materials: Vec<Arc<dyn Material>> = vec![];
+    for _ in 0..8 {
+        materials.push(Arc::new(Diffusor {
+            color: Color::random(rng),
+        }));
+    }
+    for _ in 0..4 {
+        materials.push(Arc::new(Reflector {
+            color: Color::random_in_interval(rng, (0.5, 1.0)),
+            fuzz_coeff: rng.gen_range(0.0..0.3),
+        }));
+    }
+    materials.push(Arc::new(Refractor {
+        color: Color::new(1.0, 1.0, 1.0),
+        fuzz_coeff: 0.0,
+        refr_coeff: 1.5,
+    }));
+    materials
+}
+
+// `count` spheres scattered through the cube, sized so that they cover about
+// the same part of the view whatever their number.
+pub fn spheres(count: usize) -> Scene {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let materials = materials(&mut rng);
+    let radius = 0.5 * EXTENT / (count as f64).cbrt();
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    for _ in 0..count {
+        let center = Vec3::random_in_interval(&mut rng, (-EXTENT + radius, EXTENT - radius));
+        let material = &materials[rng.gen_range(0..materials.len())];
+        scene.add(Box::new(Sphere::new(center, radius, Arc::clone(material))));
+    }
+    scene
+}
+
+// A rolling height field of `resolution`^2 quads, one per grid cell, standing
+// in for a large mesh as there are no triangle meshes. Neighbouring quads
+// don't quite meet, which doesn't matter for timing.
+pub fn mesh(resolution: u32) -> Scene {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let material: Arc<dyn Material> = Arc::new(Diffusor {
+        color: Color::new(0.6, 0.6, 0.6),
+    });
+    let waves: Vec<(f64, f64, f64)> = (0..4)
+        .map(|_| {
+            (
+                rng.gen_range(0.2..1.0),
+                rng.gen_range(0.2..1.0),
+                rng.gen_range(0.0..std::f64::consts::TAU),
+            )
+        })
+        .collect();
+    let height = |x: f64, z: f64| -> f64 {
+        waves
+            .iter()
+            .map(|&(kx, kz, phase)| (kx * x + kz * z + phase).sin())
+            .sum::<f64>()
+    };
+    let cell = 2.0 * EXTENT / resolution as f64;
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    for i in 0..resolution {
+        for j in 0..resolution {
+            let x = -EXTENT + i as f64 * cell;
+            let z = -EXTENT + j as f64 * cell;
+            let y = height(x, z);
+            let corner = Point3::new(x, y, z);
+            let u = Vec3::new(0.0, height(x, z + cell) - y, cell);
+            let v = Vec3::new(cell, height(x + cell, z) - y, 0.0);
+            scene.add(Box::new(Quad::new(corner, u, v, Arc::clone(&material))));
+        }
+    }
+    scene
+}
+
+// `count` small triangles scattered through the cube, as plain vertex
+// arrays for comparing vertex storage; there is no triangle hittable.
+pub fn triangles(count: usize) -> Vec<[Point3; 3]> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let size = EXTENT / (count as f64).cbrt();
+    (0..count)
+        .map(|_| {
+            let a = Vec3::random_in_interval(&mut rng, (-EXTENT + size, EXTENT - size));
+            [
+                a,
+                a + size * Vec3::random_in_interval(&mut rng, (-1.0, 1.0)),
+                a + size * Vec3::random_in_interval(&mut rng, (-1.0, 1.0)),
+            ]
+        })
+        .collect()
+}
+
+// Looks at the cube from outside, a little from above.
+pub fn camera() -> Camera {
+    Camera::new(
+        Point3::new(2.5 * EXTENT, 1.5 * EXTENT, 2.5 * EXTENT),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0f64.to_radians(),
+        1.0,
+        0.0,
+        1.0,
+    )
+}
+
+// Camera rays through `count` random points of the image, for timing
+// closest hits without the rest of a render.
+pub fn camera_rays(count: usize) -> Vec<Ray> {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let camera = camera();
+    (0..count)
+        .map(|_| camera.create_center_ray(rng.gen(), rng.gen()))
+        .collect()
+}
+
+// A seeded 64x64 render on one thread, so the timing doesn't depend on the
+// number of cores.
+pub fn settings(samples_per_pixel: u32) -> RenderSettings {
+    RenderSettings {
+        width: 64,
+        height: 64,
+        samples_per_pixel,
+        max_depth: 8,
+        seed: Some(SEED),
+        threads: Some(1),
+        ..RenderSettings::default()
+    }
+}
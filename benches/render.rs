@@ -0,0 +1,179 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, SeedableRng};
+
+use raytacer::ray_tracing::{Ray, Scene};
+use raytacer::vec_math::{Interval, Point3, Vec3, Vec3f};
+use raytacer::Renderer;
+
+mod bench_scenes;
+
+const SPHERE_COUNTS: [usize; 3] = [1_000, 10_000, 100_000];
+// 250^2 = 62500 quads.
+const MESH_RESOLUTION: u32 = 250;
+const RAYS: usize = 4096;
+const TRIANGLES: usize = 1_000_000;
+
+fn scenes() -> Vec<(String, Scene)> {
+    let mut scenes: Vec<(String, Scene)> = SPHERE_COUNTS
+        .iter()
+        .map(|&count| (format!("{} spheres", count), bench_scenes::spheres(count)))
+        .collect();
+    scenes.push((
+        format!("{} quad mesh", MESH_RESOLUTION * MESH_RESOLUTION),
+        bench_scenes::mesh(MESH_RESOLUTION),
+    ));
+    scenes
+}
+
+fn bvh_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bvh build");
+    group.sample_size(10);
+    for (name, mut scene) in scenes() {
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| scene.build_bvh())
+        });
+    }
+    group.finish();
+}
+
+fn closest_hit(c: &mut Criterion) {
+    let rays = bench_scenes::camera_rays(RAYS);
+    let bounds = Interval::new(0.001, f64::INFINITY);
+    let mut group = c.benchmark_group("closest hit");
+    group.throughput(Throughput::Elements(RAYS as u64));
+    for (name, mut scene) in scenes() {
+        scene.build_bvh();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                rays.iter()
+                    .filter(|ray| scene.hit(ray, bounds).is_some())
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn render_64(c: &mut Criterion) {
+    let camera = bench_scenes::camera();
+    let renderer = Renderer::new(bench_scenes::settings(4));
+    let mut group = c.benchmark_group("64x64 render, 4 spp");
+    group.sample_size(10);
+    for (name, mut scene) in scenes() {
+        scene.build_bvh();
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| renderer.render(&scene, &camera).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn vec3(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(bench_scenes::SEED);
+    let vectors: Vec<Vec3> = (0..1024)
+        .map(|_| Vec3::random_in_interval(&mut rng, (-1.0, 1.0)))
+        .collect();
+    let pairs = || vectors.iter().zip(vectors.iter().rev());
+    let mut group = c.benchmark_group("vec3");
+    group.throughput(Throughput::Elements(vectors.len() as u64));
+    group.bench_function("dot", |b| {
+        b.iter(|| pairs().map(|(a, b)| a.dot(*b)).sum::<f64>())
+    });
+    group.bench_function("cross product", |b| {
+        b.iter(|| {
+            pairs()
+                .map(|(a, b)| a.cross_product(*b))
+                .fold(Vec3::new(0.0, 0.0, 0.0), |sum, v| sum + v)
+        })
+    });
+    group.bench_function("normalized", |b| {
+        b.iter(|| {
+            vectors
+                .iter()
+                .map(|v| *v.normalized())
+                .fold(Vec3::new(0.0, 0.0, 0.0), |sum, v| sum + v)
+        })
+    });
+    group.bench_function("multiply add", |b| {
+        b.iter(|| {
+            pairs().fold(Vec3::new(0.0, 0.0, 0.0), |sum, (a, b)| {
+                sum + *a * black_box(0.5) + *b
+            })
+        })
+    });
+    group.finish();
+}
+
+// Möller-Trumbore; the distance along `ray` if it hits the triangle.
+fn hit_triangle(ray: &Ray, [a, b, c]: [Point3; 3]) -> Option<f64> {
+    let (ab, ac) = (b - a, c - a);
+    let p = ray.direction.cross_product(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let offset = ray.origin - a;
+    let u = offset.dot(p) / determinant;
+    let q = offset.cross_product(ab);
+    let v = ray.direction.dot(q) / determinant;
+    let t = ac.dot(q) / determinant;
+    (u >= 0.0 && v >= 0.0 && u + v <= 1.0 && t > 0.0).then_some(t)
+}
+
+// Brute force over every triangle, so the time is dominated by streaming
+// the vertices through the cache.
+fn closest_triangle<T: Copy>(ray: &Ray, triangles: &[[T; 3]], to_f64: impl Fn(T) -> Point3) -> f64 {
+    triangles
+        .iter()
+        .filter_map(|&[a, b, c]| hit_triangle(ray, [to_f64(a), to_f64(b), to_f64(c)]))
+        .fold(f64::INFINITY, f64::min)
+}
+
+// The same million triangles stored as f64 and as f32 vertices, the latter
+// converted to Vec3 for every intersection.
+fn vertex_storage(c: &mut Criterion) {
+    let rays = bench_scenes::camera_rays(8);
+    let wide = bench_scenes::triangles(TRIANGLES);
+    let narrow: Vec<[Vec3f; 3]> = wide
+        .iter()
+        .map(|triangle| triangle.map(Vec3f::from))
+        .collect();
+    let megabytes = |bytes: usize| bytes as f64 / 1e6;
+    let mut group = c.benchmark_group(format!("{} triangle vertices", TRIANGLES));
+    group.sample_size(10);
+    group.throughput(Throughput::Elements((rays.len() * TRIANGLES) as u64));
+    group.bench_function(
+        format!("f64, {:.0} MB", megabytes(std::mem::size_of_val(&wide[..]))),
+        |b| {
+            b.iter(|| {
+                rays.iter()
+                    .map(|ray| closest_triangle(ray, &wide, |v| v))
+                    .sum::<f64>()
+            })
+        },
+    );
+    group.bench_function(
+        format!(
+            "f32, {:.0} MB",
+            megabytes(std::mem::size_of_val(&narrow[..]))
+        ),
+        |b| {
+            b.iter(|| {
+                rays.iter()
+                    .map(|ray| closest_triangle(ray, &narrow, Vec3::from))
+                    .sum::<f64>()
+            })
+        },
+    );
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    vec3,
+    bvh_build,
+    closest_hit,
+    render_64,
+    vertex_storage
+);
+criterion_main!(benches);
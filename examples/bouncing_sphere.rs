@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use raytacer::background::Sky;
+use raytacer::material::{Diffusor, Reflector};
+use raytacer::ray_tracing::{Camera, Scene, Sphere};
+use raytacer::vec_math::{Color, Point3, Vec3};
+use raytacer::{render_animation, Frames, RenderSettings};
+
+const RADIUS: f64 = 0.5;
+const BOUNCE_HEIGHT: f64 = 1.2;
+// One bounce per second.
+const PERIOD: f64 = 1.0;
+
+fn main() -> raytacer::Result<()> {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        }),
+    )));
+    scene.add_named(
+        "ball",
+        Box::new(Sphere::new(
+            Point3::new(0.0, RADIUS, 0.0),
+            RADIUS,
+            Arc::new(Reflector {
+                color: Color::new(0.8, 0.6, 0.2),
+                fuzz_coeff: 0.05,
+            }),
+        )),
+    )?;
+
+    let mut camera = Camera::new(
+        Point3::new(0.0, 1.5, 5.0),
+        Point3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0f64.to_radians(),
+        16.0 / 9.0,
+        0.0,
+        5.0,
+    );
+    let settings = RenderSettings::builder()
+        .resolution(320, 180)
+        .samples_per_pixel(32)
+        .max_depth(10)
+        .seed(7)
+        .output("bouncing_sphere/frame_{frame}.bmp")
+        .build()?;
+
+    // 48 frames at 24 fps: two bounces.
+    let frames = Frames::new(48, 24.0);
+    let paths = render_animation(
+        &mut scene,
+        &mut camera,
+        &settings,
+        frames,
+        |scene, _camera, _index, time| {
+            // A parabola between two contacts with the floor.
+            let phase = (time / PERIOD).fract();
+            let height = 4.0 * BOUNCE_HEIGHT * phase * (1.0 - phase);
+            // Changing the object drops the BVH, which is rebuilt before the
+            // frame is rendered.
+            if let Some(ball) = scene.get_named_mut::<Sphere>("ball") {
+                ball.set_center(Point3::new(0.0, RADIUS + height, 0.0));
+            }
+        },
+    )?;
+    println!("wrote {} frames to bouncing_sphere/", paths.len());
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use raytacer::output::{VideoOutput, YuvRange};
+use raytacer::presets::Preset;
+use raytacer::ray_tracing::Camera;
+use raytacer::vec_math::Point3;
+use raytacer::{render_video, Frames, RenderSettings};
+
+// Two seconds of the camera circling the demo scene at 24 fps. A path ending
+// in .y4m gets the raw frames; anything else is encoded by ffmpeg, which has
+// to be on the PATH, so no frame ever lands on disk as an image.
+fn main() -> raytacer::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "orbit.mp4".to_string());
+    let output = if path.ends_with(".y4m") {
+        VideoOutput::Y4m {
+            path: PathBuf::from(&path),
+            range: YuvRange::Limited,
+        }
+    } else {
+        // ffmpeg turns RGB into YUV with the BT.601 matrix unless told
+        // otherwise, which shifts colors in players that go by the tags.
+        let command = [
+            "ffmpeg",
+            "-y",
+            "-loglevel",
+            "error",
+            "-vf",
+            "scale=out_color_matrix=bt709:out_range=tv",
+            "-c:v",
+            "libx264",
+            "-pix_fmt",
+            "yuv420p",
+            "-colorspace",
+            "bt709",
+            "-color_primaries",
+            "bt709",
+            "-color_trc",
+            "bt709",
+            "-color_range",
+            "tv",
+            &path,
+        ];
+        VideoOutput::Ffmpeg {
+            command: command
+                .iter()
+                .map(|argument| argument.to_string())
+                .collect(),
+        }
+    };
+
+    let (mut scene, mut camera, _) = Preset::Random.setup(Some(42));
+    let start = *camera.config();
+    let settings = RenderSettings::builder()
+        .resolution(480, 320)
+        .samples_per_pixel(16)
+        .max_depth(10)
+        .seed(7)
+        .build()?;
+    let frames = Frames::new(48, 24.0);
+    render_video(
+        &mut scene,
+        &mut camera,
+        &settings,
+        frames,
+        &output,
+        |_scene, camera, index, _time| {
+            // Around the vertical axis through the point looked at.
+            let angle = std::f64::consts::TAU * index as f64 / frames.count as f64;
+            let offset = start.look_from - start.look_at;
+            let (sin, cos) = angle.sin_cos();
+            let look_from = start.look_at
+                + Point3::new(
+                    offset.x() * cos - offset.z() * sin,
+                    offset.y(),
+                    offset.x() * sin + offset.z() * cos,
+                );
+            *camera = Camera::new(
+                look_from,
+                start.look_at,
+                start.vector_up,
+                start.fov,
+                start.aspect_ratio,
+                start.aperture,
+                start.focus_distance,
+            );
+        },
+    )?;
+    println!("wrote {}", path);
+    Ok(())
+}
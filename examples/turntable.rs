@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use raytacer::output::AnimationOptions;
+use raytacer::presets::Preset;
+use raytacer::ray_tracing::Camera;
+use raytacer::vec_math::Point3;
+use raytacer::{render_animated_image, Frames, RenderSettings};
+
+// One full turn around the demo scene, written as a looping animated PNG, or
+// as a GIF when the path given ends in .gif (which needs the gif-output
+// feature).
+fn main() -> raytacer::Result<()> {
+    let path = std::env::args_os()
+        .nth(1)
+        .map_or_else(|| PathBuf::from("turntable.png"), PathBuf::from);
+    let (mut scene, mut camera, _) = Preset::Random.setup(Some(42));
+    let start = *camera.config();
+    let settings = RenderSettings::builder()
+        .resolution(240, 160)
+        .samples_per_pixel(16)
+        .max_depth(10)
+        .seed(7)
+        .build()?;
+
+    let frames = Frames::new(24, 12.0);
+    let path = render_animated_image(
+        &mut scene,
+        &mut camera,
+        &settings,
+        frames,
+        &path,
+        &AnimationOptions::at_frame_rate(frames.frames_per_second),
+        |_scene, camera, index, _time| {
+            // Around the vertical axis through the point looked at.
+            let angle = std::f64::consts::TAU * index as f64 / frames.count as f64;
+            let offset = start.look_from - start.look_at;
+            let (sin, cos) = angle.sin_cos();
+            let look_from = start.look_at
+                + Point3::new(
+                    offset.x() * cos - offset.z() * sin,
+                    offset.y(),
+                    offset.x() * sin + offset.z() * cos,
+                );
+            *camera = Camera::new(
+                look_from,
+                start.look_at,
+                start.vector_up,
+                start.fov,
+                start.aspect_ratio,
+                start.aperture,
+                start.focus_distance,
+            );
+        },
+    )?;
+    println!("wrote {}", path.display());
+    Ok(())
+}
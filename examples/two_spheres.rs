@@ -0,0 +1,46 @@
+use std::sync::Arc;
+
+use raytacer::background::Sky;
+use raytacer::material::{Diffusor, Reflector};
+use raytacer::ray_tracing::{Camera, Scene, Sphere};
+use raytacer::vec_math::{Color, Point3, Vec3};
+use raytacer::{RenderSettings, Renderer};
+
+fn main() -> raytacer::Result<()> {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.8, 0.0),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Reflector {
+            color: Color::new(0.8, 0.6, 0.2),
+            fuzz_coeff: 0.1,
+        }),
+    )));
+    scene.build_bvh();
+
+    let camera = Camera::new(
+        Point3::new(0.0, 0.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        60.0f64.to_radians(),
+        1.0,
+        0.0,
+        2.0,
+    );
+    let settings = RenderSettings::builder()
+        .resolution(64, 64)
+        .samples_per_pixel(32)
+        .max_depth(10)
+        .output("two_spheres.bmp")
+        .build()?;
+
+    Renderer::new(settings).render_to_file(&scene, &camera)?;
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use raytacer::presets::Preset;
+use raytacer::RenderSettings;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+const PREVIEW_WIDTH: u32 = 256;
+
+// Renders a quick preview of a built-in scene into the top left corner of a
+// canvas. There's no entropy source without getrandom's JavaScript support,
+// so the caller passes the seed.
+#[wasm_bindgen]
+pub fn render_preview(
+    context: &CanvasRenderingContext2d,
+    preset: &str,
+    seed: u32,
+) -> Result<(), JsValue> {
+    let preset = Preset::from_name(preset)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown scene `{}`", preset)))?;
+    let (scene, camera, settings) = preset.setup(Some(seed as u64));
+    let height = (PREVIEW_WIDTH as f64 / camera.config().aspect_ratio).round() as u32;
+    let settings = RenderSettings {
+        width: PREVIEW_WIDTH,
+        height,
+        samples_per_pixel: 16,
+        max_depth: 8,
+        ..settings
+    };
+    let pixels = raytacer::render_to_rgba8(&scene, &camera, &settings)
+        .map_err(|error| JsValue::from_str(&error.to_string()))?;
+    let image =
+        ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), PREVIEW_WIDTH, height)?;
+    context.put_image_data(&image, 0.0, 0.0)
+}
@@ -0,0 +1,455 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::error::{Error, Result};
+#[cfg(any(feature = "png-output", feature = "gif-output"))]
+use crate::output::{self, AnimationOptions, AnimationWriter};
+use crate::output::{VideoOutput, VideoWriter};
+use crate::ray_tracing::{Camera, Scene};
+use crate::renderer::{self, Framebuffer, MultiOutput, RenderSettings};
+use crate::stopwatch::Stopwatch;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frames {
+    pub count: u32,
+    pub frames_per_second: f64,
+}
+
+impl Frames {
+    pub fn new(count: u32, frames_per_second: f64) -> Self {
+        Frames {
+            count,
+            frames_per_second,
+        }
+    }
+
+    // Seconds since the start of the animation.
+    pub fn time(&self, index: u32) -> f64 {
+        index as f64 / self.frames_per_second
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.count == 0 {
+            return Err(Error::InvalidSettings(
+                "an animation needs at least one frame".to_string(),
+            ));
+        }
+        if !(self.frames_per_second > 0.0 && self.frames_per_second.is_finite()) {
+            return Err(Error::InvalidSettings(format!(
+                "frame rate {} is not a finite, positive number",
+                self.frames_per_second
+            )));
+        }
+        Ok(())
+    }
+
+    // Wide enough for the last index, and at least 4 digits.
+    fn digits(&self) -> usize {
+        (self.count - 1).to_string().len().max(4)
+    }
+}
+
+// Each frame gets its own seed derived from the animation's, so frames don't
+// share their noise pattern and any single frame can be rendered again on its
+// own.
+pub fn frame_seed(seed: u64, index: u32) -> u64 {
+    seed.wrapping_add((index as u64 + 1).wrapping_mul(0xd1b5_4a32_d192_ed03))
+}
+
+// Replaces `{frame}` with the zero-padded frame index, or appends `_<index>`
+// to the file name when the path has no such placeholder.
+fn frame_path(path: &Path, index: u32, digits: usize) -> PathBuf {
+    let number = format!("{:0width$}", index, width = digits);
+    if let Some(template) = path.to_str().filter(|path| path.contains("{frame}")) {
+        return PathBuf::from(template.replace("{frame}", &number));
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, number, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, number),
+    };
+    path.with_file_name(name)
+}
+
+fn frame_outputs(outputs: &MultiOutput, index: u32, digits: usize) -> MultiOutput {
+    let numbered = |path: &PathBuf| frame_path(path, index, digits);
+    MultiOutput {
+        beauty: numbered(&outputs.beauty),
+        albedo: outputs.albedo.as_ref().map(numbered),
+        normal: outputs.normal.as_ref().map(numbered),
+        depth: outputs.depth.as_ref().map(numbered),
+        variance: outputs.variance.as_ref().map(numbered),
+        masks: outputs
+            .masks
+            .iter()
+            .map(|(layer, path)| (layer.clone(), numbered(path)))
+            .collect(),
+    }
+}
+
+// Renders `frames` into numbered files next to `settings.outputs`, calling
+// `update` with the frame index and its time in seconds before each one. The
+// callback may move, add or replace objects and the camera; the BVH is
+// rebuilt whenever a change dropped it. Returns the beauty paths in frame
+// order.
+pub fn render_animation<F>(
+    scene: &mut Scene,
+    camera: &mut Camera,
+    settings: &RenderSettings,
+    frames: Frames,
+    update: F,
+) -> Result<Vec<PathBuf>>
+where
+    F: FnMut(&mut Scene, &mut Camera, u32, f64),
+{
+    settings.validate()?;
+    let digits = frames.digits();
+    // Every frame names the time the animation started.
+    let started = SystemTime::now();
+    let mut written = Vec::with_capacity(frames.count as usize);
+    render_frames(
+        scene,
+        camera,
+        settings,
+        frames,
+        update,
+        |index, frame_settings, framebuffer| {
+            let outputs = frame_outputs(&settings.outputs, index, digits)
+                .expand_templates(frame_settings, started);
+            framebuffer.save(&outputs)?;
+            log::info!(
+                "frame {}/{} saved to {}",
+                index + 1,
+                frames.count,
+                outputs.beauty.display()
+            );
+            written.push(outputs.beauty);
+            Ok(())
+        },
+    )?;
+    Ok(written)
+}
+
+// Like `render_animation`, but streams the frames into one animated PNG or
+// GIF at `path`, picked by its extension, so no frame stays around once it
+// is encoded. `settings.outputs` goes unused. Returns the path written, with
+// its templates filled in.
+#[cfg(any(feature = "png-output", feature = "gif-output"))]
+pub fn render_animated_image<F>(
+    scene: &mut Scene,
+    camera: &mut Camera,
+    settings: &RenderSettings,
+    frames: Frames,
+    path: &Path,
+    options: &AnimationOptions,
+    update: F,
+) -> Result<PathBuf>
+where
+    F: FnMut(&mut Scene, &mut Camera, u32, f64),
+{
+    settings.validate_without_outputs()?;
+    frames.validate()?;
+    let settings = &without_outputs(settings);
+    let path = output::expand_template(&path.to_string_lossy(), settings, SystemTime::now());
+    let mut writer = AnimationWriter::create(
+        &path,
+        settings.width,
+        settings.height,
+        frames.count,
+        options,
+    )?;
+    render_frames(
+        scene,
+        camera,
+        settings,
+        frames,
+        update,
+        |index, _, framebuffer| {
+            writer.push_frame(&framebuffer.beauty_image())?;
+            log::info!("frame {}/{} encoded", index + 1, frames.count);
+            Ok(())
+        },
+    )?;
+    writer.finish()?;
+    Ok(path)
+}
+
+// Like `render_animation`, but streams the frames into a video, at the
+// frame rate of `frames`. `settings.outputs` goes unused.
+pub fn render_video<F>(
+    scene: &mut Scene,
+    camera: &mut Camera,
+    settings: &RenderSettings,
+    frames: Frames,
+    output: &VideoOutput,
+    update: F,
+) -> Result<()>
+where
+    F: FnMut(&mut Scene, &mut Camera, u32, f64),
+{
+    settings.validate_without_outputs()?;
+    frames.validate()?;
+    let settings = &without_outputs(settings);
+    let mut writer = VideoWriter::create(
+        output,
+        settings.width,
+        settings.height,
+        frames.frames_per_second,
+    )?;
+    render_frames(
+        scene,
+        camera,
+        settings,
+        frames,
+        update,
+        |index, _, framebuffer| {
+            writer.push_frame(&framebuffer.beauty_image())?;
+            log::info!("frame {}/{} encoded", index + 1, frames.count);
+            Ok(())
+        },
+    )?;
+    writer.finish()
+}
+
+// The settings with `outputs` back at the default, for the renders that
+// stream the beauty pass instead of saving files, so that nothing they name
+// is checked or allocated.
+fn without_outputs(settings: &RenderSettings) -> RenderSettings {
+    RenderSettings {
+        outputs: RenderSettings::default().outputs,
+        ..settings.clone()
+    }
+}
+
+// The loop all of them share: updates the scene, renders every frame with its own
+// seed and hands it to `finish_frame` along with the settings it used.
+fn render_frames<F, G>(
+    scene: &mut Scene,
+    camera: &mut Camera,
+    settings: &RenderSettings,
+    frames: Frames,
+    mut update: F,
+    mut finish_frame: G,
+) -> Result<()>
+where
+    F: FnMut(&mut Scene, &mut Camera, u32, f64),
+    G: FnMut(u32, &RenderSettings, Framebuffer) -> Result<()>,
+{
+    settings.validate_without_outputs()?;
+    frames.validate()?;
+    let seed = settings.seed.unwrap_or_else(renderer::fresh_seed);
+    let start = Stopwatch::start();
+    for index in 0..frames.count {
+        update(scene, camera, index, frames.time(index));
+        if !scene.has_bvh() {
+            scene.build_bvh();
+        }
+        let frame_settings = RenderSettings {
+            seed: Some(frame_seed(seed, index)),
+            ..settings.clone()
+        };
+        let framebuffer = renderer::render(scene, camera, &frame_settings)?;
+        finish_frame(index, &frame_settings, framebuffer)?;
+    }
+    log::info!(
+        "rendered {} frames in {:.2?}",
+        frames.count,
+        start.elapsed()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::background::ConstantBackground;
+    use crate::material::Diffusor;
+    use crate::ray_tracing::Sphere;
+    use crate::vec_math::{Color, Point3, Vec3};
+
+    const WIDTH: u32 = 32;
+    const HEIGHT: u32 = 24;
+
+    // The mean pixel position of the dark sphere against the white background.
+    fn dark_centroid(framebuffer: &Framebuffer) -> (f64, f64) {
+        let (mut x, mut y, mut count) = (0.0, 0.0, 0.0);
+        for (index, color) in framebuffer.beauty.iter().enumerate() {
+            if color.to_array()[0] < 0.5 {
+                x += (index as u32 % WIDTH) as f64;
+                y += (index as u32 / WIDTH) as f64;
+                count += 1.0;
+            }
+        }
+        assert!(count > 0.0);
+        (x / count, y / count)
+    }
+
+    #[test]
+    fn a_named_sphere_moves_across_the_frames() {
+        let mut scene = Scene::new(Box::new(ConstantBackground(Color::new(1.0, 1.0, 1.0))));
+        let black = Arc::new(Diffusor {
+            color: Color::new(0.0, 0.0, 0.0),
+        });
+        scene
+            .add_named(
+                "hero",
+                Box::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 0.5, black)),
+            )
+            .unwrap();
+        let mut camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60f64.to_radians(),
+            WIDTH as f64 / HEIGHT as f64,
+            0.0,
+            5.0,
+        );
+        let settings = RenderSettings {
+            width: WIDTH,
+            height: HEIGHT,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            seed: Some(3),
+            ..RenderSettings::default()
+        };
+        let mut centroids = Vec::new();
+        render_frames(
+            &mut scene,
+            &mut camera,
+            &settings,
+            Frames::new(3, 1.0),
+            |scene, _, index, _| {
+                let hero = scene.get_named_mut::<Sphere>("hero").unwrap();
+                hero.set_center(Point3::new(index as f64 - 1.0, 0.0, -5.0));
+            },
+            |_, _, framebuffer| {
+                centroids.push(dark_centroid(&framebuffer));
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        // One unit at depth 5 is 1 / 5 / (2 tan 30°) of the image height.
+        let step = HEIGHT as f64 / 5.0 / (2.0 * 30f64.to_radians().tan());
+        let center = ((WIDTH - 1) as f64 / 2.0, (HEIGHT - 1) as f64 / 2.0);
+        for (index, &(x, y)) in centroids.iter().enumerate() {
+            let expected = center.0 + (index as f64 - 1.0) * step;
+            assert!(
+                (x - expected).abs() < 0.5,
+                "frame {}: {} not {}",
+                index,
+                x,
+                expected
+            );
+            assert!((y - center.1).abs() < 0.5, "frame {}: {}", index, y);
+        }
+    }
+
+    // A sphere in front of a white background, looking down -z.
+    fn small_scene() -> (Scene, Camera) {
+        let mut scene = Scene::new(Box::new(ConstantBackground(Color::new(1.0, 1.0, 1.0))));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -5.0),
+            0.5,
+            Arc::new(Diffusor {
+                color: Color::new(0.5, 0.5, 0.5),
+            }),
+        )));
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60f64.to_radians(),
+            WIDTH as f64 / HEIGHT as f64,
+            0.0,
+            5.0,
+        );
+        (scene, camera)
+    }
+
+    // Outputs the streaming renders never write; `validate` rejects the
+    // extension.
+    fn settings_with_unused_outputs() -> RenderSettings {
+        let mut outputs = MultiOutput::beauty_only(PathBuf::from("unused.xyz"));
+        outputs.normal = Some(PathBuf::from("unused_normal.xyz"));
+        let settings = RenderSettings {
+            width: WIDTH,
+            height: HEIGHT,
+            samples_per_pixel: 1,
+            max_depth: 2,
+            seed: Some(5),
+            outputs,
+            ..RenderSettings::default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        settings
+    }
+
+    #[cfg(feature = "png-output")]
+    #[test]
+    fn an_animated_image_ignores_the_outputs() {
+        let (mut scene, mut camera) = small_scene();
+        let settings = settings_with_unused_outputs();
+        let path =
+            std::env::temp_dir().join(format!("raytacer-animated-{}.png", std::process::id()));
+        let written = render_animated_image(
+            &mut scene,
+            &mut camera,
+            &settings,
+            Frames::new(2, 10.0),
+            &path,
+            &AnimationOptions::at_frame_rate(10.0),
+            |_, _, _, _| {},
+        );
+        let exists = path.exists();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(written.unwrap(), path);
+        assert!(exists);
+    }
+
+    #[test]
+    fn a_video_ignores_the_outputs() {
+        let (mut scene, mut camera) = small_scene();
+        let settings = settings_with_unused_outputs();
+        let path = std::env::temp_dir().join(format!("raytacer-video-{}.y4m", std::process::id()));
+        let output = VideoOutput::Y4m {
+            path: path.clone(),
+            range: crate::output::YuvRange::Limited,
+        };
+        let result = render_video(
+            &mut scene,
+            &mut camera,
+            &settings,
+            Frames::new(2, 10.0),
+            &output,
+            |_, _, _, _| {},
+        );
+        let video = std::fs::read(&path);
+        let _ = std::fs::remove_file(&path);
+        result.unwrap();
+        assert_eq!(
+            video.unwrap().windows(5).filter(|w| w == b"FRAME").count(),
+            2
+        );
+    }
+
+    #[test]
+    fn a_numbered_animation_still_checks_the_outputs() {
+        let (mut scene, mut camera) = small_scene();
+        let settings = settings_with_unused_outputs();
+        let result = render_animation(
+            &mut scene,
+            &mut camera,
+            &settings,
+            Frames::new(2, 10.0),
+            |_, _, _, _| panic!("rendered a frame it can't save"),
+        );
+        assert!(matches!(result, Err(Error::UnsupportedFormat(_))));
+    }
+}
@@ -0,0 +1,211 @@
+use rand::RngCore;
+
+use crate::ray_tracing::{Aabb, Camera, Ray, Scene};
+use crate::renderer::RenderSettings;
+use crate::vec_math::{Color, Interval};
+
+// How close to a box edge, in pixels, `render_bboxes` lights a pixel up.
+const OUTLINE_WIDTH: f64 = 1.0;
+
+// A single scalar per pixel, rows from top to bottom like the RGBA images.
+// Pixels without a value (e.g. depth where the ray escaped) hold infinity.
+pub struct ScalarBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f64>,
+}
+
+impl ScalarBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        ScalarBuffer {
+            width,
+            height,
+            data: vec![f64::INFINITY; width as usize * height as usize],
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f64 {
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: f64) {
+        self.data[y as usize * self.width as usize + x as usize] = value;
+    }
+}
+
+// Distance from the camera to the first hit through each pixel center.
+pub fn render_depth(
+    rng: &mut dyn RngCore,
+    scene: &Scene,
+    camera: &Camera,
+    width: u32,
+    height: u32,
+) -> ScalarBuffer {
+    let mut depth = ScalarBuffer::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let u = (x as f64 + 0.5) / width as f64;
+            let v = ((height - 1 - y) as f64 + 0.5) / height as f64;
+            let ray = camera.create_ray(rng, u, v);
+            if let Some(record) = scene.hit(&ray, Interval::new(0.001, f64::INFINITY)) {
+                depth.set(x, y, record.t * ray.direction.len());
+            }
+        }
+    }
+    depth
+}
+
+// Distinct, fully saturated colors for consecutive indices, spread around the
+// hue circle by the golden ratio.
+fn index_color(index: usize) -> Color {
+    let hue = (index as f64 * 0.618_033_988_749_895).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => Color::new(1.0, x, 0.0),
+        1 => Color::new(x, 1.0, 0.0),
+        2 => Color::new(0.0, 1.0, x),
+        3 => Color::new(0.0, x, 1.0),
+        4 => Color::new(x, 0.0, 1.0),
+        _ => Color::new(1.0, 0.0, x),
+    }
+}
+
+// A point on the surface of the box lies on an edge when it's on two faces
+// at once.
+fn is_near_edge(aabb: &Aabb, ray: &Ray, t: f64, threshold: f64) -> bool {
+    let point = ray.at(t);
+    let faces = (0..3)
+        .filter(|&axis| {
+            (point[axis] - aabb.min[axis]).abs() < threshold
+                || (aabb.max[axis] - point[axis]).abs() < threshold
+        })
+        .count();
+    faces >= 2
+}
+
+// Wireframe outlines of every bounded object's bounding box, colored by the
+// object's index in the scene, on black. Both the front and the back edges are
+// drawn, the nearest one winning. Rows go from top to bottom like the images.
+pub fn render_bboxes(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Vec<Color> {
+    let (width, height) = (settings.width, settings.height);
+    let boxes: Vec<(usize, Aabb)> = scene
+        .hittables()
+        .iter()
+        .enumerate()
+        .filter_map(|(index, hittable)| hittable.bounding_box().map(|aabb| (index, aabb)))
+        .collect();
+    let pixel_u = 1.0 / width as f64;
+    let mut colors = Vec::with_capacity(width as usize * height as usize);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let u = (x as f64 + 0.5) * pixel_u;
+            let v = (y as f64 + 0.5) / height as f64;
+            let ray = camera.create_center_ray(u, v);
+            // How far apart neighboring pixels' rays are per unit of t.
+            let spread = (camera.create_center_ray(u + pixel_u, v).direction - ray.direction).len();
+            let mut closest = f64::INFINITY;
+            let mut nearest = None;
+            for (index, aabb) in &boxes {
+                let bounds = Interval::new(0.001, f64::INFINITY);
+                let inside = match aabb.hit_interval(&ray, bounds) {
+                    Some(inside) => inside,
+                    None => continue,
+                };
+                // When the camera is inside the box, the entry is clipped to
+                // the near bound and isn't on the surface.
+                for &t in &[inside.min, inside.max] {
+                    if t <= bounds.min {
+                        continue;
+                    }
+                    let threshold = OUTLINE_WIDTH * spread * t;
+                    if t < closest && is_near_edge(aabb, &ray, t, threshold) {
+                        closest = t;
+                        nearest = Some(*index);
+                    }
+                }
+            }
+            colors.push(nearest.map_or(Color::new(0.0, 0.0, 0.0), index_color));
+        }
+    }
+    colors
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::background::Sky;
+    use crate::material::Diffusor;
+    use crate::ray_tracing::Sphere;
+    use crate::vec_math::{Point3, Vec3};
+
+    #[test]
+    fn sphere_bounding_box_is_outlined_as_a_square() {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let gray = Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        });
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -5.0),
+            1.0,
+            gray,
+        )));
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0f64.to_radians(),
+            1.0,
+            0.0,
+            1.0,
+        );
+        let settings = RenderSettings {
+            width: 64,
+            height: 64,
+            ..RenderSettings::default()
+        };
+        let colors = render_bboxes(&scene, &camera, &settings);
+        assert_eq!(colors.len(), 64 * 64);
+        let lit = |x: usize, y: usize| colors[y * 64 + x].to_array() != [0.0; 3];
+        // The front face, 2 units wide at distance 4, spans a quarter of the
+        // 90 degree view: from pixel 24 to 40, its edges between pixels 23
+        // and 24 and between 39 and 40.
+        for along in 25..39 {
+            assert!(lit(23, along) || lit(24, along), "left edge, row {}", along);
+            assert!(
+                lit(39, along) || lit(40, along),
+                "right edge, row {}",
+                along
+            );
+            assert!(
+                lit(along, 23) || lit(along, 24),
+                "top edge, column {}",
+                along
+            );
+            assert!(
+                lit(along, 39) || lit(along, 40),
+                "bottom edge, column {}",
+                along
+            );
+        }
+        for y in 0..64 {
+            for x in 0..64 {
+                if lit(x, y) {
+                    assert!(
+                        (22..42).contains(&x) && (22..42).contains(&y),
+                        "({}, {})",
+                        x,
+                        y
+                    );
+                    assert_eq!(colors[y * 64 + x].to_array(), index_color(0).to_array());
+                }
+            }
+        }
+        // Inside the square and between the edges of the back face, the rays
+        // go through the box without touching an edge.
+        for x in 29..36 {
+            assert!(!lit(x, 32) && !lit(32, x));
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::any::Any;
+
+use crate::vec_math::{Color, Vec3};
+
+pub trait Background: Any + Send + Sync {
+    fn sample(&self, direction: Vec3) -> Color;
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+pub struct Sky {
+    pub zenith_color: Color,
+    pub horizon_color: Color,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Sky {
+            zenith_color: Color::new(0.5, 0.7, 1.0),
+            horizon_color: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Background for Sky {
+    fn sample(&self, direction: Vec3) -> Color {
+        let unit_direction = direction.to_unit();
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        Color::lerp(self.horizon_color, self.zenith_color, t)
+    }
+}
+
+pub struct ConstantBackground(pub Color);
+
+impl Background for ConstantBackground {
+    fn sample(&self, _direction: Vec3) -> Color {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec_eq;
+
+    #[test]
+    fn sky_runs_from_horizon_color_below_to_zenith_color_above() {
+        let sky = Sky {
+            zenith_color: Color::new(0.2, 0.4, 1.0),
+            horizon_color: Color::new(1.0, 0.8, 0.6),
+        };
+        assert_vec_eq!(sky.sample(Vec3::new(0.0, 1.0, 0.0)).0, sky.zenith_color.0);
+        assert_vec_eq!(sky.sample(Vec3::new(0.0, -1.0, 0.0)).0, sky.horizon_color.0);
+        // Level directions are halfway, however long.
+        assert_vec_eq!(
+            sky.sample(Vec3::new(3.0, 0.0, -4.0)).0,
+            Vec3::new(0.6, 0.6, 0.8)
+        );
+    }
+
+    #[test]
+    fn constant_background_ignores_the_direction() {
+        let black = ConstantBackground(Color::BLACK);
+        for direction in [Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -2.0, 1.0)] {
+            assert_vec_eq!(black.sample(direction).0, Color::BLACK.0);
+        }
+    }
+}
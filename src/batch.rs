@@ -0,0 +1,340 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt, fs,
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::material::MaterialDescription;
+use crate::output::metadata::{self, Metadata};
+use crate::output::ImageFormat;
+use crate::presets::Preset;
+use crate::renderer::{self, RenderStats};
+use crate::scene_file::{LoadedScene, SceneFile, SceneFileError};
+
+// A list of renders to run one after the other, or `parallel_jobs` at a time.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchManifest {
+    #[serde(default = "one_job")]
+    pub parallel_jobs: usize,
+    pub jobs: Vec<BatchJob>,
+}
+
+fn one_job() -> usize {
+    1
+}
+
+// Anything left out keeps the scene's own value. Relative paths are resolved
+// against the manifest's directory.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchJob {
+    // A built-in scene name or a scene file.
+    pub scene: String,
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples_per_pixel: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    // Replaces materials of a scene file by name, for material variations of
+    // the same scene.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, MaterialDescription>,
+}
+
+// Lexically, so that `out/a.png` and `out/../out/a.png` collide; symlinks
+// aren't followed.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl BatchManifest {
+    pub fn parse(source: &str) -> std::result::Result<BatchManifest, SceneFileError> {
+        ron::from_str(source).map_err(|error| SceneFileError::Parse {
+            line: error.position.line,
+            column: error.position.col,
+            message: error.code.to_string(),
+        })
+    }
+
+    pub fn from_file(path: &Path) -> std::result::Result<BatchManifest, SceneFileError> {
+        let source = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        BatchManifest::parse(&source)
+    }
+
+    // Catches what would otherwise only fail halfway through the night: two
+    // jobs writing the same file and outputs in formats this build can't
+    // write. Paths are compared as written, before placeholders like {width}
+    // are expanded.
+    pub fn validate(&self, base: &Path) -> Result<()> {
+        if self.parallel_jobs == 0 {
+            return Err(Error::InvalidSettings(
+                "parallel_jobs must be at least 1".to_string(),
+            ));
+        }
+        let mut outputs: HashMap<PathBuf, usize> = HashMap::new();
+        for (index, job) in self.jobs.iter().enumerate() {
+            let output = normalize(&base.join(&job.output));
+            if ImageFormat::from_path(&output).is_none() {
+                return Err(Error::UnsupportedFormat(output));
+            }
+            if let Some(other) = outputs.insert(output.clone(), index) {
+                return Err(Error::InvalidSettings(format!(
+                    "jobs {} and {} both write {}",
+                    other + 1,
+                    index + 1,
+                    output.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Runs every job, carrying on past failures. Fails only when the manifest
+    // itself is invalid.
+    pub fn run(&self, base: &Path) -> Result<BatchReport> {
+        self.validate(base)?;
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(self.jobs.len()));
+        let worker = || loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            let job = match self.jobs.get(index) {
+                Some(job) => job,
+                None => break,
+            };
+            log::info!("job {}/{}: {}", index + 1, self.jobs.len(), job.scene);
+            let (output, result) = match job.run(base) {
+                Ok((output, stats)) => (output, Ok(stats)),
+                Err(error) => {
+                    log::warn!("job {} failed: {}", index + 1, error);
+                    (base.join(&job.output), Err(error))
+                }
+            };
+            results.lock().unwrap().push(JobOutcome {
+                index,
+                output,
+                result,
+            });
+        };
+        let workers = self.parallel_jobs.min(self.jobs.len());
+        if workers > 1 {
+            std::thread::scope(|scope| {
+                for _ in 0..workers {
+                    scope.spawn(worker);
+                }
+            });
+        } else {
+            worker();
+        }
+        let mut jobs = results.into_inner().unwrap();
+        jobs.sort_by_key(|outcome| outcome.index);
+        Ok(BatchReport { jobs })
+    }
+}
+
+impl BatchJob {
+    // The metadata says where the scene came from.
+    fn load(&self, base: &Path) -> Result<(LoadedScene, Metadata)> {
+        let mut origin = Metadata::default();
+        if let Some(preset) = Preset::from_name(&self.scene) {
+            if !self.materials.is_empty() {
+                return Err(Error::InvalidSettings(format!(
+                    "materials can only be replaced in scene files, not in the built-in {} scene",
+                    self.scene
+                )));
+            }
+            let (scene, camera, settings) = preset.setup(self.seed);
+            origin.set(metadata::SCENE, preset.name());
+            let loaded = LoadedScene {
+                scene,
+                camera,
+                settings,
+            };
+            return Ok((loaded, origin));
+        }
+        let path = base.join(&self.scene);
+        let source = fs::read_to_string(&path).map_err(|source| SceneFileError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        origin.set_scene_file(&path, source.as_bytes());
+        let mut file = SceneFile::parse(&source)?;
+        for (name, description) in &self.materials {
+            match file.materials.get_mut(name) {
+                Some(material) => *material = *description,
+                None => {
+                    return Err(SceneFileError::UnknownMaterial {
+                        key: "materials".to_string(),
+                        material: name.clone(),
+                    }
+                    .into())
+                }
+            }
+        }
+        Ok((file.build()?, origin))
+    }
+
+    // Same rules as the command line: a lone width or height keeps the
+    // scene's aspect ratio, and the camera follows the new shape. Returns the
+    // output path with its placeholders expanded.
+    fn run(&self, base: &Path) -> Result<(PathBuf, RenderStats)> {
+        let (
+            LoadedScene {
+                scene,
+                camera,
+                mut settings,
+            },
+            origin,
+        ) = self.load(base)?;
+        let (width, height) = settings.fitted_resolution(self.width, self.height);
+        settings.width = width;
+        settings.height = height;
+        settings.samples_per_pixel = self.samples_per_pixel.unwrap_or(settings.samples_per_pixel);
+        settings.max_depth = self.max_depth.unwrap_or(settings.max_depth);
+        settings.exposure = self.exposure.unwrap_or(settings.exposure);
+        settings.seed = self.seed.or(settings.seed);
+        settings.outputs.beauty = base.join(&self.output);
+        settings.outputs = settings
+            .outputs
+            .expand_templates(&settings, SystemTime::now());
+
+        let camera = camera.with_aspect_ratio(width as f64 / height.max(1) as f64);
+        let mut framebuffer = renderer::render(&scene, &camera, &settings)?;
+        framebuffer.metadata.merge(&origin);
+        framebuffer.save(&settings.outputs)?;
+        Ok((settings.outputs.beauty, framebuffer.stats))
+    }
+}
+
+pub struct JobOutcome {
+    // Position in the manifest, from 0.
+    pub index: usize,
+    pub output: PathBuf,
+    pub result: Result<RenderStats>,
+}
+
+// Outcomes in manifest order.
+pub struct BatchReport {
+    pub jobs: Vec<JobOutcome>,
+}
+
+impl BatchReport {
+    pub fn failures(&self) -> usize {
+        self.jobs.iter().filter(|job| job.result.is_err()).count()
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for job in &self.jobs {
+            match &job.result {
+                Ok(stats) => writeln!(
+                    f,
+                    "job {}: ok, {} ({})",
+                    job.index + 1,
+                    job.output.display(),
+                    stats
+                )?,
+                Err(error) => writeln!(f, "job {}: FAILED, {}", job.index + 1, error)?,
+            }
+        }
+        writeln!(
+            f,
+            "{} of {} jobs succeeded",
+            self.jobs.len() - self.failures(),
+            self.jobs.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(jobs: &[(&str, &str)]) -> BatchManifest {
+        BatchManifest {
+            parallel_jobs: 2,
+            jobs: jobs
+                .iter()
+                .map(|&(scene, output)| BatchJob {
+                    scene: scene.to_string(),
+                    output: output.to_string(),
+                    width: Some(16),
+                    height: Some(12),
+                    samples_per_pixel: Some(2),
+                    max_depth: Some(4),
+                    seed: Some(1),
+                    ..BatchJob::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn two_jobs_write_two_distinct_images() {
+        let dir = std::env::temp_dir().join(format!("raytacer-batch-{}", std::process::id()));
+        let report = manifest(&[("single-diffuse", "a.bmp"), ("single-mirror", "b.bmp")])
+            .run(&dir)
+            .unwrap();
+        assert_eq!(report.failures(), 0);
+        let outputs: Vec<_> = report.jobs.iter().map(|job| job.output.clone()).collect();
+        assert_eq!(outputs, [dir.join("a.bmp"), dir.join("b.bmp")]);
+        let a = fs::read(dir.join("a.bmp")).unwrap();
+        let b = fs::read(dir.join("b.bmp")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(a.len(), b.len());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn a_failed_job_does_not_stop_the_batch() {
+        let dir = std::env::temp_dir().join(format!("raytacer-batch-fail-{}", std::process::id()));
+        let report = manifest(&[("missing.ron", "a.bmp"), ("single-diffuse", "b.bmp")])
+            .run(&dir)
+            .unwrap();
+        let written = dir.join("b.bmp").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(report.failures(), 1);
+        assert!(report.jobs[0].result.is_err());
+        assert!(report.jobs[1].result.is_ok());
+        assert!(written);
+    }
+
+    #[test]
+    fn colliding_outputs_are_rejected_before_rendering() {
+        let dir = std::env::temp_dir().join(format!("raytacer-batch-clash-{}", std::process::id()));
+        let batch = manifest(&[
+            ("single-diffuse", "out/a.bmp"),
+            ("single-mirror", "out/../out/a.bmp"),
+        ]);
+        assert!(matches!(batch.run(&dir), Err(Error::InvalidSettings(_))));
+        assert!(!dir.exists());
+    }
+}
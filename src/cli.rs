@@ -0,0 +1,478 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use raytacer::material::MaterialOverride;
+use raytacer::output::{ImageFormat, OutputTransform};
+use raytacer::post_process::{AtrousSettings, Denoiser};
+use raytacer::presets::Preset;
+use raytacer::ray_tracing::Camera;
+use raytacer::{RenderSettings, TileOrder};
+
+pub const USAGE: &str = "\
+Usage: raytacer [OPTIONS] [SCENE]
+
+Renders SCENE, the name of a built-in scene or a .ron scene file. Without one
+the bundled scenes/random_spheres.ron is used, or a freshly generated random
+scene if that file is missing. Options override the values stored in the
+scene.
+
+Built-in scenes: random (the random spheres, also called classic), cornell,
+three-spheres, gallery, frosted-glass, checkered, single-diffuse, single-glass
+and single-mirror.
+
+Options:
+      --scene <NAME|FILE>   built-in scene or scene file to render, same as the
+                            SCENE argument
+      --preset <NAME>       render a built-in scene, never a file
+      --batch <MANIFEST>    render every job of a .ron batch manifest, one
+                            after the other or parallel_jobs at a time, and
+                            report which ones failed; the other options are
+                            ignored
+      --inspect <IMAGE>     print the settings a PNG or EXR image was rendered
+                            with, as stored in it, and exit
+      --width <PIXELS>      image width in pixels, at least 1
+      --height <PIXELS>     image height in pixels, at least 1; if only one of
+                            width and height is given, the other follows the
+                            scene's aspect ratio
+      --spp <COUNT>         samples per pixel, at least 1
+      --max-depth <BOUNCES> maximum number of bounces per path, at least 1
+      --ray-epsilon <DISTANCE>
+                            ignore hits closer than this along a ray, 0.001 by
+                            default; lower it for tiny objects that show gaps
+                            where they touch
+      --fov <DEGREES>       vertical field of view in degrees, between 0 and 180
+      --exposure <FACTOR>   multiply the rendered radiance, 2 is one stop
+                            brighter; 1 by default
+      --seed <NUMBER>       random seed; the same seed renders the same image
+  -o, --output <FILE>       output image, the format follows the extension
+                            (png, jpg, tga, exr or bmp, depending on the
+                            build);
+                            {date}, {time}, {samples}, {width}, {height} and
+                            {seed} are replaced, and missing directories are
+                            created
+      --threads <COUNT>     render threads, 0 for one per core (the default)
+      --reproducible        leave the render time out of the image metadata, so
+                            the same seed and options write the same bytes
+                            whatever the thread count
+      --tile-order <ORDER>  the order tiles are rendered in: scanline (the
+                            default), spiral from the center out, or hilbert;
+                            the image comes out the same
+      --preview             quick draft: at most 400 pixels wide, 16 samples
+                            per pixel and 8 bounces; explicit options still win
+      --window              render progressively into a window instead of
+                            a file: Esc quits, S saves the image so far to
+                            the output, + and - change the exposure (needs
+                            the `window` feature)
+      --clay                render every surface in plain gray diffuse, lights
+                            as they are, to judge lighting apart from shading
+      --denoise <FILTER>    denoise the image; the only filter is atrous, an
+                            edge-avoiding wavelet filter guided by the normals
+                            and depth
+      --transfer <CURVE>    how the 8-bit outputs are encoded: srgb (the
+                            default), gamma2, linear, or legacy for the exact
+                            bytes of renders before sRGB
+      --debug-pixel <X,Y>   instead of rendering, print every bounce of one
+                            sample of the pixel at X,Y (from the top left),
+                            as the render with the same options traces it;
+                            needs --seed or a seeded scene to match a render
+      --debug-sample <N>    the sample --debug-pixel traces, 0 by default
+      --force               render even when checking the scene found errors,
+                            such as a zero radius or a glass index of 0
+  -q, --quiet               don't print progress
+  -h, --help                print this help
+";
+
+const PREVIEW_MAX_WIDTH: u32 = 400;
+const PREVIEW_SAMPLES_PER_PIXEL: u32 = 16;
+const PREVIEW_MAX_DEPTH: u32 = 8;
+
+#[derive(Debug)]
+pub struct CliError(String);
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn error(message: impl Into<String>) -> CliError {
+    CliError(message.into())
+}
+
+const PRESET_NAMES: &str = "random, cornell, three-spheres, gallery, frosted-glass, checkered, \
+     single-diffuse, single-glass or single-mirror";
+
+pub enum Command {
+    Help,
+    Render(Box<Options>),
+    Batch(PathBuf),
+    Inspect(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub scene: Option<PathBuf>,
+    pub preset: Option<Preset>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub samples_per_pixel: Option<u32>,
+    pub max_depth: Option<u32>,
+    pub ray_epsilon: Option<f64>,
+    // Degrees, converted to radians when the camera is rebuilt.
+    pub fov: Option<f64>,
+    pub exposure: Option<f64>,
+    pub seed: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub threads: Option<usize>,
+    pub tile_order: Option<TileOrder>,
+    pub preview: bool,
+    pub window: bool,
+    pub quiet: bool,
+    pub reproducible: bool,
+    pub clay: bool,
+    pub force: bool,
+    pub denoiser: Option<Denoiser>,
+    pub output_transform: Option<OutputTransform>,
+    pub debug_pixel: Option<(u32, u32)>,
+    pub debug_sample: u32,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: &str, expected: &str) -> Result<T, CliError> {
+    value
+        .parse()
+        .map_err(|_| error(format!("{} expects {}, got `{}`", flag, expected, value)))
+}
+
+fn parse_at_least(flag: &str, value: &str, min: u32, unit: &str) -> Result<u32, CliError> {
+    let expected = format!("a whole number of {} of at least {}", unit, min);
+    let parsed: u32 = parse_value(flag, value, &expected)?;
+    if parsed < min {
+        return Err(error(format!(
+            "{} expects {}, got `{}`",
+            flag, expected, value
+        )));
+    }
+    Ok(parsed)
+}
+
+pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut batch = None;
+    let mut inspect = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let arg = arg.into_string().map_err(|arg| {
+            error(format!(
+                "argument `{}` is not valid UTF-8",
+                arg.to_string_lossy()
+            ))
+        })?;
+        if !arg.starts_with('-') || arg == "-" {
+            if options.scene.is_some() || options.preset.is_some() {
+                return Err(error(format!("unexpected argument `{}`", arg)));
+            }
+            set_scene(&mut options, arg);
+            continue;
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--preview" => {
+                if inline_value.is_some() {
+                    return Err(error("--preview does not take a value"));
+                }
+                options.preview = true;
+                continue;
+            }
+            "--window" => {
+                if inline_value.is_some() {
+                    return Err(error("--window does not take a value"));
+                }
+                if cfg!(not(feature = "window")) {
+                    return Err(error("--window needs a build with the `window` feature"));
+                }
+                options.window = true;
+                continue;
+            }
+            "--force" => {
+                if inline_value.is_some() {
+                    return Err(error("--force does not take a value"));
+                }
+                options.force = true;
+                continue;
+            }
+            "--reproducible" => {
+                if inline_value.is_some() {
+                    return Err(error("--reproducible does not take a value"));
+                }
+                options.reproducible = true;
+                continue;
+            }
+            "--clay" => {
+                if inline_value.is_some() {
+                    return Err(error("--clay does not take a value"));
+                }
+                options.clay = true;
+                continue;
+            }
+            "-q" | "--quiet" => {
+                if inline_value.is_some() {
+                    return Err(error("--quiet does not take a value"));
+                }
+                options.quiet = true;
+                continue;
+            }
+            "--scene" | "--preset" | "--batch" | "--inspect" | "--width" | "--height" | "--spp"
+            | "--max-depth" | "--ray-epsilon" | "--fov" | "--exposure" | "--seed" | "-o"
+            | "--output" | "--threads" | "--tile-order" | "--denoise" | "--transfer"
+            | "--debug-pixel" | "--debug-sample" => {}
+            _ => return Err(error(format!("unknown option `{}`", flag))),
+        }
+        let value = match inline_value {
+            Some(value) => value,
+            None => args
+                .next()
+                .ok_or_else(|| error(format!("{} expects a value", flag)))?
+                .into_string()
+                .map_err(|_| error(format!("the value for {} is not valid UTF-8", flag)))?,
+        };
+        match flag.as_str() {
+            "--scene" => set_scene(&mut options, value),
+            "--preset" => {
+                options.preset = Some(Preset::from_name(&value).ok_or_else(|| {
+                    error(format!(
+                        "--preset expects {}, got `{}`",
+                        PRESET_NAMES, value
+                    ))
+                })?)
+            }
+            "--batch" => {
+                if cfg!(not(feature = "scene-files")) {
+                    return Err(error(
+                        "--batch needs a build with the `scene-files` feature",
+                    ));
+                }
+                batch = Some(PathBuf::from(value));
+            }
+            "--inspect" => inspect = Some(PathBuf::from(value)),
+            "--width" => options.width = Some(parse_at_least(&flag, &value, 1, "pixels")?),
+            "--height" => options.height = Some(parse_at_least(&flag, &value, 1, "pixels")?),
+            "--spp" => {
+                options.samples_per_pixel = Some(parse_at_least(&flag, &value, 1, "samples")?)
+            }
+            "--max-depth" => options.max_depth = Some(parse_at_least(&flag, &value, 1, "bounces")?),
+            "--ray-epsilon" => {
+                let ray_epsilon: f64 = parse_value(&flag, &value, "a non-negative number")?;
+                if !(ray_epsilon >= 0.0 && ray_epsilon.is_finite()) {
+                    return Err(error(format!(
+                        "--ray-epsilon expects a non-negative number, got `{}`",
+                        value
+                    )));
+                }
+                options.ray_epsilon = Some(ray_epsilon);
+            }
+            "--fov" => {
+                let fov: f64 = parse_value(&flag, &value, "an angle in degrees")?;
+                if !(fov > 0.0 && fov < 180.0) {
+                    return Err(error(format!(
+                        "--fov expects an angle between 0 and 180 degrees, got `{}`",
+                        value
+                    )));
+                }
+                options.fov = Some(fov);
+            }
+            "--exposure" => {
+                let exposure: f64 = parse_value(&flag, &value, "a non-negative number")?;
+                if !(exposure >= 0.0 && exposure.is_finite()) {
+                    return Err(error(format!(
+                        "--exposure expects a non-negative number, got `{}`",
+                        value
+                    )));
+                }
+                options.exposure = Some(exposure);
+            }
+            "--seed" => {
+                options.seed = Some(parse_value(&flag, &value, "a non-negative whole number")?)
+            }
+            "-o" | "--output" => {
+                let output = PathBuf::from(&value);
+                if ImageFormat::from_path(&output).is_none() {
+                    return Err(error(format!(
+                        "--output `{}` has an unsupported extension; this build writes {}",
+                        value,
+                        supported_formats()
+                    )));
+                }
+                options.output = Some(output);
+            }
+            "--denoise" => {
+                options.denoiser = match value.as_str() {
+                    "atrous" => Some(Denoiser::Atrous(AtrousSettings::default())),
+                    _ => return Err(error(format!("--denoise expects atrous, got `{}`", value))),
+                }
+            }
+            "--transfer" => {
+                options.output_transform =
+                    Some(OutputTransform::from_name(&value).ok_or_else(|| {
+                        error(format!(
+                            "--transfer expects srgb, gamma2, linear or legacy, got `{}`",
+                            value
+                        ))
+                    })?);
+            }
+            "--debug-pixel" => {
+                let expected = "pixel coordinates like 612,314";
+                let (x, y) = value.split_once(',').ok_or_else(|| {
+                    error(format!("{} expects {}, got `{}`", flag, expected, value))
+                })?;
+                options.debug_pixel = Some((
+                    parse_value(&flag, x.trim(), expected)?,
+                    parse_value(&flag, y.trim(), expected)?,
+                ));
+            }
+            "--debug-sample" => {
+                options.debug_sample = parse_value(&flag, &value, "a sample index")?;
+            }
+            "--threads" => {
+                let threads = parse_value(&flag, &value, "a whole number of threads")?;
+                if cfg!(not(feature = "parallel")) && threads > 1 {
+                    return Err(error("--threads needs a build with the `parallel` feature"));
+                }
+                options.threads = Some(threads);
+            }
+            "--tile-order" => {
+                options.tile_order = Some(TileOrder::from_name(&value).ok_or_else(|| {
+                    error(format!(
+                        "--tile-order expects scanline, spiral or hilbert, got `{}`",
+                        value
+                    ))
+                })?);
+            }
+            _ => unreachable!(),
+        }
+    }
+    if options.scene.is_some() && options.preset.is_some() {
+        return Err(error(
+            "a scene file and a built-in scene can't be used together",
+        ));
+    }
+    if let Some(image) = inspect {
+        return Ok(Command::Inspect(image));
+    }
+    if let Some(manifest) = batch {
+        return Ok(Command::Batch(manifest));
+    }
+    Ok(Command::Render(Box::new(options)))
+}
+
+// Built-in scene names win over files of the same name; `./cornell` still
+// picks the file.
+fn set_scene(options: &mut Options, value: String) {
+    match Preset::from_name(&value) {
+        Some(preset) => options.preset = Some(preset),
+        None => options.scene = Some(PathBuf::from(value)),
+    }
+}
+
+fn supported_formats() -> String {
+    let mut formats = vec![];
+    if cfg!(feature = "png-output") {
+        formats.push(".png");
+    }
+    if cfg!(feature = "jpeg-output") {
+        formats.push(".jpg");
+    }
+    formats.push(".tga");
+    if cfg!(feature = "exr") {
+        formats.push(".exr");
+    }
+    formats.push(".bmp");
+    let (last, rest) = formats.split_last().unwrap();
+    format!("{} and {}", rest.join(", "), last)
+}
+
+impl Options {
+    // Overrides the scene's camera and settings with whatever was given on the
+    // command line. The camera is rebuilt when the aspect ratio or fov change.
+    pub fn apply(
+        &self,
+        camera: Camera,
+        mut settings: RenderSettings,
+    ) -> Result<(Camera, RenderSettings), CliError> {
+        let (width, height) = match (self.width, self.height) {
+            (None, None) if self.preview && settings.width > PREVIEW_MAX_WIDTH => {
+                settings.fitted_resolution(Some(PREVIEW_MAX_WIDTH), None)
+            }
+            (width, height) => settings.fitted_resolution(width, height),
+        };
+        if width == 0 || height == 0 {
+            return Err(error(format!(
+                "resolution {}x{} is too small, both sides need at least 1 pixel",
+                width, height
+            )));
+        }
+        settings.width = width;
+        settings.height = height;
+
+        if self.preview {
+            settings.samples_per_pixel = settings.samples_per_pixel.min(PREVIEW_SAMPLES_PER_PIXEL);
+            settings.max_depth = settings.max_depth.min(PREVIEW_MAX_DEPTH);
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            settings.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            settings.max_depth = max_depth;
+        }
+        if let Some(ray_epsilon) = self.ray_epsilon {
+            settings.ray_epsilon = ray_epsilon;
+        }
+        if let Some(exposure) = self.exposure {
+            settings.exposure = exposure;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = Some(seed);
+        }
+        if let Some(threads) = self.threads {
+            settings.threads = Some(threads);
+        }
+        if let Some(tile_order) = self.tile_order {
+            settings.tile_order = tile_order;
+        }
+        if self.clay {
+            settings.material_override = Some(MaterialOverride::clay());
+        }
+        if let Some(denoiser) = self.denoiser {
+            settings.denoiser = Some(denoiser);
+        }
+        if let Some(output_transform) = self.output_transform {
+            settings.output_transform = output_transform;
+        }
+        if let Some(output) = &self.output {
+            settings.outputs.beauty = output.clone();
+        }
+
+        let new_aspect_ratio = width as f64 / height as f64;
+        if self.fov.is_none() && new_aspect_ratio == camera.config().aspect_ratio {
+            return Ok((camera, settings));
+        }
+        let config = camera.config();
+        let camera = Camera::new(
+            config.look_from,
+            config.look_at,
+            config.vector_up,
+            self.fov.map_or(config.fov, f64::to_radians),
+            new_aspect_ratio,
+            config.aperture,
+            config.focus_distance,
+        )
+        .with_chromatic_aberration(config.chromatic_aberration)
+        .with_distortion(config.distortion);
+        Ok((camera, settings))
+    }
+}
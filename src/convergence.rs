@@ -0,0 +1,279 @@
+use std::fmt;
+
+// Log-spaced histogram bins for relative errors from MIN_ERROR up to
+// MIN_ERROR * 10^DECADES; the 95th percentile comes out within about 4%.
+const BINS_PER_DECADE: usize = 32;
+const DECADES: usize = 6;
+const MIN_ERROR: f64 = 1e-4;
+
+// Count, mean and variance of a stream of samples, updated one sample at a
+// time (Welford) without the cancellation of summing squares. Two states
+// merge (Chan et al.) into exactly what pushing both streams into one would
+// give, so parts accumulated on different threads or in different passes
+// can be combined.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Welford {
+    count: u64,
+    mean: f64,
+    // Sum of squared differences from the mean.
+    m2: f64,
+}
+
+impl Welford {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn merge(&mut self, other: &Welford) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        let weight = other.count as f64 / count as f64;
+        self.mean += delta * weight;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * weight;
+        self.count = count;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    // The unbiased sample variance; 0 with fewer than two samples.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+
+    // How much the mean itself would vary between renders, which is what a
+    // denoiser or adaptive sampler wants.
+    pub fn variance_of_mean(&self) -> f64 {
+        self.variance() / self.count.max(1) as f64
+    }
+
+    // The standard error of the mean relative to the mean. None with fewer
+    // than two samples, or for a mean of zero, where it means nothing.
+    pub fn relative_error(&self) -> Option<f64> {
+        if self.count < 2 || self.mean <= 0.0 {
+            return None;
+        }
+        Some(self.variance_of_mean().sqrt() / self.mean)
+    }
+}
+
+// Relative errors of pixel means, gathered into a histogram so that parts
+// gathered separately merge cheaply. Pixels without a relative error are
+// left out.
+#[derive(Debug, Clone)]
+pub struct Convergence {
+    pixels: u64,
+    sum: f64,
+    bins: Vec<u64>,
+}
+
+impl Default for Convergence {
+    fn default() -> Self {
+        Convergence {
+            pixels: 0,
+            sum: 0.0,
+            bins: vec![0; BINS_PER_DECADE * DECADES],
+        }
+    }
+}
+
+// How converged a render is: the mean and 95th percentile of the relative
+// standard error of its pixels, over `pixels` pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConvergenceSummary {
+    pub pixels: u64,
+    pub mean: f64,
+    pub p95: f64,
+}
+
+impl Convergence {
+    pub fn add(&mut self, pixel: &Welford) {
+        let error = match pixel.relative_error() {
+            Some(error) if error.is_finite() => error,
+            _ => return,
+        };
+        let position = (error / MIN_ERROR).log10() * BINS_PER_DECADE as f64;
+        let bin = (position.max(0.0) as usize).min(self.bins.len() - 1);
+        self.bins[bin] += 1;
+        self.pixels += 1;
+        self.sum += error;
+    }
+
+    pub fn merge(&mut self, other: &Convergence) {
+        self.pixels += other.pixels;
+        self.sum += other.sum;
+        for (bin, other) in self.bins.iter_mut().zip(&other.bins) {
+            *bin += other;
+        }
+    }
+
+    // None until some pixel had a relative error.
+    pub fn summary(&self) -> Option<ConvergenceSummary> {
+        if self.pixels == 0 {
+            return None;
+        }
+        let target = (self.pixels as f64 * 0.95).ceil() as u64;
+        let mut seen = 0;
+        let bin = self
+            .bins
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen >= target
+            })
+            .unwrap_or(self.bins.len() - 1);
+        // The geometric middle of the bin.
+        let p95 = MIN_ERROR * 10f64.powf((bin as f64 + 0.5) / BINS_PER_DECADE as f64);
+        Some(ConvergenceSummary {
+            pixels: self.pixels,
+            mean: self.sum / self.pixels as f64,
+            p95,
+        })
+    }
+}
+
+impl fmt::Display for ConvergenceSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "relative error {:.2}% mean, {:.2}% 95th percentile",
+            self.mean * 100.0,
+            self.p95 * 100.0
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn pushed(values: &[f64]) -> Welford {
+        let mut welford = Welford::default();
+        for &value in values {
+            welford.push(value);
+        }
+        welford
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(b.abs()).max(1.0)
+    }
+
+    #[test]
+    fn known_mean_and_variance() {
+        let welford = pushed(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(welford.count(), 8);
+        assert_eq!(welford.mean(), 5.0);
+        assert!(close(welford.variance(), 32.0 / 7.0));
+        assert!(close(welford.variance_of_mean(), 32.0 / 7.0 / 8.0));
+    }
+
+    #[test]
+    fn merging_an_empty_state_changes_nothing() {
+        let full = pushed(&[1.0, 2.0, 6.0]);
+        let mut merged = full;
+        merged.merge(&Welford::default());
+        assert_eq!(merged, full);
+        let mut empty = Welford::default();
+        empty.merge(&full);
+        assert_eq!(empty, full);
+    }
+
+    // Samples far from zero with a small spread are where summing squares
+    // cancels; merged states must keep the spread.
+    #[test]
+    fn merging_keeps_a_small_spread_around_a_large_mean() {
+        let values: Vec<f64> = (0..1000).map(|i| 1e9 + (i % 7) as f64).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / 999.0;
+        let mut merged = pushed(&values[..300]);
+        merged.merge(&pushed(&values[300..]));
+        assert!((merged.variance() - variance).abs() < 1e-6 * variance);
+        assert!(close(merged.mean(), mean));
+    }
+
+    proptest! {
+        #[test]
+        fn merging_two_parts_equals_pushing_everything(
+            values in prop::collection::vec(-1e3..1e3f64, 0..64),
+            split in any::<prop::sample::Index>(),
+        ) {
+            let split = split.index(values.len() + 1);
+            let mut merged = pushed(&values[..split]);
+            merged.merge(&pushed(&values[split..]));
+            let all = pushed(&values);
+            prop_assert_eq!(merged.count(), all.count());
+            prop_assert!(close(merged.mean(), all.mean()), "{} {}", merged.mean(), all.mean());
+            prop_assert!(
+                close(merged.variance(), all.variance()),
+                "{} {}",
+                merged.variance(),
+                all.variance()
+            );
+        }
+
+        #[test]
+        fn merging_is_associative(
+            values in prop::collection::vec(-1e3..1e3f64, 3..48),
+        ) {
+            let third = values.len() / 3;
+            let (a, b, c) = (
+                pushed(&values[..third]),
+                pushed(&values[third..2 * third]),
+                pushed(&values[2 * third..]),
+            );
+            let mut left = a;
+            left.merge(&b);
+            left.merge(&c);
+            let mut right = b;
+            right.merge(&c);
+            let mut outer = a;
+            outer.merge(&right);
+            prop_assert!(close(left.mean(), outer.mean()));
+            prop_assert!(close(left.variance(), outer.variance()));
+        }
+    }
+
+    #[test]
+    fn convergence_histograms_merge_like_one() {
+        let pixels: Vec<Welford> = (1..40)
+            .map(|i| pushed(&[1.0, 1.0 + i as f64 * 0.05, 1.0 - i as f64 * 0.01]))
+            .collect();
+        let mut whole = Convergence::default();
+        let (mut left, mut right) = (Convergence::default(), Convergence::default());
+        for (index, pixel) in pixels.iter().enumerate() {
+            whole.add(pixel);
+            if index % 2 == 0 {
+                left.add(pixel)
+            } else {
+                right.add(pixel)
+            }
+        }
+        left.merge(&right);
+        let (whole, merged) = (whole.summary().unwrap(), left.summary().unwrap());
+        assert_eq!(merged.pixels, whole.pixels);
+        assert_eq!(merged.p95, whole.p95);
+        assert!(close(merged.mean, whole.mean));
+    }
+}
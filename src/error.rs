@@ -0,0 +1,81 @@
+use std::{error, fmt, io, path::PathBuf};
+
+#[cfg(feature = "scene-files")]
+use crate::scene_file::SceneFileError;
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    UnsupportedFormat(PathBuf),
+    ImageEncode {
+        path: PathBuf,
+        source: io::Error,
+    },
+    ImageDecode {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidProfile {
+        path: PathBuf,
+        message: String,
+    },
+    InvalidCamera(String),
+    InvalidSettings(String),
+    // Names of objects and materials must be unique.
+    DuplicateName(String),
+    #[cfg(feature = "scene-files")]
+    InvalidScene(SceneFileError),
+    // The preview window couldn't be opened.
+    #[cfg(feature = "window")]
+    Window(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::UnsupportedFormat(path) => {
+                write!(f, "{}: unsupported output format", path.display())
+            }
+            Error::ImageEncode { path, source } => {
+                write!(f, "{}: could not encode image: {}", path.display(), source)
+            }
+            Error::ImageDecode { path, message } => {
+                write!(f, "{}: could not decode image: {}", path.display(), message)
+            }
+            Error::InvalidProfile { path, message } => {
+                write!(f, "{}: invalid IES profile: {}", path.display(), message)
+            }
+            Error::InvalidCamera(message) => write!(f, "invalid camera: {}", message),
+            Error::InvalidSettings(message) => write!(f, "invalid render settings: {}", message),
+            Error::DuplicateName(name) => write!(f, "the name \"{}\" is already taken", name),
+            #[cfg(feature = "scene-files")]
+            Error::InvalidScene(error) => write!(f, "invalid scene: {}", error),
+            #[cfg(feature = "window")]
+            Error::Window(message) => write!(f, "could not open the preview window: {}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::ImageEncode { source, .. } => Some(source),
+            #[cfg(feature = "scene-files")]
+            Error::InvalidScene(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "scene-files")]
+impl From<SceneFileError> for Error {
+    fn from(error: SceneFileError) -> Self {
+        Error::InvalidScene(error)
+    }
+}
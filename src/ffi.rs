@@ -0,0 +1,256 @@
+// A C interface for embedding the renderer. Build the library as a cdylib or
+// staticlib with the ffi feature, e.g.
+// `cargo rustc --release --lib --features ffi --crate-type cdylib`; cbindgen
+// can generate the header from this file.
+//
+// Every function returns one of the RAYTACER_* codes and catches panics, so
+// none unwind into C.
+
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+use crate::background::Sky;
+use crate::material::MaterialDescription;
+use crate::progress::{Progress, ProgressControl};
+use crate::ray_tracing::{Camera, Scene, Sphere};
+use crate::renderer::{self, RenderSettings};
+use crate::vec_math::{Color, Point3, Vec3};
+
+pub const RAYTACER_OK: c_int = 0;
+pub const RAYTACER_NULL_POINTER: c_int = 1;
+pub const RAYTACER_INVALID_ARGUMENT: c_int = 2;
+pub const RAYTACER_RENDER_FAILED: c_int = 3;
+pub const RAYTACER_CANCELLED: c_int = 4;
+pub const RAYTACER_PANIC: c_int = 5;
+
+pub const RAYTACER_DIFFUSE: u32 = 0;
+pub const RAYTACER_METAL: u32 = 1;
+pub const RAYTACER_GLASS: u32 = 2;
+pub const RAYTACER_LIGHT: u32 = 3;
+
+// `kind` is one of RAYTACER_DIFFUSE, _METAL, _GLASS or _LIGHT. `fuzz` applies
+// to metal and glass, `refraction_index` only to glass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RaytacerMaterial {
+    pub kind: u32,
+    pub color: [f64; 3],
+    pub fuzz: f64,
+    pub refraction_index: f64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RaytacerCamera {
+    pub look_from: [f64; 3],
+    pub look_at: [f64; 3],
+    pub vector_up: [f64; 3],
+    // Vertical, in degrees.
+    pub fov: f64,
+    pub aperture: f64,
+    pub focus_distance: f64,
+}
+
+// Called after every finished tile, possibly from a worker thread, but never
+// from two threads at once. Returning nonzero cancels the render.
+pub type RaytacerProgress =
+    Option<extern "C" fn(completed: usize, total: usize, user_data: *mut c_void) -> c_int>;
+
+// Opaque to C.
+pub struct RaytacerScene {
+    scene: Scene,
+    camera: Option<RaytacerCamera>,
+}
+
+fn guard(body: impl FnOnce() -> c_int) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or(RAYTACER_PANIC)
+}
+
+impl RaytacerMaterial {
+    fn describe(&self) -> Option<MaterialDescription> {
+        let color = Color::from(self.color);
+        if !color.is_finite() {
+            return None;
+        }
+        Some(match self.kind {
+            RAYTACER_DIFFUSE => MaterialDescription::Diffusor { color },
+            RAYTACER_METAL => MaterialDescription::Reflector {
+                color,
+                fuzz_coeff: self.fuzz,
+            },
+            RAYTACER_GLASS if self.refraction_index > 0.0 => MaterialDescription::Refractor {
+                color,
+                fuzz_coeff: self.fuzz,
+                refr_coeff: self.refraction_index,
+            },
+            RAYTACER_LIGHT => MaterialDescription::Emitter { color },
+            _ => return None,
+        })
+    }
+}
+
+// A scene with the default sky and no objects. Free it with
+// raytacer_scene_free.
+#[no_mangle]
+pub extern "C" fn raytacer_scene_new() -> *mut RaytacerScene {
+    panic::catch_unwind(|| {
+        Box::into_raw(Box::new(RaytacerScene {
+            scene: Scene::new(Box::new(Sky::default())),
+            camera: None,
+        }))
+    })
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+///
+/// `scene` must be null or come from raytacer_scene_new, and not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn raytacer_scene_free(scene: *mut RaytacerScene) {
+    if !scene.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(scene))));
+    }
+}
+
+/// # Safety
+///
+/// `scene` must be null or a live handle, and `center` and `material` null
+/// or valid for reads.
+#[no_mangle]
+pub unsafe extern "C" fn raytacer_scene_add_sphere(
+    scene: *mut RaytacerScene,
+    center: *const [f64; 3],
+    radius: f64,
+    material: *const RaytacerMaterial,
+) -> c_int {
+    guard(|| {
+        let (scene, center, material) = match (scene.as_mut(), center.as_ref(), material.as_ref()) {
+            (Some(scene), Some(center), Some(material)) => (scene, *center, *material),
+            _ => return RAYTACER_NULL_POINTER,
+        };
+        let center = Point3::from(center);
+        let description = match material.describe() {
+            Some(description) if radius > 0.0 && radius.is_finite() && center.is_finite() => {
+                description
+            }
+            _ => return RAYTACER_INVALID_ARGUMENT,
+        };
+        scene
+            .scene
+            .add(Box::new(Sphere::new(center, radius, description.build())));
+        RAYTACER_OK
+    })
+}
+
+/// The camera is built when rendering, once the aspect ratio is known.
+///
+/// # Safety
+///
+/// `scene` must be null or a live handle, and `camera` null or valid for
+/// reads.
+#[no_mangle]
+pub unsafe extern "C" fn raytacer_scene_set_camera(
+    scene: *mut RaytacerScene,
+    camera: *const RaytacerCamera,
+) -> c_int {
+    guard(|| match (scene.as_mut(), camera.as_ref()) {
+        (Some(scene), Some(camera)) => {
+            scene.camera = Some(*camera);
+            RAYTACER_OK
+        }
+        _ => RAYTACER_NULL_POINTER,
+    })
+}
+
+struct ProgressCallback {
+    callback: extern "C" fn(usize, usize, *mut c_void) -> c_int,
+    user_data: *mut c_void,
+}
+
+// The caller vouches for `user_data` when passing it; the mutex below keeps
+// the callback from running on two threads at once.
+unsafe impl Send for ProgressCallback {}
+
+/// Renders into `pixels`, which must hold width * height * 4 bytes of RGBA,
+/// rows from top to bottom. A zero seed picks a fresh one.
+///
+/// # Safety
+///
+/// `scene` must be null or a live handle, and `pixels` null or valid for
+/// writes of `pixels_len` bytes. `user_data` is passed to `progress` as is.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn raytacer_render(
+    scene: *mut RaytacerScene,
+    width: u32,
+    height: u32,
+    samples_per_pixel: u32,
+    max_depth: u32,
+    seed: u64,
+    pixels: *mut u8,
+    pixels_len: usize,
+    progress: RaytacerProgress,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(|| {
+        let scene = match scene.as_mut() {
+            Some(scene) if !pixels.is_null() => scene,
+            _ => return RAYTACER_NULL_POINTER,
+        };
+        let expected = width as usize * height as usize * 4;
+        let config = match scene.camera {
+            Some(config) if pixels_len >= expected && height > 0 => config,
+            _ => return RAYTACER_INVALID_ARGUMENT,
+        };
+        let camera = Camera::new(
+            Point3::from(config.look_from),
+            Point3::from(config.look_at),
+            Vec3::from(config.vector_up),
+            config.fov.to_radians(),
+            width as f64 / height as f64,
+            config.aperture,
+            config.focus_distance,
+        );
+        let settings = RenderSettings {
+            width,
+            height,
+            samples_per_pixel,
+            max_depth,
+            seed: Some(seed).filter(|&seed| seed != 0),
+            ..RenderSettings::default()
+        };
+        scene.scene.build_bvh();
+
+        let callback = progress.map(|callback| {
+            Mutex::new(ProgressCallback {
+                callback,
+                user_data,
+            })
+        });
+        let report = |update: Progress| {
+            let cancel = callback.as_ref().is_some_and(|callback| {
+                let callback = callback.lock().unwrap_or_else(|error| error.into_inner());
+                (callback.callback)(update.completed, update.total, callback.user_data) != 0
+            });
+            if cancel {
+                ProgressControl::Cancel
+            } else {
+                ProgressControl::Continue
+            }
+        };
+        let framebuffer =
+            match renderer::render_with_progress(&scene.scene, &camera, &settings, &report) {
+                Ok(framebuffer) => framebuffer,
+                Err(_) => return RAYTACER_RENDER_FAILED,
+            };
+        let image = framebuffer.beauty_image();
+        std::slice::from_raw_parts_mut(pixels, expected).copy_from_slice(&image.data);
+        if framebuffer.cancelled {
+            RAYTACER_CANCELLED
+        } else {
+            RAYTACER_OK
+        }
+    })
+}
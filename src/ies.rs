@@ -0,0 +1,318 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+
+// How bright a luminaire is in each direction, read from an IES LM-63 file
+// with type C photometry: vertical angles from 0 at the nadir to 180 at the
+// zenith, horizontal angles around the vertical axis. Files that only cover
+// a quadrant or a half of the horizontal circle are mirrored to the rest, and
+// a single horizontal angle means the same in every direction. Tilt data is
+// skipped; it only matters for luminaires mounted at an angle other than the
+// one they were measured at.
+#[derive(Debug, Clone)]
+pub struct IesProfile {
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    // One row of `vertical.len()` values per horizontal angle.
+    candela: Vec<f64>,
+    max: f64,
+}
+
+// The lower of the two angles around `angle` and how far it is towards the
+// upper one; None outside the range.
+fn locate(angles: &[f64], angle: f64) -> Option<(usize, f64)> {
+    let (&first, &last) = (angles.first()?, angles.last()?);
+    if !(first..=last).contains(&angle) {
+        return None;
+    }
+    if angles.len() == 1 {
+        return Some((0, 0.0));
+    }
+    let upper = angles
+        .iter()
+        .position(|&a| a >= angle)
+        .unwrap_or(angles.len() - 1)
+        .max(1);
+    let (a, b) = (angles[upper - 1], angles[upper]);
+    Some((upper - 1, (angle - a) / (b - a)))
+}
+
+fn increasing(angles: &[f64]) -> bool {
+    angles.iter().all(|angle| angle.is_finite()) && angles.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+impl IesProfile {
+    pub fn parse(source: &str) -> std::result::Result<IesProfile, String> {
+        let mut lines = source.lines();
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find(|line| line.starts_with("TILT="))
+            .ok_or("no TILT= line")?;
+        let mut values = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|token| !token.is_empty())
+            .map(|token| {
+                token
+                    .parse::<f64>()
+                    .map_err(|_| format!("`{}` is not a number", token))
+            });
+        let mut next = |what: &str| {
+            values
+                .next()
+                .unwrap_or_else(|| Err(format!("the file ends before the {}", what)))
+        };
+        let count = |count: f64, what: &str| {
+            if count >= 1.0 && count.fract() == 0.0 {
+                Ok(count as usize)
+            } else {
+                Err(format!("{} {} is not a positive whole number", what, count))
+            }
+        };
+        let too_many = |what: &str| format!("too many {}", what);
+        match &tilt["TILT=".len()..] {
+            "NONE" => {}
+            "INCLUDE" => {
+                let _geometry = next("lamp to luminaire geometry")?;
+                let angles = count(next("number of tilt angles")?, "tilt angle count")?;
+                // Each angle with its multiplying factor.
+                let values = angles
+                    .checked_mul(2)
+                    .ok_or_else(|| too_many("tilt angles"))?;
+                for _ in 0..values {
+                    next("tilt table")?;
+                }
+            }
+            file => {
+                return Err(format!(
+                    "tilt data in another file ({}) isn't supported",
+                    file
+                ))
+            }
+        }
+        let _lamps = next("number of lamps")?;
+        let _lumens = next("lumens per lamp")?;
+        let multiplier = next("candela multiplier")?;
+        let vertical_count = next("number of vertical angles")?;
+        let horizontal_count = next("number of horizontal angles")?;
+        let photometric_type = next("photometric type")?;
+        for what in &["units type", "width", "length", "height"] {
+            next(what)?;
+        }
+        let ballast = next("ballast factor")?;
+        for what in &["ballast-lamp photometric factor", "input watts"] {
+            next(what)?;
+        }
+        if photometric_type != 1.0 {
+            return Err(format!(
+                "photometric type {} isn't supported, only type C (1)",
+                photometric_type
+            ));
+        }
+        let vertical_count = count(vertical_count, "vertical angle count")?;
+        let horizontal_count = count(horizontal_count, "horizontal angle count")?;
+        let vertical = (0..vertical_count)
+            .map(|_| next("vertical angles"))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let horizontal = (0..horizontal_count)
+            .map(|_| next("horizontal angles"))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let candela_count = vertical_count
+            .checked_mul(horizontal_count)
+            .ok_or_else(|| too_many("candela values"))?;
+        let candela = (0..candela_count)
+            .map(|_| next("candela values").map(|value| value * multiplier * ballast))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if !increasing(&vertical) || vertical[0] < 0.0 || vertical[vertical_count - 1] > 180.0 {
+            return Err("vertical angles must increase from 0 to at most 180".to_string());
+        }
+        let last = horizontal[horizontal_count - 1];
+        if !increasing(&horizontal)
+            || horizontal[0] != 0.0
+            || ![0.0, 90.0, 180.0, 360.0].contains(&last)
+        {
+            return Err("horizontal angles must increase from 0 to 0, 90, 180 or 360".to_string());
+        }
+        if candela
+            .iter()
+            .any(|value| !(*value >= 0.0 && value.is_finite()))
+        {
+            return Err("candela values must be finite and not negative".to_string());
+        }
+        let max = candela.iter().cloned().fold(0.0, f64::max);
+        Ok(IesProfile {
+            vertical,
+            horizontal,
+            candela,
+            max,
+        })
+    }
+
+    pub fn open(path: &Path) -> Result<IesProfile> {
+        let source = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        IesProfile::parse(&source).map_err(|message| Error::InvalidProfile {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    pub fn max_intensity(&self) -> f64 {
+        self.max
+    }
+
+    // In candela, at angles in degrees, interpolated bilinearly; zero where
+    // the file has no data.
+    pub fn intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        let mut horizontal = horizontal.rem_euclid(360.0);
+        let last = self.horizontal[self.horizontal.len() - 1];
+        if last <= 180.0 && horizontal > 180.0 {
+            horizontal = 360.0 - horizontal;
+        }
+        if last <= 90.0 && horizontal > 90.0 {
+            horizontal = 180.0 - horizontal;
+        }
+        // Outside the range only with a single angle, which stands for all.
+        let (h, h_blend) = locate(&self.horizontal, horizontal).unwrap_or((0, 0.0));
+        let (v, v_blend) = match locate(&self.vertical, vertical) {
+            Some(found) => found,
+            None => return 0.0,
+        };
+        let rows = self.vertical.len();
+        let at = |h: usize, v: usize| {
+            let h = h.min(self.horizontal.len() - 1);
+            let v = v.min(rows - 1);
+            self.candela[h * rows + v]
+        };
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        lerp(
+            lerp(at(h, v), at(h, v + 1), v_blend),
+            lerp(at(h + 1, v), at(h + 1, v + 1), v_blend),
+            h_blend,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A type C file with `vertical` and `horizontal` angles and one row of
+    // candela values per horizontal angle.
+    fn file(vertical: &[f64], horizontal: &[f64], rows: &[&[f64]]) -> String {
+        let list = |values: &[f64]| {
+            values
+                .iter()
+                .map(|value| value.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut source = format!(
+            "IESNA:LM-63-2002\n[TEST] profile\nTILT=NONE\n1 1000 1 {} {} 1 2 0 0 0\n1 1 100\n{}\n{}\n",
+            vertical.len(),
+            horizontal.len(),
+            list(vertical),
+            list(horizontal)
+        );
+        for row in rows {
+            source += &list(row);
+            source += "\n";
+        }
+        source
+    }
+
+    #[test]
+    fn a_truncated_file_is_an_error() {
+        let source = file(&[0.0, 90.0], &[0.0], &[&[100.0, 50.0]]);
+        let cut = &source[..source.trim_end().len() - "50".len()];
+        let error = IesProfile::parse(cut).unwrap_err();
+        assert!(
+            error.contains("ends before the candela values"),
+            "{}",
+            error
+        );
+        assert!(IesProfile::parse("IESNA:LM-63-2002\n").is_err());
+    }
+
+    #[test]
+    fn only_type_c_photometry_is_supported() {
+        let source = file(&[0.0, 90.0], &[0.0], &[&[100.0, 50.0]]);
+        assert!(IesProfile::parse(&source).is_ok());
+        // Type B instead.
+        let type_b = source.replace("1 1000 1 2 1 1 2", "1 1000 1 2 1 2 2");
+        let error = IesProfile::parse(&type_b).unwrap_err();
+        assert!(error.contains("photometric type 2"), "{}", error);
+    }
+
+    #[test]
+    fn absurd_counts_are_errors_not_overflows() {
+        for source in &[
+            "TILT=INCLUDE\n1 1e30\n",
+            "TILT=INCLUDE\n1 -3\n",
+            "TILT=INCLUDE\n1 2.5\n",
+            "TILT=NONE\n1 1000 1 1e19 1e19 1 2 0 0 0\n1 1 100\n",
+            "TILT=NONE\n1 1000 1 0 1 1 2 0 0 0\n1 1 100\n",
+        ] {
+            assert!(IesProfile::parse(source).is_err(), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn tilt_tables_are_skipped() {
+        let source = file(&[0.0, 90.0], &[0.0], &[&[100.0, 50.0]])
+            .replace("TILT=NONE\n", "TILT=INCLUDE\n1\n3\n0 45 90\n1 0.9 0.8\n");
+        let profile = IesProfile::parse(&source).unwrap();
+        assert_eq!(profile.intensity(0.0, 0.0), 100.0);
+    }
+
+    // A quadrant from 0 to 90 degrees stands for all four, mirrored across
+    // both axes.
+    #[test]
+    fn a_quadrant_is_mirrored_around_the_circle() {
+        let source = file(
+            &[0.0, 90.0],
+            &[0.0, 45.0, 90.0],
+            &[&[100.0, 10.0], &[200.0, 20.0], &[300.0, 30.0]],
+        );
+        let profile = IesProfile::parse(&source).unwrap();
+        for &(horizontal, expected) in &[
+            (0.0, 100.0),
+            (45.0, 200.0),
+            (90.0, 300.0),
+            (135.0, 200.0),
+            (180.0, 100.0),
+            (225.0, 200.0),
+            (270.0, 300.0),
+            (315.0, 200.0),
+            (-45.0, 200.0),
+            (22.5, 150.0),
+            (157.5, 150.0),
+        ] {
+            let intensity = profile.intensity(0.0, horizontal);
+            assert!(
+                (intensity - expected).abs() < 1e-9,
+                "{} at {}",
+                intensity,
+                horizontal
+            );
+        }
+        assert!((profile.intensity(45.0, 270.0) - 165.0).abs() < 1e-9);
+        assert_eq!(profile.intensity(120.0, 0.0), 0.0);
+        assert_eq!(profile.max_intensity(), 300.0);
+    }
+
+    #[test]
+    fn a_single_horizontal_angle_is_the_same_all_around() {
+        let source = file(&[0.0, 60.0, 180.0], &[0.0], &[&[90.0, 60.0, 0.0]]);
+        let profile = IesProfile::parse(&source).unwrap();
+        for &horizontal in &[0.0, 33.0, 90.0, 180.0, 271.0, 359.9] {
+            assert_eq!(profile.intensity(0.0, horizontal), 90.0);
+            assert_eq!(profile.intensity(30.0, horizontal), 75.0);
+            assert_eq!(profile.intensity(120.0, horizontal), 30.0);
+        }
+    }
+}
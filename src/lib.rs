@@ -0,0 +1,33 @@
+pub mod animation;
+pub mod aov;
+pub mod background;
+#[cfg(feature = "scene-files")]
+pub mod batch;
+pub mod convergence;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod ies;
+pub mod material;
+pub mod output;
+pub mod post_process;
+pub mod presets;
+pub mod progress;
+pub mod ray_tracing;
+pub mod renderer;
+pub mod sample_check;
+#[cfg(feature = "scene-files")]
+pub mod scene_file;
+pub mod stats;
+mod stopwatch;
+pub mod texture;
+pub mod vec_math;
+
+#[cfg(any(feature = "png-output", feature = "gif-output"))]
+pub use animation::render_animated_image;
+pub use animation::{render_animation, render_video, Frames};
+pub use error::{Error, Result};
+pub use renderer::{
+    render, render_to_rgba8, render_with_progress, trace_pixel, Framebuffer, MultiOutput,
+    PickResult, PixelPath, RenderSettings, RenderSettingsBuilder, RenderStats, Renderer, TileOrder,
+};
@@ -1,140 +1,231 @@
-mod material;
-mod ray_tracing;
-mod vec_math;
+mod cli;
+#[cfg(feature = "window")]
+mod window;
 
-use std::{fs::File, io::BufWriter, path::Path};
+#[cfg(feature = "scene-files")]
+use std::path::PathBuf;
+use std::time::{Instant, SystemTime};
 
-use material::{Diffusor, Material, Reflector, Refractor};
-use rand::prelude::*;
-use ray_tracing::{Camera, Scene, Sphere};
-use vec_math::{random_double_in_interval, Color, Point3, Vec3};
+#[cfg(feature = "scene-files")]
+use raytacer::batch::BatchManifest;
+use raytacer::output::metadata::{self, Metadata};
+use raytacer::presets::Preset;
+use raytacer::progress::ConsoleProgress;
+use raytacer::ray_tracing::{Camera, Scene, Severity};
+use raytacer::{RenderSettings, Renderer};
 
-fn clamp(val: f64, bounds: (f64, f64)) -> f64 {
-    if val < bounds.0 {
-        bounds.0
-    } else if val > bounds.1 {
-        bounds.1
-    } else {
-        val
+#[cfg(feature = "scene-files")]
+const DEFAULT_SCENE: &str = "scenes/random_spheres.ron";
+
+// Builds the requested preset, or loads the scene file given on the command
+// line or the bundled demo scene. Falls back to generating the random scene
+// when the demo file isn't around, and saves that next to the image so it can
+// be rendered again. The metadata says where the scene came from.
+#[cfg(feature = "scene-files")]
+fn load_scene(options: &cli::Options) -> (Scene, Camera, RenderSettings, Metadata) {
+    if let Some(preset) = options.preset {
+        return with_preset_origin(preset, preset.setup(options.seed));
+    }
+    let path = match &options.scene {
+        Some(path) => path.clone(),
+        None if std::path::Path::new(DEFAULT_SCENE).exists() => PathBuf::from(DEFAULT_SCENE),
+        None => {
+            let (scene, camera, settings) = Preset::Random.setup(options.seed);
+            let saved = settings.outputs.beauty.with_extension("ron");
+            if let Err(error) = scene.save(&saved, &camera, &settings) {
+                eprintln!("{}: {}", saved.display(), error);
+            }
+            return with_preset_origin(Preset::Random, (scene, camera, settings));
+        }
+    };
+    match Scene::from_file(&path) {
+        Ok(loaded) => {
+            let mut origin = Metadata::default();
+            if let Ok(contents) = std::fs::read(&path) {
+                origin.set_scene_file(&path, &contents);
+            }
+            (loaded.scene, loaded.camera, loaded.settings, origin)
+        }
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "scene-files"))]
+fn load_scene(options: &cli::Options) -> (Scene, Camera, RenderSettings, Metadata) {
+    if options.scene.is_some() {
+        eprintln!("error: this build was compiled without scene file support");
+        std::process::exit(2);
     }
+    let preset = options.preset.unwrap_or(Preset::Random);
+    with_preset_origin(preset, preset.setup(options.seed))
 }
 
-fn generate_random_scene(rng: &mut ThreadRng) -> Scene {
-    let mut scene = Scene { hittables: vec![] };
-    scene.add(Box::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        std::rc::Rc::new(Diffusor {
-            color: Color::new(0.2, 0.2, 0.2),
-        }),
-    )));
+fn with_preset_origin(
+    preset: Preset,
+    (scene, camera, settings): (Scene, Camera, RenderSettings),
+) -> (Scene, Camera, RenderSettings, Metadata) {
+    let mut origin = Metadata::default();
+    origin.set(metadata::SCENE, preset.name());
+    (scene, camera, settings, origin)
+}
+
+fn render(
+    scene: &Scene,
+    camera: &Camera,
+    renderer: &Renderer,
+    origin: &Metadata,
+    quiet: bool,
+    reproducible: bool,
+) -> raytacer::Result<()> {
+    let mut framebuffer = if quiet {
+        renderer.render(scene, camera)?
+    } else {
+        let console = ConsoleProgress::default();
+        renderer.render_with_progress(scene, camera, &|progress| console.report(progress))?
+    };
+    if !framebuffer.non_finite_report.is_empty() {
+        eprint!("{}", framebuffer.non_finite_report);
+    }
+    if !quiet {
+        eprintln!("{}", framebuffer.stats);
+    }
+    framebuffer.metadata.merge(origin);
+    if reproducible {
+        framebuffer.metadata.remove(metadata::RENDER_TIME);
+    }
+    framebuffer.save(&renderer.settings().outputs)
+}
 
-    for a in -11..12 {
-        for b in -11..12 {
-            let center = Point3::new(
-                a as f64 + 0.9 * rng.gen::<f64>(),
-                0.2,
-                b as f64 + 0.9 * rng.gen::<f64>(),
-            );
-            if (center - Point3::new(4.0, 0.2, 0.0)).len() > 0.9 {
-                let selector = rng.gen_range(0..5);
-                let material: std::rc::Rc<dyn Material> = if selector < 3 {
-                    std::rc::Rc::new(Diffusor {
-                        color: Color::random(rng),
-                    })
-                } else if selector < 4 {
-                    std::rc::Rc::new(Reflector {
-                        color: Color::random_in_interval(rng, (0.5, 1.0)),
-                        fuzz_coeff: random_double_in_interval(rng, (0.0, 0.3)),
-                    })
-                } else {
-                    std::rc::Rc::new(Refractor {
-                        color: Color::random(rng),
-                        fuzz_coeff: random_double_in_interval(rng, (0.0, 0.5)),
-                        refr_coeff: random_double_in_interval(rng, (1.1, 1.7)),
-                    })
-                };
-                scene.add(Box::new(Sphere::new(center, 0.2, material)));
+// Exits with 1 when any job failed; the others still run.
+#[cfg(feature = "scene-files")]
+fn run_batch(path: &std::path::Path) {
+    let base = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+    let report = BatchManifest::from_file(path)
+        .map_err(raytacer::Error::from)
+        .and_then(|manifest| manifest.run(base));
+    match report {
+        Ok(report) => {
+            print!("{}", report);
+            if report.failures() > 0 {
+                std::process::exit(1);
             }
         }
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            std::process::exit(1);
+        }
     }
-    scene.add(Box::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        std::rc::Rc::new(Refractor {
-            color: Color::random(rng),
-            fuzz_coeff: 0.0,
-            refr_coeff: 1.5,
-        }),
-    )));
-    scene.add(Box::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        std::rc::Rc::new(Diffusor {
-            color: Color::new(0.4, 0.2, 0.1),
-        }),
-    )));
-    scene.add(Box::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        std::rc::Rc::new(Reflector {
-            color: Color::new(0.7, 0.6, 0.5),
-            fuzz_coeff: 0.0,
-        }),
-    )));
-    scene
 }
 
-fn main() {
-    let aspect_ratio = 3.0 / 2.0;
-    let width = 1200;
-    let height = (width as f64 / aspect_ratio).floor() as u32;
+// The command line rejects --batch in builds without scene files.
+#[cfg(not(feature = "scene-files"))]
+fn run_batch(_: &std::path::Path) {
+    unreachable!()
+}
 
-    let look_from = Point3::new(13.0, 2.0, 3.0);
-    let look_at = Point3::new(0.0, 0.0, 0.0);
-    let vector_up = Vec3::new(0.0, 1.0, 0.0);
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        vector_up,
-        20.0f64.to_radians(),
-        aspect_ratio,
-        0.1,
-        10.0,
+fn main() {
+    #[cfg(feature = "env_logger")]
+    env_logger::init();
+    let options = match cli::parse(std::env::args_os().skip(1)) {
+        Ok(cli::Command::Render(options)) => *options,
+        Ok(cli::Command::Batch(manifest)) => {
+            run_batch(&manifest);
+            return;
+        }
+        Ok(cli::Command::Inspect(image)) => {
+            match metadata::inspect(&image) {
+                Ok(found) if found.is_empty() => {
+                    println!("{}: no render settings stored", image.display())
+                }
+                Ok(found) => print!("{}", found),
+                Err(error) => {
+                    eprintln!("error: {}", error);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("error: {}\n\nRun with --help for usage.", error);
+            std::process::exit(2);
+        }
+    };
+    let start = Instant::now();
+    let (scene, camera, settings, origin) = load_scene(&options);
+    log::info!("set up the scene in {:.2?}", start.elapsed());
+    log::debug!(
+        "{} objects, {}",
+        scene.hittables().len(),
+        scene.background.name()
     );
-    let depth = 50u32;
-    let samples_per_pixel = 500;
-    let scale = 1.0 / samples_per_pixel as f64;
+    let (camera, settings) = match options.apply(camera, settings) {
+        Ok(applied) => applied,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(2);
+        }
+    };
 
-    let mut rng = rand::thread_rng();
-    let scene = generate_random_scene(&mut rng);
+    let issues = scene.validate(&camera);
+    for issue in &issues {
+        eprintln!("{}", issue);
+    }
+    if issues.iter().any(|issue| issue.severity == Severity::Error) && !options.force {
+        eprintln!("error: the scene has errors; fix them, or render anyway with --force");
+        std::process::exit(1);
+    }
 
-    let mut result_vec: Vec<u8> = vec![];
-    result_vec.reserve(width as usize * height as usize * 4);
+    let mut settings = settings;
+    // Pick the seed now so that `{seed}` names the one the image was rendered with.
+    let names_seed = settings
+        .outputs
+        .paths()
+        .any(|path| path.to_string_lossy().contains("{seed}"));
+    if names_seed && settings.seed.is_none() {
+        settings.seed = Some(raytacer::renderer::fresh_seed());
+    }
+    settings.outputs = settings
+        .outputs
+        .expand_templates(&settings, SystemTime::now());
 
-    for j in (0..height).rev() {
-        for i in 0..width {
-            let mut color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..samples_per_pixel {
-                let u = (i as f64 + rng.gen::<f64>()) / (width - 1) as f64;
-                let v = (j as f64 + rng.gen::<f64>()) / (height - 1) as f64;
-                color += camera
-                    .create_ray(&mut rng, u, v)
-                    .color(&mut rng, &scene, depth)
-                    * scale;
+    if let Some((x, y)) = options.debug_pixel {
+        let sample = options.debug_sample;
+        match raytacer::trace_pixel(&scene, &camera, &settings, x, y, sample) {
+            Ok(path) => print!("{}", path),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                std::process::exit(1);
             }
-            result_vec.push((clamp(color.data[0].sqrt(), (0.0, 0.999)) * 256.0) as u8);
-            result_vec.push((clamp(color.data[1].sqrt(), (0.0, 0.999)) * 256.0) as u8);
-            result_vec.push((clamp(color.data[2].sqrt(), (0.0, 0.999)) * 256.0) as u8);
-            result_vec.push(255);
         }
+        return;
+    }
+
+    #[cfg(feature = "window")]
+    if options.window {
+        if let Err(error) = window::run(&scene, &camera, &settings) {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let renderer = Renderer::new(settings);
+    if let Err(error) = render(
+        &scene,
+        &camera,
+        &renderer,
+        &origin,
+        options.quiet,
+        options.reproducible,
+    ) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
     }
-    let path = Path::new(r"image1.png");
-    let file = File::create(path).unwrap();
-    let ref mut writer = BufWriter::new(file);
-    let mut encoder = png::Encoder::new(writer, width, height);
-    encoder.set_color(png::ColorType::RGBA);
-    encoder.set_depth(png::BitDepth::Eight);
-    let mut writer = encoder.write_header().unwrap();
-    writer.write_image_data(&result_vec).unwrap();
 }
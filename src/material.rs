@@ -1,9 +1,115 @@
-use crate::ray_tracing::{HitRecord, Ray};
-use crate::vec_math::{Color, Vec3};
-use rand::{prelude::ThreadRng, Rng};
+use crate::error::{Error, Result};
+use crate::ies::IesProfile;
+use crate::ray_tracing::{HitRecord, Issues, Ray};
+use crate::texture::{Texture, TextureLookup};
+use crate::vec_math::{Color, UnitVec3, Vec3};
+use rand::{Rng, RngCore};
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
 
-pub trait Material {
-    fn scatter(&self, record: &HitRecord, ray: &Ray, rng: &mut ThreadRng) -> Option<(Color, Ray)>;
+pub trait Material: Any + Send + Sync {
+    fn scatter(&self, record: &HitRecord, ray: &Ray, rng: &mut dyn RngCore)
+        -> Option<(Color, Ray)>;
+
+    fn emitted(&self, _record: &HitRecord, _ray: &Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Whether `scatter` or `emitted` reads `HitRecord::footprint`, which costs
+    // two extra rays at the first hit of every camera ray.
+    fn uses_footprint(&self) -> bool {
+        false
+    }
+
+    // Whether `scatter` sends light on in one exact direction, like a mirror
+    // or clear glass, which `PathRegularization` roughens.
+    fn is_perfectly_specular(&self) -> bool {
+        false
+    }
+
+    // Whether light goes through the surface, which then needs its back
+    // faces; see `Sphere::with_backface_culling`.
+    fn transmits(&self) -> bool {
+        false
+    }
+
+    // Whether the surface is a light, which `MaterialOverride` can leave be.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Whether it only emits from the front, the side the outward normal
+    // points to, so sampling it as a light can skip the back.
+    fn emits_one_sided(&self) -> bool {
+        false
+    }
+
+    // Reports parameters that won't render right.
+    fn validate(&self, _issues: &mut Issues) {}
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// Materials by name. Objects built with a handle from here share the
+// material, and scene files keep the names.
+#[derive(Default)]
+pub struct MaterialLibrary {
+    materials: BTreeMap<String, Arc<dyn Material>>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        MaterialLibrary::default()
+    }
+
+    // Returns a handle to pass to objects.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        material: Arc<dyn Material>,
+    ) -> Result<Arc<dyn Material>> {
+        let name = name.into();
+        if self.materials.contains_key(&name) {
+            return Err(Error::DuplicateName(name));
+        }
+        self.materials.insert(name, Arc::clone(&material));
+        Ok(material)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Material>> {
+        self.materials.get(name).cloned()
+    }
+
+    // The named material as its concrete type, None if it has another.
+    pub fn get_as<T: Material>(&self, name: &str) -> Option<&T> {
+        (self.materials.get(name)?.as_ref() as &dyn Any).downcast_ref::<T>()
+    }
+
+    // The name `material` was registered under, comparing handles rather than
+    // contents.
+    pub fn name_of(&self, material: &Arc<dyn Material>) -> Option<&str> {
+        self.materials
+            .iter()
+            .find(|(_, registered)| Arc::ptr_eq(registered, material))
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.materials.keys().map(String::as_str)
+    }
+
+    // Names this library already has are left as they are.
+    pub fn merge(&mut self, other: MaterialLibrary) {
+        for (name, material) in other.materials {
+            if let Err(error) = self.register(name, material) {
+                log::warn!("dropped a merged material: {}", error);
+            }
+        }
+    }
 }
 
 pub struct Diffusor {
@@ -11,14 +117,268 @@ pub struct Diffusor {
 }
 
 impl Material for Diffusor {
-    fn scatter(&self, record: &HitRecord, _ray: &Ray, rng: &mut ThreadRng) -> Option<(Color, Ray)> {
-        let scatter_direction = Vec3::random_in_hemisphere(rng, record.normal);
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        _ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        let scatter_direction = Vec3::random_in_hemisphere(rng, *record.normal);
         if scatter_direction.near_zero() {
-            Some((self.color, Ray::new(record.point, record.normal)))
+            Some((self.color, Ray::new(record.point, *record.normal)))
         } else {
             Some((self.color, Ray::new(record.point, scatter_direction)))
         }
     }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_albedo("color", self.color);
+    }
+}
+
+// Stands in for the material of every surface a camera path hits, leaving
+// the scene as it is. With `keep_lights`, emissive materials stay in place so
+// the scene keeps its lighting.
+#[derive(Clone)]
+pub struct MaterialOverride {
+    pub material: Arc<dyn Material>,
+    pub keep_lights: bool,
+}
+
+impl MaterialOverride {
+    // Mid gray diffuse everywhere but on the lights, for judging lighting and
+    // shapes apart from shading.
+    pub fn clay() -> Self {
+        MaterialOverride {
+            material: Arc::new(Diffusor {
+                color: Color::new(0.5, 0.5, 0.5),
+            }),
+            keep_lights: true,
+        }
+    }
+
+    pub fn apply(&self, record: &mut HitRecord) {
+        if !(self.keep_lights && record.material.is_emissive()) {
+            record.material = Arc::clone(&self.material);
+        }
+    }
+}
+
+impl fmt::Debug for MaterialOverride {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MaterialOverride")
+            .field("material", &self.material.name())
+            .field("keep_lights", &self.keep_lights)
+            .finish()
+    }
+}
+
+// Emits light of the given color and absorbs everything that hits it.
+pub struct Emitter {
+    pub color: Color,
+}
+
+impl Material for Emitter {
+    fn scatter(
+        &self,
+        _record: &HitRecord,
+        _ray: &Ray,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _record: &HitRecord, _ray: &Ray) -> Color {
+        self.color
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_emission("color", self.color);
+    }
+}
+
+// An `Emitter` with an opaque back, like a ceiling panel: it emits only from
+// the side its outward normal points to.
+pub struct OneSidedEmitter {
+    pub color: Color,
+}
+
+impl Material for OneSidedEmitter {
+    fn scatter(
+        &self,
+        _record: &HitRecord,
+        _ray: &Ray,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, record: &HitRecord, _ray: &Ray) -> Color {
+        if record.front_face {
+            self.color
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn emits_one_sided(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_emission("color", self.color);
+    }
+}
+
+// Emits light that varies across the surface, such as a picture on a screen,
+// scaled by `intensity`, and absorbs everything that hits it. Where the
+// texture is black it emits exactly nothing.
+pub struct TexturedEmitter {
+    pub texture: Arc<dyn Texture>,
+    pub intensity: f64,
+}
+
+impl Material for TexturedEmitter {
+    fn scatter(
+        &self,
+        _record: &HitRecord,
+        _ray: &Ray,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, record: &HitRecord, ray: &Ray) -> Color {
+        self.texture.value(&TextureLookup::new(record, ray)) * self.intensity
+    }
+
+    fn uses_footprint(&self) -> bool {
+        true
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        if !(self.intensity.is_finite() && self.intensity >= 0.0) {
+            issues.error(format!(
+                "intensity {} must be finite and not negative",
+                self.intensity
+            ));
+        } else if self.intensity == 0.0 {
+            issues.warning("intensity is zero, the light emits nothing");
+        }
+    }
+}
+
+// Emits `color` in the direction the profile is brightest and less elsewhere,
+// as the profile says, like a real fixture. The profile's nadir points along
+// `down` and its 0 degree horizontal angle towards `forward`, with 90 degrees
+// towards `down` x `forward`; `forward` must not be parallel to `down`.
+pub struct ProfiledEmitter {
+    pub color: Color,
+    pub profile: Arc<IesProfile>,
+    pub down: Vec3,
+    pub forward: Vec3,
+}
+
+impl Material for ProfiledEmitter {
+    fn scatter(
+        &self,
+        _record: &HitRecord,
+        _ray: &Ray,
+        _rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _record: &HitRecord, ray: &Ray) -> Color {
+        let max = self.profile.max_intensity();
+        if max <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let out = (-ray.direction).to_unit();
+        let down = self.down.to_unit_or(Vec3::new(0.0, -1.0, 0.0));
+        let forward = (self.forward - self.forward.dot(down) * down).to_unit();
+        let side = down.cross_product(forward);
+        let vertical = out.dot(down).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = out.dot(side).atan2(out.dot(forward)).to_degrees();
+        self.color * (self.profile.intensity(vertical, horizontal) / max)
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_emission("color", self.color);
+        if self.profile.max_intensity() == 0.0 {
+            issues.warning("the profile is dark in every direction");
+        }
+        if !self.forward.cross_product(self.down).to_unit().is_finite() {
+            issues.error("forward must not be parallel to down");
+        }
+    }
+}
+
+// A diffuse material alternating between two colors in a 3D checker pattern;
+// `scale` is the number of squares per unit length, divided by pi.
+pub struct Checker {
+    pub even: Color,
+    pub odd: Color,
+    pub scale: f64,
+}
+
+impl Material for Checker {
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        let point = record.point * self.scale;
+        let sines = point.x().sin() * point.y().sin() * point.z().sin();
+        let color = if sines < 0.0 { self.odd } else { self.even };
+        Diffusor { color }.scatter(record, ray, rng)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_albedo("even", self.even);
+        issues.check_albedo("odd", self.odd);
+        if !self.scale.is_finite() {
+            issues.error(format!("scale {} must be finite", self.scale));
+        }
+    }
+}
+
+// A diffuse material colored by a texture, such as an image.
+pub struct TexturedDiffusor {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Material for TexturedDiffusor {
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        let color = self.texture.value(&TextureLookup::new(record, ray));
+        Diffusor { color }.scatter(record, ray, rng)
+    }
+
+    fn uses_footprint(&self) -> bool {
+        true
+    }
 }
 
 pub struct Reflector {
@@ -27,18 +387,31 @@ pub struct Reflector {
 }
 
 impl Material for Reflector {
-    fn scatter(&self, record: &HitRecord, ray: &Ray, rng: &mut ThreadRng) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
         let reflected = ray.direction.to_unit().reflect(&record.normal);
-        let scattered = Ray::new(
-            record.point,
-            reflected + Vec3::random_in_hemisphere(rng, record.normal) * self.fuzz_coeff,
-        );
-        if scattered.direction * record.normal > 0.0 {
-            Some((self.color, scattered))
+        let direction =
+            reflected + Vec3::random_in_hemisphere(rng, *record.normal) * self.fuzz_coeff;
+        // Also rejects the zero direction a fuzz of 1 or more can produce.
+        if direction.dot(*record.normal) > 0.0 {
+            Some((self.color, Ray::new(record.point, direction)))
         } else {
             None
         }
     }
+
+    fn is_perfectly_specular(&self) -> bool {
+        self.fuzz_coeff == 0.0
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_albedo("color", self.color);
+        issues.check_fuzz(self.fuzz_coeff);
+    }
 }
 
 pub struct Refractor {
@@ -47,42 +420,705 @@ pub struct Refractor {
     pub refr_coeff: f64,
 }
 
-fn shlick_approximation_reflectance(cosine: f64, ref_idx: f64) -> f64 {
+fn schlick_reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
     let r0_squared = r0.powi(2);
     r0_squared + (1.0 - r0_squared) * (1.0 - cosine).powi(5)
 }
 
 impl Material for Refractor {
-    fn scatter(&self, record: &HitRecord, ray: &Ray, rng: &mut ThreadRng) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        // n_incident / n_transmitted, with air taken as 1: entering the surface
+        // goes 1 -> refr_coeff, leaving it goes refr_coeff -> 1. Snell's law
+        // then gives sin(transmitted) = refraction_ratio * sin(incident).
         let refraction_ratio = if record.front_face {
             1.0 / self.refr_coeff
         } else {
             self.refr_coeff
         };
-        let unit_direction = ray.direction.to_unit();
-        let cos_theta = (-unit_direction * record.normal).min(1.0);
+        let unit_direction = ray.direction.normalized();
+        let cos_theta = (-unit_direction).dot(*record.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
-        if refraction_ratio * sin_theta > 1.0
-            || shlick_approximation_reflectance(cos_theta, refraction_ratio) > rng.gen()
-        {
-            let reflected = unit_direction.reflect(&record.normal);
-            Some((
-                self.color,
-                Ray::new(
-                    record.point,
-                    reflected + Vec3::random_in_hemisphere(rng, record.normal) * self.fuzz_coeff,
-                ),
-            ))
+        let reflects = refraction_ratio * sin_theta > 1.0
+            || schlick_reflectance(cos_theta, refraction_ratio) > rng.gen();
+        let direction = if reflects {
+            unit_direction.reflect(&record.normal)
+        } else {
+            unit_direction.refract(&record.normal, refraction_ratio)
+        };
+        let fuzzed = direction + Vec3::random_in_hemisphere(rng, *record.normal) * self.fuzz_coeff;
+        // The fuzz leans towards the normal, so it can push a refracted ray
+        // back out the side it came from, or cancel the direction out
+        // entirely. Like Reflector, absorb those samples.
+        let side = fuzzed.dot(*record.normal);
+        if (reflects && side > 0.0) || (!reflects && side < 0.0) {
+            Some((self.color, Ray::new(record.point, fuzzed)))
+        } else {
+            None
+        }
+    }
+
+    fn is_perfectly_specular(&self) -> bool {
+        self.fuzz_coeff == 0.0
+    }
+
+    fn transmits(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        issues.check_albedo("color", self.color);
+        issues.check_fuzz(self.fuzz_coeff);
+        if !(self.refr_coeff.is_finite() && self.refr_coeff > 0.0) {
+            issues.error(format!(
+                "refr_coeff {} must be finite and positive",
+                self.refr_coeff
+            ));
+        }
+    }
+}
+
+// Frosted glass: a dielectric whose surface is made of GGX microfacets with
+// alpha `roughness`. A roughness of 0 is the smooth Refractor exactly. With a
+// `roughness_texture`, its luminance scales `roughness` across the surface.
+pub struct RoughRefractor {
+    pub color: Color,
+    pub roughness: f64,
+    pub refr_coeff: f64,
+    pub roughness_texture: Option<Arc<dyn Texture>>,
+}
+
+impl RoughRefractor {
+    fn smooth(&self) -> Refractor {
+        Refractor {
+            color: self.color,
+            fuzz_coeff: 0.0,
+            refr_coeff: self.refr_coeff,
+        }
+    }
+}
+
+impl Material for RoughRefractor {
+    fn scatter(
+        &self,
+        record: &HitRecord,
+        ray: &Ray,
+        rng: &mut dyn RngCore,
+    ) -> Option<(Color, Ray)> {
+        let roughness = match &self.roughness_texture {
+            Some(texture) => {
+                self.roughness * texture.value(&TextureLookup::new(record, ray)).luminance()
+            }
+            None => self.roughness,
+        };
+        if roughness <= 0.0 {
+            return self.smooth().scatter(record, ray, rng);
+        }
+        let microsurface = Microsurface {
+            alpha: roughness,
+            refraction_ratio: if record.front_face {
+                1.0 / self.refr_coeff
+            } else {
+                self.refr_coeff
+            },
+        };
+        let normal = *record.normal;
+        let helper = if normal.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let tangent = normal.cross_product(helper).to_unit();
+        let bitangent = normal.cross_product(tangent);
+        let outgoing = -ray.direction.to_unit();
+        let local = Vec3::new(
+            outgoing.dot(tangent),
+            outgoing.dot(bitangent),
+            outgoing.dot(normal),
+        );
+        if local.z() <= 0.0 {
+            return None;
+        }
+        let scattered = microsurface.walk(local, rng)?;
+        let direction =
+            scattered.x() * tangent + scattered.y() * bitangent + scattered.z() * normal;
+        Some((self.color, Ray::new(record.point, direction)))
+    }
+
+    fn uses_footprint(&self) -> bool {
+        self.roughness_texture.is_some()
+    }
+
+    fn is_perfectly_specular(&self) -> bool {
+        self.roughness == 0.0
+    }
+
+    fn transmits(&self) -> bool {
+        true
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        self.smooth().validate(issues);
+        if !(self.roughness.is_finite() && self.roughness >= 0.0) {
+            issues.error(format!(
+                "roughness {} must be finite and not negative",
+                self.roughness
+            ));
+        } else if self.roughness > 1.0 {
+            issues.warning(format!("roughness {} is above 1", self.roughness));
+        }
+    }
+}
+
+// Bounces after which a ray still caught in a microsurface is absorbed; at
+// the roughest, fewer than one in a thousand get that far.
+const MAX_MICROFACET_BOUNCES: u32 = 64;
+
+// The rough surface between two media as a random height field of GGX
+// microfacets, in the local frame where the surface normal is +z and the ray
+// comes from above. Heights are spread evenly between -1 and 1.
+struct Microsurface {
+    alpha: f64,
+    // n_above / n_below, as in `Refractor::scatter`.
+    refraction_ratio: f64,
+}
+
+impl Microsurface {
+    // Follows a ray leaving in the direction `outgoing` points back along
+    // from one microfacet to the next, reflecting or refracting at each like
+    // smooth glass about a normal visible from the ray, until it gets away
+    // above or below (Heitz et al. 2016, "Multiple-Scattering Microfacet
+    // BSDFs with the Smith Model"). Its first bounce is the usual
+    // single-scattering model; the Smith masking and shadowing then decide
+    // whether the ray escapes or hits another facet rather than weighting
+    // it, so light isn't lost at rough surfaces.
+    fn walk(&self, outgoing: Vec3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let mut direction = -outgoing;
+        let mut height = 1.0;
+        let mut above = true;
+        for _ in 0..MAX_MICROFACET_BOUNCES {
+            // Below the surface, the same walk upside down.
+            let next = if above {
+                self.next_height(direction, height, rng)
+            } else {
+                self.next_height(-direction, -height, rng)
+                    .map(|height| -height)
+            };
+            height = match next {
+                Some(height) => height,
+                None => return Some(direction),
+            };
+
+            let view = -direction;
+            let micro_normal = if above {
+                Vec3::sample_ggx_vndf(view, self.alpha, rng)
+            } else {
+                -Vec3::sample_ggx_vndf(-view, self.alpha, rng)
+            };
+            let micro_normal = UnitVec3::new_unchecked(micro_normal);
+            let refraction_ratio = if above {
+                self.refraction_ratio
+            } else {
+                1.0 / self.refraction_ratio
+            };
+            let cos_theta = view.dot(*micro_normal).min(1.0);
+            let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+            let reflects = refraction_ratio * sin_theta > 1.0
+                || schlick_reflectance(cos_theta, refraction_ratio) > rng.gen();
+            let unit_direction = UnitVec3::new_unchecked(direction);
+            direction = if reflects {
+                unit_direction.reflect(&micro_normal)
+            } else {
+                above = !above;
+                unit_direction.refract(&micro_normal, refraction_ratio)
+            }
+            .to_unit();
+        }
+        None
+    }
+
+    // Smith's Lambda for GGX, negative for directions going down.
+    fn lambda(&self, direction: Vec3) -> f64 {
+        let z = direction.z();
+        let a = z / (self.alpha * (1.0 - z * z).max(0.0).sqrt());
+        0.5 * (-1.0 + a.signum() * (1.0 + 1.0 / (a * a)).sqrt())
+    }
+
+    // The height at which a ray going in `direction` from `height` next hits
+    // the microsurface seen from above, or None if it escapes.
+    fn next_height(&self, direction: Vec3, height: f64, rng: &mut dyn RngCore) -> Option<f64> {
+        // The fraction of the microsurface below a height, and its inverse.
+        let below = |height: f64| (0.5 * (height + 1.0)).clamp(0.0, 1.0);
+        let at = |fraction: f64| (2.0 * fraction - 1.0).clamp(-1.0, 1.0);
+        let u: f64 = rng.gen();
+        let z = direction.z();
+        if z > 0.9999 {
+            return None;
+        }
+        if z < -0.9999 {
+            return Some(at(u * below(height)));
+        }
+        if z.abs() < 1e-4 {
+            return Some(height);
+        }
+        let lambda = self.lambda(direction);
+        // The chance of going up unblocked is Smith's G1 at this height.
+        if z > 0.0 && u > 1.0 - below(height).powf(lambda) {
+            return None;
+        }
+        Some(at(below(height) / (1.0 - u).powf(1.0 / lambda)))
+    }
+}
+
+// Plain data for the built-in materials, convertible to and from the trait
+// objects. Serializable with the serde feature.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaterialDescription {
+    Diffusor {
+        color: Color,
+    },
+    Emitter {
+        color: Color,
+    },
+    Checker {
+        even: Color,
+        odd: Color,
+        scale: f64,
+    },
+    Reflector {
+        color: Color,
+        fuzz_coeff: f64,
+    },
+    Refractor {
+        color: Color,
+        fuzz_coeff: f64,
+        refr_coeff: f64,
+    },
+    RoughRefractor {
+        color: Color,
+        roughness: f64,
+        refr_coeff: f64,
+    },
+}
+
+impl MaterialDescription {
+    // None for materials that aren't built in.
+    pub fn describe(material: &dyn Material) -> Option<MaterialDescription> {
+        let material = material as &dyn Any;
+        if let Some(diffusor) = material.downcast_ref::<Diffusor>() {
+            Some(MaterialDescription::Diffusor {
+                color: diffusor.color,
+            })
+        } else if let Some(emitter) = material.downcast_ref::<Emitter>() {
+            Some(MaterialDescription::Emitter {
+                color: emitter.color,
+            })
+        } else if let Some(checker) = material.downcast_ref::<Checker>() {
+            Some(MaterialDescription::Checker {
+                even: checker.even,
+                odd: checker.odd,
+                scale: checker.scale,
+            })
+        } else if let Some(reflector) = material.downcast_ref::<Reflector>() {
+            Some(MaterialDescription::Reflector {
+                color: reflector.color,
+                fuzz_coeff: reflector.fuzz_coeff,
+            })
+        } else if let Some(refractor) = material.downcast_ref::<Refractor>() {
+            Some(MaterialDescription::Refractor {
+                color: refractor.color,
+                fuzz_coeff: refractor.fuzz_coeff,
+                refr_coeff: refractor.refr_coeff,
+            })
         } else {
-            let refracted = unit_direction.refract(&record.normal, refraction_ratio);
-            Some((
-                self.color,
-                Ray::new(
-                    record.point,
-                    refracted + Vec3::random_in_hemisphere(rng, record.normal) * self.fuzz_coeff,
+            // A roughness texture has no description.
+            material
+                .downcast_ref::<RoughRefractor>()
+                .filter(|refractor| refractor.roughness_texture.is_none())
+                .map(|refractor| MaterialDescription::RoughRefractor {
+                    color: refractor.color,
+                    roughness: refractor.roughness,
+                    refr_coeff: refractor.refr_coeff,
+                })
+        }
+    }
+
+    pub fn build(&self) -> Arc<dyn Material> {
+        match *self {
+            MaterialDescription::Diffusor { color } => Arc::new(Diffusor { color }),
+            MaterialDescription::Emitter { color } => Arc::new(Emitter { color }),
+            MaterialDescription::Checker { even, odd, scale } => {
+                Arc::new(Checker { even, odd, scale })
+            }
+            MaterialDescription::Reflector { color, fuzz_coeff } => {
+                Arc::new(Reflector { color, fuzz_coeff })
+            }
+            MaterialDescription::Refractor {
+                color,
+                fuzz_coeff,
+                refr_coeff,
+            } => Arc::new(Refractor {
+                color,
+                fuzz_coeff,
+                refr_coeff,
+            }),
+            MaterialDescription::RoughRefractor {
+                color,
+                roughness,
+                refr_coeff,
+            } => Arc::new(RoughRefractor {
+                color,
+                roughness,
+                refr_coeff,
+                roughness_texture: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::background::ConstantBackground;
+    use crate::ray_tracing::{Camera, Scene, Sphere};
+    use crate::renderer::{render, RenderSettings};
+    use crate::vec_math::Point3;
+
+    #[test]
+    fn schlick_is_the_fresnel_reflectance_at_normal_incidence() {
+        for &ior in &[1.0f64, 1.33, 1.5, 2.4] {
+            let r0 = ((1.0 - ior) / (1.0 + ior)).powi(2);
+            assert_eq!(schlick_reflectance(1.0, ior), r0);
+        }
+        assert!((schlick_reflectance(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert!((schlick_reflectance(1.0, 1.0 / 1.5) - 0.04).abs() < 1e-12);
+    }
+
+    #[test]
+    fn schlick_reflects_everything_at_grazing_incidence() {
+        assert_eq!(schlick_reflectance(0.0, 1.5), 1.0);
+        let rising = (0..=100).map(|i| schlick_reflectance(1.0 - i as f64 / 100.0, 1.5));
+        let rising: Vec<f64> = rising.collect();
+        assert!(rising.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    fn glass(refr_coeff: f64, fuzz_coeff: f64) -> Refractor {
+        Refractor {
+            color: Color::WHITE,
+            fuzz_coeff,
+            refr_coeff,
+        }
+    }
+
+    fn frosted(roughness: f64) -> RoughRefractor {
+        RoughRefractor {
+            color: Color::WHITE,
+            roughness,
+            refr_coeff: 1.5,
+            roughness_texture: None,
+        }
+    }
+
+    // A ray hitting the z = 0 plane at the origin, whose outward normal is +z,
+    // at `theta` from the normal: entering the glass from above, or leaving
+    // it from below.
+    fn hit(theta: f64, phi: f64, entering: bool) -> (HitRecord, Ray) {
+        let z = if entering { -theta.cos() } else { theta.cos() };
+        let direction = Vec3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), z);
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0) - direction, direction);
+        let outward = UnitVec3::new_unchecked(Vec3::new(0.0, 0.0, 1.0));
+        let record = HitRecord::new(ray.at(1.0), outward, Arc::new(glass(1.5, 0.0)), &ray, 1.0);
+        (record, ray)
+    }
+
+    // The left half of a 4x4 picture is black. Clamped, the texels past its
+    // left edge are black too, so that whole edge gives out no light at all,
+    // not merely very little; repeating wraps in the white right edge.
+    #[test]
+    fn black_texels_emit_exactly_nothing_at_a_clamped_edge() {
+        use crate::texture::{ImageTexture, Wrap};
+
+        let emitter = |wrap| {
+            let texels = (0..16)
+                .map(|i| {
+                    if i % 4 < 2 {
+                        Color::BLACK
+                    } else {
+                        Color::WHITE
+                    }
+                })
+                .collect();
+            TexturedEmitter {
+                texture: Arc::new(
+                    ImageTexture::from_colors(4, 4, texels)
+                        .unwrap()
+                        .with_wrap(wrap),
                 ),
-            ))
+                intensity: 5.0,
+            }
+        };
+        let at = |u: f64, v: f64| {
+            let (record, ray) = hit(0.0, 0.0, true);
+            (record.with_uv(u, v), ray)
+        };
+        let clamped = emitter(Wrap::Clamp);
+        for &u in &[0.0, 0.1, 0.2] {
+            for &v in &[0.0, 0.3, 1.0] {
+                let (record, ray) = at(u, v);
+                let emitted = clamped.emitted(&record, &ray);
+                assert_eq!(
+                    emitted.to_array(),
+                    Color::BLACK.to_array(),
+                    "at {}, {}",
+                    u,
+                    v
+                );
+            }
         }
+        let (record, ray) = at(0.0, 0.5);
+        assert!(emitter(Wrap::Repeat).emitted(&record, &ray).to_array()[0] > 0.0);
+    }
+
+    fn refraction_ratio(record: &HitRecord, refr_coeff: f64) -> f64 {
+        if record.front_face {
+            1.0 / refr_coeff
+        } else {
+            refr_coeff
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn smooth_glass_follows_snell_and_reflects_totally_past_the_critical_angle(
+            theta in 0.0..1.57f64,
+            phi in 0.0..std::f64::consts::TAU,
+            entering in any::<bool>(),
+            refr_coeff in 1.0..2.5f64,
+            seed in any::<u64>(),
+        ) {
+            let (record, ray) = hit(theta, phi, entering);
+            let normal = *record.normal;
+            let ratio = refraction_ratio(&record, refr_coeff);
+            let sin_incident = theta.sin();
+            let total = ratio * sin_incident > 1.0;
+            // Right at the critical angle the refracted ray runs along the
+            // surface and is absorbed.
+            prop_assume!((ratio * sin_incident - 1.0).abs() > 1e-9);
+            let rng = &mut StdRng::seed_from_u64(seed);
+            let (_, scattered) = glass(refr_coeff, 0.0).scatter(&record, &ray, rng).unwrap();
+            let out = scattered.direction;
+            prop_assert!(out.is_finite() && (out.len() - 1.0).abs() < 1e-9, "{}", out);
+            prop_assert!(out.dot(ray.direction.cross_product(normal)).abs() < 1e-9);
+            let mirror = ray.direction.reflect(&record.normal);
+            if total {
+                prop_assert!(out.approx_eq(&mirror, 1e-9), "{} is not {}", out, mirror);
+            } else if out.dot(normal) > 0.0 {
+                prop_assert!(out.approx_eq(&mirror, 1e-9), "{} is not {}", out, mirror);
+            } else {
+                let sin_transmitted = out.cross_product(normal).len();
+                prop_assert!((sin_transmitted - ratio * sin_incident).abs() < 1e-9);
+            }
+        }
+
+        // The fuzz mustn't flip a ray to the other side of the surface: a
+        // sample ends up on the side the smooth glass would have sent it to,
+        // or is absorbed.
+        #[test]
+        fn fuzz_never_moves_a_ray_across_the_surface(
+            theta in 0.0..1.57f64,
+            phi in 0.0..std::f64::consts::TAU,
+            entering in any::<bool>(),
+            refr_coeff in 1.0..2.5f64,
+            fuzz in 0.5..1.0f64,
+            seed in any::<u64>(),
+        ) {
+            let (record, ray) = hit(theta, phi, entering);
+            let normal = *record.normal;
+            let rng = StdRng::seed_from_u64(seed);
+            // Both draw the same numbers, so they take the same branch.
+            let smooth = glass(refr_coeff, 0.0).scatter(&record, &ray, &mut rng.clone());
+            let fuzzed = glass(refr_coeff, fuzz).scatter(&record, &ray, &mut rng.clone());
+            if let (Some((_, smooth)), Some((_, fuzzed))) = (smooth, fuzzed) {
+                let side = smooth.direction.dot(normal).signum();
+                prop_assert!(fuzzed.direction.is_finite());
+                prop_assert!(fuzzed.direction.dot(normal) * side > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn total_internal_reflection_starts_at_the_critical_angle() {
+        let critical = (1.0 / 1.5f64).asin();
+        let rng = &mut StdRng::seed_from_u64(9);
+        let mut transmitted = 0;
+        for _ in 0..1000 {
+            let (record, ray) = hit(critical - 1e-6, 0.3, false);
+            let (_, below) = glass(1.5, 0.0).scatter(&record, &ray, rng).unwrap();
+            if below.direction.dot(*record.normal) < 0.0 {
+                transmitted += 1;
+            }
+            let (record, ray) = hit(critical + 1e-6, 0.3, false);
+            let (_, above) = glass(1.5, 0.0).scatter(&record, &ray, rng).unwrap();
+            assert!(above.direction.dot(*record.normal) > 0.0);
+        }
+        // Schlick reflects about 4% from inside at this angle.
+        assert!(transmitted > 900, "{} of 1000 refracted", transmitted);
+    }
+
+    #[test]
+    fn fuzz_absorbs_grazing_refractions_it_would_flip() {
+        let rng = &mut StdRng::seed_from_u64(2);
+        let (record, ray) = hit(1.5, 0.0, true);
+        let absorbed = (0..1000)
+            .filter(|_| glass(1.5, 1.0).scatter(&record, &ray, rng).is_none())
+            .count();
+        assert!(absorbed > 0);
+    }
+
+    #[test]
+    fn smooth_rough_glass_scatters_like_smooth_glass() {
+        // Entering, leaving, and reflected totally inside.
+        for &(theta, entering) in &[
+            (0.0, true),
+            (0.7, true),
+            (1.4, true),
+            (0.3, false),
+            (0.8, false),
+        ] {
+            let (record, ray) = hit(theta, 0.4, entering);
+            let rough_rng = &mut StdRng::seed_from_u64(8);
+            let smooth_rng = &mut StdRng::seed_from_u64(8);
+            for _ in 0..100 {
+                let (rough_color, rough) = frosted(0.0).scatter(&record, &ray, rough_rng).unwrap();
+                let (color, smooth) = glass(1.5, 0.0).scatter(&record, &ray, smooth_rng).unwrap();
+                assert_eq!(rough_color.to_array(), color.to_array());
+                assert_eq!(rough.origin.to_array(), smooth.origin.to_array());
+                assert_eq!(rough.direction.to_array(), smooth.direction.to_array());
+            }
+        }
+    }
+
+    // `MAX_MICROFACET_BOUNCES` absorbs fewer than one ray in a thousand, from
+    // either side, up to grazing angles and beyond the usual roughness.
+    #[test]
+    fn few_rays_stay_caught_in_a_rough_microsurface() {
+        const RAYS: usize = 10_000;
+        let rng = &mut StdRng::seed_from_u64(4);
+        for &alpha in &[0.3, 1.0, 2.0] {
+            for &refraction_ratio in &[1.0 / 1.5, 1.5] {
+                for &theta in &[0.0f64, 0.7, 1.2, 1.55] {
+                    let surface = Microsurface {
+                        alpha,
+                        refraction_ratio,
+                    };
+                    let outgoing = Vec3::new(theta.sin(), 0.0, theta.cos());
+                    let lost = (0..RAYS)
+                        .filter(|_| surface.walk(outgoing, rng).is_none())
+                        .count();
+                    assert!(
+                        lost * 1000 < RAYS,
+                        "alpha {}, ratio {}, theta {}: {} lost",
+                        alpha,
+                        refraction_ratio,
+                        theta,
+                        lost
+                    );
+                }
+            }
+        }
+    }
+
+    // A white sphere of `material` in a uniformly white world, as the
+    // per-channel minimum, mean and maximum of the pixels.
+    fn furnace(material: Arc<dyn Material>) -> (f64, f64, f64) {
+        let mut scene = Scene::new(Box::new(ConstantBackground(Color::WHITE)));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -3.0),
+            1.0,
+            material,
+        )));
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            40f64.to_radians(),
+            1.0,
+            0.0,
+            3.0,
+        );
+        let settings = RenderSettings {
+            width: 16,
+            height: 16,
+            samples_per_pixel: 16,
+            // Nearly smooth glass can trap a ray by total internal reflection
+            // for well over 64 bounces, which a lower depth would count as
+            // absorbed.
+            max_depth: 256,
+            seed: Some(1),
+            ..RenderSettings::default()
+        };
+        let framebuffer = render(&scene, &camera, &settings).unwrap();
+        let channels: Vec<f64> = framebuffer
+            .beauty
+            .iter()
+            .flat_map(|color| color.to_array().to_vec())
+            .collect();
+        let min = channels.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = channels.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        (
+            min,
+            channels.iter().sum::<f64>() / channels.len() as f64,
+            max,
+        )
+    }
+
+    // No material may send out more light than reaches it. Lossless ones send
+    // out all of it, so the sphere disappears.
+    #[test]
+    fn white_furnace() {
+        const TOLERANCE: f64 = 1e-9;
+        let reflector = |fuzz_coeff| Reflector {
+            color: Color::WHITE,
+            fuzz_coeff,
+        };
+        let lossless: Vec<(&str, Arc<dyn Material>)> = vec![
+            (
+                "diffuse",
+                Arc::new(Diffusor {
+                    color: Color::WHITE,
+                }),
+            ),
+            ("mirror", Arc::new(reflector(0.0))),
+            ("fuzzy mirror", Arc::new(reflector(0.5))),
+            ("glass", Arc::new(glass(1.5, 0.0))),
+            ("slightly rough glass", Arc::new(frosted(0.05))),
+            ("rough glass", Arc::new(frosted(0.3))),
+            ("roughest glass", Arc::new(frosted(1.0))),
+        ];
+        for (name, material) in lossless {
+            let (min, _, max) = furnace(material);
+            assert!((min - 1.0).abs() < TOLERANCE, "{}: min {}", name, min);
+            assert!((max - 1.0).abs() < TOLERANCE, "{}: max {}", name, max);
+        }
+
+        // Fuzzy glass absorbs the samples its fuzz pushes across the surface.
+        let (min, mean, max) = furnace(Arc::new(glass(1.5, 0.3)));
+        assert!(max <= 1.0 + TOLERANCE, "fuzzy glass: max {}", max);
+        assert!(
+            min > 0.5 && mean > 0.95,
+            "fuzzy glass: min {}, mean {}",
+            min,
+            mean
+        );
     }
 }
@@ -0,0 +1,589 @@
+#[cfg(any(feature = "png-output", feature = "gif-output"))]
+mod animated;
+mod bmp;
+#[cfg(feature = "exr")]
+mod exr;
+mod false_color;
+#[cfg(feature = "jpeg-output")]
+mod jpeg;
+#[cfg(feature = "gif-output")]
+mod median_cut;
+pub mod metadata;
+#[cfg(feature = "png-output")]
+mod png;
+mod tga;
+mod video;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::{Error, Result};
+use crate::renderer::RenderSettings;
+use crate::vec_math::{Color, Interval};
+
+#[cfg(any(feature = "png-output", feature = "gif-output"))]
+pub use self::animated::{AnimationFormat, AnimationOptions, AnimationWriter};
+#[cfg(feature = "exr")]
+pub use self::exr::{write_exr, write_exr_with_metadata};
+pub use self::metadata::{inspect, Metadata};
+#[cfg(feature = "png-output")]
+pub use self::png::{write_png, write_png_with_metadata};
+pub use bmp::write_bmp;
+pub use false_color::{
+    false_color_image, write_false_color, Colormap, FalseColorRange, NO_DATA_COLOR,
+};
+#[cfg(feature = "jpeg-output")]
+pub use jpeg::{write_jpeg, ChromaSubsampling, JpegOptions};
+pub use tga::{write_tga, TgaDepth};
+pub use video::{VideoOutput, VideoWriter, YuvRange};
+
+#[cfg(feature = "png-output")]
+pub const DEFAULT_OUTPUT: &str = "image1.png";
+#[cfg(not(feature = "png-output"))]
+pub const DEFAULT_OUTPUT: &str = "image1.tga";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferFunction {
+    Linear,
+    // A plain square root; what the renderer used before sRGB.
+    Gamma2,
+    // The piecewise sRGB curve, linear near black.
+    Srgb,
+}
+
+impl TransferFunction {
+    pub fn encode(self, linear: f64) -> f64 {
+        match self {
+            TransferFunction::Linear => linear,
+            TransferFunction::Gamma2 => linear.sqrt(),
+            TransferFunction::Srgb => {
+                if linear <= 0.0031308 {
+                    12.92 * linear
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                }
+            }
+        }
+    }
+
+    pub fn decode(self, encoded: f64) -> f64 {
+        match self {
+            TransferFunction::Linear => encoded,
+            TransferFunction::Gamma2 => encoded * encoded,
+            TransferFunction::Srgb => {
+                if encoded <= 0.04045 {
+                    encoded / 12.92
+                } else {
+                    ((encoded + 0.055) / 1.055).powf(2.4)
+                }
+            }
+        }
+    }
+
+    pub fn encode_color(self, linear: Color) -> Color {
+        Color::new(
+            self.encode(linear.r()),
+            self.encode(linear.g()),
+            self.encode(linear.b()),
+        )
+    }
+
+    pub fn decode_color(self, encoded: Color) -> Color {
+        Color::new(
+            self.decode(encoded.r()),
+            self.decode(encoded.g()),
+            self.decode(encoded.b()),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantization {
+    // Rounds half up, so 0 and 1 map exactly to 0 and 255 and every byte
+    // covers an equal share of [0, 1].
+    Round,
+    // Truncates after scaling by 256 with a clamp at 0.999, as the renderer
+    // used to. 1.0 comes out as 255 but the top byte covers a thinner slice.
+    Truncate,
+}
+
+const UNIT: Interval = Interval::new(0.0, 1.0);
+const LEGACY_INTENSITY: Interval = Interval::new(0.0, 0.999);
+
+impl Quantization {
+    // One encoded color component in [0, 1] to a byte. Out of range values
+    // are clamped and NaN becomes 0, so a bad sample shows up as black rather
+    // than as an arbitrary byte.
+    pub fn quantize(self, component: f64) -> u8 {
+        let value = match self {
+            Quantization::Round => UNIT.saturate(component) * 255.0 + 0.5,
+            Quantization::Truncate => LEGACY_INTENSITY.saturate(component) * 256.0,
+        };
+        debug_assert!(value.is_finite(), "quantizing {}", component);
+        value as u8
+    }
+}
+
+// How linear radiance becomes 8-bit pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputTransform {
+    pub transfer: TransferFunction,
+    pub quantization: Quantization,
+}
+
+impl OutputTransform {
+    pub const SRGB: OutputTransform = OutputTransform {
+        transfer: TransferFunction::Srgb,
+        quantization: Quantization::Round,
+    };
+    // Byte for byte what the renderer wrote before sRGB, for comparing against
+    // old renders.
+    pub const LEGACY: OutputTransform = OutputTransform {
+        transfer: TransferFunction::Gamma2,
+        quantization: Quantization::Truncate,
+    };
+
+    // `srgb`, `gamma2`, `linear` or `legacy`.
+    pub fn from_name(name: &str) -> Option<OutputTransform> {
+        let transfer = match name {
+            "srgb" => TransferFunction::Srgb,
+            "gamma2" => TransferFunction::Gamma2,
+            "linear" => TransferFunction::Linear,
+            "legacy" => return Some(OutputTransform::LEGACY),
+            _ => return None,
+        };
+        Some(OutputTransform {
+            transfer,
+            quantization: Quantization::Round,
+        })
+    }
+
+    pub fn encode(self, linear: Color) -> [u8; 3] {
+        let encoded = self.transfer.encode_color(linear);
+        [
+            self.quantization.quantize(encoded.r()),
+            self.quantization.quantize(encoded.g()),
+            self.quantization.quantize(encoded.b()),
+        ]
+    }
+}
+
+impl Default for OutputTransform {
+    fn default() -> Self {
+        OutputTransform::SRGB
+    }
+}
+
+// 8-bit RGBA pixels in rows from top to bottom; each writer takes care of the
+// row order its format expects. `transfer` records how the linear radiance was
+// encoded into the bytes.
+pub struct Rgba8Image {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    pub transfer: TransferFunction,
+}
+
+impl Rgba8Image {
+    fn check_len(&self) -> io::Result<()> {
+        let expected = self.width as usize * self.height as usize * 4;
+        if self.data.len() == expected {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "pixel buffer holds {} bytes, expected {} for {}x{} RGBA",
+                    self.data.len(),
+                    expected,
+                    self.width,
+                    self.height
+                ),
+            ))
+        }
+    }
+
+    fn rows(&self) -> std::slice::ChunksExact<'_, u8> {
+        self.data.chunks_exact((self.width as usize * 4).max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    #[cfg(feature = "png-output")]
+    Png,
+    #[cfg(feature = "jpeg-output")]
+    Jpeg,
+    Tga,
+    Bmp,
+    #[cfg(feature = "exr")]
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            #[cfg(feature = "png-output")]
+            "png" => Some(ImageFormat::Png),
+            #[cfg(feature = "jpeg-output")]
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "tga" => Some(ImageFormat::Tga),
+            "bmp" => Some(ImageFormat::Bmp),
+            #[cfg(feature = "exr")]
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    #[cfg(feature = "jpeg-output")]
+    pub jpeg: JpegOptions,
+    // Stored by the formats that have room for it, PNG and EXR.
+    pub metadata: Metadata,
+}
+
+impl OutputOptions {
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+pub fn write_image(path: &Path, image: &Rgba8Image) -> Result<()> {
+    write_image_with_options(path, image, &OutputOptions::default())
+}
+
+pub fn write_image_with_options(
+    path: &Path,
+    image: &Rgba8Image,
+    options: &OutputOptions,
+) -> Result<()> {
+    // Only the PNG, JPEG and EXR encoders take options.
+    #[cfg(not(any(feature = "png-output", feature = "jpeg-output", feature = "exr")))]
+    let _ = options;
+    let format =
+        ImageFormat::from_path(path).ok_or_else(|| Error::UnsupportedFormat(path.to_path_buf()))?;
+    // Encode in memory first so a rejected image doesn't leave an empty file behind.
+    let mut writer = Vec::new();
+    match format {
+        #[cfg(feature = "png-output")]
+        ImageFormat::Png => write_png_with_metadata(&mut writer, image, &options.metadata),
+        #[cfg(feature = "jpeg-output")]
+        ImageFormat::Jpeg => write_jpeg(&mut writer, image, &options.jpeg),
+        ImageFormat::Tga => {
+            // Fully opaque images don't need the alpha channel.
+            let depth = if image.data.chunks_exact(4).all(|pixel| pixel[3] == 255) {
+                TgaDepth::Bits24
+            } else {
+                TgaDepth::Bits32
+            };
+            write_tga(&mut writer, image, depth)
+        }
+        ImageFormat::Bmp => write_bmp(&mut writer, image),
+        #[cfg(feature = "exr")]
+        ImageFormat::Exr => write_exr_with_metadata(
+            io::Cursor::new(&mut writer),
+            image.width,
+            image.height,
+            &self::exr::linear_colors(image),
+            &options.metadata,
+        ),
+    }
+    .map_err(|source| Error::ImageEncode {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    write_file(path, &writer)
+}
+
+// Writes linear colors to an EXR file as they are, so nothing above 1 is lost.
+#[cfg(feature = "exr")]
+pub fn write_exr_file(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+    metadata: &Metadata,
+) -> Result<()> {
+    let mut writer = Vec::new();
+    write_exr_with_metadata(
+        io::Cursor::new(&mut writer),
+        width,
+        height,
+        pixels,
+        metadata,
+    )
+    .map_err(|source| Error::ImageEncode {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    write_file(path, &writer)
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(|source| Error::Io {
+            path: parent.to_path_buf(),
+            source,
+        })?;
+    }
+    fs::write(path, contents).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// Fills in `{date}`, `{time}`, `{samples}`, `{width}`, `{height}` and `{seed}`
+// in an output path, so repeated renders don't overwrite each other. Date and
+// time are those of `time` in UTC, with dashes so the result is a valid file
+// name everywhere; take it once per render, so all of its outputs agree.
+// Unknown names are left as they are.
+pub fn expand_template(template: &str, settings: &RenderSettings, time: SystemTime) -> PathBuf {
+    let since_epoch = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days((since_epoch / 86_400) as i64);
+    let seconds = since_epoch % 86_400;
+
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        match &rest[1..end] {
+            "date" => expanded.push_str(&format!("{:04}-{:02}-{:02}", year, month, day)),
+            "time" => expanded.push_str(&format!(
+                "{:02}-{:02}-{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            )),
+            "samples" => expanded.push_str(&settings.samples_per_pixel.to_string()),
+            "width" => expanded.push_str(&settings.width.to_string()),
+            "height" => expanded.push_str(&settings.height.to_string()),
+            "seed" => match settings.seed {
+                Some(seed) => expanded.push_str(&seed.to_string()),
+                None => expanded.push_str("random"),
+            },
+            _ => expanded.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+// Proleptic Gregorian date for a day count since 1970-01-01, after Howard
+// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::renderer::MultiOutput;
+
+    #[test]
+    fn picks_the_format_by_extension() {
+        let format = |path: &str| ImageFormat::from_path(Path::new(path));
+        assert_eq!(format("out.tga"), Some(ImageFormat::Tga));
+        assert_eq!(format("OUT.BMP"), Some(ImageFormat::Bmp));
+        #[cfg(feature = "png-output")]
+        assert_eq!(format("renders/out.png"), Some(ImageFormat::Png));
+        #[cfg(not(feature = "png-output"))]
+        assert_eq!(format("renders/out.png"), None);
+        assert_eq!(format("out.webp"), None);
+        assert_eq!(format("out"), None);
+    }
+
+    #[test]
+    fn rounding_maps_the_unit_range_onto_every_byte() {
+        let below_one = 1.0 - f64::EPSILON;
+        let quantize = |component| Quantization::Round.quantize(component);
+        assert_eq!(quantize(0.0), 0);
+        assert_eq!(quantize(below_one), 255);
+        assert_eq!(quantize(1.0), 255);
+        assert_eq!(quantize(1.0 + f64::EPSILON), 255);
+        assert_eq!(quantize(1.5), 255);
+        assert_eq!(quantize(f64::INFINITY), 255);
+        assert_eq!(quantize(-0.1), 0);
+        assert_eq!(quantize(f64::NAN), 0);
+        // Half a step rounds up, anything less rounds down.
+        assert_eq!(quantize(0.5 / 255.0), 1);
+        assert_eq!(quantize(0.499 / 255.0), 0);
+        assert_eq!(quantize(254.5 / 255.0), 255);
+        assert_eq!(quantize(254.499 / 255.0), 254);
+        for byte in 0..=255u8 {
+            assert_eq!(quantize(byte as f64 / 255.0), byte);
+        }
+    }
+
+    #[test]
+    fn truncation_keeps_the_old_byte_boundaries() {
+        let below_one = 1.0 - f64::EPSILON;
+        let quantize = |component| Quantization::Truncate.quantize(component);
+        assert_eq!(quantize(0.0), 0);
+        assert_eq!(quantize(below_one), 255);
+        assert_eq!(quantize(1.0), 255);
+        assert_eq!(quantize(1.0 + f64::EPSILON), 255);
+        assert_eq!(quantize(1.5), 255);
+        assert_eq!(quantize(f64::NAN), 0);
+        // Every byte starts at a multiple of 1/256 and the top one is cut off
+        // at 0.999.
+        for byte in 0..=255u8 {
+            let start = byte as f64 / 256.0;
+            assert_eq!(quantize(start), byte);
+            if byte > 0 {
+                assert_eq!(quantize(start - 1e-9), byte - 1);
+            }
+        }
+        assert_eq!(quantize(0.999), 255);
+    }
+
+    #[test]
+    fn transforms_encode_white_and_black_exactly() {
+        let below_one = 1.0 - f64::EPSILON;
+        for &transform in &[OutputTransform::SRGB, OutputTransform::LEGACY] {
+            assert_eq!(transform.encode(Color::new(0.0, 0.0, 0.0)), [0, 0, 0]);
+            assert_eq!(
+                transform.encode(Color::new(below_one, 1.0, 1.0 + f64::EPSILON)),
+                [255, 255, 255]
+            );
+        }
+        // The legacy transform's gamma 2 and truncation against sRGB's curve
+        // and rounding: a linear quarter is 0.5 encoded.
+        assert_eq!(
+            OutputTransform::LEGACY.encode(Color::new(0.25, 0.25, 0.25)),
+            [128; 3]
+        );
+        assert_eq!(
+            OutputTransform::SRGB.encode(Color::new(0.25, 0.25, 0.25)),
+            [137; 3]
+        );
+    }
+
+    #[test]
+    fn unwritable_output_path_is_an_io_error() {
+        let blocker = std::env::temp_dir().join(format!("raytacer-blocker-{}", std::process::id()));
+        fs::write(&blocker, b"not a directory").unwrap();
+        let image = Rgba8Image {
+            width: 1,
+            height: 1,
+            data: vec![0, 0, 0, 255],
+            transfer: TransferFunction::Srgb,
+        };
+        let result = write_image(&blocker.join("out.tga"), &image);
+        fs::remove_file(&blocker).unwrap();
+        match result {
+            Err(Error::Io { path, .. }) => assert_eq!(path, blocker),
+            other => panic!("expected an I/O error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn templates_expand_settings_date_and_time() {
+        let settings = RenderSettings {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            seed: Some(42),
+            ..RenderSettings::default()
+        };
+        let template = "renders/{date}/scene_{samples}spp_{width}x{height}_{seed}_{time}.png";
+        assert_eq!(
+            expand_template(template, &settings, at(1_705_329_022)),
+            PathBuf::from("renders/2024-01-15/scene_500spp_1200x800_42_14-30-22.png")
+        );
+        // A leap day, a second before midnight.
+        assert_eq!(
+            expand_template("{date} {time}", &settings, at(951_868_799)),
+            PathBuf::from("2000-02-29 23-59-59")
+        );
+        let unseeded = RenderSettings {
+            seed: None,
+            ..settings
+        };
+        assert_eq!(
+            expand_template("{seed}/{unknown}/{date", &unseeded, at(0)),
+            PathBuf::from("random/{unknown}/{date")
+        );
+    }
+
+    #[test]
+    fn every_output_of_a_render_gets_the_same_time() {
+        let mut outputs = MultiOutput::beauty_only(PathBuf::from("{date}_{time}_beauty.tga"));
+        outputs.albedo = Some(PathBuf::from("{date}_{time}_albedo.tga"));
+        outputs.depth = Some(PathBuf::from("{time}/depth.tga"));
+        let expanded = outputs.expand_templates(&RenderSettings::default(), at(1_705_329_022));
+        assert_eq!(
+            expanded.beauty,
+            PathBuf::from("2024-01-15_14-30-22_beauty.tga")
+        );
+        assert_eq!(
+            expanded.albedo,
+            Some(PathBuf::from("2024-01-15_14-30-22_albedo.tga"))
+        );
+        assert_eq!(expanded.depth, Some(PathBuf::from("14-30-22/depth.tga")));
+    }
+
+    #[test]
+    fn missing_output_directories_are_created() {
+        let root = std::env::temp_dir().join(format!("raytacer-template-{}", std::process::id()));
+        let settings = RenderSettings {
+            width: 2,
+            height: 1,
+            ..RenderSettings::default()
+        };
+        let template = format!("{}/{{date}}/{{width}}x{{height}}/out.tga", root.display());
+        let path = expand_template(&template, &settings, at(1_705_329_022));
+        let image = Rgba8Image {
+            width: 2,
+            height: 1,
+            data: vec![255; 8],
+            transfer: TransferFunction::Srgb,
+        };
+        let written = write_image(&path, &image);
+        let exists = root
+            .join("2024-01-15")
+            .join("2x1")
+            .join("out.tga")
+            .is_file();
+        fs::remove_dir_all(&root).unwrap();
+        written.unwrap();
+        assert!(exists);
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs::File;
+#[cfg(feature = "gif-output")]
+use std::io::Write;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "gif-output")]
+use super::median_cut;
+use super::Rgba8Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationFormat {
+    #[cfg(feature = "png-output")]
+    Apng,
+    #[cfg(feature = "gif-output")]
+    Gif,
+}
+
+impl AnimationFormat {
+    // `.png` and `.apng` are APNGs, `.gif` GIFs.
+    pub fn from_path(path: &Path) -> Option<AnimationFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            #[cfg(feature = "png-output")]
+            "png" | "apng" => Some(AnimationFormat::Apng),
+            #[cfg(feature = "gif-output")]
+            "gif" => Some(AnimationFormat::Gif),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationOptions {
+    // How long each frame shows. GIF counts in hundredths of a second and
+    // most viewers slow down anything under two of them, so GIFs round to
+    // hundredths and show frames for at least 20 ms.
+    pub frame_delay: Duration,
+    // How many times the animation plays; 0 loops forever.
+    pub plays: u32,
+}
+
+impl AnimationOptions {
+    // One frame per 1 / `frames_per_second` seconds, looping forever.
+    pub fn at_frame_rate(frames_per_second: f64) -> AnimationOptions {
+        AnimationOptions {
+            frame_delay: Duration::from_secs_f64(1.0 / frames_per_second),
+            plays: 0,
+        }
+    }
+}
+
+enum Encoder {
+    #[cfg(feature = "png-output")]
+    Apng(png::Writer<BufWriter<File>>),
+    #[cfg(feature = "gif-output")]
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        delay: u16,
+    },
+}
+
+// Writes frames to an animated image as they come, so only the frame being
+// encoded is held in memory. The number of frames is fixed up front, as an
+// APNG states it before the first one.
+pub struct AnimationWriter {
+    encoder: Encoder,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    remaining: u32,
+}
+
+impl AnimationWriter {
+    // Creates the file at `path`, in the format its extension names, for
+    // `frame_count` frames of `width` x `height` pixels.
+    pub fn create(
+        path: &Path,
+        width: u32,
+        height: u32,
+        frame_count: u32,
+        options: &AnimationOptions,
+    ) -> Result<AnimationWriter> {
+        let format = AnimationFormat::from_path(path)
+            .ok_or_else(|| Error::UnsupportedFormat(path.to_path_buf()))?;
+        if frame_count == 0 {
+            return Err(Error::InvalidSettings(
+                "an animation needs at least one frame".to_string(),
+            ));
+        }
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|source| Error::Io {
+                path: parent.to_path_buf(),
+                source,
+            })?;
+        }
+        let file = File::create(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let writer = BufWriter::new(file);
+        let encoder = match format {
+            #[cfg(feature = "png-output")]
+            AnimationFormat::Apng => apng_encoder(writer, width, height, frame_count, options),
+            #[cfg(feature = "gif-output")]
+            AnimationFormat::Gif => gif_encoder(writer, width, height, options),
+        }
+        .map_err(|source| Error::ImageEncode {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(AnimationWriter {
+            encoder,
+            path: path.to_path_buf(),
+            width,
+            height,
+            remaining: frame_count,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Frames still to come before `finish`.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    // Encodes the next frame and writes it out.
+    pub fn push_frame(&mut self, image: &Rgba8Image) -> Result<()> {
+        self.encode_frame(image)
+            .map_err(|source| Error::ImageEncode {
+                path: self.path.clone(),
+                source,
+            })
+    }
+
+    fn encode_frame(&mut self, image: &Rgba8Image) -> io::Result<()> {
+        image.check_len()?;
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "a {}x{} frame in a {}x{} animation",
+                    image.width, image.height, self.width, self.height
+                ),
+            ));
+        }
+        if self.remaining == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more frames than the animation was created for",
+            ));
+        }
+        self.remaining -= 1;
+        match &mut self.encoder {
+            #[cfg(feature = "png-output")]
+            Encoder::Apng(writer) => writer.write_image_data(&image.data)?,
+            #[cfg(feature = "gif-output")]
+            Encoder::Gif { encoder, delay } => {
+                let indexed = median_cut::quantize(&image.data, 256);
+                let frame = gif::Frame {
+                    width: image.width as u16,
+                    height: image.height as u16,
+                    delay: *delay,
+                    // Transparent pixels would show the frame before
+                    // otherwise.
+                    dispose: match indexed.transparent {
+                        Some(_) => gif::DisposalMethod::Background,
+                        None => gif::DisposalMethod::Keep,
+                    },
+                    transparent: indexed.transparent,
+                    palette: Some(indexed.palette),
+                    buffer: indexed.indices.into(),
+                    ..gif::Frame::default()
+                };
+                encoder.write_frame(&frame).map_err(gif_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Completes the file once every frame is in.
+    pub fn finish(self) -> Result<()> {
+        let path = self.path;
+        let encode_error = |source| Error::ImageEncode {
+            path: path.clone(),
+            source,
+        };
+        if self.remaining > 0 {
+            return Err(encode_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} frames short", self.remaining),
+            )));
+        }
+        let finished = match self.encoder {
+            #[cfg(feature = "png-output")]
+            Encoder::Apng(writer) => writer.finish().map_err(io::Error::from),
+            #[cfg(feature = "gif-output")]
+            Encoder::Gif { encoder, .. } => {
+                encoder.into_inner().and_then(|mut writer| writer.flush())
+            }
+        };
+        finished.map_err(encode_error)
+    }
+}
+
+#[cfg(feature = "png-output")]
+fn apng_encoder(
+    writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    frame_count: u32,
+    options: &AnimationOptions,
+) -> io::Result<Encoder> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frame_count, options.plays)?;
+    let (numerator, denominator) = delay_fraction(options.frame_delay);
+    encoder.set_frame_delay(numerator, denominator)?;
+    Ok(Encoder::Apng(encoder.write_header()?))
+}
+
+// An APNG delay is a fraction of two 16-bit numbers; the finest of these
+// denominators that can hold the numerator.
+#[cfg(feature = "png-output")]
+fn delay_fraction(delay: Duration) -> (u16, u16) {
+    let seconds = delay.as_secs_f64();
+    for &denominator in &[10_000u16, 1000, 100, 10, 1] {
+        let numerator = (seconds * denominator as f64).round();
+        if numerator <= u16::MAX as f64 {
+            return (numerator as u16, denominator);
+        }
+    }
+    (u16::MAX, 1)
+}
+
+#[cfg(feature = "gif-output")]
+fn gif_encoder(
+    writer: BufWriter<File>,
+    width: u32,
+    height: u32,
+    options: &AnimationOptions,
+) -> io::Result<Encoder> {
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "GIFs are at most 65535 pixels across, not {}x{}",
+                width, height
+            ),
+        ));
+    }
+    let mut encoder =
+        gif::Encoder::new(writer, width as u16, height as u16, &[]).map_err(gif_error)?;
+    // Without the repeat extension a GIF plays once; with it, it plays once
+    // and then repeats the given number of times.
+    match options.plays {
+        0 => encoder.set_repeat(gif::Repeat::Infinite),
+        1 => Ok(()),
+        plays => encoder.set_repeat(gif::Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16)),
+    }
+    .map_err(gif_error)?;
+    let delay = (options.frame_delay.as_secs_f64() * 100.0).round();
+    Ok(Encoder::Gif {
+        encoder,
+        delay: delay.clamp(2.0, u16::MAX as f64) as u16,
+    })
+}
+
+#[cfg(feature = "gif-output")]
+fn gif_error(error: gif::EncodingError) -> io::Error {
+    match error {
+        gif::EncodingError::Io(error) => error,
+        error => io::Error::new(io::ErrorKind::InvalidInput, error.to_string()),
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use super::Rgba8Image;
+
+const FILE_HEADER_SIZE: u32 = 14;
+const INFO_HEADER_SIZE: u32 = 40;
+
+// Writes a 24-bit BI_RGB bitmap; the alpha channel is dropped.
+pub fn write_bmp<W: Write>(mut writer: W, image: &Rgba8Image) -> io::Result<()> {
+    image.check_len()?;
+    let (width, height) = (image.width, image.height);
+    // Every row is padded to a multiple of four bytes.
+    let row_size = (width as usize * 3 + 3) & !3;
+    let image_size = row_size * height as usize;
+    let offset = FILE_HEADER_SIZE + INFO_HEADER_SIZE;
+    let file_size = offset as usize + image_size;
+    if file_size > u32::MAX as usize || width > i32::MAX as u32 || height > i32::MAX as u32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}x{} is too large for BMP", width, height),
+        ));
+    }
+
+    writer.write_all(b"BM")?;
+    writer.write_all(&(file_size as u32).to_le_bytes())?;
+    writer.write_all(&[0u8; 4])?;
+    writer.write_all(&offset.to_le_bytes())?;
+
+    writer.write_all(&INFO_HEADER_SIZE.to_le_bytes())?;
+    writer.write_all(&(width as i32).to_le_bytes())?;
+    // A positive height means the rows are stored bottom-up.
+    writer.write_all(&(height as i32).to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&24u16.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&(image_size as u32).to_le_bytes())?;
+    // 2835 pixels per metre is 72 DPI.
+    writer.write_all(&2835i32.to_le_bytes())?;
+    writer.write_all(&2835i32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+
+    let mut row = vec![0u8; row_size];
+    for source_row in image.rows().rev() {
+        for (pixel, target) in source_row.chunks_exact(4).zip(row.chunks_exact_mut(3)) {
+            target.copy_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TransferFunction;
+
+    #[test]
+    fn pads_rows_and_writes_them_bottom_up() {
+        // Red and green on top, blue and white below; two pixels take six
+        // bytes, padded to eight.
+        let image = Rgba8Image {
+            width: 2,
+            height: 2,
+            data: vec![
+                255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 255, 255, 255, 255, 255, 128,
+            ],
+            transfer: TransferFunction::Srgb,
+        };
+        let mut bytes = Vec::new();
+        write_bmp(&mut bytes, &image).unwrap();
+        let expected: &[u8] = &[
+            b'B', b'M', 70, 0, 0, 0, 0, 0, 0, 0, 54, 0, 0, 0, //
+            40, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 24, 0, //
+            0, 0, 0, 0, 16, 0, 0, 0, 0x13, 0x0b, 0, 0, 0x13, 0x0b, 0, 0, //
+            0, 0, 0, 0, 0, 0, 0, 0, //
+            255, 0, 0, 255, 255, 255, 0, 0, //
+            0, 0, 255, 0, 255, 0, 0, 0,
+        ];
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn single_pixel_rows_are_padded_to_four_bytes() {
+        let image = Rgba8Image {
+            width: 1,
+            height: 3,
+            data: vec![1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255],
+            transfer: TransferFunction::Srgb,
+        };
+        let mut bytes = Vec::new();
+        write_bmp(&mut bytes, &image).unwrap();
+        assert_eq!(bytes.len(), 54 + 3 * 4);
+        assert_eq!(&bytes[54..], &[9, 8, 7, 0, 6, 5, 4, 0, 3, 2, 1, 0]);
+    }
+}
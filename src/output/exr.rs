@@ -0,0 +1,107 @@
+use std::io::{self, Seek, Write};
+
+use exr::meta::attribute::{AttributeValue, Text};
+use exr::prelude::{Image, SpecificChannels, Vec2, WritableImage};
+
+use super::metadata::{latin1, Metadata};
+use super::Rgba8Image;
+use crate::vec_math::Color;
+
+// Writes linear RGB as 32-bit floats, rows from top to bottom, without the
+// clamping and gamma the 8-bit formats need.
+pub fn write_exr<W: Write + Seek>(
+    writer: W,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+) -> io::Result<()> {
+    write_exr_with_metadata(writer, width, height, pixels, &Metadata::default())
+}
+
+// Stores the metadata as string attributes of the image; see `inspect`.
+pub fn write_exr_with_metadata<W: Write + Seek>(
+    writer: W,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+    metadata: &Metadata,
+) -> io::Result<()> {
+    let expected = width as usize * height as usize;
+    if pixels.len() != expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} pixels don't make a {}x{} image",
+                pixels.len(),
+                width,
+                height
+            ),
+        ));
+    }
+    let channels = SpecificChannels::rgb(|Vec2(x, y): Vec2<usize>| {
+        let color = pixels[y * width as usize + x];
+        (color.r() as f32, color.g() as f32, color.b() as f32)
+    });
+    let mut image = Image::from_channels((width as usize, height as usize), channels);
+    for (key, value) in metadata.iter() {
+        // EXR text is Latin-1.
+        match (Text::new_or_none(key), Text::new_or_none(value)) {
+            (Some(key), Some(value)) => {
+                image
+                    .attributes
+                    .other
+                    .insert(key, AttributeValue::Text(value));
+            }
+            _ => log::warn!("left out the metadata key \"{}\", EXR can't store it", key),
+        }
+    }
+    image
+        .write()
+        .to_buffered(writer)
+        .map_err(|error| io::Error::other(error.to_string()))
+}
+
+// Undoes the transfer function of 8-bit pixels, for writing them as EXR.
+pub(super) fn linear_colors(image: &Rgba8Image) -> Vec<Color> {
+    image
+        .data
+        .chunks_exact(4)
+        .map(|pixel| {
+            let color = Color::new(
+                pixel[0] as f64 / 255.0,
+                pixel[1] as f64 / 255.0,
+                pixel[2] as f64 / 255.0,
+            );
+            image.transfer.decode_color(color)
+        })
+        .collect()
+}
+
+// The string attributes of the image, sorted by key as EXR keeps no order.
+pub(super) fn read_metadata(bytes: &[u8]) -> Result<Metadata, String> {
+    let meta = exr::meta::MetaData::read_from_buffered(io::Cursor::new(bytes), false)
+        .map_err(|error| error.to_string())?;
+    let mut entries: Vec<(String, String)> = meta
+        .headers
+        .first()
+        .into_iter()
+        // Reading can't tell image from layer attributes apart.
+        .flat_map(|header| {
+            header
+                .shared_attributes
+                .other
+                .iter()
+                .chain(&header.own_attributes.other)
+        })
+        .filter_map(|(key, value)| match value {
+            AttributeValue::Text(text) => Some((latin1(key.bytes()), latin1(text.bytes()))),
+            _ => None,
+        })
+        .collect();
+    entries.sort();
+    let mut metadata = Metadata::default();
+    for (key, value) in entries {
+        metadata.set(&key, value);
+    }
+    Ok(metadata)
+}
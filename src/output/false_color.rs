@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use super::{write_image, Rgba8Image, TransferFunction};
+use crate::aov::ScalarBuffer;
+use crate::error::Result;
+
+// Matplotlib's viridis at eleven evenly spaced stops.
+const VIRIDIS: [[u8; 3]; 11] = [
+    [68, 1, 84],
+    [72, 36, 117],
+    [65, 68, 135],
+    [53, 95, 141],
+    [42, 120, 142],
+    [33, 145, 140],
+    [34, 168, 132],
+    [68, 191, 112],
+    [122, 209, 81],
+    [189, 223, 38],
+    [253, 231, 37],
+];
+
+// Turbo sampled at seventeen evenly spaced stops from its polynomial fit.
+const TURBO: [[u8; 3]; 17] = [
+    [35, 23, 27],
+    [73, 62, 175],
+    [68, 106, 238],
+    [50, 149, 247],
+    [38, 189, 225],
+    [41, 221, 187],
+    [64, 243, 146],
+    [102, 253, 109],
+    [150, 250, 80],
+    [198, 235, 59],
+    [238, 208, 45],
+    [255, 171, 36],
+    [255, 128, 29],
+    [238, 84, 21],
+    [201, 45, 12],
+    [161, 18, 2],
+    [144, 13, 0],
+];
+
+// Used for infinite and NaN values; neither colormap comes close to it.
+pub const NO_DATA_COLOR: [u8; 3] = [255, 0, 255];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Colormap {
+    Viridis,
+    Turbo,
+}
+
+impl Colormap {
+    pub fn sample(self, t: f64) -> [u8; 3] {
+        let lut: &[[u8; 3]] = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Turbo => &TURBO,
+        };
+        let position = t.clamp(0.0, 1.0) * (lut.len() - 1) as f64;
+        let index = (position.floor() as usize).min(lut.len() - 2);
+        let fraction = position - index as f64;
+        let mut color = [0u8; 3];
+        for (channel, value) in color.iter_mut().enumerate() {
+            let low = lut[index][channel] as f64;
+            let high = lut[index + 1][channel] as f64;
+            *value = (low + (high - low) * fraction).round() as u8;
+        }
+        color
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FalseColorRange {
+    Fixed { min: f64, max: f64 },
+    // Percentiles in 0..=100 of the finite values in the buffer.
+    Percentile { low: f64, high: f64 },
+}
+
+impl FalseColorRange {
+    pub fn resolve(self, buffer: &ScalarBuffer) -> (f64, f64) {
+        match self {
+            FalseColorRange::Fixed { min, max } => (min, max),
+            FalseColorRange::Percentile { low, high } => {
+                let mut values: Vec<f64> = buffer
+                    .data
+                    .iter()
+                    .copied()
+                    .filter(|value| value.is_finite())
+                    .collect();
+                if values.is_empty() {
+                    return (0.0, 1.0);
+                }
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let at = |percentile: f64| {
+                    let rank = percentile.clamp(0.0, 100.0) / 100.0 * (values.len() - 1) as f64;
+                    values[rank.round() as usize]
+                };
+                (at(low), at(high))
+            }
+        }
+    }
+}
+
+pub fn false_color_image(
+    buffer: &ScalarBuffer,
+    range: FalseColorRange,
+    colormap: Colormap,
+) -> Rgba8Image {
+    let (min, max) = range.resolve(buffer);
+    let span = max - min;
+    let mut data = Vec::with_capacity(buffer.data.len() * 4);
+    for &value in &buffer.data {
+        let color = if value.is_finite() {
+            let t = if span > 0.0 {
+                (value - min) / span
+            } else {
+                0.0
+            };
+            colormap.sample(t)
+        } else {
+            NO_DATA_COLOR
+        };
+        data.extend_from_slice(&color);
+        data.push(255);
+    }
+    Rgba8Image {
+        width: buffer.width,
+        height: buffer.height,
+        data,
+        // The colormaps are already perceptually ordered display values.
+        transfer: TransferFunction::Gamma2,
+    }
+}
+
+pub fn write_false_color(path: &Path, buffer: &ScalarBuffer, range: FalseColorRange) -> Result<()> {
+    write_image(path, &false_color_image(buffer, range, Colormap::Turbo))
+}
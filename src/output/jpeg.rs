@@ -0,0 +1,65 @@
+use std::io::{self, Write};
+
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+use super::{Rgba8Image, TransferFunction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    // 4:4:4 keeps full chroma resolution, which avoids smearing the edges of
+    // small saturated objects.
+    None,
+    Horizontal,
+    HorizontalAndVertical,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct JpegOptions {
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        JpegOptions {
+            quality: 90,
+            subsampling: ChromaSubsampling::None,
+        }
+    }
+}
+
+pub fn write_jpeg<W: Write>(
+    writer: W,
+    image: &Rgba8Image,
+    options: &JpegOptions,
+) -> io::Result<()> {
+    image.check_len()?;
+    // JPEG quantization is tuned for display-referred data; linear values
+    // would lose most of their shadow detail.
+    if image.transfer == TransferFunction::Linear {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "refusing to write linear pixel data as JPEG, apply a transfer function first",
+        ));
+    }
+    if image.width > u16::MAX as u32 || image.height > u16::MAX as u32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}x{} is too large for JPEG", image.width, image.height),
+        ));
+    }
+    let mut encoder = Encoder::new(writer, options.quality.clamp(1, 100));
+    encoder.set_sampling_factor(match options.subsampling {
+        ChromaSubsampling::None => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Horizontal => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::HorizontalAndVertical => SamplingFactor::R_4_2_0,
+    });
+    encoder
+        .encode(
+            &image.data,
+            image.width as u16,
+            image.height as u16,
+            ColorType::Rgba,
+        )
+        .map_err(io::Error::other)
+}
@@ -0,0 +1,180 @@
+// Palettes for indexed formats. Colors are first counted into buckets of 5
+// bits per channel, then the buckets are split into boxes at the median of
+// their widest channel, weighted by pixel counts, until there are enough
+// boxes. Each box becomes the mean of the pixels in it.
+const BITS: u32 = 5;
+const BUCKETS: usize = 1 << (3 * BITS);
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    index: usize,
+    count: u64,
+    sums: [u64; 3],
+    // The mean of the pixels counted in.
+    color: [u8; 3],
+}
+
+fn mean(count: u64, sums: [u64; 3]) -> [u8; 3] {
+    let channel = |sum: u64| ((sum + count / 2) / count) as u8;
+    [channel(sums[0]), channel(sums[1]), channel(sums[2])]
+}
+
+fn bucket_index([r, g, b]: [u8; 3]) -> usize {
+    let shift = 8 - BITS;
+    ((r as usize >> shift) << (2 * BITS)) | ((g as usize >> shift) << BITS) | (b as usize >> shift)
+}
+
+// An indexed version of an RGBA image: `palette` holds RGB triples and every
+// pixel one index into it. Pixels less than half opaque share the index
+// `transparent`, whose palette entry is black.
+pub(super) struct Indexed {
+    pub palette: Vec<u8>,
+    pub indices: Vec<u8>,
+    pub transparent: Option<u8>,
+}
+
+// Quantizes RGBA pixels to at most `max_colors` colors, a transparent one
+// included, `max_colors` between 2 and 256.
+pub(super) fn quantize(rgba: &[u8], max_colors: usize) -> Indexed {
+    debug_assert!((2..=256).contains(&max_colors));
+    let opaque = |pixel: &[u8]| pixel[3] >= 128;
+    let mut histogram = vec![(0u64, [0u64; 3]); BUCKETS];
+    let mut has_transparent = false;
+    for pixel in rgba.chunks_exact(4) {
+        if !opaque(pixel) {
+            has_transparent = true;
+            continue;
+        }
+        let (count, sums) = &mut histogram[bucket_index([pixel[0], pixel[1], pixel[2]])];
+        *count += 1;
+        for channel in 0..3 {
+            sums[channel] += pixel[channel] as u64;
+        }
+    }
+    let mut buckets: Vec<Bucket> = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, (count, _))| *count > 0)
+        .map(|(index, &(count, sums))| Bucket {
+            index,
+            count,
+            sums,
+            color: mean(count, sums),
+        })
+        .collect();
+
+    let colors = max_colors - has_transparent as usize;
+    let boxes = median_cut(&mut buckets, colors);
+
+    let means: Vec<[u8; 3]> = boxes
+        .iter()
+        .map(|range| {
+            let (mut count, mut sums) = (0, [0; 3]);
+            for bucket in &buckets[range.clone()] {
+                count += bucket.count;
+                for (sum, &add) in sums.iter_mut().zip(&bucket.sums) {
+                    *sum += add;
+                }
+            }
+            mean(count, sums)
+        })
+        .collect();
+    // A bucket can sit closer to the mean of a neighbouring box than to that
+    // of its own, so each one maps to the nearest color.
+    let mut lookup = vec![0u8; BUCKETS];
+    for bucket in &buckets {
+        lookup[bucket.index] = nearest(&means, bucket.color);
+    }
+    let mut palette: Vec<u8> = means.iter().flatten().copied().collect();
+    let transparent = has_transparent.then(|| {
+        palette.extend_from_slice(&[0, 0, 0]);
+        (palette.len() / 3 - 1) as u8
+    });
+    let indices = rgba
+        .chunks_exact(4)
+        .map(|pixel| match transparent {
+            Some(transparent) if !opaque(pixel) => transparent,
+            _ => lookup[bucket_index([pixel[0], pixel[1], pixel[2]])],
+        })
+        .collect();
+    // GIF needs at least one color.
+    if palette.is_empty() {
+        palette.extend_from_slice(&[0, 0, 0]);
+    }
+    Indexed {
+        palette,
+        indices,
+        transparent,
+    }
+}
+
+// Sorts `buckets` into at most `colors` boxes and returns their ranges.
+fn median_cut(buckets: &mut [Bucket], colors: usize) -> Vec<std::ops::Range<usize>> {
+    let mut boxes = vec![];
+    if !buckets.is_empty() {
+        boxes.push(0..buckets.len());
+    }
+    while boxes.len() < colors {
+        // The box spanning the widest range of one channel, weighted by how
+        // many pixels it holds, so big areas of similar colors get more.
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.len() > 1)
+            .map(|(index, range)| {
+                let (channel, extent) = widest_channel(&buckets[range.clone()]);
+                let count: u64 = buckets[range.clone()].iter().map(|b| b.count).sum();
+                (index, channel, extent as u64 * count)
+            })
+            .max_by_key(|&(_, _, score)| score);
+        let (index, channel) = match widest {
+            Some((index, channel, _)) => (index, channel),
+            None => break,
+        };
+        let range = boxes[index].clone();
+        let slice = &mut buckets[range.clone()];
+        slice.sort_unstable_by_key(|bucket| (bucket.color[channel], bucket.index));
+        let total: u64 = slice.iter().map(|bucket| bucket.count).sum();
+        let mut seen = 0;
+        // Both halves keep at least one bucket.
+        let mut split = slice.len() - 1;
+        for (position, bucket) in slice.iter().enumerate().take(slice.len() - 1) {
+            seen += bucket.count;
+            if 2 * seen >= total {
+                split = position + 1;
+                break;
+            }
+        }
+        boxes[index] = range.start..range.start + split;
+        boxes.push(range.start + split..range.end);
+    }
+    boxes
+}
+
+fn widest_channel(buckets: &[Bucket]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = buckets.iter().map(|bucket| bucket.color[channel]);
+            let min = values.clone().min().unwrap_or(0);
+            let max = values.max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|&(_, extent)| extent)
+        .unwrap_or((0, 0))
+}
+
+fn nearest(palette: &[[u8; 3]], color: [u8; 3]) -> u8 {
+    let distance = |entry: &[u8; 3]| -> i32 {
+        (0..3)
+            .map(|channel| {
+                let difference = entry[channel] as i32 - color[channel] as i32;
+                difference * difference
+            })
+            .sum()
+    };
+    palette
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry))
+        .map_or(0, |(index, _)| index as u8)
+}
@@ -0,0 +1,472 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::ray_tracing::{Camera, LensDistortion, DEFAULT_RAY_EPSILON};
+use crate::renderer::RenderSettings;
+
+// Keys of what a render stores in its images, as PNG text chunks and EXR
+// string attributes. Files written by older versions keep being readable, so
+// keys are only ever added, never renamed or given other meanings.
+
+// "raytacer" and the crate version; the standard PNG keyword.
+pub const SOFTWARE: &str = "Software";
+pub const WIDTH: &str = "raytacer.width";
+pub const HEIGHT: &str = "raytacer.height";
+pub const SAMPLES_PER_PIXEL: &str = "raytacer.samples_per_pixel";
+pub const MAX_DEPTH: &str = "raytacer.max_depth";
+// The seed the render used, also when the settings left it to chance.
+pub const SEED: &str = "raytacer.seed";
+pub const EXPOSURE: &str = "raytacer.exposure";
+// Points and vectors as three numbers separated by spaces.
+pub const LOOK_FROM: &str = "raytacer.camera.look_from";
+pub const LOOK_AT: &str = "raytacer.camera.look_at";
+pub const VECTOR_UP: &str = "raytacer.camera.vector_up";
+// Vertical, in degrees.
+pub const FOV: &str = "raytacer.camera.fov";
+pub const APERTURE: &str = "raytacer.camera.aperture";
+pub const FOCUS_DISTANCE: &str = "raytacer.camera.focus_distance";
+// This key and the ones below are left out while their settings are at the
+// defaults.
+pub const CHROMATIC_ABERRATION: &str = "raytacer.camera.chromatic_aberration";
+// k1, k2, p1 and p2, separated by spaces.
+pub const LENS_DISTORTION: &str = "raytacer.camera.lens_distortion";
+pub const RAY_EPSILON: &str = "raytacer.ray_epsilon";
+// Roughness and growth, separated by a space.
+pub const REGULARIZATION: &str = "raytacer.regularization";
+// The type name of the material, followed by " except lights" when the
+// lights keep theirs.
+pub const MATERIAL_OVERRIDE: &str = "raytacer.material_override";
+// The scene file as given, or the name of a built-in scene.
+pub const SCENE: &str = "raytacer.scene";
+// FNV-1a of the scene file's bytes as 16 hex digits, to tell edits apart.
+pub const SCENE_HASH: &str = "raytacer.scene_hash";
+// Seconds, without saving and denoising.
+pub const RENDER_TIME: &str = "raytacer.render_time";
+
+// Key and value pairs in the order they were set; setting a key again
+// replaces its value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    // What `render` knows about the image it produced.
+    pub fn for_render(
+        settings: &RenderSettings,
+        camera: &Camera,
+        seed: u64,
+        render_time: Duration,
+    ) -> Metadata {
+        let config = camera.config();
+        let vector = |v: crate::vec_math::Vec3| format!("{} {} {}", v.x(), v.y(), v.z());
+        let mut metadata = Metadata::default();
+        metadata.set(SOFTWARE, format!("raytacer {}", env!("CARGO_PKG_VERSION")));
+        metadata.set(WIDTH, settings.width.to_string());
+        metadata.set(HEIGHT, settings.height.to_string());
+        metadata.set(SAMPLES_PER_PIXEL, settings.samples_per_pixel.to_string());
+        metadata.set(MAX_DEPTH, settings.max_depth.to_string());
+        metadata.set(SEED, seed.to_string());
+        metadata.set(EXPOSURE, settings.exposure.to_string());
+        metadata.set(LOOK_FROM, vector(config.look_from));
+        metadata.set(LOOK_AT, vector(config.look_at));
+        metadata.set(VECTOR_UP, vector(config.vector_up));
+        metadata.set(FOV, config.fov.to_degrees().to_string());
+        metadata.set(APERTURE, config.aperture.to_string());
+        metadata.set(FOCUS_DISTANCE, config.focus_distance.to_string());
+        if config.chromatic_aberration != 0.0 {
+            metadata.set(
+                CHROMATIC_ABERRATION,
+                config.chromatic_aberration.to_string(),
+            );
+        }
+        if !config.distortion.is_none() {
+            let LensDistortion { k1, k2, p1, p2 } = config.distortion;
+            metadata.set(LENS_DISTORTION, format!("{} {} {} {}", k1, k2, p1, p2));
+        }
+        if settings.ray_epsilon != DEFAULT_RAY_EPSILON {
+            metadata.set(RAY_EPSILON, settings.ray_epsilon.to_string());
+        }
+        if let Some(regularization) = &settings.regularization {
+            metadata.set(
+                REGULARIZATION,
+                format!("{} {}", regularization.roughness, regularization.growth),
+            );
+        }
+        if let Some(material_override) = &settings.material_override {
+            let lights = if material_override.keep_lights {
+                " except lights"
+            } else {
+                ""
+            };
+            metadata.set(
+                MATERIAL_OVERRIDE,
+                format!("{}{}", material_override.material.name(), lights),
+            );
+        }
+        metadata.set(RENDER_TIME, format!("{:.3}", render_time.as_secs_f64()));
+        metadata
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self
+            .entries
+            .iter_mut()
+            .find(|(existing, _)| existing == key)
+        {
+            Some((_, existing)) => *existing = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(existing, _)| existing != key);
+    }
+
+    // Sets every entry of `other`, replacing values of keys both have.
+    pub fn merge(&mut self, other: &Metadata) {
+        for (key, value) in other.iter() {
+            self.set(key, value);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Records the scene file a render came from, with a hash of its contents.
+    pub fn set_scene_file(&mut self, path: &Path, contents: &[u8]) {
+        self.set(SCENE, path.to_string_lossy());
+        self.set(SCENE_HASH, format!("{:016x}", fnv1a(contents)));
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.iter() {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+// 64-bit FNV-1a. Unlike the standard library's hashers it is the same in
+// every Rust version, so the hashes stay comparable.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Reads back the metadata of a PNG, or of an EXR in builds with the exr
+// feature. Images without any give an empty `Metadata`.
+pub fn inspect(path: &Path) -> Result<Metadata> {
+    let bytes = fs::read(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let decode_error = |message: String| Error::ImageDecode {
+        path: path.to_path_buf(),
+        message,
+    };
+    if bytes.starts_with(PNG_SIGNATURE) {
+        return read_png_text(&bytes).map_err(decode_error);
+    }
+    #[cfg(feature = "exr")]
+    if bytes.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+        return super::exr::read_metadata(&bytes).map_err(decode_error);
+    }
+    Err(Error::UnsupportedFormat(path.to_path_buf()))
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+// PNG keywords are 1 to 79 Latin-1 characters.
+#[cfg(feature = "png-output")]
+fn png_keyword(key: &str) -> Option<Vec<u8>> {
+    let bytes: Option<Vec<u8>> = key
+        .chars()
+        .map(|c| (c as u32 <= 0xff).then_some(c as u8))
+        .collect();
+    bytes.filter(|bytes| (1..=79).contains(&bytes.len()) && !bytes.contains(&0))
+}
+
+// The data of a tEXt chunk when the value is plain ASCII, otherwise of an
+// uncompressed iTXt chunk, which holds UTF-8. None for keys PNG can't store.
+#[cfg(feature = "png-output")]
+pub(super) fn png_text_chunk(key: &str, value: &str) -> Option<([u8; 4], Vec<u8>)> {
+    let mut data = png_keyword(key)?;
+    data.push(0);
+    if value.is_ascii() {
+        data.extend_from_slice(value.as_bytes());
+        return Some((*b"tEXt", data));
+    }
+    // Not compressed, no language tag and no translated keyword.
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    data.extend_from_slice(value.as_bytes());
+    Some((*b"iTXt", data))
+}
+
+fn read_png_text(bytes: &[u8]) -> std::result::Result<Metadata, String> {
+    let mut metadata = Metadata::default();
+    let mut rest = &bytes[PNG_SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        if rest.len() - 12 < length {
+            return Err("a chunk runs past the end of the file".to_string());
+        }
+        // Length, type, data and CRC.
+        let (kind, data) = (&rest[4..8], &rest[8..8 + length]);
+        rest = &rest[12 + length..];
+        match kind {
+            b"tEXt" => {
+                let (key, value) = split_at_nul(data)?;
+                metadata.set(&latin1(key), latin1(value));
+            }
+            b"iTXt" => {
+                let (key, rest) = split_at_nul(data)?;
+                if rest.len() < 2 {
+                    return Err("an iTXt chunk is cut short".to_string());
+                }
+                let (flags, rest) = rest.split_at(2);
+                if flags[0] != 0 {
+                    log::warn!("skipped the compressed text chunk {}", latin1(key));
+                } else {
+                    let (_language, rest) = split_at_nul(rest)?;
+                    let (_translated, value) = split_at_nul(rest)?;
+                    let value = String::from_utf8(value.to_vec())
+                        .map_err(|_| "an iTXt chunk isn't UTF-8".to_string())?;
+                    metadata.set(&latin1(key), value);
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+fn split_at_nul(data: &[u8]) -> std::result::Result<(&[u8], &[u8]), String> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| "a text chunk has no keyword".to_string())?;
+    Ok((&data[..end], &data[end + 1..]))
+}
+
+pub(super) fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "png-output", feature = "exr"))]
+    fn sample() -> Metadata {
+        let mut metadata = Metadata::default();
+        metadata.set(SOFTWARE, "raytacer test");
+        metadata.set(SEED, "42");
+        metadata.set(LOOK_FROM, "13 2 3");
+        metadata.set_scene_file(Path::new("scenes/demo.ron"), b"Scene()");
+        metadata
+    }
+
+    #[cfg(feature = "png-output")]
+    #[test]
+    fn png_metadata_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("raytacer-metadata-{}.png", std::process::id()));
+        let image = crate::output::Rgba8Image {
+            width: 2,
+            height: 2,
+            data: vec![128; 16],
+            transfer: crate::output::TransferFunction::Srgb,
+        };
+        let mut metadata = sample();
+        // Not ASCII, so it goes into an iTXt chunk instead of tEXt.
+        metadata.set("Comment", "lumière du soir ☀");
+        let options = crate::output::OutputOptions::default().with_metadata(metadata.clone());
+        crate::output::write_image_with_options(&path, &image, &options).unwrap();
+        let read = inspect(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), metadata);
+    }
+
+    #[cfg(feature = "png-output")]
+    #[test]
+    fn png_without_metadata_reads_as_empty() {
+        let path =
+            std::env::temp_dir().join(format!("raytacer-no-metadata-{}.png", std::process::id()));
+        let image = crate::output::Rgba8Image {
+            width: 1,
+            height: 1,
+            data: vec![0, 0, 0, 255],
+            transfer: crate::output::TransferFunction::Srgb,
+        };
+        crate::output::write_image_with_options(&path, &image, &Default::default()).unwrap();
+        let read = inspect(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(read.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "exr")]
+    #[test]
+    fn exr_metadata_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("raytacer-metadata-{}.exr", std::process::id()));
+        let pixels = vec![crate::vec_math::Color::new(0.25, 0.5, 2.0); 4];
+        crate::output::write_exr_file(&path, 2, 2, &pixels, &sample()).unwrap();
+        let read = inspect(&path);
+        fs::remove_file(&path).unwrap();
+        // EXR keeps no order, `inspect` sorts the keys.
+        let mut expected: Vec<_> = sample()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        expected.sort();
+        let read: Vec<_> = read
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(read, expected);
+    }
+
+    // What `render` records is what `inspect` finds in the saved file.
+    #[cfg(feature = "png-output")]
+    #[test]
+    fn a_saved_render_carries_its_settings() {
+        use std::sync::Arc;
+
+        use crate::background::Sky;
+        use crate::material::Diffusor;
+        use crate::ray_tracing::{Scene, Sphere};
+        use crate::renderer::{MultiOutput, Renderer};
+        use crate::vec_math::{Color, Point3, Vec3};
+
+        let path = std::env::temp_dir().join(format!(
+            "raytacer-render-metadata-{}.png",
+            std::process::id()
+        ));
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Diffusor {
+                color: Color::new(0.5, 0.5, 0.5),
+            }),
+        )));
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            4.0 / 3.0,
+            0.0,
+            2.0,
+        );
+        let settings = RenderSettings {
+            width: 8,
+            height: 6,
+            samples_per_pixel: 2,
+            seed: Some(7),
+            outputs: MultiOutput::beauty_only(path.clone()),
+            ..RenderSettings::default()
+        };
+        let framebuffer = Renderer::new(settings)
+            .render_to_file(&scene, &camera)
+            .unwrap();
+        let read = inspect(&path);
+        fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+        assert_eq!(read, framebuffer.metadata);
+        assert_eq!(read.get(WIDTH), Some("8"));
+        assert_eq!(read.get(HEIGHT), Some("6"));
+        assert_eq!(read.get(SEED), Some("7"));
+        assert_eq!(read.get(LOOK_FROM), Some("0 0 1"));
+    }
+
+    #[test]
+    fn settings_away_from_their_defaults_are_stored() {
+        use crate::material::MaterialOverride;
+        use crate::ray_tracing::PathRegularization;
+        use crate::vec_math::{Point3, Vec3};
+
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            1.5,
+            0.0,
+            2.0,
+        );
+        let settings = RenderSettings::default();
+        let metadata = Metadata::for_render(&settings, &camera, 1, Duration::ZERO);
+        for &key in &[
+            CHROMATIC_ABERRATION,
+            LENS_DISTORTION,
+            RAY_EPSILON,
+            REGULARIZATION,
+            MATERIAL_OVERRIDE,
+        ] {
+            assert_eq!(metadata.get(key), None, "{}", key);
+        }
+
+        let camera = camera
+            .with_chromatic_aberration(0.02)
+            .with_distortion(LensDistortion {
+                k1: -0.1,
+                k2: 0.01,
+                p1: 0.0,
+                p2: 0.002,
+            });
+        let settings = RenderSettings {
+            ray_epsilon: 1e-6,
+            regularization: Some(PathRegularization {
+                roughness: 0.1,
+                growth: 0.05,
+            }),
+            material_override: Some(MaterialOverride::clay()),
+            ..settings
+        };
+        let metadata = Metadata::for_render(&settings, &camera, 1, Duration::ZERO);
+        assert_eq!(metadata.get(CHROMATIC_ABERRATION), Some("0.02"));
+        assert_eq!(metadata.get(LENS_DISTORTION), Some("-0.1 0.01 0 0.002"));
+        assert_eq!(metadata.get(RAY_EPSILON), Some("0.000001"));
+        assert_eq!(metadata.get(REGULARIZATION), Some("0.1 0.05"));
+        assert_eq!(
+            metadata.get(MATERIAL_OVERRIDE),
+            Some("raytacer::material::Diffusor except lights")
+        );
+    }
+
+    #[test]
+    fn inspect_rejects_files_that_are_not_images() {
+        let path =
+            std::env::temp_dir().join(format!("raytacer-not-an-image-{}.txt", std::process::id()));
+        fs::write(&path, "not an image").unwrap();
+        let read = inspect(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(read, Err(Error::UnsupportedFormat(_))));
+    }
+}
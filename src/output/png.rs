@@ -0,0 +1,29 @@
+use std::io::{self, Write};
+
+use super::metadata::{png_text_chunk, Metadata};
+use super::Rgba8Image;
+
+pub fn write_png<W: Write>(writer: W, image: &Rgba8Image) -> io::Result<()> {
+    write_png_with_metadata(writer, image, &Metadata::default())
+}
+
+// Stores the metadata as text chunks ahead of the pixels; see `inspect`.
+pub fn write_png_with_metadata<W: Write>(
+    writer: W,
+    image: &Rgba8Image,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    image.check_len()?;
+    let mut encoder = png::Encoder::new(writer, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    for (key, value) in metadata.iter() {
+        match png_text_chunk(key, value) {
+            Some((kind, data)) => writer.write_chunk(png::chunk::ChunkType(kind), &data)?,
+            None => log::warn!("left out the metadata key \"{}\", PNG can't store it", key),
+        }
+    }
+    writer.write_image_data(&image.data)?;
+    Ok(())
+}
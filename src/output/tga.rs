@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+use super::Rgba8Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TgaDepth {
+    Bits24,
+    Bits32,
+}
+
+const UNCOMPRESSED_TRUE_COLOR: u8 = 2;
+
+pub fn write_tga<W: Write>(mut writer: W, image: &Rgba8Image, depth: TgaDepth) -> io::Result<()> {
+    image.check_len()?;
+    let (width, height) = (image.width, image.height);
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{}x{} is too large for TGA", width, height),
+        ));
+    }
+    let (bits_per_pixel, alpha_bits) = match depth {
+        TgaDepth::Bits24 => (24u8, 0u8),
+        TgaDepth::Bits32 => (32u8, 8u8),
+    };
+    let mut header = [0u8; 18];
+    header[2] = UNCOMPRESSED_TRUE_COLOR;
+    header[12..14].copy_from_slice(&(width as u16).to_le_bytes());
+    header[14..16].copy_from_slice(&(height as u16).to_le_bytes());
+    header[16] = bits_per_pixel;
+    // Bit 5 of the descriptor stays clear: the origin is the lower-left corner,
+    // so rows are written from the bottom of the image up.
+    header[17] = alpha_bits;
+    writer.write_all(&header)?;
+
+    let mut row = Vec::with_capacity(width as usize * bits_per_pixel as usize / 8);
+    for source_row in image.rows().rev() {
+        row.clear();
+        for pixel in source_row.chunks_exact(4) {
+            row.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+            if depth == TgaDepth::Bits32 {
+                row.push(pixel[3]);
+            }
+        }
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TransferFunction;
+
+    // Red and green on top, blue and a half transparent white below.
+    fn two_by_two() -> Rgba8Image {
+        Rgba8Image {
+            width: 2,
+            height: 2,
+            data: vec![
+                255, 0, 0, 255, 0, 255, 0, 255, //
+                0, 0, 255, 255, 255, 255, 255, 128,
+            ],
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    #[test]
+    fn writes_32_bit_bottom_row_first_in_bgra() {
+        let mut bytes = Vec::new();
+        write_tga(&mut bytes, &two_by_two(), TgaDepth::Bits32).unwrap();
+        let expected: &[u8] = &[
+            0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 32, 8, //
+            255, 0, 0, 255, 255, 255, 255, 128, //
+            0, 0, 255, 255, 0, 255, 0, 255,
+        ];
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn writes_24_bit_without_alpha() {
+        let mut bytes = Vec::new();
+        write_tga(&mut bytes, &two_by_two(), TgaDepth::Bits24).unwrap();
+        let expected: &[u8] = &[
+            0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0, //
+            255, 0, 0, 255, 255, 255, //
+            0, 0, 255, 0, 255, 0,
+        ];
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn rejects_a_buffer_of_the_wrong_size() {
+        let mut image = two_by_two();
+        image.data.pop();
+        let error = write_tga(Vec::new(), &image, TgaDepth::Bits32).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,372 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use crate::error::{Error, Result};
+
+use super::Rgba8Image;
+
+// Which codes the luma and chroma bytes use. Video is usually limited: luma
+// from 16 to 235 and chroma from 16 to 240, with room for overshoot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvRange {
+    Limited,
+    Full,
+}
+
+impl YuvRange {
+    pub fn from_name(name: &str) -> Option<YuvRange> {
+        match name {
+            "limited" | "tv" => Some(YuvRange::Limited),
+            "full" | "pc" => Some(YuvRange::Full),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VideoOutput {
+    // Raw yuv4mpeg2 frames, BT.709 4:2:0, into a file, or to standard output
+    // when the path is "-".
+    Y4m { path: PathBuf, range: YuvRange },
+    // Runs an ffmpeg command line and writes raw RGB frames to its standard
+    // input. The input arguments go in right after the program, so the
+    // command only names the encoder settings and the output, e.g.
+    // ["ffmpeg", "-y", "-c:v", "libx264", "-pix_fmt", "yuv420p", "out.mp4"].
+    Ffmpeg { command: Vec<String> },
+}
+
+enum Sink {
+    Y4m {
+        writer: Box<dyn Write>,
+        range: YuvRange,
+    },
+    Ffmpeg {
+        child: Child,
+        stdin: BufWriter<std::process::ChildStdin>,
+    },
+}
+
+// Writes frames to a video stream as they come. Both kinds carry the frame
+// rate: y4m in its header, ffmpeg as the rate of its input.
+pub struct VideoWriter {
+    sink: Sink,
+    // The file or program, for errors.
+    target: PathBuf,
+    width: u32,
+    height: u32,
+    // Reused between frames.
+    buffer: Vec<u8>,
+}
+
+impl VideoWriter {
+    pub fn create(
+        output: &VideoOutput,
+        width: u32,
+        height: u32,
+        frames_per_second: f64,
+    ) -> Result<VideoWriter> {
+        if !(frames_per_second > 0.0 && frames_per_second.is_finite()) {
+            return Err(Error::InvalidSettings(format!(
+                "frame rate {} is not a finite, positive number",
+                frames_per_second
+            )));
+        }
+        let (numerator, denominator) = frame_rate_fraction(frames_per_second);
+        let (sink, target) = match output {
+            VideoOutput::Y4m { path, range } => {
+                let mut writer = y4m_target(path)?;
+                let header = format!(
+                    "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C420jpeg XCOLORRANGE={}\n",
+                    width,
+                    height,
+                    numerator,
+                    denominator,
+                    match range {
+                        YuvRange::Limited => "LIMITED",
+                        YuvRange::Full => "FULL",
+                    }
+                );
+                writer
+                    .write_all(header.as_bytes())
+                    .map_err(|source| Error::Io {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let sink = Sink::Y4m {
+                    writer,
+                    range: *range,
+                };
+                (sink, path.clone())
+            }
+            VideoOutput::Ffmpeg { command } => {
+                let (program, arguments) = command.split_first().ok_or_else(|| {
+                    Error::InvalidSettings("the ffmpeg command is empty".to_string())
+                })?;
+                let mut child = Command::new(program)
+                    .args(["-f", "rawvideo", "-pix_fmt", "rgb24"])
+                    .arg("-video_size")
+                    .arg(format!("{}x{}", width, height))
+                    .arg("-framerate")
+                    .arg(format!("{}/{}", numerator, denominator))
+                    .args(["-i", "-"])
+                    .args(arguments)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|source| Error::Io {
+                        path: PathBuf::from(program),
+                        source,
+                    })?;
+                let stdin = child.stdin.take().expect("stdin is piped");
+                let sink = Sink::Ffmpeg {
+                    child,
+                    stdin: BufWriter::new(stdin),
+                };
+                (sink, PathBuf::from(program))
+            }
+        };
+        Ok(VideoWriter {
+            sink,
+            target,
+            width,
+            height,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn push_frame(&mut self, image: &Rgba8Image) -> Result<()> {
+        self.write_frame(image).map_err(|source| Error::Io {
+            path: self.target.clone(),
+            source,
+        })
+    }
+
+    fn write_frame(&mut self, image: &Rgba8Image) -> io::Result<()> {
+        image.check_len()?;
+        if (image.width, image.height) != (self.width, self.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "a {}x{} frame in a {}x{} video",
+                    image.width, image.height, self.width, self.height
+                ),
+            ));
+        }
+        self.buffer.clear();
+        match &mut self.sink {
+            Sink::Y4m { writer, range } => {
+                self.buffer.extend_from_slice(b"FRAME\n");
+                append_yuv420(&mut self.buffer, image, *range);
+                writer.write_all(&self.buffer)
+            }
+            Sink::Ffmpeg { stdin, .. } => {
+                // Alpha is dropped; frames are opaque.
+                self.buffer.extend(
+                    image
+                        .data
+                        .chunks_exact(4)
+                        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]),
+                );
+                stdin.write_all(&self.buffer)
+            }
+        }
+    }
+
+    // Flushes the stream; for ffmpeg, closes its input and waits for it to
+    // finish encoding.
+    pub fn finish(self) -> Result<()> {
+        let target = self.target;
+        let io_error = |source| Error::Io {
+            path: target.clone(),
+            source,
+        };
+        match self.sink {
+            Sink::Y4m { mut writer, .. } => writer.flush().map_err(io_error),
+            Sink::Ffmpeg { mut child, stdin } => {
+                // Dropping the pipe is what tells ffmpeg the input ended.
+                let flushed = stdin
+                    .into_inner()
+                    .map(drop)
+                    .map_err(|error| error.into_error());
+                // Waits even when the flush failed, so no zombie is left.
+                let status = child.wait().map_err(io_error)?;
+                flushed.map_err(io_error)?;
+                if status.success() {
+                    Ok(())
+                } else {
+                    Err(io_error(io::Error::other(format!(
+                        "exited with {}",
+                        status
+                    ))))
+                }
+            }
+        }
+    }
+}
+
+fn y4m_target(path: &Path) -> Result<Box<dyn Write>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(BufWriter::new(io::stdout())));
+    }
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent).map_err(|source| Error::Io {
+            path: parent.to_path_buf(),
+            source,
+        })?;
+    }
+    let file = File::create(path).map_err(|source| Error::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+// Integer frame rates exactly, the NTSC ones like 29.97 as n * 1000 / 1001,
+// anything else to a thousandth.
+fn frame_rate_fraction(frames_per_second: f64) -> (u32, u32) {
+    if (frames_per_second - frames_per_second.round()).abs() < 1e-6 {
+        return (frames_per_second.round() as u32, 1);
+    }
+    let ntsc = frames_per_second * 1.001;
+    if (ntsc - ntsc.round()).abs() < 1e-3 {
+        return (ntsc.round() as u32 * 1000, 1001);
+    }
+    ((frames_per_second * 1000.0).round() as u32, 1000)
+}
+
+// BT.709 luma weights of red and blue.
+const KR: f64 = 0.2126;
+const KB: f64 = 0.0722;
+
+// The Y, U and V planes of a 4:2:0 frame with chroma sited between the
+// pixels, as y4m's C420jpeg has it: each chroma sample is the mean of a two
+// by two block, of fewer at odd edges. The bytes are taken as the encoded
+// R'G'B' the matrix expects.
+fn append_yuv420(buffer: &mut Vec<u8>, image: &Rgba8Image, range: YuvRange) {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let (luma_scale, luma_offset, chroma_scale) = match range {
+        YuvRange::Limited => (219.0, 16.0, 224.0),
+        YuvRange::Full => (255.0, 0.0, 255.0),
+    };
+    let to_byte = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+    let pixel = |x: usize, y: usize| {
+        let offset = 4 * (y * width + x);
+        let channel = |index: usize| image.data[offset + index] as f64 / 255.0;
+        (channel(0), channel(1), channel(2))
+    };
+    let luma = |(r, g, b): (f64, f64, f64)| KR * r + (1.0 - KR - KB) * g + KB * b;
+
+    for y in 0..height {
+        for x in 0..width {
+            buffer.push(to_byte(luma_offset + luma_scale * luma(pixel(x, y))));
+        }
+    }
+    let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut blue = Vec::with_capacity(chroma_width * chroma_height);
+    let mut red = Vec::with_capacity(chroma_width * chroma_height);
+    for chroma_y in 0..chroma_height {
+        for chroma_x in 0..chroma_width {
+            let (mut sum, mut count) = ((0.0, 0.0, 0.0), 0.0);
+            for y in 2 * chroma_y..(2 * chroma_y + 2).min(height) {
+                for x in 2 * chroma_x..(2 * chroma_x + 2).min(width) {
+                    let (r, g, b) = pixel(x, y);
+                    sum = (sum.0 + r, sum.1 + g, sum.2 + b);
+                    count += 1.0;
+                }
+            }
+            let mean = (sum.0 / count, sum.1 / count, sum.2 / count);
+            let y = luma(mean);
+            let cb = (mean.2 - y) / (2.0 * (1.0 - KB));
+            let cr = (mean.0 - y) / (2.0 * (1.0 - KR));
+            blue.push(to_byte(128.0 + chroma_scale * cb));
+            red.push(to_byte(128.0 + chroma_scale * cr));
+        }
+    }
+    buffer.extend_from_slice(&blue);
+    buffer.extend_from_slice(&red);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::TransferFunction;
+
+    const BLACK: [u8; 3] = [0, 0, 0];
+    const WHITE: [u8; 3] = [255, 255, 255];
+    const RED: [u8; 3] = [255, 0, 0];
+    const GREEN: [u8; 3] = [0, 255, 0];
+    const BLUE: [u8; 3] = [0, 0, 255];
+
+    fn image(width: u32, height: u32, pixel: impl Fn(u32, u32) -> [u8; 3]) -> Rgba8Image {
+        let mut data = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&pixel(x, y));
+                data.push(255);
+            }
+        }
+        Rgba8Image {
+            width,
+            height,
+            data,
+            transfer: TransferFunction::Srgb,
+        }
+    }
+
+    fn yuv(image: &Rgba8Image, range: YuvRange) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        append_yuv420(&mut buffer, image, range);
+        buffer
+    }
+
+    // The codes of the BT.709 tables, for the whole two by two frame.
+    #[test]
+    fn primaries_get_the_bt709_codes() {
+        let cases = [
+            (BLACK, [16, 128, 128], [0, 128, 128]),
+            (WHITE, [235, 128, 128], [255, 128, 128]),
+            (RED, [63, 102, 240], [54, 99, 255]),
+            (GREEN, [173, 42, 26], [182, 30, 12]),
+            (BLUE, [32, 240, 118], [18, 255, 116]),
+        ];
+        for &(color, limited, full) in &cases {
+            let frame = image(2, 2, |_, _| color);
+            for &(range, [y, cb, cr]) in &[(YuvRange::Limited, limited), (YuvRange::Full, full)] {
+                assert_eq!(
+                    yuv(&frame, range),
+                    [y, y, y, y, cb, cr],
+                    "{:?} in {:?} range",
+                    color,
+                    range
+                );
+            }
+        }
+    }
+
+    // The last column and row of chroma samples cover a single column or row
+    // of pixels, and the corner a single pixel.
+    #[test]
+    fn odd_sizes_average_chroma_over_the_pixels_there_are() {
+        let frame = image(3, 3, |x, y| match (x, y) {
+            (2, _) => RED,
+            (_, 2) => BLUE,
+            _ => BLACK,
+        });
+        let buffer = yuv(&frame, YuvRange::Limited);
+        let (luma, chroma) = buffer.split_at(9);
+        assert_eq!(luma, [16, 16, 63, 16, 16, 63, 32, 32, 63]);
+        let (blue, red) = chroma.split_at(4);
+        assert_eq!(blue, [128, 102, 240, 102]);
+        assert_eq!(red, [128, 240, 118, 240]);
+
+        // A lone pixel is its own chroma sample.
+        assert_eq!(
+            yuv(&image(1, 1, |_, _| GREEN), YuvRange::Full),
+            [182, 30, 12]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(feature = "denoise")]
+use crate::vec_math::{Color, Vec3};
+
+// B3 spline weights of the 5x5 À-Trous kernel, per axis.
+#[cfg(feature = "denoise")]
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+#[derive(Debug, Clone, Copy)]
+pub struct AtrousSettings {
+    pub iterations: u32,
+    pub sigma_color: f64,
+    pub sigma_normal: f64,
+    // Relative to the center pixel's depth, so it doesn't depend on scene scale.
+    pub sigma_depth: f64,
+}
+
+impl Default for AtrousSettings {
+    fn default() -> Self {
+        AtrousSettings {
+            iterations: 5,
+            sigma_color: 0.2,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Denoiser {
+    Atrous(AtrousSettings),
+}
+
+// Infinite depth marks pixels where the camera ray escaped; those only blend
+// with each other.
+#[cfg(feature = "denoise")]
+fn depth_weight(center: f64, neighbor: f64, sigma: f64) -> f64 {
+    match (center.is_finite(), neighbor.is_finite()) {
+        (true, true) => {
+            let difference = (center - neighbor) / center.max(1e-6);
+            (-difference * difference / (sigma * sigma)).exp()
+        }
+        (false, false) => 1.0,
+        _ => 0.0,
+    }
+}
+
+// Edge-avoiding À-Trous wavelet filter (Dammertz et al. 2010). Iteration i
+// samples the 5x5 kernel with holes of 2^i pixels. Neighbors are weighted by
+// how similar their color, normal and depth are to the center pixel. The color
+// stop halves every iteration, since each pass removes noise.
+#[cfg(feature = "denoise")]
+#[allow(clippy::too_many_arguments)]
+pub fn denoise_atrous(
+    beauty: &[Color],
+    normals: &[Vec3],
+    depth: &[f64],
+    width: u32,
+    height: u32,
+    iterations: u32,
+    sigma_color: f64,
+    sigma_normal: f64,
+    sigma_depth: f64,
+) -> Vec<Color> {
+    let (width, height) = (width as i64, height as i64);
+    let mut current = beauty.to_vec();
+    let mut next = current.clone();
+    let normal_denominator = sigma_normal * sigma_normal;
+    for iteration in 0..iterations {
+        let step = 1i64 << iteration;
+        let sigma = sigma_color * 0.5f64.powi(iteration as i32);
+        let color_denominator = (sigma * sigma).max(1e-12);
+        for y in 0..height {
+            for x in 0..width {
+                let center = (y * width + x) as usize;
+                let mut sum = Color::BLACK;
+                let mut total_weight = 0.0;
+                for (j, ky) in KERNEL.iter().enumerate() {
+                    let sample_y = y + (j as i64 - 2) * step;
+                    if sample_y < 0 || sample_y >= height {
+                        continue;
+                    }
+                    for (i, kx) in KERNEL.iter().enumerate() {
+                        let sample_x = x + (i as i64 - 2) * step;
+                        if sample_x < 0 || sample_x >= width {
+                            continue;
+                        }
+                        let sample = (sample_y * width + sample_x) as usize;
+                        let color_weight = (-current[center].distance_squared(current[sample])
+                            / color_denominator)
+                            .exp();
+                        let normal_weight = (-(normals[center] - normals[sample]).len_squared()
+                            / normal_denominator)
+                            .exp();
+                        let weight = kx
+                            * ky
+                            * color_weight
+                            * normal_weight
+                            * depth_weight(depth[center], depth[sample], sigma_depth);
+                        if weight.is_finite() {
+                            sum += current[sample] * weight;
+                            total_weight += weight;
+                        }
+                    }
+                }
+                next[center] = if total_weight > 0.0 {
+                    sum / total_weight
+                } else {
+                    current[center]
+                };
+            }
+        }
+        std::mem::swap(&mut current, &mut next);
+    }
+    current
+}
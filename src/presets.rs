@@ -0,0 +1,592 @@
+use std::sync::Arc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::background::{ConstantBackground, Sky};
+use crate::material::{
+    Checker, Diffusor, Emitter, Material, MaterialDescription, Reflector, Refractor, RoughRefractor,
+};
+use crate::ray_tracing::{Camera, FlipNormals, Quad, Scene, Sphere};
+use crate::renderer::{fresh_seed, RenderSettings};
+use crate::vec_math::{random_double_in_interval, Color, Mat4, Point3, Vec3};
+
+// The built-in scenes, each with a camera and render settings that suit it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    Random,
+    Cornell,
+    ThreeSpheres,
+    Gallery,
+    FrostedGlass,
+    Checkered,
+    SingleDiffuse,
+    SingleGlass,
+    SingleMirror,
+}
+
+impl Preset {
+    // "classic" is kept as another name for the random spheres.
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Some(match name {
+            "random" | "classic" => Preset::Random,
+            "cornell" => Preset::Cornell,
+            "three-spheres" => Preset::ThreeSpheres,
+            "gallery" => Preset::Gallery,
+            "frosted-glass" => Preset::FrostedGlass,
+            "checkered" => Preset::Checkered,
+            "single-diffuse" => Preset::SingleDiffuse,
+            "single-glass" => Preset::SingleGlass,
+            "single-mirror" => Preset::SingleMirror,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Random => "random",
+            Preset::Cornell => "cornell",
+            Preset::ThreeSpheres => "three-spheres",
+            Preset::Gallery => "gallery",
+            Preset::FrostedGlass => "frosted-glass",
+            Preset::Checkered => "checkered",
+            Preset::SingleDiffuse => "single-diffuse",
+            Preset::SingleGlass => "single-glass",
+            Preset::SingleMirror => "single-mirror",
+        }
+    }
+
+    // `seed` fixes the random spheres' layout as well as the render.
+    pub fn setup(self, seed: Option<u64>) -> (Scene, Camera, RenderSettings) {
+        let (scene, camera, settings) = match self {
+            Preset::Random => random_spheres(&RandomSpheresConfig {
+                seed,
+                ..RandomSpheresConfig::default()
+            }),
+            Preset::Cornell => cornell_box(),
+            Preset::ThreeSpheres => three_spheres(),
+            Preset::Gallery => gallery(),
+            Preset::FrostedGlass => frosted_glass(),
+            Preset::Checkered => checkered(),
+            Preset::SingleDiffuse => single_sphere(Arc::new(Diffusor {
+                color: Color::new(0.7, 0.3, 0.3),
+            })),
+            Preset::SingleGlass => single_sphere(Arc::new(Refractor {
+                color: Color::WHITE,
+                fuzz_coeff: 0.0,
+                refr_coeff: 1.5,
+            })),
+            Preset::SingleMirror => single_sphere(Arc::new(Reflector {
+                color: Color::new(0.8, 0.8, 0.8),
+                fuzz_coeff: 0.0,
+            })),
+        };
+        (scene, camera, RenderSettings { seed, ..settings })
+    }
+}
+
+fn finish(
+    mut scene: Scene,
+    camera: Camera,
+    width: u32,
+    height: u32,
+) -> (Scene, Camera, RenderSettings) {
+    scene.build_bvh();
+    let settings = RenderSettings {
+        width,
+        height,
+        ..RenderSettings::default()
+    };
+    (scene, camera, settings)
+}
+
+// The final scene of "Ray Tracing in One Weekend".
+pub fn random_spheres(config: &RandomSpheresConfig) -> (Scene, Camera, RenderSettings) {
+    let camera = Camera::new(
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0f64.to_radians(),
+        3.0 / 2.0,
+        0.1,
+        10.0,
+    );
+    let (scene, camera, settings) = finish(generate(config), camera, 1200, 800);
+    (
+        scene,
+        camera,
+        RenderSettings {
+            seed: config.seed,
+            ..settings
+        },
+    )
+}
+
+// Parameters of the random spheres scene from "Ray Tracing in One Weekend".
+// Small spheres sit on a grid from -grid_half_extent to grid_half_extent in x
+// and z, so there are up to (2 * grid_half_extent + 1)^2 of them;
+// `material_weights` are the relative odds of a diffuse, metal and glass
+// sphere. The default is the scene the book renders.
+#[derive(Debug, Clone)]
+pub struct RandomSpheresConfig {
+    // The same seed generates the same scene; None picks a fresh one.
+    pub seed: Option<u64>,
+    pub grid_half_extent: i32,
+    pub sphere_radius: f64,
+    pub material_weights: [f32; 3],
+    pub fuzz_range: (f64, f64),
+    pub glass_fuzz_range: (f64, f64),
+    pub ior_range: (f64, f64),
+    // The large glass, diffuse and metal spheres in the middle.
+    pub include_hero_spheres: bool,
+    pub ground_material: MaterialDescription,
+}
+
+impl Default for RandomSpheresConfig {
+    fn default() -> Self {
+        RandomSpheresConfig {
+            seed: None,
+            grid_half_extent: 11,
+            sphere_radius: 0.2,
+            material_weights: [3.0, 1.0, 1.0],
+            fuzz_range: (0.0, 0.3),
+            glass_fuzz_range: (0.0, 0.5),
+            ior_range: (1.1, 1.7),
+            include_hero_spheres: true,
+            ground_material: MaterialDescription::Diffusor {
+                color: Color::new(0.2, 0.2, 0.2),
+            },
+        }
+    }
+}
+
+fn pick_weighted<R: Rng + ?Sized>(rng: &mut R, weights: &[f32]) -> usize {
+    let total: f32 = weights.iter().map(|weight| weight.max(0.0)).sum();
+    let mut pick = rng.gen::<f32>() * total;
+    for (index, weight) in weights.iter().enumerate() {
+        let weight = weight.max(0.0);
+        if pick < weight {
+            return index;
+        }
+        pick -= weight;
+    }
+    0
+}
+
+pub fn generate(config: &RandomSpheresConfig) -> Scene {
+    match config.seed {
+        Some(seed) => generate_with_rng(config, &mut StdRng::seed_from_u64(seed)),
+        None => generate_with_rng(config, &mut StdRng::seed_from_u64(fresh_seed())),
+    }
+}
+
+fn generate_with_rng<R: Rng + ?Sized>(config: &RandomSpheresConfig, rng: &mut R) -> Scene {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        config.ground_material.build(),
+    )));
+
+    let radius = config.sphere_radius;
+    let extent = config.grid_half_extent;
+    for a in -extent..=extent {
+        for b in -extent..=extent {
+            let center = Point3::new(
+                a as f64 + 0.9 * rng.gen::<f64>(),
+                radius,
+                b as f64 + 0.9 * rng.gen::<f64>(),
+            );
+            if (center - Point3::new(4.0, radius, 0.0)).len() > 0.9 {
+                let material: Arc<dyn Material> = match pick_weighted(rng, &config.material_weights)
+                {
+                    0 => Arc::new(Diffusor {
+                        color: Color::random(rng),
+                    }),
+                    1 => Arc::new(Reflector {
+                        color: Color::random_in_interval(rng, (0.5, 1.0)),
+                        fuzz_coeff: random_double_in_interval(rng, config.fuzz_range),
+                    }),
+                    _ => Arc::new(Refractor {
+                        color: Color::random(rng),
+                        fuzz_coeff: random_double_in_interval(rng, config.glass_fuzz_range),
+                        refr_coeff: random_double_in_interval(rng, config.ior_range),
+                    }),
+                };
+                scene.add(Box::new(Sphere::new(center, radius, material)));
+            }
+        }
+    }
+    if !config.include_hero_spheres {
+        return scene;
+    }
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Refractor {
+            color: Color::random(rng),
+            fuzz_coeff: 0.0,
+            refr_coeff: 1.5,
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.4, 0.2, 0.1),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Reflector {
+            color: Color::new(0.7, 0.6, 0.5),
+            fuzz_coeff: 0.0,
+        }),
+    )));
+    scene
+}
+
+// The Cornell box as in "Ray Tracing: The Next Week": a 555 unit cube, open
+// towards the camera, with red and green side walls and two rotated boxes.
+// The ceiling light is split into two panels.
+pub fn cornell_box() -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(ConstantBackground(Color::BLACK)));
+    let red: Arc<dyn Material> = Arc::new(Diffusor {
+        color: Color::new(0.65, 0.05, 0.05),
+    });
+    let white: Arc<dyn Material> = Arc::new(Diffusor {
+        color: Color::new(0.73, 0.73, 0.73),
+    });
+    let green: Arc<dyn Material> = Arc::new(Diffusor {
+        color: Color::new(0.12, 0.45, 0.15),
+    });
+    let light: Arc<dyn Material> = Arc::new(Emitter {
+        color: Color::new(15.0, 15.0, 15.0),
+    });
+    let walls = [
+        (
+            Point3::new(555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            &red,
+        ),
+        (
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            &green,
+        ),
+        (
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 555.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            &white,
+        ),
+        (
+            Point3::new(555.0, 555.0, 555.0),
+            Vec3::new(-555.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -555.0),
+            &white,
+        ),
+        (
+            Point3::new(0.0, 0.0, 555.0),
+            Vec3::new(0.0, 555.0, 0.0),
+            Vec3::new(555.0, 0.0, 0.0),
+            &white,
+        ),
+    ];
+    for (corner, u, v, material) in walls.iter() {
+        scene.add(Box::new(Quad::new(*corner, *u, *v, Arc::clone(material))));
+    }
+    for x in [163.0, 293.0].iter() {
+        scene.add(Box::new(Quad::new(
+            Point3::new(*x, 554.0, 227.0),
+            Vec3::new(0.0, 0.0, 105.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Arc::clone(&light),
+        )));
+    }
+    // (corner, size, rotation about y in degrees)
+    let boxes = [
+        (
+            Point3::new(265.0, 0.0, 295.0),
+            Vec3::new(165.0, 330.0, 165.0),
+            15.0f64,
+        ),
+        (
+            Point3::new(130.0, 0.0, 65.0),
+            Vec3::new(165.0, 165.0, 165.0),
+            -18.0f64,
+        ),
+    ];
+    for (corner, size, angle) in boxes.iter() {
+        let rotation = Mat4::rotation(Vec3::new(0.0, 1.0, 0.0), angle.to_radians());
+        let edges = [
+            rotation.transform_vector(Vec3::new(size.x(), 0.0, 0.0)),
+            Vec3::new(0.0, size.y(), 0.0),
+            rotation.transform_vector(Vec3::new(0.0, 0.0, size.z())),
+        ];
+        for side in Quad::cuboid(*corner, edges, Arc::clone(&white)) {
+            scene.add(Box::new(side));
+        }
+    }
+    let camera = Camera::new(
+        Point3::new(278.0, 278.0, -800.0),
+        Point3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0f64.to_radians(),
+        1.0,
+        0.0,
+        10.0,
+    );
+    finish(scene, camera, 600, 600)
+}
+
+// One unit sphere resting on a large gray ground sphere, for looking at a
+// material on its own.
+pub fn single_sphere(material: Arc<dyn Material>) -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        material,
+    )));
+    let camera = Camera::new(
+        Point3::new(0.0, 2.0, 6.0),
+        Point3::new(0.0, 0.9, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0f64.to_radians(),
+        3.0 / 2.0,
+        0.0,
+        6.0,
+    );
+    finish(scene, camera, 600, 400)
+}
+
+// Two checkered spheres touching at the origin.
+pub fn checkered() -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    let checker: Arc<dyn Material> = Arc::new(Checker {
+        even: Color::new(0.9, 0.9, 0.9),
+        odd: Color::new(0.2, 0.3, 0.1),
+        scale: 10.0,
+    });
+    for y in [-10.0, 10.0] {
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, y, 0.0),
+            10.0,
+            Arc::clone(&checker),
+        )));
+    }
+    let camera = Camera::new(
+        Point3::new(13.0, 2.0, 3.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        20.0f64.to_radians(),
+        3.0 / 2.0,
+        0.0,
+        10.0,
+    );
+    finish(scene, camera, 1200, 800)
+}
+
+// The three spheres from the middle of "Ray Tracing in One Weekend": a diffuse
+// sphere between a hollow glass one and a metal one.
+pub fn three_spheres() -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    let glass: Arc<dyn Material> = Arc::new(Refractor {
+        color: Color::WHITE,
+        fuzz_coeff: 0.0,
+        refr_coeff: 1.5,
+    });
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.8, 0.0),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Diffusor {
+            color: Color::new(0.1, 0.2, 0.5),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.5,
+        Arc::clone(&glass),
+    )));
+    scene.add(Box::new(FlipNormals::new(Box::new(Sphere::new(
+        Point3::new(-1.0, 0.0, -1.0),
+        0.4,
+        glass,
+    )))));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(1.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Reflector {
+            color: Color::new(0.8, 0.6, 0.2),
+            fuzz_coeff: 0.0,
+        }),
+    )));
+    let camera = Camera::new(
+        Point3::new(-2.0, 2.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0f64.to_radians(),
+        16.0 / 9.0,
+        0.0,
+        3.4,
+    );
+    finish(scene, camera, 800, 450)
+}
+
+// A row of diffuse, metal and glass spheres, smooth and rough, on a checker
+// floor with unit squares.
+pub fn gallery() -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(checker_floor());
+    let materials: [Arc<dyn Material>; 5] = [
+        Arc::new(Diffusor {
+            color: Color::new(0.7, 0.2, 0.2),
+        }),
+        Arc::new(Reflector {
+            color: Color::new(0.9, 0.7, 0.3),
+            fuzz_coeff: 0.3,
+        }),
+        Arc::new(Reflector {
+            color: Color::new(0.9, 0.9, 0.9),
+            fuzz_coeff: 0.0,
+        }),
+        Arc::new(Refractor {
+            color: Color::WHITE,
+            fuzz_coeff: 0.0,
+            refr_coeff: 1.5,
+        }),
+        Arc::new(Refractor {
+            color: Color::new(0.8, 0.9, 1.0),
+            fuzz_coeff: 0.2,
+            refr_coeff: 1.5,
+        }),
+    ];
+    for (index, material) in materials.iter().enumerate() {
+        scene.add(Box::new(Sphere::new(
+            Point3::new(index as f64 * 1.2 - 2.4, 0.0, 0.0),
+            0.5,
+            Arc::clone(material),
+        )));
+    }
+    finish(scene, gallery_camera(), 800, 450)
+}
+
+// Glass spheres from smooth to frosted, with roughness 0, 0.05, 0.2 and 0.5
+// from left to right, on the gallery's floor.
+pub fn frosted_glass() -> (Scene, Camera, RenderSettings) {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(checker_floor());
+    for (index, &roughness) in [0.0, 0.05, 0.2, 0.5].iter().enumerate() {
+        scene.add(Box::new(Sphere::new(
+            Point3::new(index as f64 * 1.2 - 1.8, 0.0, 0.0),
+            0.5,
+            Arc::new(RoughRefractor {
+                color: Color::WHITE,
+                roughness,
+                refr_coeff: 1.5,
+                roughness_texture: None,
+            }),
+        )));
+    }
+    finish(scene, gallery_camera(), 800, 450)
+}
+
+// A checker floor with unit squares under spheres of radius 0.5 at y = 0.
+fn checker_floor() -> Box<Quad> {
+    // The checker is the sign of sin(x) sin(y) sin(z); at y = -0.5 a scale of
+    // pi keeps the middle factor at -1 and flips the other two every unit.
+    Box::new(Quad::new(
+        Point3::new(-20.0, -0.5, -20.0),
+        Vec3::new(0.0, 0.0, 40.0),
+        Vec3::new(40.0, 0.0, 0.0),
+        Arc::new(Checker {
+            even: Color::new(0.9, 0.9, 0.9),
+            odd: Color::new(0.15, 0.15, 0.15),
+            scale: std::f64::consts::PI,
+        }),
+    ))
+}
+
+fn gallery_camera() -> Camera {
+    Camera::new(
+        Point3::new(0.0, 1.5, 6.0),
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        30.0f64.to_radians(),
+        16.0 / 9.0,
+        0.0,
+        6.0,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::Any;
+
+    use super::*;
+
+    fn small_spheres(config: &RandomSpheresConfig) -> Vec<Point3> {
+        let scene = generate(config);
+        scene
+            .hittables()
+            .iter()
+            .filter_map(|hittable| (hittable.as_ref() as &dyn Any).downcast_ref::<Sphere>())
+            .filter(|sphere| sphere.radius() == config.sphere_radius)
+            .map(|sphere| sphere.center())
+            .collect()
+    }
+
+    #[test]
+    fn default_random_scene_fills_a_23_by_23_grid() {
+        let config = RandomSpheresConfig {
+            seed: Some(42),
+            ..RandomSpheresConfig::default()
+        };
+        let scene = generate(&config);
+        let small = small_spheres(&config);
+        // (2 * 11 + 1)^2 = 529 candidates, less the few that would touch the
+        // metal hero sphere at (4, 1, 0).
+        assert!((525..=529).contains(&small.len()), "{}", small.len());
+        assert_eq!(scene.hittables().len(), 1 + small.len() + 3);
+        for center in &small {
+            assert!((-11.0..=11.9).contains(&center.x()) && (-11.0..=11.9).contains(&center.z()));
+            assert!((*center - Point3::new(4.0, 0.2, 0.0)).len() > 0.9);
+        }
+    }
+
+    #[test]
+    fn random_scene_follows_its_seed_and_grid_extent() {
+        let config = RandomSpheresConfig {
+            seed: Some(42),
+            grid_half_extent: 2,
+            include_hero_spheres: false,
+            ..RandomSpheresConfig::default()
+        };
+        let first = small_spheres(&config);
+        assert_eq!(first.len(), 25);
+        let again = small_spheres(&config);
+        assert!(first.iter().zip(&again).all(|(a, b)| a.approx_eq(b, 0.0)));
+        let other = small_spheres(&RandomSpheresConfig {
+            seed: Some(43),
+            ..config
+        });
+        assert!(!first.iter().zip(&other).all(|(a, b)| a.approx_eq(b, 0.0)));
+    }
+}
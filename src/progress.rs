@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::convergence::ConvergenceSummary;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    // Over the tiles finished so far.
+    pub convergence: Option<ConvergenceSummary>,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.completed as f64 / self.total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressControl {
+    Continue,
+    Cancel,
+}
+
+const PRINT_INTERVAL: Duration = Duration::from_millis(250);
+// Weight of the newest measurement in the smoothed rate.
+const RATE_SMOOTHING: f64 = 0.3;
+
+struct ConsoleState {
+    last_print: Option<(Instant, usize)>,
+    rate: Option<f64>,
+}
+
+// Prints percentage and ETA to stderr, at most every PRINT_INTERVAL. Updates
+// that arrive while another thread is printing are dropped.
+pub struct ConsoleProgress {
+    state: Mutex<ConsoleState>,
+}
+
+impl Default for ConsoleProgress {
+    fn default() -> Self {
+        ConsoleProgress {
+            state: Mutex::new(ConsoleState {
+                last_print: None,
+                rate: None,
+            }),
+        }
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60)
+    } else if seconds >= 60 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+impl ConsoleProgress {
+    pub fn report(&self, progress: Progress) -> ProgressControl {
+        let done = progress.completed >= progress.total;
+        let mut state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(_) if !done => return ProgressControl::Continue,
+            Err(_) => self.state.lock().unwrap_or_else(|error| error.into_inner()),
+        };
+        let now = Instant::now();
+        let started = now.checked_sub(progress.elapsed).unwrap_or(now);
+        let (since, completed_then) = state.last_print.unwrap_or((started, 0));
+        let interval = now.duration_since(since);
+        if !done && state.last_print.is_some() && interval < PRINT_INTERVAL {
+            return ProgressControl::Continue;
+        }
+        if interval > Duration::ZERO && progress.completed > completed_then {
+            let rate = (progress.completed - completed_then) as f64 / interval.as_secs_f64();
+            state.rate = Some(match state.rate {
+                Some(smoothed) => smoothed + RATE_SMOOTHING * (rate - smoothed),
+                None => rate,
+            });
+        }
+        state.last_print = Some((now, progress.completed));
+
+        let mut stderr = io::stderr();
+        let _ = if done {
+            writeln!(
+                stderr,
+                "\rrendered 100.0% in {}        ",
+                format_duration(progress.elapsed)
+            )
+        } else {
+            let eta = match state.rate {
+                Some(rate) if rate > 0.0 => format_duration(Duration::from_secs_f64(
+                    (progress.total - progress.completed) as f64 / rate,
+                )),
+                _ => "--".to_string(),
+            };
+            let error = match progress.convergence {
+                Some(convergence) => format!("  error {:.1}%", convergence.mean * 100.0),
+                None => String::new(),
+            };
+            write!(
+                stderr,
+                "\rrendering {:5.1}%  ETA {}{}    ",
+                progress.fraction() * 100.0,
+                eta,
+                error
+            )
+        };
+        let _ = stderr.flush();
+        ProgressControl::Continue
+    }
+}
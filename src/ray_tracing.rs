@@ -1,56 +1,538 @@
-use crate::material::Material;
-use crate::vec_math::{Color, Point3, Vec3};
-use rand::prelude::ThreadRng;
+mod aabb;
+mod bvh;
+mod path_debug;
+mod validation;
 
+pub use aabb::Aabb;
+pub use bvh::BvhNode;
+pub use path_debug::{BounceEvent, BounceInfo, PathObserver, PathRecorder, SurfaceInfo};
+pub use validation::{Issues, Severity, ValidationIssue};
+
+use crate::background::Background;
+use crate::error::Error;
+use crate::material::{Material, MaterialLibrary, MaterialOverride};
+use crate::stats;
+use crate::stopwatch::Stopwatch;
+use crate::vec_math::{Color, Interval, Mat4, Point3, Quat, SingularMatrix, UnitVec3, Vec3};
+use rand::{Rng, RngCore};
+use std::any::Any;
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::convert::TryFrom;
+use std::num::NonZeroU32;
+use std::time::Duration;
+use std::{fmt, io};
+
+// Hits closer than this along a ray are ignored, in units of the ray's
+// direction.
+pub const DEFAULT_RAY_EPSILON: f64 = 0.001;
+// Relative to the size of the hit point's coordinates; a few hundred times the
+// rounding error of a double.
+const ORIGIN_OFFSET: f64 = 1e-13;
+
+#[derive(Clone, Copy)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, t_bounds: (f64, f64)) -> Option<HitRecord>;
+// How the origin and direction of a camera ray change from one pixel to the
+// next, right (x) and up (y), for sizing texture lookups to the pixel.
+#[derive(Debug, Clone, Copy)]
+pub struct RayDifferentials {
+    pub origin_dx: Vec3,
+    pub origin_dy: Vec3,
+    pub direction_dx: Vec3,
+    pub direction_dy: Vec3,
+}
+
+// How far the texture coordinates move from one pixel to the next.
+#[derive(Debug, Clone, Copy)]
+pub struct UvFootprint {
+    pub du_dx: f64,
+    pub dv_dx: f64,
+    pub du_dy: f64,
+    pub dv_dy: f64,
+}
+
+impl RayDifferentials {
+    // Traces the rays through the next pixels over and reads off how far u
+    // and v moved between their hits and `record`. A neighbour that hits
+    // another material, misses, or lands across a seam where u or v wraps
+    // around is replaced by the one on the other side; None when both fail.
+    fn uv_footprint(
+        &self,
+        scene: &Scene,
+        ray: &Ray,
+        record: &HitRecord,
+        bounds: Interval,
+    ) -> Option<UvFootprint> {
+        let step = |origin_d: Vec3, direction_d: Vec3| {
+            let mut best: Option<(f64, f64)> = None;
+            for &sign in &[1.0, -1.0] {
+                let neighbour = Ray::new(
+                    ray.origin + sign * origin_d,
+                    ray.direction + sign * direction_d,
+                );
+                let hit = match scene.hit(&neighbour, bounds) {
+                    Some(hit) if std::sync::Arc::ptr_eq(&hit.material, &record.material) => hit,
+                    _ => continue,
+                };
+                let (du, dv) = (sign * (hit.u - record.u), sign * (hit.v - record.v));
+                if best.is_none_or(|(u, v)| du.abs() + dv.abs() < u.abs() + v.abs()) {
+                    best = Some((du, dv));
+                }
+                // Less than half the texture per pixel can't be a seam.
+                if du.abs() + dv.abs() < 0.5 {
+                    break;
+                }
+            }
+            best
+        };
+        let (du_dx, dv_dx) = step(self.origin_dx, self.direction_dx)?;
+        let (du_dy, dv_dy) = step(self.origin_dy, self.direction_dy)?;
+        Some(UvFootprint {
+            du_dx,
+            dv_dx,
+            du_dy,
+            dv_dy,
+        })
+    }
+}
+
+// A ray from the camera through a pixel. Differentials ride along here rather
+// than on every `Ray`, which would make all of them three times as large.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraRay {
+    pub ray: Ray,
+    pub differentials: RayDifferentials,
+    // Multiplies what the ray sees; picks out one channel under chromatic
+    // aberration and is white otherwise.
+    pub weight: Color,
+}
+
+pub trait Hittable: Any + Send + Sync {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord>;
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // A direction from `origin` towards this object, for sampling it as a
+    // light, and how likely that is to pick `direction`, as a density over
+    // solid angle. Objects that can't be sampled give None and 0, and so do
+    // one-sided emitters for samples on their back; see
+    // `Material::emits_one_sided`.
+    fn random_toward(&self, _origin: Point3, _rng: &mut dyn RngCore) -> Option<Vec3> {
+        None
+    }
+
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    // Reports parameters that won't render right, the material's included.
+    fn validate(&self, _issues: &mut Issues) {}
+
+    // Whether `point` is inside the object; None for objects without an
+    // inside, or that can't tell.
+    fn contains(&self, _point: Point3) -> Option<bool> {
+        None
+    }
+
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+// Shares geometry between objects: wrap clones of one `Arc`, say in
+// `Transformed`, and add each to the scene. Every one gets its own id.
+impl<H: Hittable + ?Sized> Hittable for std::sync::Arc<H> {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        (**self).hit(ray, t_bounds)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        (**self).random_toward(origin, rng)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        (**self).pdf_value(origin, direction)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        (**self).validate(issues)
+    }
+
+    fn contains(&self, point: Point3) -> Option<bool> {
+        (**self).contains(point)
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
 }
 
-//#[derive(Clone, Copy)]
 pub struct HitRecord {
     pub point: Point3,
-    pub normal: Vec3,
-    pub material: std::rc::Rc<dyn Material>,
+    // Faces against the incoming ray; see `front_face`.
+    pub normal: UnitVec3,
+    pub material: std::sync::Arc<dyn Material>,
     pub t: f64,
     pub front_face: bool,
+    // The scene object that was hit, filled in by `Scene::hit`; None from a
+    // hittable on its own. Objects that share geometry still get their own.
+    pub object: Option<ObjectId>,
+    // Surface coordinates in [0, 1], for texturing.
+    pub u: f64,
+    pub v: f64,
+    // Only at the first hit of a camera ray, on materials that ask for it.
+    // Boxed, since every hit record is moved around many times while the
+    // closest hit is searched for, and the larger size slows that down more
+    // than the rare allocation costs.
+    pub footprint: Option<Box<UvFootprint>>,
 }
 
+// Stays valid for the lifetime of the scene, no matter what else is added or
+// removed. Ids are never reused. They are 32 bits and never zero, so that
+// `Option<ObjectId>` fits into the padding of a hit record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(NonZeroU32);
+
 pub struct Scene {
-    pub hittables: Vec<Box<dyn Hittable>>,
+    hittables: Vec<Box<dyn Hittable>>,
+    ids: Vec<ObjectId>,
+    names: BTreeMap<String, ObjectId>,
+    // Objects in no layer aren't in here.
+    layers: BTreeMap<ObjectId, String>,
+    // The next id to hand out.
+    next_id: u32,
+    pub background: Box<dyn Background>,
+    pub materials: MaterialLibrary,
+    bvh: Option<BvhNode>,
+    unbounded: Vec<usize>,
+    bvh_build_time: Duration,
 }
 
 impl Scene {
-    pub fn hit(&self, ray: &Ray, t_bounds: (f64, f64)) -> Option<HitRecord> {
+    pub fn new(background: Box<dyn Background>) -> Self {
+        Scene {
+            hittables: vec![],
+            ids: vec![],
+            names: BTreeMap::new(),
+            layers: BTreeMap::new(),
+            next_id: 1,
+            background,
+            materials: MaterialLibrary::new(),
+            bvh: None,
+            unbounded: vec![],
+            bvh_build_time: Duration::ZERO,
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        if let Some(bvh) = &self.bvh {
+            let mut result = bvh.hit(&self.hittables, ray, t_bounds);
+            let mut closest = result.as_ref().map_or(t_bounds.max, |record| record.t);
+            for &index in &self.unbounded {
+                stats::count_object_test();
+                if let Some(mut hit_record) =
+                    self.hittables[index].hit(ray, t_bounds.with_max(closest))
+                {
+                    closest = hit_record.t;
+                    hit_record.object = Some(self.ids[index]);
+                    result = Some(hit_record)
+                }
+            }
+            return result;
+        }
         let mut result = None;
-        let mut closest = t_bounds.1;
-        for hittable in &self.hittables {
-            if let Some(hit_record) = hittable.hit(ray, (t_bounds.0, closest)) {
+        let mut closest = t_bounds.max;
+        for (hittable, &id) in self.hittables.iter().zip(&self.ids) {
+            stats::count_object_test();
+            if let Some(mut hit_record) = hittable.hit(ray, t_bounds.with_max(closest)) {
                 closest = hit_record.t;
+                hit_record.object = Some(id);
                 result = Some(hit_record)
             }
         }
         result
     }
 
-    pub fn add(&mut self, hittable: Box<dyn Hittable>) {
+    // Like `hit`, with the id of the object that was hit taken out.
+    pub fn hit_object(&self, ray: &Ray, t_bounds: Interval) -> Option<(ObjectId, HitRecord)> {
+        let record = self.hit(ray, t_bounds)?;
+        Some((record.object?, record))
+    }
+
+    // Adding, removing or replacing objects invalidates the BVH; call
+    // `build_bvh` again once the scene is complete.
+    pub fn add(&mut self, hittable: Box<dyn Hittable>) -> ObjectId {
+        let id = self.reserve_ids(1).next().unwrap();
         self.hittables.push(hittable);
+        self.ids.push(id);
+        self.bvh = None;
+        id
+    }
+
+    fn reserve_ids(&mut self, count: usize) -> impl Iterator<Item = ObjectId> {
+        let first = self.next_id;
+        self.next_id = u32::try_from(count)
+            .ok()
+            .and_then(|count| first.checked_add(count))
+            .expect("a scene ran out of object ids");
+        (first..self.next_id).map(|id| ObjectId(NonZeroU32::new(id).unwrap()))
+    }
+
+    // Like `add`, and the object can be looked up by `name` afterwards.
+    pub fn add_named(
+        &mut self,
+        name: impl Into<String>,
+        hittable: Box<dyn Hittable>,
+    ) -> Result<ObjectId, Error> {
+        let name = name.into();
+        if self.names.contains_key(&name) {
+            return Err(Error::DuplicateName(name));
+        }
+        let id = self.add(hittable);
+        self.names.insert(name, id);
+        Ok(id)
+    }
+
+    // Like `add`, and the object belongs to `layer`, for render masks.
+    pub fn add_to_layer(
+        &mut self,
+        layer: impl Into<String>,
+        hittable: Box<dyn Hittable>,
+    ) -> ObjectId {
+        let id = self.add(hittable);
+        self.layers.insert(id, layer.into());
+        id
+    }
+
+    // Moves an object into `layer`, or out of any with None. False if the id
+    // is unknown.
+    pub fn set_layer(&mut self, id: ObjectId, layer: Option<String>) -> bool {
+        if self.position(id).is_none() {
+            return false;
+        }
+        match layer {
+            Some(layer) => self.layers.insert(id, layer),
+            None => self.layers.remove(&id),
+        };
+        true
+    }
+
+    pub fn layer_of(&self, id: ObjectId) -> Option<&str> {
+        self.layers.get(&id).map(String::as_str)
+    }
+
+    // Every object in a layer, by id.
+    pub fn layers(&self) -> impl Iterator<Item = (ObjectId, &str)> {
+        self.layers.iter().map(|(&id, layer)| (id, layer.as_str()))
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<ObjectId> {
+        self.names.get(name).copied()
+    }
+
+    pub fn name_of(&self, id: ObjectId) -> Option<&str> {
+        self.names
+            .iter()
+            .find(|(_, &named)| named == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<&dyn Hittable> {
+        self.get(self.id_of(name)?)
+    }
+
+    // The named object as its concrete type, None if it has another. The
+    // object may be changed, so the BVH is dropped.
+    pub fn get_named_mut<T: Hittable>(&mut self, name: &str) -> Option<&mut T> {
+        let index = self.position(self.id_of(name)?)?;
+        let hittable = self.hittables[index].as_mut() as &mut dyn Any;
+        let hittable = hittable.downcast_mut::<T>()?;
+        self.bvh = None;
+        Some(hittable)
+    }
+
+    fn forget_removed(&mut self) {
+        let ids = &self.ids;
+        self.names.retain(|_, id| ids.contains(id));
+        self.layers.retain(|id, _| ids.contains(id));
+    }
+
+    fn position(&self, id: ObjectId) -> Option<usize> {
+        self.ids.iter().position(|&other| other == id)
+    }
+
+    pub fn get(&self, id: ObjectId) -> Option<&dyn Hittable> {
+        self.position(id)
+            .map(|index| self.hittables[index].as_ref())
+    }
+
+    pub fn remove(&mut self, id: ObjectId) -> Option<Box<dyn Hittable>> {
+        let index = self.position(id)?;
+        self.ids.remove(index);
+        self.names.retain(|_, named| *named != id);
+        self.layers.remove(&id);
+        self.bvh = None;
+        Some(self.hittables.remove(index))
+    }
+
+    // Panics if `index` is out of bounds, like `Vec::remove`.
+    pub fn remove_at(&mut self, index: usize) -> Box<dyn Hittable> {
+        let hittable = self.hittables.remove(index);
+        let id = self.ids.remove(index);
+        self.names.retain(|_, named| *named != id);
+        self.layers.remove(&id);
+        self.bvh = None;
+        hittable
+    }
+
+    // Moves the last object into the gap instead of shifting everything after
+    // `index` down.
+    pub fn swap_remove(&mut self, index: usize) -> Box<dyn Hittable> {
+        let hittable = self.hittables.swap_remove(index);
+        let id = self.ids.swap_remove(index);
+        self.names.retain(|_, named| *named != id);
+        self.layers.remove(&id);
+        self.bvh = None;
+        hittable
+    }
+
+    pub fn retain<F: Fn(&Box<dyn Hittable>) -> bool>(&mut self, f: F) {
+        let keep: Vec<bool> = self.hittables.iter().map(&f).collect();
+        let mut flags = keep.iter();
+        self.hittables.retain(|_| *flags.next().unwrap());
+        let mut flags = keep.iter();
+        self.ids.retain(|_| *flags.next().unwrap());
+        self.forget_removed();
+        self.bvh = None;
+    }
+
+    // Moves every object of `other` over, without copying them. They get new
+    // ids in this scene, so ids from `other` don't carry over; layers do, and
+    // object and material names unless this scene already uses them.
+    // `other`'s background is dropped.
+    pub fn extend_from_scene(&mut self, other: Scene) {
+        let new_ids: Vec<ObjectId> = self.reserve_ids(other.hittables.len()).collect();
+        let new_id = |id: ObjectId| {
+            let index = other.ids.iter().position(|&other_id| other_id == id);
+            new_ids[index.unwrap()]
+        };
+        for (&id, layer) in &other.layers {
+            self.layers.insert(new_id(id), layer.clone());
+        }
+        for (name, &id) in &other.names {
+            let new_id = new_id(id);
+            match self.names.entry(name.clone()) {
+                Entry::Vacant(entry) => {
+                    entry.insert(new_id);
+                }
+                Entry::Occupied(entry) => log::warn!(
+                    "dropped the name \"{}\" of a merged object, it's taken",
+                    entry.key()
+                ),
+            }
+        }
+        self.materials.merge(other.materials);
+        self.ids.extend(new_ids);
+        self.hittables.extend(other.hittables);
+        self.bvh = None;
+    }
+
+    // Keeps `left`'s background.
+    pub fn merge(mut left: Scene, right: Scene) -> Scene {
+        left.extend_from_scene(right);
+        left
+    }
+
+    // Returns the previous object, or gives `hittable` back if the id is unknown.
+    pub fn replace(
+        &mut self,
+        id: ObjectId,
+        hittable: Box<dyn Hittable>,
+    ) -> Result<Box<dyn Hittable>, Box<dyn Hittable>> {
+        match self.position(id) {
+            Some(index) => {
+                self.bvh = None;
+                Ok(std::mem::replace(&mut self.hittables[index], hittable))
+            }
+            None => Err(hittable),
+        }
+    }
+
+    pub fn hittables(&self) -> &[Box<dyn Hittable>] {
+        &self.hittables
+    }
+
+    // Ids in the same order as `hittables`.
+    pub fn ids(&self) -> &[ObjectId] {
+        &self.ids
+    }
+
+    // Surrounds every bounded object; planes and other unbounded objects are
+    // left out. None when nothing is bounded.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.hittables
+            .iter()
+            .filter_map(|hittable| hittable.bounding_box())
+            .reduce(|first, second| Aabb::surrounding(&first, &second))
+    }
+
+    // False until `build_bvh` runs and again after any change to the objects.
+    pub fn has_bvh(&self) -> bool {
+        self.bvh.is_some()
+    }
+
+    // How long the last `build_bvh` took, while its tree is still in use.
+    pub fn bvh_build_time(&self) -> Option<Duration> {
+        self.bvh.as_ref().map(|_| self.bvh_build_time)
+    }
+
+    pub fn build_bvh(&mut self) {
+        let start = Stopwatch::start();
+        self.bvh = BvhNode::build(&self.hittables, &self.ids);
+        self.unbounded = self
+            .hittables
+            .iter()
+            .enumerate()
+            .filter(|(_, hittable)| hittable.bounding_box().is_none())
+            .map(|(index, _)| index)
+            .collect();
+        self.bvh_build_time = start.elapsed();
+        log::info!("built the BVH in {:.2?}", self.bvh_build_time);
+        if let Some(bvh) = self
+            .bvh
+            .as_ref()
+            .filter(|_| log::log_enabled!(log::Level::Debug))
+        {
+            let nodes = bvh.node_count();
+            log::debug!(
+                "BVH over {} objects, {} unbounded: {} nodes, depth {}, about {} KiB",
+                self.hittables.len() - self.unbounded.len(),
+                self.unbounded.len(),
+                nodes,
+                bvh.depth(),
+                nodes * std::mem::size_of::<BvhNode>() / 1024
+            );
+        }
     }
 }
 
 impl HitRecord {
     pub fn new(
         point: Point3,
-        outward_normal: Vec3,
-        material: std::rc::Rc<dyn Material>,
+        outward_normal: UnitVec3,
+        material: std::sync::Arc<dyn Material>,
         ray: &Ray,
         t: f64,
     ) -> Self {
-        let front_face = (ray.direction * outward_normal).is_sign_negative();
+        // A tangent ray only grazes the outside, so a zero dot product counts
+        // as the front face whatever its sign.
+        let front_face = ray.direction.dot(*outward_normal) <= 0.0;
         let normal = if front_face {
             outward_normal
         } else {
@@ -62,152 +544,2719 @@ impl HitRecord {
             material,
             t,
             front_face,
+            object: None,
+            u: 0.0,
+            v: 0.0,
+            footprint: None,
         }
     }
+
+    pub fn with_uv(mut self, u: f64, v: f64) -> Self {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    // Moves the origin of a ray scattered from this hit off the surface,
+    // along the normal to the side the ray leaves through. Rounding errors in
+    // the hit point grow with its coordinates and with the distance the
+    // incoming ray travelled, and so does the offset, so huge scenes don't
+    // hit the surface a ray starts on again and tiny ones can lower the ray
+    // epsilon without bringing that back. Being along the normal, it also
+    // clears rays leaving at grazing angles, which a distance along the ray
+    // doesn't.
+    pub fn leave(&self, incoming: &Ray, scattered: Ray) -> Ray {
+        let distance = self.t * incoming.direction.len();
+        let offset = ORIGIN_OFFSET * (self.point.abs().max_component() + distance);
+        let normal = if scattered.direction.dot(*self.normal) < 0.0 {
+            -*self.normal
+        } else {
+            *self.normal
+        };
+        Ray::new(scattered.origin + offset * normal, scattered.direction)
+    }
 }
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    material: std::rc::Rc<dyn Material>,
+    material: std::sync::Arc<dyn Material>,
+    cull_backfaces: bool,
+}
+
+// Two unit vectors at right angles to unit `w` and to each other.
+fn perpendicular_basis(w: Vec3) -> (Vec3, Vec3) {
+    let a = if w.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = w.cross_product(a).to_unit();
+    (w.cross_product(v), v)
+}
+
+// Shared by the primitives' `with_backface_culling`.
+fn backface_culling(cull: bool, material: &dyn Material) -> bool {
+    if cull && material.transmits() {
+        log::warn!(
+            "backface culling left off for an object made of {}, light goes through it",
+            material.name()
+        );
+        return false;
+    }
+    cull
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, material: std::rc::Rc<dyn Material>) -> Self {
+    pub fn new(center: Point3, radius: f64, material: std::sync::Arc<dyn Material>) -> Self {
         Sphere {
             center,
             radius,
             material,
+            cull_backfaces: false,
         }
     }
-}
 
-fn is_within_range(t: f64, t_bounds: (f64, f64)) -> bool {
-    t >= t_bounds.0 && t <= t_bounds.1
+    // Ignores hits on the inside, or on the outside of a sphere with a
+    // negative radius, which saves work for a camera that never sees them.
+    // Stays off for materials that transmit light, as they need both sides.
+    pub fn with_backface_culling(mut self, cull: bool) -> Self {
+        self.cull_backfaces = backface_culling(cull, self.material.as_ref());
+        self
+    }
+
+    pub fn culls_backfaces(&self) -> bool {
+        self.cull_backfaces
+    }
+
+    pub fn center(&self) -> Point3 {
+        self.center
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn set_center(&mut self, center: Point3) {
+        self.center = center;
+    }
+
+    pub fn material(&self) -> &std::sync::Arc<dyn Material> {
+        &self.material
+    }
+
+    // Whether a one-sided emitter only shows its back from inside, or from
+    // outside with a negative radius. Light from the inner side of the far
+    // half is then blocked by the near half anyway.
+    fn sees_back(&self, inside: bool) -> bool {
+        self.material.emits_one_sided() && inside == (self.radius > 0.0)
+    }
+
+    // Maps a point on the unit sphere around the origin to texture
+    // coordinates: u is the angle around the y axis, starting from -x, and v
+    // the angle from the -y pole, both scaled to [0, 1].
+    pub fn get_uv(point: Point3) -> (f64, f64) {
+        let theta = (-point.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-point.z()).atan2(point.x()) + std::f64::consts::PI;
+        (
+            phi / (2.0 * std::f64::consts::PI),
+            theta / std::f64::consts::PI,
+        )
+    }
 }
 
-impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, t_bounds: (f64, f64)) -> Option<HitRecord> {
+impl Sphere {
+    // `hit`, culling back faces or not.
+    fn intersect(&self, ray: &Ray, t_bounds: Interval, cull: bool) -> Option<HitRecord> {
         let origin_to_center = ray.origin - self.center;
         let a = ray.direction * ray.direction;
+        // A zero direction goes nowhere and hits nothing, rather than dividing
+        // by zero below; an infinite one would only produce NaN.
+        if a == 0.0 || !a.is_finite() {
+            return None;
+        }
         let half_b = origin_to_center * ray.direction;
         let c = origin_to_center * origin_to_center - self.radius * self.radius;
-        let discriminant = 4.0 * half_b * half_b - 4.0 * a * c;
-        if discriminant.is_sign_negative() {
-            None
-        } else {
-            let minus_b_to_a = -half_b / a;
-            let divided_discriminant = discriminant.sqrt() / (2.0 * a);
-            let t = minus_b_to_a - divided_discriminant;
-            if is_within_range(t, t_bounds) {
-                Some(HitRecord::new(
-                    ray.at(t),
-                    (ray.at(t) - self.center) / self.radius,
-                    std::rc::Rc::clone(&self.material),
-                    ray,
-                    t,
-                ))
+        // half_b^2 - a * c, but taken from the distance between the center and
+        // the ray's line, which doesn't cancel out when the sphere is large or
+        // far away compared to that distance.
+        let closest = origin_to_center - (half_b / a) * ray.direction;
+        let discriminant = a * (self.radius * self.radius - closest * closest);
+        // Exactly zero is a tangent ray touching the sphere in one point.
+        if discriminant < 0.0 || discriminant.is_nan() {
+            return None;
+        }
+        // The root that adds magnitudes comes from q, the other one from
+        // c / q, so neither subtracts two nearly equal numbers.
+        let q = -(half_b + discriminant.sqrt().copysign(half_b));
+        let (near, far) = {
+            let (t0, t1) = (c / q, q / a);
+            if t0 <= t1 {
+                (t0, t1)
             } else {
-                let t = minus_b_to_a + divided_discriminant;
-                if is_within_range(t, t_bounds) {
-                    Some(HitRecord::new(
-                        ray.at(t),
-                        (ray.at(t) - self.center) / self.radius,
-                        self.material.clone(),
-                        ray,
-                        t,
-                    ))
-                } else {
-                    None
-                }
+                (t1, t0)
             }
-        }
+        };
+        // The ray enters at the near root and leaves at the far one, which
+        // are the other way round for the normals of a negative radius.
+        let (near, far) = match (cull, self.radius > 0.0) {
+            (false, _) => (near, far),
+            (true, true) => (near, f64::NAN),
+            (true, false) => (far, f64::NAN),
+        };
+        let t = if t_bounds.contains(near) {
+            near
+        } else if t_bounds.contains(far) {
+            far
+        } else {
+            return None;
+        };
+        // Put the point back on the surface, so rounding in t doesn't start the
+        // next ray just below it.
+        let offset = ray.at(t) - self.center;
+        let normal = offset / offset.len();
+        let point = self.center + normal * self.radius.abs();
+        let (u, v) = Sphere::get_uv(normal);
+        Some(
+            HitRecord::new(
+                point,
+                UnitVec3::new_unchecked(normal * self.radius.signum()),
+                std::sync::Arc::clone(&self.material),
+                ray,
+                t,
+            )
+            .with_uv(u, v),
+        )
     }
 }
 
-impl Ray {
-    pub fn new(origin: Point3, direction: Vec3) -> Ray {
-        Ray { origin, direction }
+impl Hittable for Sphere {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        self.intersect(ray, t_bounds, self.cull_backfaces)
     }
 
-    pub fn at(&self, t: f64) -> Vec3 {
-        self.origin + self.direction * t
+    fn validate(&self, issues: &mut Issues) {
+        if !self.center.is_finite() {
+            issues.error(format!("center {} must be finite", self.center));
+        }
+        if !self.radius.is_finite() || self.radius == 0.0 {
+            issues.error(format!(
+                "radius {} must be finite and not zero",
+                self.radius
+            ));
+        }
+        self.material.validate(issues);
     }
 
-    pub fn color(&self, rng: &mut ThreadRng, scene: &Scene, depth: u32) -> Color {
-        if depth == 0 {
-            Color::new(0.0, 0.0, 0.0)
-        } else {
-            if let Some(record) = scene.hit(self, (0.001, f64::INFINITY)) {
-                if let Some(scatter_result) = record.material.scatter(&record, &self, rng) {
-                    let new_color = scatter_result.1.color(rng, scene, depth - 1);
-                    Vec3::new(
-                        scatter_result.0.data[0] * new_color.data[0],
-                        scatter_result.0.data[1] * new_color.data[1],
-                        scatter_result.0.data[2] * new_color.data[2],
-                    )
-                } else {
-                    Color::new(0.0, 0.0, 0.0)
-                }
-            } else {
-                let unit_direction = self.direction.to_unit();
-                let t = 0.5 * (unit_direction.data[1] + 1.0);
-                (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
-            }
+    fn contains(&self, point: Point3) -> Option<bool> {
+        let offset = point - self.center;
+        Some(offset * offset < self.radius * self.radius)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // A zero radius would give a flat box the slab test can't hit.
+        let radius = self.radius.abs().max(1e-7);
+        let radius = Vec3::new(radius, radius, radius);
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    // From outside, uniformly over the cone of directions the sphere covers,
+    // so none are wasted on its far side. From inside, every direction hits
+    // it, so a uniformly picked point on the surface will do.
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let to_center = self.center - origin;
+        let radius = self.radius.abs();
+        let distance_squared = to_center * to_center;
+        let inside = distance_squared <= radius * radius;
+        if self.sees_back(inside) {
+            return None;
+        }
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        if inside {
+            let z = 1.0 - 2.0 * rng.gen::<f64>();
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let on_sphere = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+            return Some(self.center + radius * on_sphere - origin);
+        }
+        let cos_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+        let z = 1.0 + rng.gen::<f64>() * (cos_max - 1.0);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let w = to_center / distance_squared.sqrt();
+        let (u, v) = perpendicular_basis(w);
+        Some(r * phi.cos() * u + r * phi.sin() * v + z * w)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let to_center = self.center - origin;
+        let radius = self.radius.abs();
+        let distance_squared = to_center * to_center;
+        let inside = distance_squared <= radius * radius;
+        if self.sees_back(inside) {
+            return 0.0;
+        }
+        // Culling only hides the back from the camera, while `random_toward`
+        // samples the whole surface.
+        let record = match self.intersect(
+            &Ray::new(origin, direction),
+            Interval::new(0.0, f64::INFINITY),
+            false,
+        ) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+        if inside {
+            // The area density, 1 / (4 pi r^2), turned into one over solid
+            // angle by the distance squared over the cosine at the surface.
+            let along = record.point - origin;
+            let cosine = (direction.to_unit() * *record.normal).abs();
+            let area = 4.0 * std::f64::consts::PI * radius * radius;
+            return (along * along) / (cosine * area);
         }
+        let cos_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+        1.0 / (2.0 * std::f64::consts::PI * (1.0 - cos_max))
     }
 }
 
-pub struct Camera {
-    origin: Point3,
-    lower_left: Point3,
-    horizontal: Vec3,
-    vertical: Vec3,
-    // w: Vec3,
+// A parallelogram with one corner at `corner` and sides `u` and `v`. The
+// outward normal is u x v.
+pub struct Quad {
+    corner: Point3,
     u: Vec3,
     v: Vec3,
-    lens_radius: f64,
+    normal: UnitVec3,
+    // The plane is normal . p = offset.
+    offset: f64,
+    // Turns a point in the plane into its coordinates along u and v.
+    w: Vec3,
+    material: std::sync::Arc<dyn Material>,
+    cull_backfaces: bool,
 }
 
-impl Camera {
-    pub fn new(
-        look_from: Point3,
-        look_at: Point3,
-        vector_up: Vec3,
-        fov: f64,
-        aspect_ratio: f64,
-        aperture: f64,
-        focus_distance: f64,
-    ) -> Self {
-        let h = (fov / 2.0).tan();
-        let viewport_height = h * 2.0;
-        let viewport_width = aspect_ratio * viewport_height;
-
-        let w = (look_from - look_at).to_unit();
-        let u = vector_up.cross_product(w).to_unit();
-        let v = w.cross_product(u);
-
-        let horizontal = focus_distance * viewport_width * u;
-        let vertical = focus_distance * viewport_height * v;
-        let lower_left = look_from - horizontal / 2.0 - vertical / 2.0 - focus_distance * w;
-        Camera {
-            origin: look_from,
-            lower_left,
-            horizontal,
-            vertical,
-            //    w,
+impl Quad {
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, material: std::sync::Arc<dyn Material>) -> Self {
+        let n = u.cross_product(v);
+        let normal = UnitVec3::or_nan(n);
+        Quad {
+            corner,
             u,
             v,
-            lens_radius: aperture / 2.0,
+            normal,
+            offset: normal.dot(corner),
+            w: n / n.dot(n),
+            material,
+            cull_backfaces: false,
         }
     }
 
-    pub fn create_ray(&self, rng: &mut ThreadRng, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
-        let offset = self.u * rd.data[0] + self.v * rd.data[1];
-        Ray::new(
-            self.origin + offset,
-            self.lower_left + s * self.horizontal + t * self.vertical - self.origin - offset,
+    // Ignores hits from the side the normal points away from; see
+    // `Sphere::with_backface_culling`.
+    pub fn with_backface_culling(mut self, cull: bool) -> Self {
+        self.cull_backfaces = backface_culling(cull, self.material.as_ref());
+        self
+    }
+
+    pub fn culls_backfaces(&self) -> bool {
+        self.cull_backfaces
+    }
+
+    // Whether `origin` is behind a one-sided emitter, or in its plane.
+    fn sees_back(&self, origin: Point3) -> bool {
+        self.material.emits_one_sided() && self.normal.dot(origin) <= self.offset
+    }
+
+    // The six faces of the parallelepiped spanned by `edges` from `corner`,
+    // with outward normals. Rotated boxes are just rotated edges.
+    pub fn cuboid(
+        corner: Point3,
+        edges: [Vec3; 3],
+        material: std::sync::Arc<dyn Material>,
+    ) -> Vec<Quad> {
+        let [mut a, mut b, c] = edges;
+        if a.cross_product(b).dot(c) < 0.0 {
+            std::mem::swap(&mut a, &mut b);
+        }
+        let quad = |corner, u, v| Quad::new(corner, u, v, std::sync::Arc::clone(&material));
+        vec![
+            quad(corner, b, a),
+            quad(corner + c, a, b),
+            quad(corner, c, b),
+            quad(corner + a, b, c),
+            quad(corner, a, c),
+            quad(corner + b, c, a),
+        ]
+    }
+}
+
+impl Quad {
+    // `hit`, culling back faces or not.
+    fn intersect(&self, ray: &Ray, t_bounds: Interval, cull: bool) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        // Parallel to the plane.
+        if denominator.abs() < 1e-8 || (cull && denominator > 0.0) {
+            return None;
+        }
+        let t = (self.offset - self.normal.dot(ray.origin)) / denominator;
+        if !t_bounds.contains(t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let planar = point - self.corner;
+        let alpha = self.w.dot(planar.cross_product(self.v));
+        let beta = self.w.dot(self.u.cross_product(planar));
+        let unit = Interval::new(0.0, 1.0);
+        if !(unit.contains(alpha) && unit.contains(beta)) {
+            return None;
+        }
+        Some(
+            HitRecord::new(
+                point,
+                self.normal,
+                std::sync::Arc::clone(&self.material),
+                ray,
+                t,
+            )
+            .with_uv(alpha, beta),
         )
     }
 }
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        self.intersect(ray, t_bounds, self.cull_backfaces)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        if !(self.corner.is_finite() && self.u.is_finite() && self.v.is_finite()) {
+            issues.error("corner and edges must be finite");
+        } else if !self.normal.is_finite() {
+            issues.error(format!("edges {} and {} must span an area", self.u, self.v));
+        }
+        self.material.validate(issues);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = [
+            self.corner + self.u,
+            self.corner + self.v,
+            self.corner + self.u + self.v,
+        ];
+        let (mut min, mut max) = (self.corner, self.corner);
+        for &corner in &corners {
+            min = min.min(corner);
+            max = max.max(corner);
+        }
+        // Axis-aligned quads are flat; pad them so the slab test can hit them.
+        for axis in 0..3 {
+            if max[axis] - min[axis] < 1e-4 {
+                min[axis] -= 5e-5;
+                max[axis] += 5e-5;
+            }
+        }
+        Some(Aabb::new(min, max))
+    }
+
+    // Uniformly over the area, which is as good as it gets for a quad that is
+    // small or far away.
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        if self.sees_back(origin) {
+            return None;
+        }
+        let point = self.corner + rng.gen::<f64>() * self.u + rng.gen::<f64>() * self.v;
+        Some(point - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.sees_back(origin) {
+            return 0.0;
+        }
+        // See `Sphere::pdf_value`.
+        let record = match self.intersect(
+            &Ray::new(origin, direction),
+            Interval::new(0.0, f64::INFINITY),
+            false,
+        ) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+        let distance = record.t * direction.len();
+        let cosine = (self.normal.dot(direction) / direction.len()).abs();
+        let area = self.u.cross_product(self.v).len();
+        distance * distance / (cosine * area)
+    }
+}
+
+// A round disk around `center`. The outward normal is `normal`.
+pub struct Disk {
+    center: Point3,
+    normal: UnitVec3,
+    radius: f64,
+    // Along the plane, for the texture angle.
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: std::sync::Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(
+        center: Point3,
+        normal: Vec3,
+        radius: f64,
+        material: std::sync::Arc<dyn Material>,
+    ) -> Self {
+        let normal = UnitVec3::or_nan(normal);
+        let (tangent, bitangent) = perpendicular_basis(*normal);
+        Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+
+    // See `Quad::sees_back`.
+    fn sees_back(&self, origin: Point3) -> bool {
+        self.material.emits_one_sided() && self.normal.dot(origin - self.center) <= 0.0
+    }
+}
+
+impl Hittable for Disk {
+    // u is the angle around the normal, v the distance from the center, both
+    // scaled to [0, 1].
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        let denominator = self.normal.dot(ray.direction);
+        // Parallel to the plane.
+        if denominator.abs() < 1e-8 {
+            return None;
+        }
+        let t = self.normal.dot(self.center - ray.origin) / denominator;
+        if !t_bounds.contains(t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let planar = point - self.center;
+        let distance = planar.len();
+        if distance > self.radius {
+            return None;
+        }
+        let angle = planar.dot(self.bitangent).atan2(planar.dot(self.tangent));
+        Some(
+            HitRecord::new(
+                point,
+                self.normal,
+                std::sync::Arc::clone(&self.material),
+                ray,
+                t,
+            )
+            .with_uv(
+                angle / (2.0 * std::f64::consts::PI) + 0.5,
+                distance / self.radius,
+            ),
+        )
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        if !self.center.is_finite() {
+            issues.error(format!("center {} must be finite", self.center));
+        }
+        if !self.normal.is_finite() {
+            issues.error("normal must be finite and not zero");
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            issues.error(format!(
+                "radius {} must be finite and positive",
+                self.radius
+            ));
+        }
+        self.material.validate(issues);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The disk reaches sqrt(1 - n_i^2) of its radius along each axis; the
+        // padding keeps axis-aligned ones from being flat, as for `Quad`.
+        let mut extent = Vec3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let normal = self.normal[axis];
+            extent[axis] = self.radius * (1.0 - normal * normal).max(0.0).sqrt() + 5e-5;
+        }
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+
+    // Uniformly over the area, like `Quad`.
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        if self.sees_back(origin) {
+            return None;
+        }
+        let r = self.radius * rng.gen::<f64>().sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let point = self.center + r * phi.cos() * self.tangent + r * phi.sin() * self.bitangent;
+        Some(point - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.sees_back(origin) {
+            return 0.0;
+        }
+        let record = match self.hit(
+            &Ray::new(origin, direction),
+            Interval::new(0.0, f64::INFINITY),
+        ) {
+            Some(record) => record,
+            None => return 0.0,
+        };
+        let distance = record.t * direction.len();
+        let cosine = (self.normal.dot(direction) / direction.len()).abs();
+        let area = std::f64::consts::PI * self.radius * self.radius;
+        distance * distance / (cosine * area)
+    }
+}
+
+// An open tube of radius `radius` around the segment from `base` to
+// `base + axis`, for tube lights and the like; cap it with `Disk`s to close
+// it. The outward normal points away from the axis.
+pub struct Cylinder {
+    base: Point3,
+    axis: UnitVec3,
+    height: f64,
+    radius: f64,
+    // Across the axis, for the texture angle and for sampling.
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: std::sync::Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(
+        base: Point3,
+        axis: Vec3,
+        radius: f64,
+        material: std::sync::Arc<dyn Material>,
+    ) -> Self {
+        let height = axis.len();
+        let axis = UnitVec3::or_nan(axis);
+        let (tangent, bitangent) = perpendicular_basis(*axis);
+        Cylinder {
+            base,
+            axis,
+            height,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+
+    // Where a ray with the given origin and direction crosses the infinite
+    // tube, nearest first; the caller checks the height.
+    fn crossings(&self, origin: Point3, direction: Vec3) -> Option<(f64, f64)> {
+        let offset = origin - self.base;
+        // Both taken across the axis, where the tube is a circle.
+        let offset = offset - self.axis.dot(offset) * *self.axis;
+        let across = direction - self.axis.dot(direction) * *self.axis;
+        let a = across.dot(across);
+        // Along the axis, or a zero direction: no crossings worth having.
+        if a <= 1e-16 * direction.dot(direction) || !a.is_finite() {
+            return None;
+        }
+        let half_b = offset.dot(across);
+        let c = offset.dot(offset) - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 || discriminant.is_nan() {
+            return None;
+        }
+        let root = discriminant.sqrt();
+        Some(((-half_b - root) / a, (-half_b + root) / a))
+    }
+
+    // The height along the axis of a point, and the outward normal there.
+    fn surface_at(&self, point: Point3) -> (f64, UnitVec3) {
+        let offset = point - self.base;
+        let height = self.axis.dot(offset);
+        let radial = offset - height * *self.axis;
+        (height, UnitVec3::new_unchecked(radial / radial.len()))
+    }
+
+    fn area(&self) -> f64 {
+        2.0 * std::f64::consts::PI * self.radius * self.height
+    }
+}
+
+impl Hittable for Cylinder {
+    // u is the angle around the axis, v the height, both scaled to [0, 1].
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        let (near, far) = self.crossings(ray.origin, ray.direction)?;
+        for &t in &[near, far] {
+            if !t_bounds.contains(t) {
+                continue;
+            }
+            let point = ray.at(t);
+            let (height, normal) = self.surface_at(point);
+            if !(0.0..=self.height).contains(&height) {
+                continue;
+            }
+            let angle = normal.dot(self.bitangent).atan2(normal.dot(self.tangent));
+            return Some(
+                HitRecord::new(point, normal, std::sync::Arc::clone(&self.material), ray, t)
+                    .with_uv(
+                        angle / (2.0 * std::f64::consts::PI) + 0.5,
+                        height / self.height,
+                    ),
+            );
+        }
+        None
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        if !self.base.is_finite() {
+            issues.error(format!("base {} must be finite", self.base));
+        }
+        if !(self.axis.is_finite() && self.height.is_finite()) {
+            issues.error("axis must be finite and not zero");
+        }
+        if !(self.radius.is_finite() && self.radius > 0.0) {
+            issues.error(format!(
+                "radius {} must be finite and positive",
+                self.radius
+            ));
+        }
+        self.material.validate(issues);
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // Each end is a circle, which reaches sqrt(1 - a_i^2) of the radius
+        // along each axis.
+        let top = self.base + self.height * *self.axis;
+        let mut extent = Vec3::new(0.0, 0.0, 0.0);
+        for axis in 0..3 {
+            let along = self.axis[axis];
+            extent[axis] = self.radius * (1.0 - along * along).max(0.0).sqrt() + 5e-5;
+        }
+        Some(Aabb::new(
+            self.base.min(top) - extent,
+            self.base.max(top) + extent,
+        ))
+    }
+
+    // Uniformly over the area, like `Quad`. A ray can cross the tube twice,
+    // so the density adds up both crossings it could have been sampled at.
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        let height = self.height * rng.gen::<f64>();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let normal = phi.cos() * self.tangent + phi.sin() * self.bitangent;
+        let point = self.base + height * *self.axis + self.radius * normal;
+        if self.material.emits_one_sided() && normal.dot(origin - point) <= 0.0 {
+            return None;
+        }
+        Some(point - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let (near, far) = match self.crossings(origin, direction) {
+            Some(crossings) => crossings,
+            None => return 0.0,
+        };
+        let mut density = 0.0;
+        for &t in &[near, far] {
+            if t <= 0.0 {
+                continue;
+            }
+            let point = origin + t * direction;
+            let (height, normal) = self.surface_at(point);
+            let facing = normal.dot(direction);
+            if !(0.0..=self.height).contains(&height)
+                || (self.material.emits_one_sided() && facing >= 0.0)
+            {
+                continue;
+            }
+            let distance = t * direction.len();
+            let cosine = (facing / direction.len()).abs();
+            density += distance * distance / (cosine * self.area());
+        }
+        density
+    }
+}
+
+// Turns an object inside out, e.g. to render a room from within a sphere.
+// Hit records keep the normal facing the ray, so flipping the outward normal
+// only swaps which side counts as the front; a glass sphere turned inside out
+// behaves like an air bubble.
+pub struct FlipNormals {
+    pub object: Box<dyn Hittable>,
+}
+
+impl FlipNormals {
+    pub fn new(object: Box<dyn Hittable>) -> Self {
+        FlipNormals { object }
+    }
+}
+
+impl Hittable for FlipNormals {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        let mut record = self.object.hit(ray, t_bounds)?;
+        record.front_face = !record.front_face;
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    // Still goes by the object's own sides, so a one-sided emitter turned
+    // inside out is sampled on the side that no longer emits; build it facing
+    // the other way instead.
+    fn random_toward(&self, origin: Point3, rng: &mut dyn RngCore) -> Option<Vec3> {
+        self.object.random_toward(origin, rng)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        self.object.validate(issues)
+    }
+
+    fn contains(&self, point: Point3) -> Option<bool> {
+        self.object.contains(point)
+    }
+}
+
+// Places an object with an affine transform. Rays are moved into the object's
+// space rather than the object into world space, so any Hittable can be
+// instanced without knowing about it. The ray direction isn't renormalized,
+// which keeps `t` the same in both spaces.
+pub struct Transformed {
+    object: Box<dyn Hittable>,
+    transform: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+}
+
+impl Transformed {
+    pub fn new(object: Box<dyn Hittable>, transform: Mat4) -> Result<Self, SingularMatrix> {
+        let inverse = transform.inverse()?;
+        Ok(Transformed {
+            object,
+            transform,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        })
+    }
+
+    // Rotations always have an inverse.
+    pub fn rotated(object: Box<dyn Hittable>, rotation: Quat) -> Self {
+        let rotation = rotation.normalized();
+        let transform = rotation.to_mat4();
+        let inverse = rotation.conjugate().to_mat4();
+        Transformed {
+            object,
+            transform,
+            inverse,
+            // The inverse transpose of a rotation is the rotation itself.
+            normal_matrix: transform,
+        }
+    }
+
+    pub fn transform(&self) -> &Mat4 {
+        &self.transform
+    }
+}
+
+impl Hittable for Transformed {
+    fn hit(&self, ray: &Ray, t_bounds: Interval) -> Option<HitRecord> {
+        let local = Ray::new(
+            self.inverse.transform_point(ray.origin),
+            self.inverse.transform_vector(ray.direction),
+        );
+        let mut record = self.object.hit(&local, t_bounds)?;
+        record.point = self.transform.transform_point(record.point);
+        // The inverse transpose preserves the sign of the dot product with the
+        // ray direction, so the normal keeps facing the ray.
+        record.normal = self
+            .normal_matrix
+            .transform_vector(record.normal.get())
+            .normalized();
+        Some(record)
+    }
+
+    fn validate(&self, issues: &mut Issues) {
+        let finite = (0..3).all(|axis| {
+            let mut unit = [0.0; 3];
+            unit[axis] = 1.0;
+            self.transform
+                .transform_vector(Vec3::from(unit))
+                .is_finite()
+        });
+        if !(finite
+            && self
+                .transform
+                .transform_point(Vec3::new(0.0, 0.0, 0.0))
+                .is_finite())
+        {
+            issues.error("the transform must be finite");
+        }
+        self.object.validate(issues);
+    }
+
+    fn contains(&self, point: Point3) -> Option<bool> {
+        self.object.contains(self.inverse.transform_point(point))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.object.bounding_box()?;
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = -min;
+        for corner in local.corners().iter() {
+            let point = self.transform.transform_point(*corner);
+            min = min.min(point);
+            max = max.max(point);
+        }
+        Some(Aabb::new(min, max))
+    }
+}
+
+impl fmt::Debug for Ray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Ray({} \u{2192} {})", self.origin, self.direction)
+    }
+}
+
+impl fmt::Debug for HitRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HitRecord")
+            .field("point", &self.point)
+            .field("normal", &self.normal)
+            .field("t", &self.t)
+            .field("front_face", &self.front_face)
+            .field("object", &self.object)
+            .field("uv", &(self.u, self.v))
+            .field("material", &self.material.name())
+            .finish()
+    }
+}
+
+// Traces a single path through the scene like `Ray::color` does and writes
+// every bounce to `out`.
+pub fn dump_ray_path(
+    ray: &Ray,
+    scene: &Scene,
+    max_depth: u32,
+    rng: &mut dyn RngCore,
+    out: &mut dyn io::Write,
+) -> io::Result<()> {
+    let mut recorder = PathRecorder::new();
+    ray.color_observed(rng, scene, max_depth, DEFAULT_RAY_EPSILON, &mut recorder);
+    for bounce in &recorder.bounces {
+        writeln!(out, "{}", bounce)?;
+    }
+    Ok(())
+}
+
+impl Ray {
+    // A zero direction makes every intersection test divide by zero.
+    pub fn new(origin: Point3, direction: Vec3) -> Ray {
+        debug_assert!(!direction.near_zero(), "Ray direction must not be zero");
+        Ray { origin, direction }
+    }
+
+    pub fn new_normalized(origin: Point3, direction: Vec3) -> Ray {
+        debug_assert!(!direction.near_zero(), "Ray direction must not be zero");
+        Ray::new(origin, direction.to_unit())
+    }
+
+    pub fn at(&self, t: f64) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    // Traces with DEFAULT_RAY_EPSILON.
+    pub fn color(&self, rng: &mut dyn RngCore, scene: &Scene, depth: u32) -> Color {
+        let mut info = PathInfo::default();
+        self.trace(
+            rng,
+            scene,
+            depth,
+            DEFAULT_RAY_EPSILON,
+            None,
+            PathOptions::default(),
+            0,
+            &mut info,
+            &mut (),
+        )
+    }
+
+    // Like `color`, and tells `observer` about every bounce. Draws the same
+    // random numbers, so it retraces exactly what `color_with_info` computes
+    // with the same `ray_epsilon`.
+    pub fn color_observed<O: PathObserver>(
+        &self,
+        rng: &mut dyn RngCore,
+        scene: &Scene,
+        depth: u32,
+        ray_epsilon: f64,
+        observer: &mut O,
+    ) -> Color {
+        let mut info = PathInfo::default();
+        self.trace(
+            rng,
+            scene,
+            depth,
+            ray_epsilon,
+            None,
+            PathOptions::default(),
+            0,
+            &mut info,
+            observer,
+        )
+    }
+
+    // Like `color`, but also reports what the path hit first and the first
+    // bounce at which it picked up a NaN or infinite value. Hits closer than
+    // `ray_epsilon` along a ray are ignored.
+    pub fn color_with_info(
+        &self,
+        rng: &mut dyn RngCore,
+        scene: &Scene,
+        depth: u32,
+        ray_epsilon: f64,
+    ) -> (Color, PathInfo) {
+        let mut info = PathInfo::default();
+        let color = self.trace(
+            rng,
+            scene,
+            depth,
+            ray_epsilon,
+            None,
+            PathOptions::default(),
+            0,
+            &mut info,
+            &mut (),
+        );
+        (color, info)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn trace<O: PathObserver>(
+        &self,
+        rng: &mut dyn RngCore,
+        scene: &Scene,
+        depth: u32,
+        ray_epsilon: f64,
+        differentials: Option<&RayDifferentials>,
+        options: PathOptions<'_>,
+        bounce: u32,
+        info: &mut PathInfo,
+        observer: &mut O,
+    ) -> Color {
+        if depth == 0 {
+            observer.depth_limit(bounce, self);
+            Color::new(0.0, 0.0, 0.0)
+        } else {
+            info.rays += 1;
+            let bounds = Interval::new(ray_epsilon, f64::INFINITY);
+            if let Some(mut record) = scene.hit(self, bounds) {
+                if let Some(material_override) = options.material_override {
+                    material_override.apply(&mut record);
+                }
+                if let Some(differentials) =
+                    differentials.filter(|_| record.material.uses_footprint())
+                {
+                    record.footprint = differentials
+                        .uv_footprint(scene, self, &record, bounds)
+                        .map(Box::new);
+                }
+                let specular = record.material.is_perfectly_specular();
+                stats::count_scatter(&*record.material);
+                let scatter_result = record.material.scatter(&record, self, rng).map(
+                    |(attenuation, mut scattered)| {
+                        match (options.regularization, info.first_diffuse) {
+                            (Some(regularization), Some(diffuse)) if specular => {
+                                scattered.direction = regularization.roughen(
+                                    rng,
+                                    scattered.direction,
+                                    *record.normal,
+                                    bounce - diffuse - 1,
+                                );
+                            }
+                            _ => {}
+                        }
+                        (attenuation, record.leave(self, scattered))
+                    },
+                );
+                if scatter_result.is_some() && !specular && info.first_diffuse.is_none() {
+                    info.first_diffuse = Some(bounce);
+                }
+                if bounce == 0 {
+                    info.first_hit = Some(FirstHit {
+                        normal: record.normal.get(),
+                        albedo: scatter_result
+                            .as_ref()
+                            .map_or(Color::new(0.0, 0.0, 0.0), |(attenuation, _)| *attenuation),
+                        distance: record.t * self.direction.len(),
+                        object: record.object,
+                    });
+                }
+                if info.non_finite.is_none() {
+                    let finite = record.point.is_finite()
+                        && record.normal.is_finite()
+                        && scatter_result.as_ref().is_none_or(|(attenuation, ray)| {
+                            attenuation.is_finite() && ray.direction.is_finite()
+                        });
+                    if !finite {
+                        info.non_finite = Some(NonFiniteBounce {
+                            bounce,
+                            material: record.material.name(),
+                        });
+                    }
+                }
+                let emitted = record.material.emitted(&record, self);
+                observer.hit(bounce, self, &record, emitted, scatter_result.as_ref());
+                if let Some((attenuation, scattered)) = scatter_result {
+                    let new_color = scattered.trace(
+                        rng,
+                        scene,
+                        depth - 1,
+                        ray_epsilon,
+                        None,
+                        options,
+                        bounce + 1,
+                        info,
+                        observer,
+                    );
+                    emitted + attenuation * new_color
+                } else {
+                    emitted
+                }
+            } else {
+                let background = scene.background.sample(self.direction);
+                observer.escaped(bounce, self, background);
+                background
+            }
+        }
+    }
+}
+
+impl CameraRay {
+    // `Ray::color_observed`, with texture footprints at the first hit and
+    // `options`.
+    pub fn color_observed<O: PathObserver>(
+        &self,
+        rng: &mut dyn RngCore,
+        scene: &Scene,
+        depth: u32,
+        ray_epsilon: f64,
+        options: PathOptions<'_>,
+        observer: &mut O,
+    ) -> Color {
+        let mut info = PathInfo::default();
+        let differentials = Some(&self.differentials);
+        self.ray.trace(
+            rng,
+            scene,
+            depth,
+            ray_epsilon,
+            differentials,
+            options,
+            0,
+            &mut info,
+            observer,
+        ) * self.weight
+    }
+
+    // `Ray::color_with_info`, with texture footprints at the first hit and
+    // `options`.
+    pub fn color_with_info(
+        &self,
+        rng: &mut dyn RngCore,
+        scene: &Scene,
+        depth: u32,
+        ray_epsilon: f64,
+        options: PathOptions<'_>,
+    ) -> (Color, PathInfo) {
+        let mut info = PathInfo::default();
+        let differentials = Some(&self.differentials);
+        let color = self.ray.trace(
+            rng,
+            scene,
+            depth,
+            ray_epsilon,
+            differentials,
+            options,
+            0,
+            &mut info,
+            &mut (),
+        );
+        (color * self.weight, info)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FirstHit {
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub distance: f64,
+    // None for hits that didn't go through a `Scene`.
+    pub object: Option<ObjectId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NonFiniteBounce {
+    pub bounce: u32,
+    pub material: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathInfo {
+    pub first_hit: Option<FirstHit>,
+    pub non_finite: Option<NonFiniteBounce>,
+    // Rays traced along the path, the camera ray included.
+    pub rays: u32,
+    // The bounce at which the path first scattered off a surface that isn't
+    // perfectly specular.
+    pub first_diffuse: Option<u32>,
+}
+
+// What camera paths do differently from plain path tracing. The default
+// changes nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathOptions<'a> {
+    pub regularization: Option<&'a PathRegularization>,
+    pub material_override: Option<&'a MaterialOverride>,
+}
+
+// Treats perfectly specular surfaces as slightly rough once a path has
+// bounced off a diffuse one, which blurs light focused through glass or
+// mirrors onto diffuse surfaces. That is what lets an integrator that samples
+// lights directly reach caustics; while paths only find lights by chance it
+// changes their noise little. A specular bounce n bounces after the first
+// diffuse one gets `roughness + growth * (n - 1)`, in units of the fuzz of
+// `Reflector`. Biased, but only on paths that direct reflections and
+// refractions never take.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathRegularization {
+    pub roughness: f64,
+    pub growth: f64,
+}
+
+impl PathRegularization {
+    // Nudges `direction` by up to `roughness`, keeping it on its side of
+    // `normal`.
+    fn roughen(&self, rng: &mut dyn RngCore, direction: Vec3, normal: Vec3, after: u32) -> Vec3 {
+        let roughness = self.roughness + self.growth * after as f64;
+        let rough = direction.to_unit() + roughness * Vec3::random_in_unit_sphere(rng);
+        if rough.dot(normal) * direction.dot(normal) > 0.0 {
+            rough
+        } else {
+            direction
+        }
+    }
+}
+
+// Brown-Conrady radial (k1, k2) and tangential (p1, p2) lens distortion, with
+// the coefficients as camera calibration tools such as OpenCV report them:
+// in normalized image coordinates, one unit per focal length, with y pointing
+// down. In that convention k1 > 0 is pincushion and k1 < 0 barrel.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct LensDistortion {
+    pub k1: f64,
+    pub k2: f64,
+    pub p1: f64,
+    pub p2: f64,
+}
+
+impl LensDistortion {
+    pub fn is_none(&self) -> bool {
+        *self == LensDistortion::default()
+    }
+
+    // Where the lens images the point `x`, `y`.
+    pub fn distort(&self, x: f64, y: f64) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * self.k2);
+        (
+            x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x),
+            y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y,
+        )
+    }
+
+    // The point the lens images at `x`, `y`, by fixed-point iteration, which
+    // converges for the distortion of real lenses.
+    pub fn undistort(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut ux, mut uy) = (x, y);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort(ux, uy);
+            ux -= dx - x;
+            uy -= dy - y;
+        }
+        (ux, uy)
+    }
+}
+
+// The parameters a camera was built from; `fov` is the vertical field of view
+// in radians.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CameraConfig {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vector_up: Vec3,
+    pub fov: f64,
+    pub aspect_ratio: f64,
+    pub aperture: f64,
+    pub focus_distance: f64,
+    // See `Camera::with_chromatic_aberration`.
+    pub chromatic_aberration: f64,
+    pub distortion: LensDistortion,
+}
+
+pub struct Camera {
+    config: CameraConfig,
+    origin: Point3,
+    lower_left: Point3,
+    horizontal: Vec3,
+    vertical: Vec3,
+    u: UnitVec3,
+    v: UnitVec3,
+    lens_radius: f64,
+}
+
+impl Camera {
+    pub fn new(
+        look_from: Point3,
+        look_at: Point3,
+        vector_up: Vec3,
+        fov: f64,
+        aspect_ratio: f64,
+        aperture: f64,
+        focus_distance: f64,
+    ) -> Self {
+        let h = (fov / 2.0).tan();
+        let viewport_height = h * 2.0;
+        let viewport_width = aspect_ratio * viewport_height;
+
+        // `validate` catches a degenerate basis.
+        let w = UnitVec3::or_nan(look_from - look_at);
+        let u = UnitVec3::or_nan(vector_up.cross_product(*w));
+        let v = UnitVec3::new_unchecked(w.cross_product(*u));
+
+        let horizontal = focus_distance * viewport_width * *u;
+        let vertical = focus_distance * viewport_height * *v;
+        let lower_left = look_from - horizontal / 2.0 - vertical / 2.0 - focus_distance * *w;
+        Camera {
+            config: CameraConfig {
+                look_from,
+                look_at,
+                vector_up,
+                fov,
+                aspect_ratio,
+                aperture,
+                focus_distance,
+                chromatic_aberration: 0.0,
+                distortion: LensDistortion::default(),
+            },
+            origin: look_from,
+            lower_left,
+            horizontal,
+            vertical,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
+        }
+    }
+
+    // Looks at the center of `bounds` from along `direction`, which points
+    // from the scene towards the camera, close enough that every corner of
+    // the box is in view. `margin` is extra room around the box as a fraction
+    // of its projected size, e.g. 0.1 for 10%. Focuses on the center, with no
+    // depth of field.
+    pub fn framing(
+        bounds: &Aabb,
+        direction: Vec3,
+        vector_up: Vec3,
+        fov: f64,
+        aspect_ratio: f64,
+        margin: f64,
+    ) -> Camera {
+        let center = bounds.centroid();
+        // A zero direction looks at the box from +z.
+        let w = direction.to_unit_or(Vec3::new(0.0, 0.0, 1.0));
+        // Looking straight along `vector_up` leaves the roll undefined; any
+        // perpendicular up vector will do.
+        let vector_up = if !vector_up.cross_product(w).near_zero() {
+            vector_up
+        } else if w.x().abs() < 0.9 {
+            Vec3::new(1.0, 0.0, 0.0)
+        } else {
+            Vec3::new(0.0, 1.0, 0.0)
+        };
+        let u = vector_up.cross_product(w).to_unit();
+        let v = w.cross_product(u);
+
+        let tan_vertical = (fov / 2.0).tan() / (1.0 + margin.max(0.0));
+        let tan_horizontal = tan_vertical * aspect_ratio;
+        // A corner at depth z towards the camera and x, y across the view is
+        // inside the frustum once the camera is at least z + |x| / tan away.
+        let size = (bounds.max - bounds.min).len();
+        let mut distance: f64 = 0.0;
+        let mut nearest = f64::NEG_INFINITY;
+        for corner in bounds.corners().iter() {
+            let offset = *corner - center;
+            let z = offset.dot(w);
+            distance = distance
+                .max(z + offset.dot(u).abs() / tan_horizontal)
+                .max(z + offset.dot(v).abs() / tan_vertical);
+            nearest = nearest.max(z);
+        }
+        // Flat boxes seen edge-on put corners on the view axis, and a box
+        // shrunk to a point has no size at all; keep the camera clear of both.
+        let clearance = if size > 0.0 { size * 0.01 } else { 1.0 };
+        let distance = distance.max(nearest + clearance);
+        Camera::new(
+            center + distance * w,
+            center,
+            vector_up,
+            fov,
+            aspect_ratio,
+            0.0,
+            distance,
+        )
+    }
+
+    pub fn config(&self) -> &CameraConfig {
+        &self.config
+    }
+
+    // Makes the lens bend red, green and blue differently: each sample sees
+    // one channel, whose image is scaled about the center by 1 - `strength`,
+    // 1 and 1 + `strength` respectively and whose focus distance by the
+    // inverse, the way a lens focuses blue nearer than red. Small values,
+    // around 0.005, give colored fringes towards the frame edges and around
+    // out of focus highlights. Zero turns it off.
+    pub fn with_chromatic_aberration(mut self, strength: f64) -> Camera {
+        self.config.chromatic_aberration = strength;
+        self
+    }
+
+    // Renders the image the way a lens with `distortion` would.
+    pub fn with_distortion(mut self, distortion: LensDistortion) -> Camera {
+        self.config.distortion = distortion;
+        self
+    }
+
+    // The same camera with a different image shape; the vertical fov stays.
+    pub fn with_aspect_ratio(&self, aspect_ratio: f64) -> Camera {
+        let config = &self.config;
+        Camera::new(
+            config.look_from,
+            config.look_at,
+            config.vector_up,
+            config.fov,
+            aspect_ratio,
+            config.aperture,
+            config.focus_distance,
+        )
+        .with_chromatic_aberration(config.chromatic_aberration)
+        .with_distortion(config.distortion)
+    }
+
+    // Catches the setups that leave the camera basis degenerate or NaN.
+    pub fn validate(&self) -> Result<(), Error> {
+        let config = &self.config;
+        let invalid = |message: String| Err(Error::InvalidCamera(message));
+        if !(config.look_from.is_finite() && config.look_at.is_finite()) {
+            return invalid("look_from and look_at must be finite".to_string());
+        }
+        if (config.look_from - config.look_at).near_zero() {
+            return invalid("look_from and look_at are the same point".to_string());
+        }
+        if !(config.fov > 0.0 && config.fov < std::f64::consts::PI) {
+            return invalid(format!(
+                "fov of {} degrees is not between 0 and 180",
+                config.fov.to_degrees()
+            ));
+        }
+        if !(config.aspect_ratio > 0.0 && config.aspect_ratio.is_finite()) {
+            return invalid(format!(
+                "aspect ratio {} is not positive",
+                config.aspect_ratio
+            ));
+        }
+        if !(config.aperture >= 0.0 && config.aperture.is_finite()) {
+            return invalid(format!("aperture {} is negative", config.aperture));
+        }
+        if !(config.focus_distance > 0.0 && config.focus_distance.is_finite()) {
+            return invalid(format!(
+                "focus distance {} is not positive",
+                config.focus_distance
+            ));
+        }
+        if !(config.chromatic_aberration > -1.0 && config.chromatic_aberration < 1.0) {
+            return invalid(format!(
+                "chromatic aberration {} is not between -1 and 1",
+                config.chromatic_aberration
+            ));
+        }
+        let distortion = config.distortion;
+        if ![distortion.k1, distortion.k2, distortion.p1, distortion.p2]
+            .iter()
+            .all(|coefficient| coefficient.is_finite())
+        {
+            return invalid("lens distortion coefficients must be finite".to_string());
+        }
+        if !(self.u.is_finite() && self.v.is_finite()) {
+            return invalid("vector_up is parallel to the viewing direction".to_string());
+        }
+        Ok(())
+    }
+
+    // A ray through the center of the lens, ignoring depth of field.
+    pub fn create_center_ray(&self, s: f64, t: f64) -> Ray {
+        Ray::new(self.origin, self.focus_point(s, t) - self.origin)
+    }
+
+    // The point on the focus plane imaged at `s`, `t`.
+    fn focus_point(&self, s: f64, t: f64) -> Point3 {
+        let (s, t) = if self.config.distortion.is_none() {
+            (s, t)
+        } else {
+            let height = 2.0 * (self.config.fov / 2.0).tan();
+            let width = self.config.aspect_ratio * height;
+            let (x, y) = self
+                .config
+                .distortion
+                .undistort((s - 0.5) * width, (0.5 - t) * height);
+            (x / width + 0.5, 0.5 - y / height)
+        };
+        self.lower_left + s * self.horizontal + t * self.vertical
+    }
+
+    // Draws the lens sample from `rng` only when there is a lens to sample,
+    // so a pinhole camera leaves the random stream to the path after it.
+    pub fn create_ray<R: Rng + ?Sized>(&self, rng: &mut R, s: f64, t: f64) -> Ray {
+        self.create_dispersed_ray(rng, s, t, 0.0)
+    }
+
+    // `create_ray` for a channel whose image is scaled by 1 + `shift` and
+    // whose focus distance by 1 - `shift`.
+    fn create_dispersed_ray<R: Rng + ?Sized>(
+        &self,
+        rng: &mut R,
+        s: f64,
+        t: f64,
+        shift: f64,
+    ) -> Ray {
+        let (s, t) = if shift == 0.0 {
+            (s, t)
+        } else {
+            (
+                0.5 + (s - 0.5) * (1.0 + shift),
+                0.5 + (t - 0.5) * (1.0 + shift),
+            )
+        };
+        if self.lens_radius == 0.0 {
+            return self.create_center_ray(s, t);
+        }
+        let rd = self.lens_radius * Vec3::random_in_unit_disk(rng);
+        let offset = *self.u * rd.x() + *self.v * rd.y();
+        let mut direction = self.focus_point(s, t) - self.origin;
+        if shift != 0.0 {
+            direction *= 1.0 - shift;
+        }
+        let direction = direction - offset;
+        // Only possible when the focus plane passes through the lens; fall
+        // back to the pinhole ray rather than handing out a zero direction.
+        if direction.near_zero() {
+            return self.create_center_ray(s, t);
+        }
+        Ray::new(self.origin + offset, direction)
+    }
+
+    // Pixel coordinates count from the lower left corner; the sample position
+    // is jittered uniformly inside the pixel. The differentials are one pixel
+    // wide.
+    pub fn create_jittered_ray<R: Rng + ?Sized>(
+        &self,
+        px: u32,
+        py: u32,
+        width: u32,
+        height: u32,
+        rng: &mut R,
+    ) -> CameraRay {
+        let s = (px as f64 + rng.gen::<f64>()) / width as f64;
+        let t = (py as f64 + rng.gen::<f64>()) / height as f64;
+        let strength = self.config.chromatic_aberration;
+        let (ray, weight) = if strength == 0.0 {
+            (self.create_ray(rng, s, t), Color::WHITE)
+        } else {
+            // Three times the channel, so the average over samples is white.
+            let (shift, weight) = match rng.gen_range(0..3) {
+                0 => (-strength, Color::new(3.0, 0.0, 0.0)),
+                1 => (0.0, Color::new(0.0, 3.0, 0.0)),
+                _ => (strength, Color::new(0.0, 0.0, 3.0)),
+            };
+            (self.create_dispersed_ray(rng, s, t, shift), weight)
+        };
+        // For a fixed lens sample the origin doesn't move across the image.
+        let origin_d = Vec3::new(0.0, 0.0, 0.0);
+        CameraRay {
+            ray,
+            differentials: RayDifferentials {
+                origin_dx: origin_d,
+                origin_dy: origin_d,
+                direction_dx: self.horizontal / width as f64,
+                direction_dy: self.vertical / height as f64,
+            },
+            weight,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::assert_vec_eq;
+    use crate::background::Sky;
+    use crate::material::{Diffusor, OneSidedEmitter};
+    use crate::renderer::{render, RenderSettings};
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        })
+    }
+
+    fn unit_sphere() -> Sphere {
+        Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, gray())
+    }
+
+    fn ahead() -> Interval {
+        Interval::new(DEFAULT_RAY_EPSILON, f64::INFINITY)
+    }
+
+    #[test]
+    fn ray_through_the_center_hits_at_both_ends() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let sphere = unit_sphere();
+        let near = sphere.hit(&ray, Interval::new(0.0, f64::INFINITY)).unwrap();
+        assert_eq!(near.t, 0.0);
+        assert_vec_eq!(near.point, Point3::new(0.0, 0.0, -1.0));
+        let far = sphere.hit(&ray, ahead()).unwrap();
+        assert_eq!(far.t, 2.0);
+        assert_vec_eq!(far.point, Point3::new(0.0, 0.0, 1.0));
+        assert!(!far.front_face);
+    }
+
+    #[test]
+    fn ray_from_inside_hits_the_back_face() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0));
+        let record = unit_sphere().hit(&ray, ahead()).unwrap();
+        assert_eq!(record.t, 0.5);
+        assert_vec_eq!(record.point, Point3::new(0.0, 1.0, 0.0));
+        assert!(!record.front_face);
+        assert_vec_eq!(*record.normal, Vec3::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn tangent_ray_touches_in_one_point() {
+        let ray = Ray::new(Point3::new(1.0, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let record = unit_sphere().hit(&ray, ahead()).unwrap();
+        assert_eq!(record.t, 5.0);
+        assert_vec_eq!(record.point, Point3::new(1.0, 0.0, 0.0));
+        assert!(record.front_face);
+        let beside = Ray::new(Point3::new(1.0 + 1e-9, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(unit_sphere().hit(&beside, ahead()).is_none());
+    }
+
+    #[test]
+    fn sphere_behind_the_origin_is_missed() {
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, gray());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(sphere.hit(&ray, ahead()).is_none());
+        let behind = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        assert_eq!(sphere.hit(&ray, behind).unwrap().t, -6.0);
+    }
+
+    #[test]
+    fn rays_from_the_center_leave_through_the_back_face() {
+        let center = Point3::new(1.0, -2.0, 3.0);
+        let sphere = Sphere::new(center, 2.0, gray());
+        let rng = &mut StdRng::seed_from_u64(4);
+        for _ in 0..100 {
+            // Lengths other than one too, since t is in units of the direction.
+            let direction = Vec3::random_in_unit_sphere(rng) * 3.0;
+            let record = sphere.hit(&Ray::new(center, direction), ahead()).unwrap();
+            assert!((record.t * direction.len() - 2.0).abs() < 1e-12);
+            assert!(((record.point - center).len() - 2.0).abs() < 1e-12);
+            assert!(!record.front_face);
+            assert_vec_eq!(*record.normal, -direction.to_unit(), 1e-12);
+        }
+    }
+
+    #[test]
+    fn a_chord_enters_through_the_front_and_leaves_through_the_back() {
+        let sphere = unit_sphere();
+        let half_chord = 0.75f64.sqrt();
+        let ray = Ray::new(Point3::new(-5.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let entry = sphere.hit(&ray, ahead()).unwrap();
+        assert!(entry.front_face);
+        assert_vec_eq!(entry.point, Point3::new(-half_chord, 0.5, 0.0), 1e-12);
+        assert_vec_eq!(*entry.normal, Vec3::new(-half_chord, 0.5, 0.0), 1e-12);
+
+        // Carrying on from the entry point, as a refracted ray would.
+        let inside = Ray::new(entry.point, ray.direction);
+        let exit = sphere.hit(&inside, ahead()).unwrap();
+        assert!(!exit.front_face);
+        assert!((exit.t - 2.0 * half_chord).abs() < 1e-12);
+        assert_vec_eq!(exit.point, Point3::new(half_chord, 0.5, 0.0), 1e-12);
+        assert_vec_eq!(*exit.normal, Vec3::new(-half_chord, -0.5, 0.0), 1e-12);
+
+        let outside = Ray::new(exit.point, ray.direction);
+        assert!(sphere.hit(&outside, ahead()).is_none());
+    }
+
+    #[test]
+    fn from_inside_a_sphere_the_one_behind_is_not_hit() {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let around = scene.add(Box::new(unit_sphere()));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, 5.0),
+            1.0,
+            gray(),
+        )));
+        scene.build_bvh();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.5), Vec3::new(0.0, 0.0, -1.0));
+        let (id, record) = scene.hit_object(&ray, ahead()).unwrap();
+        assert_eq!(id, around);
+        assert_eq!(record.t, 1.5);
+        assert!(!record.front_face);
+        let past = Ray::new(record.point, ray.direction);
+        assert!(scene.hit(&past, ahead()).is_none());
+    }
+
+    // Built from the fields, since Ray::new rejects zero directions in debug
+    // builds.
+    #[test]
+    fn rays_without_a_usable_direction_hit_nothing() {
+        let sphere = unit_sphere();
+        for &origin in &[Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -5.0)] {
+            for &direction in &[
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(f64::INFINITY, 0.0, 0.0),
+                Vec3::new(f64::NAN, 0.0, 1.0),
+            ] {
+                let ray = Ray { origin, direction };
+                let everywhere = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+                assert!(sphere.hit(&ray, everywhere).is_none(), "{}", direction);
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_sphere_far_away_is_hit_on_its_surface() {
+        let center = Point3::new(0.0, 0.0, -1e8);
+        let sphere = Sphere::new(center, 1e-3, gray());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = sphere.hit(&ray, ahead()).unwrap();
+        assert!((record.t - (1e8 - 1e-3)).abs() < 1e-7);
+        // Within a couple of ulps of 1e8, the spacing of the coordinates.
+        assert!(((record.point - center).len() - 1e-3).abs() < 3e-8);
+        assert_vec_eq!(*record.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    fn camera(aperture: f64) -> Camera {
+        Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0f64.to_radians(),
+            1.0,
+            aperture,
+            1.0,
+        )
+    }
+
+    // Counts the 32 and 64 bit words drawn from the generator it wraps.
+    struct CountingRng {
+        inner: StdRng,
+        draws: usize,
+    }
+
+    impl RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            self.draws += 1;
+            self.inner.next_u32()
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.draws += 1;
+            self.inner.next_u64()
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            self.draws += 1;
+            self.inner.fill_bytes(dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.draws += 1;
+            self.inner.try_fill_bytes(dest)
+        }
+    }
+
+    #[test]
+    fn pinhole_rays_draw_only_the_pixel_jitter() {
+        let draws = |camera: &Camera, seed| {
+            let mut rng = CountingRng {
+                inner: StdRng::seed_from_u64(seed),
+                draws: 0,
+            };
+            camera.create_jittered_ray(3, 5, 8, 8, &mut rng);
+            (rng.draws, rng.inner)
+        };
+        for seed in 0..100 {
+            let (pinhole, mut after) = draws(&camera(0.0), seed);
+            let (lens, _) = draws(&camera(0.5), seed);
+            assert_eq!(pinhole, 2);
+            assert!(lens > pinhole);
+            // The path after the camera ray sees the same stream as if only the
+            // jitter had been drawn.
+            let mut jitter_only = StdRng::seed_from_u64(seed);
+            jitter_only.gen::<f64>();
+            jitter_only.gen::<f64>();
+            assert_eq!(after.next_u64(), jitter_only.next_u64());
+        }
+    }
+
+    #[test]
+    fn pinhole_renders_with_the_same_seed_match() {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        for i in 40..50 {
+            scene.add(grid_sphere(i));
+        }
+        scene.build_bvh();
+        assert_eq!(beauty(&scene), beauty(&scene));
+    }
+
+    #[test]
+    fn jittered_rays_follow_the_random_state() {
+        let camera = camera(0.0);
+        let ray = |seed| {
+            camera
+                .create_jittered_ray(3, 5, 8, 8, &mut StdRng::seed_from_u64(seed))
+                .ray
+        };
+        let (first, again, other) = (ray(1), ray(1), ray(2));
+        assert_vec_eq!(first.direction, again.direction, 0.0);
+        assert!(!first.direction.approx_eq(&other.direction, 1e-6));
+        // Both stay inside pixel (3, 5) of the 2 by 2 viewport at z = -1.
+        for ray in [first, other] {
+            let on_viewport = ray.direction / -ray.direction.z();
+            assert!((-1.0 + 3.0 / 4.0..-1.0 + 4.0 / 4.0).contains(&on_viewport.x()));
+            assert!((-1.0 + 5.0 / 4.0..-1.0 + 6.0 / 4.0).contains(&on_viewport.y()));
+        }
+    }
+
+    #[test]
+    fn unit_sphere_bounding_box_is_exact_and_hit_by_rays_through_it() {
+        let bounds = unit_sphere().bounding_box().unwrap();
+        assert_eq!(bounds.min.to_array(), [-1.0, -1.0, -1.0]);
+        assert_eq!(bounds.max.to_array(), [1.0, 1.0, 1.0]);
+        let ray = Ray::new(Point3::new(0.5, 0.5, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let inside = bounds.hit_interval(&ray, ahead()).unwrap();
+        assert_eq!((inside.min, inside.max), (4.0, 6.0));
+        let beside = Ray::new(Point3::new(1.5, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!bounds.hit(&beside, ahead()));
+        let away = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!bounds.hit(&away, ahead()));
+    }
+
+    #[test]
+    fn zero_radius_sphere_gets_a_padded_box() {
+        let point = Sphere::new(Point3::new(1.0, 2.0, 3.0), 0.0, gray());
+        let bounds = point.bounding_box().unwrap();
+        assert_vec_eq!(bounds.max - bounds.min, Vec3::new(2e-7, 2e-7, 2e-7), 1e-12);
+        let ray = Ray::new(Point3::new(1.0, 2.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bounds.hit(&ray, ahead()));
+    }
+
+    #[test]
+    fn glass_sphere_bends_rays_in_and_out_by_snells_law() {
+        let ior = 1.5;
+        let sphere = unit_sphere();
+        for &height in &[0.0, 0.3, 0.7] {
+            let ray = Ray::new(Point3::new(0.0, height, -5.0), Vec3::new(0.0, 0.0, 1.0));
+            let entry = sphere.hit(&ray, ahead()).unwrap();
+            let incoming = ray.direction.normalized();
+            let inside = incoming.refract(&entry.normal, 1.0 / ior);
+            let sin_in = incoming.cross_product(*entry.normal).len();
+            assert!(
+                (inside.normalized().cross_product(*entry.normal).len() - sin_in / ior).abs()
+                    < 1e-12
+            );
+            let through = Ray::new(entry.point, inside);
+            let exit = sphere.hit(&through, ahead()).unwrap();
+            assert!(!exit.front_face);
+            let inside = inside.normalized();
+            let outgoing = inside.refract(&exit.normal, ior).normalized();
+            let sin_exit_in = inside.cross_product(*exit.normal).len();
+            let sin_exit_out = outgoing.cross_product(*exit.normal).len();
+            assert!((sin_exit_out - ior * sin_exit_in).abs() < 1e-12);
+            // A sphere is symmetric, so the ray leaves at the angle it came in.
+            assert!((sin_exit_out - sin_in).abs() < 1e-12);
+        }
+    }
+
+    fn grid_sphere(index: usize) -> Box<dyn Hittable> {
+        let center = Point3::new((index % 10) as f64 - 4.5, (index / 10) as f64 - 4.5, -10.0);
+        Box::new(Sphere::new(center, 0.3, gray()))
+    }
+
+    // A small seeded render through a camera at the origin looking down -z.
+    fn beauty(scene: &Scene) -> Vec<[f64; 3]> {
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            1.0,
+            0.0,
+            10.0,
+        );
+        let settings = RenderSettings {
+            width: 24,
+            height: 24,
+            samples_per_pixel: 2,
+            max_depth: 4,
+            seed: Some(3),
+            ..RenderSettings::default()
+        };
+        let framebuffer = render(scene, &camera, &settings).unwrap();
+        framebuffer
+            .beauty
+            .iter()
+            .map(|color| color.to_array())
+            .collect()
+    }
+
+    #[test]
+    fn removed_objects_leave_no_trace_in_the_bvh_or_the_image() {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let ids: Vec<ObjectId> = (0..100).map(|i| scene.add(grid_sphere(i))).collect();
+        scene.build_bvh();
+        for &id in ids.iter().step_by(2) {
+            assert!(scene.remove(id).is_some());
+            assert!(!scene.has_bvh());
+        }
+        assert_eq!(scene.hittables().len(), 50);
+        let unaccelerated: Vec<_> = (0..100)
+            .map(|i| {
+                let center = grid_sphere(i).bounding_box().unwrap().centroid();
+                scene.hit_object(&Ray::new(Point3::new(0.0, 0.0, 0.0), center), ahead())
+            })
+            .map(|hit| hit.map(|(id, _)| id))
+            .collect();
+        scene.build_bvh();
+        for (i, &id) in ids.iter().enumerate() {
+            let center = grid_sphere(i).bounding_box().unwrap().centroid();
+            let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), center);
+            let hit = scene.hit_object(&ray, ahead()).map(|(id, _)| id);
+            let expected = if i % 2 == 0 { None } else { Some(id) };
+            assert_eq!(hit, expected, "sphere {}", i);
+            assert_eq!(unaccelerated[i], expected, "sphere {} without the BVH", i);
+        }
+
+        let mut fresh = Scene::new(Box::new(Sky::default()));
+        for i in (1..100).step_by(2) {
+            fresh.add(grid_sphere(i));
+        }
+        fresh.build_bvh();
+        assert_eq!(beauty(&scene), beauty(&fresh));
+    }
+
+    #[test]
+    fn flipped_sphere_seen_from_inside_faces_inward() {
+        let flipped = FlipNormals::new(Box::new(unit_sphere()));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let original = unit_sphere().hit(&ray, ahead()).unwrap();
+        let record = flipped.hit(&ray, ahead()).unwrap();
+        let outward = Vec3::new(1.0, 0.0, 0.0);
+        // Inside out, the inside is the front and its normal points inward,
+        // the negative of the sphere's own outward normal.
+        assert!(record.front_face && !original.front_face);
+        assert_vec_eq!(*record.normal, -outward);
+        assert_eq!(record.t, original.t);
+        assert_vec_eq!(record.point, original.point);
+
+        let from_outside = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = flipped.hit(&from_outside, ahead()).unwrap();
+        assert!(!record.front_face);
+        assert_vec_eq!(*record.normal, Vec3::new(-1.0, 0.0, 0.0));
+        let bounds = flipped.bounding_box().unwrap();
+        assert_eq!(bounds.max.to_array(), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn retain_keeps_the_matching_objects_out_of_the_image() {
+        let centers = [-4.0, -2.0, 0.0, 2.0, 4.0];
+        let row = |keep: &dyn Fn(f64) -> bool| {
+            let mut scene = Scene::new(Box::new(Sky::default()));
+            for &x in centers.iter().filter(|&&x| keep(x)) {
+                let center = Point3::new(x, 0.0, -10.0);
+                scene.add(Box::new(Sphere::new(center, 0.8, gray())));
+            }
+            scene
+        };
+        let mut scene = row(&|_| true);
+        scene.build_bvh();
+        let center_x = |hittable: &dyn Hittable| hittable.bounding_box().unwrap().centroid().x();
+        scene.retain(|hittable| center_x(hittable.as_ref()).abs() != 2.0);
+        assert_eq!(scene.hittables().len(), 3);
+        assert_eq!(scene.ids().len(), 3);
+        assert!(!scene.has_bvh());
+        let left: Vec<f64> = scene
+            .hittables()
+            .iter()
+            .map(|hittable| center_x(hittable.as_ref()))
+            .collect();
+        assert_eq!(left, [-4.0, 0.0, 4.0]);
+        let removed = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, -10.0));
+        assert!(scene.hit(&removed, ahead()).is_none());
+        assert_eq!(beauty(&scene), beauty(&row(&|x: f64| x.abs() != 2.0)));
+        assert_ne!(beauty(&scene), beauty(&row(&|_| true)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Ray direction must not be zero")]
+    fn zero_direction_fails_the_debug_assertion() {
+        Ray::new(Point3::new(1.0, 2.0, 3.0), Vec3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Ray direction must not be zero")]
+    fn zero_direction_fails_the_debug_assertion_when_normalizing() {
+        Ray::new_normalized(Point3::new(1.0, 2.0, 3.0), Vec3::new(1e-9, 0.0, 0.0));
+    }
+
+    // Degenerate cameras and quads get built without tripping `normalized`,
+    // so `validate` can say what is wrong with them.
+    #[test]
+    fn degenerate_cameras_and_quads_are_reported_by_validate() {
+        let point = Point3::new(1.0, 2.0, 3.0);
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let same_point = Camera::new(point, point, up, 1.0, 1.0, 0.0, 1.0);
+        assert!(same_point.validate().is_err());
+        let looking_up = Camera::new(point, point + up, up, 1.0, 1.0, 0.0, 1.0);
+        assert!(looking_up.validate().is_err());
+
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let edge = Vec3::new(1.0, 0.0, 0.0);
+        scene.add(Box::new(Quad::new(point, edge, 2.0 * edge, gray())));
+        let camera = Camera::new(Point3::new(0.0, 0.0, 10.0), point, up, 1.0, 1.0, 0.0, 1.0);
+        let issues = scene.validate(&camera);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("must span an area"));
+    }
+
+    #[test]
+    fn normalized_rays_have_unit_directions() {
+        let ray = Ray::new_normalized(Point3::new(0.0, 0.0, 0.0), Vec3::new(3.0, 0.0, 4.0));
+        assert_vec_eq!(ray.direction, Vec3::new(0.6, 0.0, 0.8));
+    }
+
+    #[test]
+    fn sphere_uvs_stay_in_the_unit_square() {
+        let rng = &mut StdRng::seed_from_u64(11);
+        let sphere = Sphere::new(Point3::new(1.0, -2.0, 0.5), 1.5, gray());
+        let mut hits = 0;
+        for _ in 0..1000 {
+            let origin = sphere.center() + 10.0 * Vec3::random_in_unit_sphere(rng);
+            let target = sphere.center() + 1.5 * Vec3::random_in_unit_sphere(rng);
+            if (target - origin).near_zero() {
+                continue;
+            }
+            let ray = Ray::new(origin, target - origin);
+            let record = match sphere.hit(&ray, ahead()) {
+                Some(record) => record,
+                None => continue,
+            };
+            hits += 1;
+            assert!((0.0..=1.0).contains(&record.u), "u = {}", record.u);
+            assert!((0.0..=1.0).contains(&record.v), "v = {}", record.v);
+        }
+        assert!(hits > 900, "only {} rays hit", hits);
+        // The poles and the seam at -x.
+        assert_eq!(Sphere::get_uv(Vec3::new(0.0, -1.0, 0.0)).1, 0.0);
+        assert_eq!(Sphere::get_uv(Vec3::new(0.0, 1.0, 0.0)).1, 1.0);
+        let (u, _) = Sphere::get_uv(Vec3::new(-1.0, 0.0, 0.0));
+        assert!(u == 0.0 || u == 1.0, "u = {}", u);
+    }
+
+    #[test]
+    fn merged_scenes_hold_and_render_every_object() {
+        let scene_of = |indices: std::ops::Range<usize>| {
+            let mut scene = Scene::new(Box::new(Sky::default()));
+            for i in indices {
+                scene
+                    .add_named(format!("sphere {}", i % 5), grid_sphere(i))
+                    .unwrap();
+            }
+            scene
+        };
+        let mut merged = Scene::merge(scene_of(40..45), scene_of(45..50));
+        assert_eq!(merged.hittables().len(), 10);
+        assert_eq!(merged.ids().len(), 10);
+        let mut unique = merged.ids().to_vec();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 10);
+        // The left scene keeps its names; the right one's clash and are dropped.
+        assert_eq!(merged.id_of("sphere 0"), Some(merged.ids()[0]));
+        assert!(merged.ids()[5..]
+            .iter()
+            .all(|&id| merged.name_of(id).is_none()));
+
+        let mut all = Scene::new(Box::new(Sky::default()));
+        for i in 40..50 {
+            all.add(grid_sphere(i));
+        }
+        merged.build_bvh();
+        all.build_bvh();
+        assert_eq!(beauty(&merged), beauty(&all));
+    }
+
+    // Where `point` lands in the image, as the `s`, `t` that `create_ray`
+    // takes, or None behind the camera.
+    fn project(camera: &Camera, point: Point3) -> Option<(f64, f64)> {
+        let w = camera.u.cross_product(*camera.v);
+        let offset = point - camera.origin;
+        let depth = -offset.dot(w);
+        if depth <= 0.0 {
+            return None;
+        }
+        let tan_vertical = (camera.config.fov / 2.0).tan();
+        let tan_horizontal = tan_vertical * camera.config.aspect_ratio;
+        Some((
+            0.5 + offset.dot(*camera.u) / depth / (2.0 * tan_horizontal),
+            0.5 + offset.dot(*camera.v) / depth / (2.0 * tan_vertical),
+        ))
+    }
+
+    // Every jittered ray lands inside its own pixel's slice of the unit
+    // square, and together the pixels cover all of it, also when a side is
+    // a single pixel.
+    #[test]
+    fn jittered_rays_cover_the_image_at_odd_resolutions() {
+        let rng = &mut StdRng::seed_from_u64(6);
+        for &(width, height) in &[(3u32, 2u32), (7, 5), (1, 1)] {
+            let camera = Camera::new(
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                60.0f64.to_radians(),
+                width as f64 / height as f64,
+                0.0,
+                1.0,
+            );
+            let (mut low, mut high) = ((1.0f64, 1.0f64), (0.0f64, 0.0f64));
+            for px in 0..width {
+                for py in 0..height {
+                    for _ in 0..64 {
+                        let ray = camera.create_jittered_ray(px, py, width, height, rng).ray;
+                        let (s, t) = project(&camera, ray.origin + ray.direction).unwrap();
+                        let (u, v) = (s * width as f64, t * height as f64);
+                        let eps = 1e-9;
+                        assert!(u >= px as f64 - eps && u <= (px + 1) as f64 + eps, "{}", s);
+                        assert!(v >= py as f64 - eps && v <= (py + 1) as f64 + eps, "{}", t);
+                        low = (low.0.min(s), low.1.min(t));
+                        high = (high.0.max(s), high.1.max(t));
+                    }
+                }
+            }
+            assert!(low.0 >= -1e-9 && low.0 < 0.1 && low.1 >= -1e-9 && low.1 < 0.1);
+            assert!(high.0 <= 1.0 + 1e-9 && high.0 > 0.9 && high.1 <= 1.0 + 1e-9 && high.1 > 0.9);
+        }
+    }
+
+    #[test]
+    fn framing_keeps_every_box_corner_in_the_image() {
+        let boxes = [
+            Aabb::new(Point3::new(-1.0, -2.0, -3.0), Point3::new(4.0, 1.0, 0.5)),
+            // A single plane of spheres, seen from above and edge-on.
+            Aabb::new(Point3::new(-10.0, 0.0, -10.0), Point3::new(10.0, 0.0, 10.0)),
+            Aabb::new(Point3::new(2.0, 2.0, 2.0), Point3::new(2.0, 2.0, 2.0)),
+        ];
+        let directions = [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-3.0, 0.5, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ];
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        for bounds in boxes.iter() {
+            for &direction in directions.iter() {
+                for &aspect_ratio in &[16.0 / 9.0, 1.0, 0.5] {
+                    for &margin in &[0.0, 0.1] {
+                        let fov = 50f64.to_radians();
+                        let camera =
+                            Camera::framing(bounds, direction, up, fov, aspect_ratio, margin);
+                        assert!(camera.validate().is_ok());
+                        assert_vec_eq!(camera.config().look_at, bounds.centroid());
+                        for &corner in bounds.corners().iter() {
+                            let (s, t) = project(&camera, corner).unwrap();
+                            let ray = camera.create_center_ray(s, t);
+                            assert_vec_eq!(
+                                ray.direction.to_unit(),
+                                (corner - camera.origin).to_unit(),
+                                1e-9
+                            );
+                            let inside = -1e-9..=1.0 + 1e-9;
+                            assert!(
+                                inside.contains(&s) && inside.contains(&t),
+                                "{:?} from {:?} lands at {}, {}",
+                                corner,
+                                direction,
+                                s,
+                                t
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn framing_is_tight_without_a_margin() {
+        let bounds = Aabb::new(Point3::new(-1.0, -2.0, -3.0), Point3::new(4.0, 1.0, 0.5));
+        let camera = Camera::framing(
+            &bounds,
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            50f64.to_radians(),
+            16.0 / 9.0,
+            0.0,
+        );
+        let widest = bounds
+            .corners()
+            .iter()
+            .map(|&corner| {
+                let (s, t) = project(&camera, corner).unwrap();
+                (s - 0.5).abs().max((t - 0.5).abs())
+            })
+            .fold(0.0, f64::max);
+        assert!((widest - 0.5).abs() < 1e-9, "{}", widest);
+    }
+
+    // A grazing look at a patch of a 1000 unit ground sphere from 5 cm above
+    // it, once at the origin and once far from it. Acne shows up as pixels
+    // much darker than every one of their neighbours.
+    #[test]
+    fn giant_ground_sphere_renders_without_isolated_dark_pixels() {
+        const SIZE: usize = 32;
+        for &offset in &[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1e5, 0.0, -1e5)] {
+            let mut scene = Scene::new(Box::new(Sky::default()));
+            scene.add(Box::new(Sphere::new(
+                offset + Vec3::new(0.0, -1000.0, 0.0),
+                1000.0,
+                gray(),
+            )));
+            scene.build_bvh();
+            let camera = Camera::new(
+                offset + Vec3::new(0.0, 0.05, 0.0),
+                offset + Vec3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                30.0f64.to_radians(),
+                1.0,
+                0.0,
+                1.0,
+            );
+            let settings = RenderSettings {
+                width: SIZE as u32,
+                height: SIZE as u32,
+                samples_per_pixel: 64,
+                max_depth: 8,
+                seed: Some(5),
+                ..RenderSettings::default()
+            };
+            let framebuffer = render(&scene, &camera, &settings).unwrap();
+            let luminance: Vec<f64> = framebuffer
+                .beauty
+                .iter()
+                .map(|color| color.to_array().iter().sum::<f64>())
+                .collect();
+            for y in 1..SIZE - 1 {
+                for x in 1..SIZE - 1 {
+                    let pixel = luminance[y * SIZE + x];
+                    let darkest_neighbour = (y - 1..=y + 1)
+                        .flat_map(|ny| (x - 1..=x + 1).map(move |nx| (nx, ny)))
+                        .filter(|&neighbour| neighbour != (x, y))
+                        .map(|(nx, ny)| luminance[ny * SIZE + nx])
+                        .fold(f64::INFINITY, f64::min);
+                    assert!(
+                        pixel.is_finite() && pixel >= 0.5 * darkest_neighbour,
+                        "pixel {}, {} at {:?} is {} next to {}",
+                        x,
+                        y,
+                        offset,
+                        pixel,
+                        darkest_neighbour
+                    );
+                }
+            }
+        }
+    }
+
+    // A sphere of radius `scale` resting on a floor, seen from a little above
+    // the contact point, with the same framing at every scale.
+    fn resting_sphere(scale: f64, ray_epsilon: f64) -> Vec<f64> {
+        const SIZE: u32 = 32;
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        scene.add(Box::new(Quad::new(
+            Point3::new(-50.0, 0.0, -50.0) * scale,
+            Vec3::new(0.0, 0.0, 100.0) * scale,
+            Vec3::new(100.0, 0.0, 0.0) * scale,
+            gray(),
+        )));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 1.0, 0.0) * scale,
+            scale,
+            gray(),
+        )));
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 1.5, 4.0) * scale,
+            Point3::new(0.0, 0.5, 0.0) * scale,
+            Vec3::new(0.0, 1.0, 0.0),
+            40.0f64.to_radians(),
+            1.0,
+            0.0,
+            4.0 * scale,
+        );
+        let settings = RenderSettings {
+            width: SIZE,
+            height: SIZE,
+            samples_per_pixel: 64,
+            max_depth: 8,
+            seed: Some(9),
+            ray_epsilon,
+            ..RenderSettings::default()
+        };
+        render(&scene, &camera, &settings)
+            .unwrap()
+            .beauty
+            .iter()
+            .map(|color| color.to_array().iter().sum::<f64>() / 3.0)
+            .collect()
+    }
+
+    // At a thousandth of the size, the floor right around the contact point
+    // only stays shaded by the sphere when the ray epsilon shrinks with the
+    // scene; with the default one the short rays between the two are skipped
+    // and light leaks into the gap. The offset along the normal scales by
+    // itself, so the macro scene then renders like the unit one.
+    #[test]
+    fn a_macro_scale_contact_renders_like_the_unit_scale_one() {
+        let unit = resting_sphere(1.0, 1e-6);
+        let difference = |image: &[f64]| {
+            let differences: Vec<f64> = image
+                .iter()
+                .zip(&unit)
+                .map(|(a, b)| (a - b).abs())
+                .collect();
+            let mean = differences.iter().sum::<f64>() / differences.len() as f64;
+            (mean, differences.iter().copied().fold(0.0, f64::max))
+        };
+        let (mean, max) = difference(&resting_sphere(1e-3, 1e-9));
+        assert!(mean < 1e-3 && max < 0.05, "mean {}, max {}", mean, max);
+        let (_, max) = difference(&resting_sphere(1e-3, DEFAULT_RAY_EPSILON));
+        assert!(max > 0.1, "the default epsilon left no gap: {}", max);
+    }
+
+    const DIRECTIONS: usize = 100_000;
+
+    // Uniformly random directions around `axis`, at most the angle with
+    // cosine `cos_max` from it, and the solid angle they cover.
+    fn uniform_in_cone(axis: Vec3, cos_max: f64, rng: &mut StdRng) -> Vec3 {
+        let z = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let w = axis.to_unit();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross_product(a).to_unit();
+        let u = w.cross_product(v);
+        r * phi.cos() * u + r * phi.sin() * v + z * w
+    }
+
+    // A cone from `origin` around everything the light's bounding box could
+    // cover, or every direction from inside it. Confining the estimates to
+    // it keeps them from being mostly misses.
+    fn cone_around(light: &dyn Hittable, origin: Point3) -> (Vec3, f64) {
+        let bounds = light.bounding_box().unwrap();
+        let to_center = bounds.centroid() - origin;
+        let radius = (bounds.max - bounds.min).len() / 2.0;
+        let distance = to_center.len();
+        if distance <= radius * 1.01 {
+            return (Vec3::new(0.0, 0.0, 1.0), -1.0);
+        }
+        (to_center, (1.0 - (radius / distance).powi(2)).sqrt())
+    }
+
+    // The integral of the light's density over all directions, which should
+    // be the share of samples the light accepts, one unless it is one-sided,
+    // and the solid angle the accepted samples cover twice: as the mean of
+    // 1 / pdf over the samples, counting rejected ones as zero, and by brute
+    // force as the share of random directions that hit the emitting side.
+    fn check_light(light: &dyn Hittable, origins: &[Point3]) {
+        let rng = &mut StdRng::seed_from_u64(11);
+        let everywhere = Interval::new(0.0, f64::INFINITY);
+        for &origin in origins {
+            let (axis, cos_max) = cone_around(light, origin);
+            let cone = 2.0 * std::f64::consts::PI * (1.0 - cos_max);
+            let (mut density, mut hits) = (0.0, 0);
+            for _ in 0..DIRECTIONS {
+                let direction = uniform_in_cone(axis, cos_max, rng);
+                density += light.pdf_value(origin, direction);
+                if let Some(record) = light.hit(&Ray::new(origin, direction), everywhere) {
+                    if record.front_face || !record.material.emits_one_sided() {
+                        hits += 1;
+                    }
+                }
+            }
+            let integral = cone * density / DIRECTIONS as f64;
+            let brute_force = cone * hits as f64 / DIRECTIONS as f64;
+
+            let (mut sampled, mut accepted) = (0.0, 0);
+            for _ in 0..DIRECTIONS {
+                let direction = match light.random_toward(origin, rng) {
+                    Some(direction) => direction,
+                    None => continue,
+                };
+                let pdf = light.pdf_value(origin, direction);
+                assert!(
+                    pdf > 0.0 && pdf.is_finite(),
+                    "{} towards {}",
+                    pdf,
+                    direction
+                );
+                sampled += 1.0 / pdf;
+                accepted += 1;
+            }
+            let sampled = sampled / DIRECTIONS as f64;
+            let acceptance = accepted as f64 / DIRECTIONS as f64;
+
+            assert!(
+                (integral - acceptance).abs() < 0.03,
+                "from {}: {} for {} accepted",
+                origin,
+                integral,
+                acceptance
+            );
+            if hits == 0 {
+                assert_eq!(accepted, 0, "from {}", origin);
+                continue;
+            }
+            assert!(
+                (sampled / brute_force - 1.0).abs() < 0.03,
+                "from {}: {} sampled, {} by brute force",
+                origin,
+                sampled,
+                brute_force
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_light_pdf_integrates_to_one_and_matches_brute_force() {
+        let sphere = Sphere::new(Point3::new(0.0, 1.0, -2.0), 0.5, gray());
+        check_light(
+            &sphere,
+            &[
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(3.0, -1.0, 1.0),
+                Point3::new(0.0, 1.0, -1.2),
+                Point3::new(0.1, 1.2, -2.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn quad_light_pdf_integrates_to_one_and_matches_brute_force() {
+        let quad = Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.5, 0.0, 1.5),
+            gray(),
+        );
+        check_light(
+            &quad,
+            &[
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(0.0, 1.9, 0.0),
+                Point3::new(4.0, 0.0, 3.0),
+                Point3::new(0.0, 4.0, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn disk_light_pdf_integrates_to_one_and_matches_brute_force() {
+        let disk = Disk::new(
+            Point3::new(0.5, 2.0, -1.0),
+            Vec3::new(0.3, -1.0, 0.2),
+            0.8,
+            gray(),
+        );
+        check_light(
+            &disk,
+            &[
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(0.5, 1.9, -1.0),
+                Point3::new(4.0, 0.0, 3.0),
+                Point3::new(0.0, 4.0, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn cylinder_light_pdf_integrates_to_one_and_matches_brute_force() {
+        let tube = Cylinder::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.5, 0.0),
+            0.3,
+            gray(),
+        );
+        check_light(
+            &tube,
+            &[
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(0.0, 2.8, -1.0),
+                Point3::new(1.0, 4.0, 1.0),
+                // Inside the tube, and looking in through an open end.
+                Point3::new(0.0, 2.25, -1.0),
+                Point3::new(-3.0, 1.5, -1.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn one_sided_lights_are_sampled_only_on_their_front() {
+        let light: Arc<dyn Material> = Arc::new(OneSidedEmitter {
+            color: Color::new(4.0, 4.0, 4.0),
+        });
+        // All facing down towards the origin, from above.
+        let quad = Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.5, 0.0, 1.5),
+            Arc::clone(&light),
+        );
+        let disk = Disk::new(
+            Point3::new(0.5, 2.0, -1.0),
+            Vec3::new(0.3, -1.0, 0.2),
+            0.8,
+            Arc::clone(&light),
+        );
+        let tube = Cylinder::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.5, 0.0),
+            0.3,
+            Arc::clone(&light),
+        );
+        let sphere = Sphere::new(Point3::new(0.0, 1.0, -2.0), 0.5, Arc::clone(&light));
+        let below = Point3::new(0.0, 0.0, 0.0);
+        check_light(&quad, &[below, Point3::new(0.0, 1.9, 0.0)]);
+        check_light(&disk, &[below, Point3::new(0.5, 1.9, -1.0)]);
+        // Partly the front from anywhere outside, none of it from inside.
+        check_light(
+            &tube,
+            &[
+                below,
+                Point3::new(0.0, 2.8, -1.0),
+                Point3::new(-3.0, 1.5, -1.0),
+                Point3::new(0.0, 2.25, -1.0),
+            ],
+        );
+        check_light(&sphere, &[below, Point3::new(3.0, -1.0, 1.0)]);
+
+        let rng = &mut StdRng::seed_from_u64(3);
+        let behind: [(&dyn Hittable, Point3); 4] = [
+            (&quad, Point3::new(0.0, 3.0, 0.0)),
+            (&disk, Point3::new(0.0, 4.0, 0.0)),
+            (&tube, Point3::new(0.0, 2.25, -1.0)),
+            (&sphere, Point3::new(0.1, 1.2, -2.0)),
+        ];
+        for (light, origin) in behind.iter() {
+            let bounds = light.bounding_box().unwrap();
+            for _ in 0..1000 {
+                assert!(light.random_toward(*origin, rng).is_none());
+                let toward = bounds.min + Vec3::random(rng).hadamard(bounds.max - bounds.min);
+                assert_eq!(light.pdf_value(*origin, toward - *origin), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn culling_leaves_light_sampling_alone() {
+        let rng = &mut StdRng::seed_from_u64(5);
+        let sphere = || Sphere::new(Point3::new(0.0, 1.0, -2.0), 0.5, gray());
+        let quad = || {
+            Quad::new(
+                Point3::new(-1.0, 2.0, -1.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.5, 0.0, 1.5),
+                gray(),
+            )
+        };
+        let mut check = |open: &dyn Hittable, culled: &dyn Hittable, origin: Point3| {
+            for _ in 0..1000 {
+                let direction = culled.random_toward(origin, rng).unwrap();
+                let pdf = culled.pdf_value(origin, direction);
+                assert!(pdf > 0.0, "towards {}", direction);
+                assert_eq!(pdf, open.pdf_value(origin, direction));
+            }
+        };
+        // From inside the sphere, and from behind the quad.
+        check(
+            &sphere(),
+            &sphere().with_backface_culling(true),
+            Point3::new(0.1, 1.2, -2.0),
+        );
+        check(
+            &quad(),
+            &quad().with_backface_culling(true),
+            Point3::new(0.0, 4.0, 0.0),
+        );
+    }
+
+    // Closed boxes and spheres on a floor, seen from outside, where culling
+    // only drops faces the camera can't see.
+    fn closed_shapes(cull: bool) -> Scene {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        scene.add(Box::new(
+            Quad::new(
+                Point3::new(-5.0, 0.0, 5.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 0.0, -10.0),
+                gray(),
+            )
+            .with_backface_culling(cull),
+        ));
+        for i in 0..4 {
+            let x = i as f64 * 1.5 - 2.5;
+            let angle = i as f64 * 0.4;
+            let edges = [
+                Vec3::new(angle.cos(), 0.0, angle.sin()),
+                Vec3::new(0.0, 0.5 + 0.3 * i as f64, 0.0),
+                Vec3::new(-angle.sin(), 0.0, angle.cos()) * 0.7,
+            ];
+            for face in Quad::cuboid(Point3::new(x, 0.0, -1.0), edges, gray()) {
+                scene.add(Box::new(face.with_backface_culling(cull)));
+            }
+            scene.add(Box::new(
+                Sphere::new(Point3::new(x + 0.5, 0.4, 1.2), 0.4, gray())
+                    .with_backface_culling(cull),
+            ));
+        }
+        scene.build_bvh();
+        scene
+    }
+
+    #[test]
+    fn culling_keeps_the_first_hit_through_every_pixel() {
+        let (open, culled) = (closed_shapes(false), closed_shapes(true));
+        let camera = Camera::new(
+            Point3::new(4.0, 3.0, 6.0),
+            Point3::new(0.0, 0.3, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            50.0f64.to_radians(),
+            1.5,
+            0.0,
+            1.0,
+        );
+        let (width, height) = (96, 64);
+        let mut objects = 0;
+        for py in 0..height {
+            for px in 0..width {
+                let ray = camera.create_center_ray(
+                    (px as f64 + 0.5) / width as f64,
+                    (py as f64 + 0.5) / height as f64,
+                );
+                let expected = open.hit_object(&ray, ahead());
+                let actual = culled.hit_object(&ray, ahead());
+                match (expected, actual) {
+                    (None, None) => {}
+                    (Some((id, expected)), Some((culled_id, actual))) => {
+                        assert_eq!(id, culled_id, "pixel {}, {}", px, py);
+                        assert_eq!(expected.t, actual.t);
+                        assert_eq!(expected.normal.to_array(), actual.normal.to_array());
+                        objects += 1;
+                    }
+                    _ => panic!("pixel {}, {} hit only one of them", px, py),
+                }
+            }
+        }
+        // Not just sky.
+        assert!(objects > width * height / 2, "{}", objects);
+    }
+
+    #[test]
+    fn a_tube_is_hit_on_its_wall_between_its_ends() {
+        let tube = Cylinder::new(
+            Point3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            0.5,
+            gray(),
+        );
+        let across = Ray::new(Point3::new(-2.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = tube.hit(&across, ahead()).unwrap();
+        assert!((record.t - 1.5).abs() < 1e-12);
+        assert!(record.front_face);
+        assert_vec_eq!(*record.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!((record.v - 0.75).abs() < 1e-12);
+        // From inside, the far wall's back.
+        let from_axis = Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = tube.hit(&from_axis, ahead()).unwrap();
+        assert!((record.t - 0.5).abs() < 1e-12 && !record.front_face);
+        // Past the ends, and in through the open one.
+        let above = Ray::new(Point3::new(-2.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let down_the_axis = Ray::new(Point3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(tube.hit(&above, ahead()).is_none());
+        assert!(tube.hit(&down_the_axis, ahead()).is_none());
+    }
+
+    #[test]
+    fn one_sided_emitters_light_only_their_front() {
+        let panel = Quad::new(
+            Point3::new(-1.0, 0.0, -1.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Arc::new(OneSidedEmitter {
+                color: Color::new(2.0, 2.0, 2.0),
+            }),
+        );
+        let down = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let up = Ray::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let emitted = |ray: &Ray| {
+            let record = panel.hit(ray, ahead()).unwrap();
+            record.material.emitted(&record, ray).to_array()
+        };
+        assert_eq!(emitted(&down), [2.0, 2.0, 2.0]);
+        assert_eq!(emitted(&up), [0.0, 0.0, 0.0]);
+    }
+}
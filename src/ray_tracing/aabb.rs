@@ -0,0 +1,75 @@
+use super::Ray;
+use crate::vec_math::{Interval, Point3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    pub fn new(min: Point3, max: Point3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn surrounding(first: &Aabb, second: &Aabb) -> Aabb {
+        Aabb {
+            min: first.min.min(second.min),
+            max: first.max.max(second.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
+    }
+
+    // Bit 0 of the index picks x from max instead of min, bit 1 y, bit 2 z.
+    pub fn corners(&self) -> [Point3; 8] {
+        let pick = |corner: usize, axis: usize| {
+            if corner & (1 << axis) == 0 {
+                self.min[axis]
+            } else {
+                self.max[axis]
+            }
+        };
+        let mut corners = [Point3::new(0.0, 0.0, 0.0); 8];
+        for (index, corner) in corners.iter_mut().enumerate() {
+            *corner = Point3::new(pick(index, 0), pick(index, 1), pick(index, 2));
+        }
+        corners
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+        if extent.x() >= extent.y() && extent.x() >= extent.z() {
+            0
+        } else if extent.y() >= extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_bounds: Interval) -> bool {
+        self.hit_interval(ray, t_bounds).is_some()
+    }
+
+    // The part of `t_bounds` during which the ray is inside the box.
+    pub fn hit_interval(&self, ray: &Ray, t_bounds: Interval) -> Option<Interval> {
+        let (mut t_min, mut t_max) = (t_bounds.min, t_bounds.max);
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some(Interval::new(t_min, t_max))
+    }
+}
@@ -0,0 +1,143 @@
+use super::{Aabb, HitRecord, Hittable, ObjectId, Ray};
+use crate::stats;
+use crate::vec_math::Interval;
+
+// Subtrees smaller than this are built on the current thread; spawning tasks
+// for them costs more than it saves.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 64;
+
+// The tree refers to objects by their index in the slice it was built from, so
+// the scene keeps owning them and can throw the tree away when it changes.
+// Leaves also keep the object's id, to put into its hit records.
+pub enum BvhNode {
+    Leaf {
+        index: usize,
+        id: ObjectId,
+        bbox: Aabb,
+    },
+    Branch {
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+        bbox: Aabb,
+    },
+}
+
+impl BvhNode {
+    // Objects without a bounding box are skipped and have to be tested separately.
+    // `ids` go with `objects` by position.
+    pub fn build(objects: &[Box<dyn Hittable>], ids: &[ObjectId]) -> Option<BvhNode> {
+        let mut entries: Vec<(usize, ObjectId, Aabb)> = objects
+            .iter()
+            .zip(ids)
+            .enumerate()
+            .filter_map(|(index, (object, &id))| {
+                object.bounding_box().map(|bbox| (index, id, bbox))
+            })
+            .collect();
+        if entries.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build_from(&mut entries))
+        }
+    }
+
+    // Splits at the median along the longest axis of the centroid bounds. The
+    // choice only depends on the input, so the parallel and sequential builds
+    // produce the same tree.
+    fn build_from(entries: &mut [(usize, ObjectId, Aabb)]) -> BvhNode {
+        if let [(index, id, bbox)] = entries {
+            return BvhNode::Leaf {
+                index: *index,
+                id: *id,
+                bbox: *bbox,
+            };
+        }
+        let centroid_bounds = entries
+            .iter()
+            .map(|(_, _, bbox)| Aabb::new(bbox.centroid(), bbox.centroid()))
+            .reduce(|first, second| Aabb::surrounding(&first, &second))
+            .unwrap();
+        let axis = centroid_bounds.longest_axis();
+        entries.sort_by(|(_, _, first), (_, _, second)| {
+            first.centroid()[axis].total_cmp(&second.centroid()[axis])
+        });
+        let (left_entries, right_entries) = entries.split_at_mut(entries.len() / 2);
+        let (left, right) = BvhNode::build_children(left_entries, right_entries);
+        let bbox = Aabb::surrounding(left.bbox(), right.bbox());
+        BvhNode::Branch {
+            left: Box::new(left),
+            right: Box::new(right),
+            bbox,
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    fn build_children(
+        left: &mut [(usize, ObjectId, Aabb)],
+        right: &mut [(usize, ObjectId, Aabb)],
+    ) -> (BvhNode, BvhNode) {
+        if left.len() + right.len() < PARALLEL_THRESHOLD {
+            (BvhNode::build_from(left), BvhNode::build_from(right))
+        } else {
+            rayon::join(|| BvhNode::build_from(left), || BvhNode::build_from(right))
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn build_children(
+        left: &mut [(usize, ObjectId, Aabb)],
+        right: &mut [(usize, ObjectId, Aabb)],
+    ) -> (BvhNode, BvhNode) {
+        (BvhNode::build_from(left), BvhNode::build_from(right))
+    }
+
+    pub fn node_count(&self) -> usize {
+        match self {
+            BvhNode::Leaf { .. } => 1,
+            BvhNode::Branch { left, right, .. } => 1 + left.node_count() + right.node_count(),
+        }
+    }
+
+    // A lone leaf has depth 1.
+    pub fn depth(&self) -> usize {
+        match self {
+            BvhNode::Leaf { .. } => 1,
+            BvhNode::Branch { left, right, .. } => 1 + left.depth().max(right.depth()),
+        }
+    }
+
+    pub fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } | BvhNode::Branch { bbox, .. } => bbox,
+        }
+    }
+
+    pub fn hit(
+        &self,
+        objects: &[Box<dyn Hittable>],
+        ray: &Ray,
+        t_bounds: Interval,
+    ) -> Option<HitRecord> {
+        stats::count_bvh_node();
+        if !self.bbox().hit(ray, t_bounds) {
+            return None;
+        }
+        match self {
+            BvhNode::Leaf { index, id, .. } => {
+                stats::count_object_test();
+                objects[*index].hit(ray, t_bounds).map(|mut record| {
+                    record.object = Some(*id);
+                    record
+                })
+            }
+            BvhNode::Branch { left, right, .. } => {
+                let left_hit = left.hit(objects, ray, t_bounds);
+                let closest = left_hit.as_ref().map_or(t_bounds.max, |record| record.t);
+                right
+                    .hit(objects, ray, t_bounds.with_max(closest))
+                    .or(left_hit)
+            }
+        }
+    }
+}
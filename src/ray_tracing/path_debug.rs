@@ -0,0 +1,171 @@
+use std::fmt;
+
+use super::{HitRecord, ObjectId, Ray};
+use crate::vec_math::{Color, Point3, Vec3};
+
+// Hooks into `Ray::color_observed`, called once per traced ray in path order.
+// Every method does nothing by default, and `()` observes nothing, which the
+// ordinary render path uses at no cost.
+pub trait PathObserver {
+    fn hit(
+        &mut self,
+        _bounce: u32,
+        _ray: &Ray,
+        _record: &HitRecord,
+        _emitted: Color,
+        _scattered: Option<&(Color, Ray)>,
+    ) {
+    }
+
+    fn escaped(&mut self, _bounce: u32, _ray: &Ray, _background: Color) {}
+
+    // The path ran out of bounces before this ray could be traced.
+    fn depth_limit(&mut self, _bounce: u32, _ray: &Ray) {}
+}
+
+impl PathObserver for () {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceInfo {
+    pub point: Point3,
+    pub normal: Vec3,
+    pub front_face: bool,
+    pub distance: f64,
+    pub material: &'static str,
+    // None for hits on a hittable outside any scene.
+    pub object: Option<ObjectId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum BounceEvent {
+    Scattered {
+        surface: SurfaceInfo,
+        emitted: Color,
+        attenuation: Color,
+        scattered: Ray,
+    },
+    Absorbed {
+        surface: SurfaceInfo,
+        emitted: Color,
+    },
+    Escaped {
+        background: Color,
+    },
+    DepthLimit,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BounceInfo {
+    pub bounce: u32,
+    pub ray: Ray,
+    pub event: BounceEvent,
+}
+
+// Collects every bounce of a path.
+#[derive(Default)]
+pub struct PathRecorder {
+    pub bounces: Vec<BounceInfo>,
+}
+
+impl PathRecorder {
+    pub fn new() -> Self {
+        PathRecorder::default()
+    }
+}
+
+impl PathObserver for PathRecorder {
+    fn hit(
+        &mut self,
+        bounce: u32,
+        ray: &Ray,
+        record: &HitRecord,
+        emitted: Color,
+        scattered: Option<&(Color, Ray)>,
+    ) {
+        let surface = SurfaceInfo {
+            point: record.point,
+            normal: record.normal.get(),
+            front_face: record.front_face,
+            distance: record.t * ray.direction.len(),
+            material: record.material.name(),
+            object: record.object,
+        };
+        let event = match scattered {
+            Some(&(attenuation, scattered)) => BounceEvent::Scattered {
+                surface,
+                emitted,
+                attenuation,
+                scattered,
+            },
+            None => BounceEvent::Absorbed { surface, emitted },
+        };
+        self.bounces.push(BounceInfo {
+            bounce,
+            ray: *ray,
+            event,
+        });
+    }
+
+    fn escaped(&mut self, bounce: u32, ray: &Ray, background: Color) {
+        self.bounces.push(BounceInfo {
+            bounce,
+            ray: *ray,
+            event: BounceEvent::Escaped { background },
+        });
+    }
+
+    fn depth_limit(&mut self, bounce: u32, ray: &Ray) {
+        self.bounces.push(BounceInfo {
+            bounce,
+            ray: *ray,
+            event: BounceEvent::DepthLimit,
+        });
+    }
+}
+
+impl fmt::Display for SurfaceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.object {
+            Some(object) => write!(f, "{:?}", object)?,
+            None => f.write_str("unknown object")?,
+        }
+        write!(
+            f,
+            " ({}) at {:.4}, {} face, normal {:.4}, distance {:.4}",
+            self.material,
+            self.point,
+            if self.front_face { "front" } else { "back" },
+            self.normal,
+            self.distance
+        )
+    }
+}
+
+impl fmt::Display for BounceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "bounce {}: {:?}", self.bounce, self.ray)?;
+        match &self.event {
+            BounceEvent::Scattered {
+                surface,
+                emitted,
+                attenuation,
+                scattered,
+            } => {
+                writeln!(f, "  hit {}", surface)?;
+                write!(
+                    f,
+                    "  scattered towards {:.4}, emitted {:.4}, attenuation {:.4}",
+                    scattered.direction, emitted, attenuation
+                )
+            }
+            BounceEvent::Absorbed { surface, emitted } => {
+                writeln!(f, "  hit {}", surface)?;
+                write!(f, "  absorbed, emitted {:.4}", emitted)
+            }
+            BounceEvent::Escaped { background } => {
+                write!(f, "  escaped, background {:.4}", background)
+            }
+            BounceEvent::DepthLimit => f.write_str("  not traced, out of bounces"),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use super::{Camera, ObjectId, Scene};
+use crate::vec_math::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    // Renders, but likely not as meant.
+    Warning,
+    // Renders wrong or not at all.
+    Error,
+}
+
+// A problem `Scene::validate` found. `object` is None for the camera.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub object: Option<ObjectId>,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        match self.object {
+            Some(object) => write!(f, "{}: {:?}: {}", severity, object, self.message),
+            None => write!(f, "{}: camera: {}", severity, self.message),
+        }
+    }
+}
+
+// What `Hittable::validate` and `Material::validate` report into.
+#[derive(Debug, Default)]
+pub struct Issues {
+    found: Vec<(Severity, String)>,
+}
+
+impl Issues {
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.found.push((Severity::Error, message.into()));
+    }
+
+    pub fn warning(&mut self, message: impl Into<String>) {
+        self.found.push((Severity::Warning, message.into()));
+    }
+
+    // A color a surface scatters light with. Above 1 it gives out more light
+    // than it receives, which only a light should.
+    pub fn check_albedo(&mut self, what: &str, color: Color) {
+        let components = color.to_array();
+        if !components.iter().all(|c| c.is_finite() && *c >= 0.0) {
+            self.error(format!(
+                "{} {} must be finite and not negative",
+                what, color
+            ));
+        } else if components.iter().any(|&c| c > 1.0) {
+            self.warning(format!(
+                "{} {} reflects more light than it gets",
+                what, color
+            ));
+        }
+    }
+
+    pub fn check_emission(&mut self, what: &str, color: Color) {
+        let components = color.to_array();
+        if !components.iter().all(|c| c.is_finite() && *c >= 0.0) {
+            self.error(format!(
+                "{} {} must be finite and not negative",
+                what, color
+            ));
+        } else if components.iter().all(|&c| c == 0.0) {
+            self.warning(format!("{} is black, the light emits nothing", what));
+        }
+    }
+
+    pub fn check_fuzz(&mut self, fuzz: f64) {
+        if !fuzz.is_finite() {
+            self.error(format!("fuzz {} must be finite", fuzz));
+        } else if !(0.0..=1.0).contains(&fuzz) {
+            self.warning(format!("fuzz {} is outside 0 to 1", fuzz));
+        }
+    }
+}
+
+impl Scene {
+    // Looks for parameters that render wrong or not at all, errors first.
+    // Also warns when the camera starts inside an object, as far as objects
+    // can tell (see `Hittable::contains`).
+    pub fn validate(&self, camera: &Camera) -> Vec<ValidationIssue> {
+        let mut found = vec![];
+        if let Err(error) = camera.validate() {
+            found.push(ValidationIssue {
+                severity: Severity::Error,
+                object: None,
+                message: error.to_string(),
+            });
+        }
+        let eye = camera.config().look_from;
+        for (hittable, &id) in self.hittables.iter().zip(&self.ids) {
+            let mut issues = Issues::default();
+            hittable.validate(&mut issues);
+            if hittable.contains(eye) == Some(true) {
+                issues.warning("the camera is inside it");
+            }
+            found.extend(
+                issues
+                    .found
+                    .into_iter()
+                    .map(|(severity, message)| ValidationIssue {
+                        severity,
+                        object: Some(id),
+                        message,
+                    }),
+            );
+        }
+        found.sort_by_key(|issue| std::cmp::Reverse(issue.severity));
+        found
+    }
+}
@@ -0,0 +1,1467 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::aov::ScalarBuffer;
+use crate::convergence::{Convergence, ConvergenceSummary, Welford};
+use crate::error::{Error, Result};
+use crate::material::MaterialOverride;
+use crate::output::{
+    self, FalseColorRange, Metadata, OutputOptions, OutputTransform, Rgba8Image, TransferFunction,
+};
+#[cfg(feature = "denoise")]
+use crate::post_process::denoise_atrous;
+use crate::post_process::Denoiser;
+use crate::progress::{Progress, ProgressControl};
+use crate::ray_tracing::{
+    BounceInfo, Camera, FirstHit, ObjectId, PathOptions, PathRecorder, PathRegularization, Scene,
+    DEFAULT_RAY_EPSILON,
+};
+use crate::sample_check::{NonFiniteReport, SampleCheck, SampleChecker};
+use crate::stats::{self, Counters, PerRay};
+use crate::stopwatch::Stopwatch;
+use crate::vec_math::{Color, Interval, Point3, Vec3};
+
+mod tile_order;
+
+pub use tile_order::TileOrder;
+
+const TILE_SIZE: u32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct MultiOutput {
+    pub beauty: PathBuf,
+    pub albedo: Option<PathBuf>,
+    pub normal: Option<PathBuf>,
+    pub depth: Option<PathBuf>,
+    // The variance of each pixel's mean luminance, in false color, or as
+    // plain values in an EXR file.
+    pub variance: Option<PathBuf>,
+    // A coverage mask per scene layer: the fraction of each pixel's samples
+    // whose camera ray first hit an object in the layer, in gray.
+    pub masks: BTreeMap<String, PathBuf>,
+}
+
+impl MultiOutput {
+    pub fn beauty_only(beauty: PathBuf) -> Self {
+        MultiOutput {
+            beauty,
+            albedo: None,
+            normal: None,
+            depth: None,
+            variance: None,
+            masks: BTreeMap::new(),
+        }
+    }
+
+    // Expands the placeholders of `output::expand_template` in every path,
+    // all with the same `time`.
+    pub fn expand_templates(&self, settings: &RenderSettings, time: SystemTime) -> MultiOutput {
+        let expand = |path: &PathBuf| match path.to_str() {
+            Some(template) => output::expand_template(template, settings, time),
+            None => path.clone(),
+        };
+        MultiOutput {
+            beauty: expand(&self.beauty),
+            albedo: self.albedo.as_ref().map(expand),
+            normal: self.normal.as_ref().map(expand),
+            depth: self.depth.as_ref().map(expand),
+            variance: self.variance.as_ref().map(expand),
+            masks: self
+                .masks
+                .iter()
+                .map(|(layer, path)| (layer.clone(), expand(path)))
+                .collect(),
+        }
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.beauty)
+            .chain(self.albedo.iter())
+            .chain(self.normal.iter())
+            .chain(self.depth.iter())
+            .chain(self.variance.iter())
+            .chain(self.masks.values())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    // Hits closer than this along a ray are ignored. Lower it for scenes with
+    // objects much smaller than a unit, where the default leaves gaps at
+    // contact points.
+    pub ray_epsilon: f64,
+    // Scales the averaged radiance, like a camera's exposure; 1 leaves the
+    // image as rendered.
+    pub exposure: f64,
+    // A fixed seed makes renders reproducible; None picks a fresh one.
+    pub seed: Option<u64>,
+    // Worker threads for the parallel renderer, None or 0 for one per core.
+    pub threads: Option<usize>,
+    pub tile_order: TileOrder,
+    pub sample_check: SampleCheck,
+    // Off by default; see `PathRegularization`.
+    pub regularization: Option<PathRegularization>,
+    // Renders every surface with one material, e.g. `MaterialOverride::clay`.
+    pub material_override: Option<MaterialOverride>,
+    pub denoiser: Option<Denoiser>,
+    pub output_transform: OutputTransform,
+    pub outputs: MultiOutput,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            ray_epsilon: DEFAULT_RAY_EPSILON,
+            exposure: 1.0,
+            seed: None,
+            threads: None,
+            tile_order: TileOrder::Scanline,
+            sample_check: SampleCheck::Off,
+            regularization: None,
+            material_override: None,
+            denoiser: None,
+            output_transform: OutputTransform::default(),
+            outputs: MultiOutput::beauty_only(PathBuf::from(output::DEFAULT_OUTPUT)),
+        }
+    }
+}
+
+impl RenderSettings {
+    pub fn builder() -> RenderSettingsBuilder {
+        RenderSettingsBuilder {
+            settings: RenderSettings::default(),
+        }
+    }
+
+    fn path_options(&self) -> PathOptions<'_> {
+        PathOptions {
+            regularization: self.regularization.as_ref(),
+            material_override: self.material_override.as_ref(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.validate_without_outputs()?;
+        for path in self.outputs.paths() {
+            if output::ImageFormat::from_path(path).is_none() {
+                return Err(Error::UnsupportedFormat(path.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    // Everything `validate` checks except the output paths, for renders that
+    // don't write them.
+    pub fn validate_without_outputs(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidSettings(format!(
+                "resolution {}x{} is too small",
+                self.width, self.height
+            )));
+        }
+        if self.samples_per_pixel == 0 {
+            return Err(Error::InvalidSettings(
+                "samples_per_pixel must be at least 1".to_string(),
+            ));
+        }
+        if self.max_depth == 0 {
+            return Err(Error::InvalidSettings(
+                "max_depth must be at least 1".to_string(),
+            ));
+        }
+        if !(self.ray_epsilon >= 0.0 && self.ray_epsilon.is_finite()) {
+            return Err(Error::InvalidSettings(format!(
+                "ray epsilon {} is not a finite, non-negative number",
+                self.ray_epsilon
+            )));
+        }
+        if !(self.exposure >= 0.0 && self.exposure.is_finite()) {
+            return Err(Error::InvalidSettings(format!(
+                "exposure {} is not a finite, non-negative number",
+                self.exposure
+            )));
+        }
+        if let Some(regularization) = self.regularization {
+            let valid = |value: f64| value >= 0.0 && value.is_finite();
+            if !(valid(regularization.roughness) && valid(regularization.growth)) {
+                return Err(Error::InvalidSettings(format!(
+                    "path regularization roughness {} and growth {} must be finite and \
+                     non-negative",
+                    regularization.roughness, regularization.growth
+                )));
+            }
+        }
+        if cfg!(not(feature = "denoise")) && self.denoiser.is_some() {
+            return Err(Error::InvalidSettings(
+                "this build was compiled without denoising".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // The camera has to produce images of the same shape, or everything
+    // comes out stretched.
+    pub fn check_camera(&self, camera: &Camera) -> Result<()> {
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        let camera_aspect_ratio = camera.config().aspect_ratio;
+        if (camera_aspect_ratio - aspect_ratio).abs() > aspect_ratio * 0.01 {
+            return Err(Error::InvalidCamera(format!(
+                "aspect ratio {} doesn't match the {}x{} image",
+                camera_aspect_ratio, self.width, self.height
+            )));
+        }
+        Ok(())
+    }
+
+    // Within the 1% `check_camera` allows, a camera that doesn't match the
+    // image exactly is rebuilt to match, rather than stretching the image.
+    fn fitted_camera(&self, camera: &Camera) -> Result<Option<Camera>> {
+        self.check_camera(camera)?;
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        if camera.config().aspect_ratio == aspect_ratio {
+            return Ok(None);
+        }
+        log::warn!(
+            "camera aspect ratio {} adjusted to {} to match the {}x{} image",
+            camera.config().aspect_ratio,
+            aspect_ratio,
+            self.width,
+            self.height
+        );
+        Ok(Some(camera.with_aspect_ratio(aspect_ratio)))
+    }
+
+    // The resolution for a new width, height or both. A lone one keeps the
+    // current aspect ratio as closely as whole pixels allow, with a warning
+    // when that isn't exact.
+    pub fn fitted_resolution(&self, width: Option<u32>, height: Option<u32>) -> (u32, u32) {
+        let aspect_ratio = self.width as f64 / self.height as f64;
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => return (width, height),
+            (Some(width), None) => (width, (width as f64 / aspect_ratio).round() as u32),
+            (None, Some(height)) => ((height as f64 * aspect_ratio).round() as u32, height),
+            (None, None) => return (self.width, self.height),
+        };
+        if width as f64 / height as f64 != aspect_ratio {
+            log::warn!(
+                "{}x{} is as close as whole pixels get to aspect ratio {}",
+                width,
+                height,
+                aspect_ratio
+            );
+        }
+        (width, height)
+    }
+}
+
+// Starts from the defaults; `build` validates the result.
+pub struct RenderSettingsBuilder {
+    settings: RenderSettings,
+}
+
+impl RenderSettingsBuilder {
+    pub fn resolution(mut self, width: u32, height: u32) -> Self {
+        self.settings.width = width;
+        self.settings.height = height;
+        self
+    }
+
+    pub fn samples_per_pixel(mut self, samples_per_pixel: u32) -> Self {
+        self.settings.samples_per_pixel = samples_per_pixel;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u32) -> Self {
+        self.settings.max_depth = max_depth;
+        self
+    }
+
+    pub fn ray_epsilon(mut self, ray_epsilon: f64) -> Self {
+        self.settings.ray_epsilon = ray_epsilon;
+        self
+    }
+
+    pub fn exposure(mut self, exposure: f64) -> Self {
+        self.settings.exposure = exposure;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.settings.seed = Some(seed);
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.settings.threads = Some(threads);
+        self
+    }
+
+    pub fn tile_order(mut self, tile_order: TileOrder) -> Self {
+        self.settings.tile_order = tile_order;
+        self
+    }
+
+    pub fn sample_check(mut self, sample_check: SampleCheck) -> Self {
+        self.settings.sample_check = sample_check;
+        self
+    }
+
+    pub fn regularization(mut self, regularization: PathRegularization) -> Self {
+        self.settings.regularization = Some(regularization);
+        self
+    }
+
+    pub fn material_override(mut self, material_override: MaterialOverride) -> Self {
+        self.settings.material_override = Some(material_override);
+        self
+    }
+
+    pub fn denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.settings.denoiser = Some(denoiser);
+        self
+    }
+
+    pub fn output_transform(mut self, output_transform: OutputTransform) -> Self {
+        self.settings.output_transform = output_transform;
+        self
+    }
+
+    pub fn output(mut self, beauty: impl Into<PathBuf>) -> Self {
+        self.settings.outputs.beauty = beauty.into();
+        self
+    }
+
+    pub fn outputs(mut self, outputs: MultiOutput) -> Self {
+        self.settings.outputs = outputs;
+        self
+    }
+
+    pub fn build(self) -> Result<RenderSettings> {
+        self.settings.validate()?;
+        Ok(self.settings)
+    }
+}
+
+pub struct Renderer {
+    settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Self {
+        Renderer { settings }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn render(&self, scene: &Scene, camera: &Camera) -> Result<Framebuffer> {
+        render(scene, camera, &self.settings)
+    }
+
+    pub fn render_with_progress(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        progress: &(dyn Fn(Progress) -> ProgressControl + Sync),
+    ) -> Result<Framebuffer> {
+        render_with_progress(scene, camera, &self.settings, progress)
+    }
+
+    pub fn render_to_file(&self, scene: &Scene, camera: &Camera) -> Result<Framebuffer> {
+        let framebuffer = self.render(scene, camera)?;
+        framebuffer.save(&self.settings.outputs)?;
+        Ok(framebuffer)
+    }
+
+    // What is visible at the center of pixel `x`, `y` (rows from the top), by
+    // the ray a render would trace there, without lens or pixel jitter. None
+    // for the background or a pixel outside the image.
+    pub fn pick(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        x: u32,
+        y: u32,
+    ) -> Result<Option<PickResult>> {
+        let settings = &self.settings;
+        settings.validate()?;
+        camera.validate()?;
+        let fitted = settings.fitted_camera(camera)?;
+        let camera = fitted.as_ref().unwrap_or(camera);
+        let (width, height) = (settings.width, settings.height);
+        if x >= width || y >= height {
+            return Ok(None);
+        }
+        let s = (x as f64 + 0.5) / width as f64;
+        let t = ((height - 1 - y) as f64 + 0.5) / height as f64;
+        let ray = camera.create_center_ray(s, t);
+        let bounds = Interval::new(settings.ray_epsilon, f64::INFINITY);
+        Ok(scene
+            .hit_object(&ray, bounds)
+            .map(|(id, record)| PickResult {
+                id,
+                point: record.point,
+                normal: record.normal.into(),
+                distance: record.t * ray.direction.len(),
+                material: scene
+                    .materials
+                    .name_of(&record.material)
+                    .map(str::to_string),
+            }))
+    }
+}
+
+// The first hit along a picking ray. The normal faces the camera; the
+// material name is None for materials not registered with the scene.
+#[derive(Debug, Clone)]
+pub struct PickResult {
+    pub id: ObjectId,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub distance: f64,
+    pub material: Option<String>,
+}
+
+// Linear, averaged render results. The auxiliary buffers are only allocated
+// when the matching output was requested.
+pub struct Framebuffer {
+    pub width: u32,
+    pub height: u32,
+    pub beauty: Vec<Color>,
+    pub albedo: Option<Vec<Color>>,
+    pub normal: Option<Vec<Vec3>>,
+    pub depth: Option<ScalarBuffer>,
+    // Per-pixel luminance statistics of the exposed samples, which merge with
+    // those of further passes over the same pixels.
+    pub variance: Option<Vec<Welford>>,
+    // Coverage from 0 to 1 per requested layer.
+    pub masks: BTreeMap<String, ScalarBuffer>,
+    pub non_finite_report: NonFiniteReport,
+    // Set when the progress callback cancelled the render; tiles that were not
+    // rendered are left black.
+    pub cancelled: bool,
+    pub stats: RenderStats,
+    // Settings, camera and timing of the render, stored in the PNG and EXR
+    // images `save` writes. Add to it, e.g. the scene file, before saving.
+    pub metadata: Metadata,
+    // How `beauty_image` and `albedo_image` encode the buffers; the normal
+    // image stays linear.
+    pub output_transform: OutputTransform,
+}
+
+// Totals gathered while rendering. Each tile counts on its own and the totals
+// are added up once it's done, so keeping them costs next to nothing.
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    // Camera rays, one per sample.
+    pub samples: u64,
+    // Every ray traced, camera rays included. The integrator doesn't sample
+    // lights, so there are no shadow rays.
+    pub rays: u64,
+    pub elapsed: Duration,
+    // None when no pixel had a nonzero mean and two samples.
+    pub convergence: Option<ConvergenceSummary>,
+    // Empty unless built with the stats feature.
+    pub counters: Counters,
+    // None when the scene had no BVH.
+    pub bvh_build: Option<Duration>,
+    // None when nothing was denoised.
+    pub denoise: Option<Duration>,
+}
+
+impl RenderStats {
+    // Rays per camera ray, so 1 means nothing was hit.
+    pub fn average_path_length(&self) -> f64 {
+        self.rays as f64 / self.samples.max(1) as f64
+    }
+
+    // Rays that bounced off something, the camera rays taken out.
+    pub fn secondary_rays(&self) -> u64 {
+        self.rays.saturating_sub(self.samples)
+    }
+
+    pub fn samples_per_second(&self) -> f64 {
+        self.samples as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} samples, {} rays ({} secondary), {:.2} rays per path, {:.0} samples/s in {:.2?}",
+            self.samples,
+            self.rays,
+            self.secondary_rays(),
+            self.average_path_length(),
+            self.samples_per_second(),
+            self.elapsed
+        )?;
+        if let Some(convergence) = self.convergence {
+            write!(f, ", {}", convergence)?;
+        }
+        if !self.counters.is_empty() {
+            write!(f, ", {}", PerRay(&self.counters, self.rays))?;
+        }
+        if let Some(bvh_build) = self.bvh_build {
+            write!(f, ", BVH built in {:.2?}", bvh_build)?;
+        }
+        if let Some(denoise) = self.denoise {
+            write!(f, ", denoised in {:.2?}", denoise)?;
+        }
+        Ok(())
+    }
+}
+
+fn to_rgba8(width: u32, height: u32, colors: &[Color], transform: OutputTransform) -> Rgba8Image {
+    let mut data = Vec::with_capacity(colors.len() * 4);
+    for &color in colors {
+        let [r, g, b] = transform.encode(color);
+        data.extend_from_slice(&[r, g, b, 255]);
+    }
+    Rgba8Image {
+        width,
+        height,
+        data,
+        transfer: transform.transfer,
+    }
+}
+
+impl Framebuffer {
+    // The denoiser needs normals and depth even when they aren't saved.
+    fn new(width: u32, height: u32, settings: &RenderSettings) -> Self {
+        let (outputs, denoise) = (&settings.outputs, settings.denoiser.is_some());
+        let len = width as usize * height as usize;
+        let black = Color::BLACK;
+        Framebuffer {
+            width,
+            height,
+            beauty: vec![black; len],
+            albedo: outputs.albedo.as_ref().map(|_| vec![black; len]),
+            normal: (denoise || outputs.normal.is_some())
+                .then(|| vec![Vec3::new(0.0, 0.0, 0.0); len]),
+            depth: (denoise || outputs.depth.is_some()).then(|| ScalarBuffer::new(width, height)),
+            variance: outputs
+                .variance
+                .as_ref()
+                .map(|_| vec![Welford::default(); len]),
+            masks: outputs
+                .masks
+                .keys()
+                .map(|layer| {
+                    let mut mask = ScalarBuffer::new(width, height);
+                    mask.data.fill(0.0);
+                    (layer.clone(), mask)
+                })
+                .collect(),
+            non_finite_report: NonFiniteReport::default(),
+            cancelled: false,
+            stats: RenderStats::default(),
+            metadata: Metadata::default(),
+            output_transform: settings.output_transform,
+        }
+    }
+
+    // Filters the beauty buffer in place, guided by the normal and depth buffers.
+    #[cfg(feature = "denoise")]
+    pub fn denoise(&mut self, denoiser: Denoiser) {
+        let (normal, depth) = match (&self.normal, &self.depth) {
+            (Some(normal), Some(depth)) => (normal, depth),
+            _ => return,
+        };
+        match denoiser {
+            Denoiser::Atrous(atrous) => {
+                self.beauty = denoise_atrous(
+                    &self.beauty,
+                    normal,
+                    &depth.data,
+                    self.width,
+                    self.height,
+                    atrous.iterations,
+                    atrous.sigma_color,
+                    atrous.sigma_normal,
+                    atrous.sigma_depth,
+                );
+            }
+        }
+    }
+
+    pub fn beauty_image(&self) -> Rgba8Image {
+        to_rgba8(self.width, self.height, &self.beauty, self.output_transform)
+    }
+
+    pub fn albedo_image(&self) -> Option<Rgba8Image> {
+        let albedo = self.albedo.as_ref()?;
+        Some(to_rgba8(
+            self.width,
+            self.height,
+            albedo,
+            self.output_transform,
+        ))
+    }
+
+    // Normals are remapped from [-1, 1] to [0, 1] per component.
+    pub fn normal_image(&self) -> Option<Rgba8Image> {
+        let normal = self.normal.as_ref()?;
+        let remapped: Vec<Color> = normal
+            .iter()
+            .map(|&normal| Color::from((normal + Vec3::new(1.0, 1.0, 1.0)) * 0.5))
+            .collect();
+        let transform = OutputTransform {
+            transfer: TransferFunction::Linear,
+            ..self.output_transform
+        };
+        Some(to_rgba8(self.width, self.height, &remapped, transform))
+    }
+
+    // EXR files get the linear colors as they are; the other formats get
+    // 8-bit pixels encoded with `output_transform`.
+    fn save_colors(
+        &self,
+        path: &Path,
+        #[allow(unused_variables)] colors: &[Color],
+        to_image: impl FnOnce() -> Rgba8Image,
+    ) -> Result<()> {
+        #[cfg(feature = "exr")]
+        if output::ImageFormat::from_path(path) == Some(output::ImageFormat::Exr) {
+            return output::write_exr_file(path, self.width, self.height, colors, &self.metadata);
+        }
+        self.write_image(path, &to_image())
+    }
+
+    fn write_image(&self, path: &Path, image: &Rgba8Image) -> Result<()> {
+        let options = OutputOptions::default().with_metadata(self.metadata.clone());
+        output::write_image_with_options(path, image, &options)
+    }
+
+    fn save_variance(&self, path: &Path, variance: &[Welford]) -> Result<()> {
+        #[cfg(feature = "exr")]
+        if output::ImageFormat::from_path(path) == Some(output::ImageFormat::Exr) {
+            let gray: Vec<Color> = variance
+                .iter()
+                .map(|pixel| {
+                    let value = pixel.variance_of_mean();
+                    Color::new(value, value, value)
+                })
+                .collect();
+            return output::write_exr_file(path, self.width, self.height, &gray, &self.metadata);
+        }
+        let mut buffer = ScalarBuffer::new(self.width, self.height);
+        for (value, pixel) in buffer.data.iter_mut().zip(variance) {
+            *value = pixel.variance_of_mean();
+        }
+        let range = FalseColorRange::Percentile {
+            low: 1.0,
+            high: 99.0,
+        };
+        output::write_false_color(path, &buffer, range)
+    }
+
+    // Coverage as linear gray, so 0.5 is half covered in any format.
+    pub fn mask_image(&self, layer: &str) -> Option<Rgba8Image> {
+        let mask = self.masks.get(layer)?;
+        let gray: Vec<Color> = mask
+            .data
+            .iter()
+            .map(|&value| Color::new(value, value, value))
+            .collect();
+        let transform = OutputTransform {
+            transfer: TransferFunction::Linear,
+            ..self.output_transform
+        };
+        Some(to_rgba8(self.width, self.height, &gray, transform))
+    }
+
+    pub fn save(&self, outputs: &MultiOutput) -> Result<()> {
+        let start = Stopwatch::start();
+        self.save_colors(&outputs.beauty, &self.beauty, || self.beauty_image())?;
+        if let (Some(path), Some(albedo)) = (&outputs.albedo, &self.albedo) {
+            self.save_colors(path, albedo, || {
+                to_rgba8(self.width, self.height, albedo, self.output_transform)
+            })?;
+        }
+        if let (Some(path), Some(image)) = (&outputs.normal, self.normal_image()) {
+            self.write_image(path, &image)?;
+        }
+        if let (Some(path), Some(depth)) = (&outputs.depth, &self.depth) {
+            let range = FalseColorRange::Percentile {
+                low: 1.0,
+                high: 99.0,
+            };
+            output::write_false_color(path, depth, range)?;
+        }
+        if let (Some(path), Some(variance)) = (&outputs.variance, &self.variance) {
+            self.save_variance(path, variance)?;
+        }
+        for (layer, path) in &outputs.masks {
+            if let Some(image) = self.mask_image(layer) {
+                self.write_image(path, &image)?;
+            }
+        }
+        log::info!("encoded and saved the images in {:.2?}", start.elapsed());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tile {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+// Per-pixel averages for one tile, row major within the tile. Pixels where
+// every sample was dropped have no beauty value.
+struct RenderedTile {
+    tile: Tile,
+    beauty: Vec<Option<Color>>,
+    first_hits: Vec<Option<FirstHit>>,
+    // Empty unless the variance output was requested.
+    variance: Vec<Welford>,
+    // Coverage of each requested layer, one run of them per pixel.
+    masks: Vec<f64>,
+    convergence: Convergence,
+    report: NonFiniteReport,
+    rays: u64,
+    counters: Counters,
+}
+
+// The tiles covering the image, in the order they should be rendered.
+fn tiles(width: u32, height: u32, order: TileOrder) -> Vec<Tile> {
+    let columns = width.div_ceil(TILE_SIZE);
+    let rows = height.div_ceil(TILE_SIZE);
+    order
+        .coordinates(columns, rows)
+        .map(|(column, row)| {
+            let (x, y) = (column * TILE_SIZE, row * TILE_SIZE);
+            Tile {
+                x,
+                y,
+                width: TILE_SIZE.min(width - x),
+                height: TILE_SIZE.min(height - y),
+            }
+        })
+        .collect()
+}
+
+// A seed for renders that didn't ask for one. Without the os-rng feature there
+// is no entropy source, and every unseeded render uses the same seed.
+pub fn fresh_seed() -> u64 {
+    #[cfg(feature = "os-rng")]
+    return rand::random();
+    #[cfg(not(feature = "os-rng"))]
+    return 0x5eed_5eed_5eed_5eed;
+}
+
+// Every pixel draws from its own generator, derived from the render seed and
+// the pixel alone, and takes its samples from it in order, so sample n always
+// sees the same numbers. Nothing random is shared between pixels, and each
+// pixel adds its samples up in order on one thread, so a seeded render comes
+// out bit for bit the same whatever the thread count, tile size or tile
+// order. (A generator per sample would be simpler still, but setting one up
+// costs about as much as tracing a short path.)
+fn pixel_rng(seed: u64, x: u32, y: u32) -> StdRng {
+    let pixel = (y as u64) << 32 | x as u64;
+    StdRng::seed_from_u64(mix(seed ^ mix(pixel)))
+}
+
+// The SplitMix64 finalizer: a bijection that spreads every input bit over
+// the whole output.
+fn mix(value: u64) -> u64 {
+    let value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+// What a tile gathers besides the beauty image.
+struct TileOutputs {
+    first_hits: bool,
+    variance: bool,
+    // The mask each object counts towards; objects in layers without a mask
+    // are left out.
+    mask_of: BTreeMap<ObjectId, usize>,
+    masks: usize,
+}
+
+impl TileOutputs {
+    fn new(scene: &Scene, framebuffer: &Framebuffer) -> Self {
+        let layers: Vec<&String> = framebuffer.masks.keys().collect();
+        let mask_of: BTreeMap<ObjectId, usize> = scene
+            .layers()
+            .filter_map(|(id, layer)| {
+                let mask = layers.iter().position(|&requested| requested == layer)?;
+                Some((id, mask))
+            })
+            .collect();
+        for layer in &layers {
+            if !scene.layers().any(|(_, other)| other == layer.as_str()) {
+                log::warn!(
+                    "no object is in the layer \"{}\", its mask stays empty",
+                    layer
+                );
+            }
+        }
+        TileOutputs {
+            first_hits: framebuffer.albedo.is_some()
+                || framebuffer.normal.is_some()
+                || framebuffer.depth.is_some(),
+            variance: framebuffer.variance.is_some(),
+            mask_of,
+            masks: layers.len(),
+        }
+    }
+}
+
+fn render_tile(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    tile: Tile,
+    seed: u64,
+    outputs: &TileOutputs,
+) -> RenderedTile {
+    let (width, height) = (settings.width, settings.height);
+    let len = tile.width as usize * tile.height as usize;
+    // Whatever the thread counted before belongs to another tile or render.
+    stats::take();
+    let mut rendered = RenderedTile {
+        tile,
+        beauty: Vec::with_capacity(len),
+        first_hits: Vec::with_capacity(len),
+        variance: Vec::with_capacity(if outputs.variance { len } else { 0 }),
+        masks: Vec::with_capacity(len * outputs.masks),
+        convergence: Convergence::default(),
+        report: NonFiniteReport::default(),
+        rays: 0,
+        counters: Counters::default(),
+    };
+    let mut sample_checker = SampleChecker::new(settings.sample_check);
+    let mut covered = vec![0u32; outputs.masks];
+    for y in tile.y..tile.y + tile.height {
+        for x in tile.x..tile.x + tile.width {
+            let mut color = Color::BLACK;
+            let mut albedo = Color::BLACK;
+            let mut normal = Vec3::new(0.0, 0.0, 0.0);
+            let mut distance = 0.0;
+            let mut accumulated = 0u32;
+            let mut hits = 0u32;
+            let mut variance = Welford::default();
+            covered.iter_mut().for_each(|count| *count = 0);
+            let rng = &mut pixel_rng(seed, x, y);
+            for sample in 0..settings.samples_per_pixel {
+                let ray = camera.create_jittered_ray(x, height - 1 - y, width, height, rng);
+                let (sample_color, info) = ray.color_with_info(
+                    rng,
+                    scene,
+                    settings.max_depth,
+                    settings.ray_epsilon,
+                    settings.path_options(),
+                );
+                rendered.rays += info.rays as u64;
+                let checked = sample_checker.check(sample_color, (x, y), sample, info.non_finite);
+                if let Some(sample_color) = checked {
+                    color += sample_color;
+                    accumulated += 1;
+                    variance.push(sample_color.luminance() * settings.exposure);
+                    let mask = info
+                        .first_hit
+                        .and_then(|first_hit| first_hit.object)
+                        .and_then(|id| outputs.mask_of.get(&id));
+                    if let Some(&mask) = mask {
+                        covered[mask] += 1;
+                    }
+                    if let Some(first_hit) = info.first_hit.filter(|_| outputs.first_hits) {
+                        albedo += first_hit.albedo;
+                        normal += first_hit.normal;
+                        distance += first_hit.distance;
+                        hits += 1;
+                    }
+                }
+            }
+            rendered
+                .beauty
+                .push((accumulated > 0).then(|| color / accumulated as f64));
+            rendered.first_hits.push((hits > 0).then(|| FirstHit {
+                normal: normal / hits as f64,
+                albedo: albedo / hits as f64,
+                distance: distance / hits as f64,
+                // Samples can hit different objects.
+                object: None,
+            }));
+            rendered.convergence.add(&variance);
+            if outputs.variance {
+                rendered.variance.push(variance);
+            }
+            let samples = accumulated.max(1) as f64;
+            rendered
+                .masks
+                .extend(covered.iter().map(|&count| count as f64 / samples));
+        }
+    }
+    rendered.report = sample_checker.report;
+    rendered.counters = stats::take();
+    rendered
+}
+
+// Threads take the tiles in order, one at a time, so the order holds up to the
+// tiles in flight. The results come back in any order.
+#[cfg(feature = "parallel")]
+fn render_tiles<F, T>(tiles: &[Tile], threads: Option<usize>, render_tile: F) -> Vec<T>
+where
+    F: Fn(Tile) -> T + Sync,
+    T: Send,
+{
+    use rayon::prelude::*;
+
+    let run = || {
+        tiles
+            .iter()
+            .par_bridge()
+            .map(|&tile| render_tile(tile))
+            .collect()
+    };
+    // Zero threads lets rayon pick one per core, same as the global pool.
+    match threads.map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build()) {
+        Some(Ok(pool)) => pool.install(run),
+        _ => run(),
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn render_tiles<F, T>(tiles: &[Tile], _threads: Option<usize>, render_tile: F) -> Vec<T>
+where
+    F: Fn(Tile) -> T,
+{
+    tiles.iter().map(|&tile| render_tile(tile)).collect()
+}
+
+pub fn render(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Result<Framebuffer> {
+    render_with_progress(scene, camera, settings, &|_| ProgressControl::Continue)
+}
+
+// The gamma encoded beauty image as 8-bit RGBA, rows from top to bottom, the
+// layout of a canvas' ImageData. Touches neither the filesystem nor any
+// encoder, so it also works in a browser; set a seed there, or build with
+// the os-rng feature and getrandom's JavaScript support.
+pub fn render_to_rgba8(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+) -> Result<Vec<u8>> {
+    Ok(render(scene, camera, settings)?.beauty_image().data)
+}
+
+// One sample of one pixel, bounce by bounce.
+pub struct PixelPath {
+    pub x: u32,
+    pub y: u32,
+    pub sample: u32,
+    pub seed: u64,
+    pub color: Color,
+    pub bounces: Vec<BounceInfo>,
+}
+
+impl fmt::Display for PixelPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "pixel ({}, {}), sample {}, seed {}",
+            self.x, self.y, self.sample, self.seed
+        )?;
+        for bounce in &self.bounces {
+            writeln!(f, "{}", bounce)?;
+        }
+        writeln!(f, "color {:.4}", self.color)
+    }
+}
+
+// Retraces sample `sample` of the pixel at (x, y), counted from the top left,
+// exactly as `render` traced it with the same settings. Its random numbers
+// follow those of the pixel's earlier samples, so these are traced again
+// first. Without a seed in `settings` this traces a fresh, unrelated sample.
+pub fn trace_pixel(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    x: u32,
+    y: u32,
+    sample: u32,
+) -> Result<PixelPath> {
+    settings.validate()?;
+    camera.validate()?;
+    let fitted = settings.fitted_camera(camera)?;
+    let camera = fitted.as_ref().unwrap_or(camera);
+    let (width, height) = (settings.width, settings.height);
+    if x >= width || y >= height {
+        return Err(Error::InvalidSettings(format!(
+            "pixel ({}, {}) is outside the {}x{} image",
+            x, y, width, height
+        )));
+    }
+    if sample >= settings.samples_per_pixel {
+        return Err(Error::InvalidSettings(format!(
+            "sample {} is out of range, pixels get {} samples",
+            sample, settings.samples_per_pixel
+        )));
+    }
+    let seed = settings.seed.unwrap_or_else(fresh_seed);
+    // Same draws as `render_tile`: the samples before this one first.
+    let mut rng = pixel_rng(seed, x, y);
+    for _ in 0..sample {
+        let ray = camera.create_jittered_ray(x, height - 1 - y, width, height, &mut rng);
+        ray.color_with_info(
+            &mut rng,
+            scene,
+            settings.max_depth,
+            settings.ray_epsilon,
+            settings.path_options(),
+        );
+    }
+    let ray = camera.create_jittered_ray(x, height - 1 - y, width, height, &mut rng);
+    let mut recorder = PathRecorder::new();
+    let color = ray.color_observed(
+        &mut rng,
+        scene,
+        settings.max_depth,
+        settings.ray_epsilon,
+        settings.path_options(),
+        &mut recorder,
+    );
+    Ok(PixelPath {
+        x,
+        y,
+        sample,
+        seed,
+        color,
+        bounces: recorder.bounces,
+    })
+}
+
+// `progress` is called after every finished tile, from whichever worker thread
+// finished it. Returning Cancel stops the render once the tiles already in
+// flight are done.
+pub fn render_with_progress(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    progress: &(dyn Fn(Progress) -> ProgressControl + Sync),
+) -> Result<Framebuffer> {
+    settings.validate()?;
+    camera.validate()?;
+    let fitted = settings.fitted_camera(camera)?;
+    let camera = fitted.as_ref().unwrap_or(camera);
+    let (width, height) = (settings.width, settings.height);
+    let seed = settings.seed.unwrap_or_else(fresh_seed);
+    let mut framebuffer = Framebuffer::new(width, height, settings);
+    let outputs = TileOutputs::new(scene, &framebuffer);
+
+    let tiles = tiles(width, height, settings.tile_order);
+    let start = Stopwatch::start();
+    let completed = AtomicUsize::new(0);
+    let cancelled = AtomicBool::new(false);
+    let convergence = Mutex::new(Convergence::default());
+    let rendered = render_tiles(&tiles, settings.threads, |tile| {
+        if cancelled.load(Ordering::Relaxed) {
+            return None;
+        }
+        let rendered = render_tile(scene, camera, settings, tile, seed, &outputs);
+        let convergence = {
+            let mut convergence = convergence.lock().unwrap();
+            convergence.merge(&rendered.convergence);
+            convergence.summary()
+        };
+        let update = Progress {
+            completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+            total: tiles.len(),
+            elapsed: start.elapsed(),
+            convergence,
+        };
+        if progress(update) == ProgressControl::Cancel {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+        Some(rendered)
+    });
+    for rendered in rendered.into_iter().flatten() {
+        let tile = rendered.tile;
+        let pixels = rendered.beauty.iter().zip(&rendered.first_hits);
+        for (offset, (beauty, first_hit)) in pixels.enumerate() {
+            let x = tile.x + offset as u32 % tile.width;
+            let y = tile.y + offset as u32 / tile.width;
+            let index = y as usize * width as usize + x as usize;
+            if let Some(buffer) = &mut framebuffer.variance {
+                buffer[index] = rendered.variance[offset];
+            }
+            let coverage = &rendered.masks[offset * outputs.masks..(offset + 1) * outputs.masks];
+            for (mask, &coverage) in framebuffer.masks.values_mut().zip(coverage) {
+                mask.set(x, y, coverage);
+            }
+            if let Some(beauty) = beauty {
+                framebuffer.beauty[index] = *beauty * settings.exposure;
+            }
+            if let Some(first_hit) = first_hit {
+                if let Some(buffer) = &mut framebuffer.albedo {
+                    buffer[index] = first_hit.albedo;
+                }
+                if let Some(buffer) = &mut framebuffer.normal {
+                    buffer[index] = first_hit.normal;
+                }
+                if let Some(buffer) = &mut framebuffer.depth {
+                    buffer.set(x, y, first_hit.distance);
+                }
+            }
+        }
+        framebuffer.non_finite_report.append(rendered.report);
+        framebuffer.stats.rays += rendered.rays;
+        framebuffer.stats.counters.merge(&rendered.counters);
+        framebuffer.stats.samples +=
+            rendered.beauty.len() as u64 * settings.samples_per_pixel as u64;
+    }
+    // Tiles finish in any order; report the bad samples in a stable one.
+    framebuffer
+        .non_finite_report
+        .samples
+        .sort_by_key(|sample| (sample.pixel.1, sample.pixel.0, sample.sample));
+    framebuffer.stats.elapsed = start.elapsed();
+    framebuffer.metadata = Metadata::for_render(settings, camera, seed, framebuffer.stats.elapsed);
+    framebuffer.stats.convergence = convergence.into_inner().unwrap().summary();
+    framebuffer.stats.bvh_build = scene.bvh_build_time();
+    framebuffer.cancelled = cancelled.into_inner();
+    log::info!("rendered {}x{}: {}", width, height, framebuffer.stats);
+    #[cfg(feature = "denoise")]
+    if let Some(denoiser) = settings.denoiser {
+        let start = Stopwatch::start();
+        framebuffer.denoise(denoiser);
+        let elapsed = start.elapsed();
+        framebuffer.stats.denoise = Some(elapsed);
+        log::info!("denoised in {:.2?}", elapsed);
+    }
+    Ok(framebuffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::background::{ConstantBackground, Sky};
+    use crate::material::{Diffusor, Reflector};
+    use crate::ray_tracing::Sphere;
+
+    fn two_spheres() -> (Scene, Camera) {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            Arc::new(Diffusor {
+                color: Color::new(0.8, 0.8, 0.0),
+            }),
+        )));
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Reflector {
+                color: Color::new(0.8, 0.6, 0.2),
+                fuzz_coeff: 0.3,
+            }),
+        )));
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            4.0 / 3.0,
+            0.0,
+            2.0,
+        );
+        (scene, camera)
+    }
+
+    fn settings(width: u32, height: u32) -> RenderSettings {
+        RenderSettings {
+            width,
+            height,
+            samples_per_pixel: 4,
+            max_depth: 8,
+            seed: Some(7),
+            ..RenderSettings::default()
+        }
+    }
+
+    fn beauty(framebuffer: &Framebuffer) -> Vec<[f64; 3]> {
+        framebuffer
+            .beauty
+            .iter()
+            .map(|color| color.to_array())
+            .collect()
+    }
+
+    #[test]
+    fn auxiliary_outputs_leave_the_beauty_image_alone() {
+        let (scene, camera) = two_spheres();
+        let single = render(&scene, &camera, &settings(16, 12)).unwrap();
+        let mut with_aovs = settings(16, 12);
+        with_aovs.outputs.albedo = Some(PathBuf::from("albedo.bmp"));
+        with_aovs.outputs.normal = Some(PathBuf::from("normal.bmp"));
+        with_aovs.outputs.depth = Some(PathBuf::from("depth.bmp"));
+        let multi = render(&scene, &camera, &with_aovs).unwrap();
+        assert_eq!(beauty(&single), beauty(&multi));
+        assert!(multi.albedo.is_some() && multi.normal.is_some() && multi.depth.is_some());
+        assert!(single.albedo.is_none() && single.normal.is_none() && single.depth.is_none());
+    }
+
+    // `two_spheres` with the ground and the ball in the given layers.
+    fn layered(ground: &str, ball: &str) -> Scene {
+        let (mut scene, _) = two_spheres();
+        let ids = scene.ids().to_vec();
+        scene.set_layer(ids[0], Some(ground.to_string()));
+        scene.set_layer(ids[1], Some(ball.to_string()));
+        scene
+    }
+
+    // Every sample counts towards the layer of what it hit first, so masks of
+    // layers that split the scene add up to the share of samples that hit
+    // anything: 1 where the scene covers the whole pixel, 0 on the sky.
+    #[test]
+    fn complementary_layer_masks_add_up_to_the_coverage() {
+        let (_, camera) = two_spheres();
+        let with_masks = |layers: &[&str]| {
+            let mut settings = settings(32, 24);
+            for &layer in layers {
+                let path = PathBuf::from(format!("{}.bmp", layer));
+                settings.outputs.masks.insert(layer.to_string(), path);
+            }
+            settings
+        };
+        let split = render(
+            &layered("ground", "ball"),
+            &camera,
+            &with_masks(&["ground", "ball"]),
+        )
+        .unwrap();
+        let whole = render(&layered("all", "all"), &camera, &with_masks(&["all"])).unwrap();
+        let (ground, ball, all) = (
+            &split.masks["ground"].data,
+            &split.masks["ball"].data,
+            &whole.masks["all"].data,
+        );
+        for i in 0..all.len() {
+            assert!((ground[i] + ball[i] - all[i]).abs() < 1e-12, "pixel {}", i);
+        }
+        assert!(all.contains(&0.0) && all.contains(&1.0));
+        // Both layers show, and share some pixels along the ball's edge.
+        assert!(ground.contains(&1.0) && ball.contains(&1.0));
+        assert!((0..all.len()).any(|i| ground[i] > 0.0 && ball[i] > 0.0));
+
+        // The masks come from the paths already traced.
+        let plain = render(&layered("ground", "ball"), &camera, &settings(32, 24)).unwrap();
+        assert_eq!(beauty(&plain), beauty(&split));
+        assert_eq!(plain.stats.rays, split.stats.rays);
+    }
+
+    // Each pixel has its own generator and sums its samples in order, so
+    // neither the thread count nor the order the tiles finish in can change a
+    // bit of the result.
+    #[test]
+    fn thread_count_and_tile_order_give_identical_framebuffers() {
+        let (scene, camera) = two_spheres();
+        // 3x3 tiles, more than one per thread.
+        let mut base = settings(96, 72);
+        base.outputs.albedo = Some(PathBuf::from("albedo.bmp"));
+        base.outputs.depth = Some(PathBuf::from("depth.bmp"));
+        let run = |threads: usize, tile_order: TileOrder| {
+            let settings = RenderSettings {
+                threads: Some(threads),
+                tile_order,
+                ..base.clone()
+            };
+            let framebuffer = render(&scene, &camera, &settings).unwrap();
+            let albedo: Vec<[f64; 3]> = framebuffer
+                .albedo
+                .as_ref()
+                .unwrap()
+                .iter()
+                .map(|color| color.to_array())
+                .collect();
+            let depth = framebuffer.depth.as_ref().unwrap().data.clone();
+            (beauty(&framebuffer), albedo, depth)
+        };
+        let single = run(1, TileOrder::Scanline);
+        for &threads in &[2, 4, 7] {
+            for &tile_order in &[TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+                assert!(
+                    run(threads, tile_order) == single,
+                    "{} threads in {:?} order",
+                    threads,
+                    tile_order
+                );
+            }
+        }
+    }
+
+    // The pixel loop as it was before it moved to `OutputTransform`, with its
+    // own clamp that sent NaN to the lower bound.
+    fn old_pixel_loop(colors: &[Color]) -> Vec<u8> {
+        let clamp = |val: f64, bounds: (f64, f64)| {
+            if val.is_nan() || val < bounds.0 {
+                bounds.0
+            } else if val > bounds.1 {
+                bounds.1
+            } else {
+                val
+            }
+        };
+        let mut data = Vec::new();
+        for color in colors {
+            for component in color.to_array() {
+                data.push((clamp(component.sqrt(), (0.0, 0.999)) * 256.0) as u8);
+            }
+            data.push(255);
+        }
+        data
+    }
+
+    #[test]
+    fn legacy_transform_writes_the_old_pixel_loop_bytes() {
+        let values = [
+            -1.0,
+            0.0,
+            1e-6,
+            0.1,
+            0.25,
+            0.5,
+            0.998,
+            0.999,
+            1.0,
+            4.0,
+            f64::NAN,
+            f64::INFINITY,
+        ];
+        let mut colors: Vec<Color> = values.iter().map(|&v| Color::new(v, v, v)).collect();
+        colors.extend((0..=1000).map(|i| {
+            let v = i as f64 / 1000.0;
+            Color::new(v, v * v, 1.0 - v)
+        }));
+        let image = to_rgba8(colors.len() as u32, 1, &colors, OutputTransform::LEGACY);
+        assert_eq!(image.data, old_pixel_loop(&colors));
+    }
+
+    #[test]
+    fn odd_and_single_pixel_resolutions_render() {
+        let (scene, _) = two_spheres();
+        for &(width, height) in &[(3u32, 2u32), (7, 5), (1, 1)] {
+            let camera = Camera::new(
+                Point3::new(0.0, 0.0, 1.0),
+                Point3::new(0.0, 0.0, -1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                60.0f64.to_radians(),
+                width as f64 / height as f64,
+                0.0,
+                2.0,
+            );
+            let framebuffer = render(&scene, &camera, &settings(width, height)).unwrap();
+            assert_eq!(framebuffer.beauty.len(), (width * height) as usize);
+            assert!(framebuffer.beauty.iter().all(|color| color.is_finite()));
+            assert_eq!(
+                framebuffer.beauty_image().data.len(),
+                (width * height * 4) as usize
+            );
+        }
+    }
+
+    #[test]
+    fn a_lone_width_or_height_keeps_the_aspect_ratio_in_whole_pixels() {
+        let settings = settings(1200, 800);
+        assert_eq!(settings.fitted_resolution(Some(1000), None), (1000, 667));
+        assert_eq!(settings.fitted_resolution(None, Some(5)), (8, 5));
+        assert_eq!(settings.fitted_resolution(Some(1), None), (1, 1));
+        assert_eq!(settings.fitted_resolution(Some(7), Some(5)), (7, 5));
+        assert_eq!(settings.fitted_resolution(None, None), (1200, 800));
+    }
+
+    // The pixel `point` shows, projected through the camera's configuration.
+    fn pixel_of(camera: &Camera, settings: &RenderSettings, point: Point3) -> (u32, u32) {
+        let config = camera.config();
+        let w = (config.look_from - config.look_at).to_unit();
+        let u = config.vector_up.cross_product(w).to_unit();
+        let v = w.cross_product(u);
+        let offset = point - config.look_from;
+        let depth = -offset.dot(w);
+        let tan_vertical = (config.fov / 2.0).tan();
+        let s = 0.5 + offset.dot(u) / depth / (2.0 * tan_vertical * config.aspect_ratio);
+        let t = 0.5 + offset.dot(v) / depth / (2.0 * tan_vertical);
+        let x = (s * settings.width as f64) as u32;
+        let row = (t * settings.height as f64) as u32;
+        (x, settings.height - 1 - row)
+    }
+
+    #[test]
+    fn picking_the_center_of_the_demo_scene_finds_the_glass_sphere() {
+        for seed in 0..4 {
+            let (scene, camera, settings) = crate::presets::Preset::Random.setup(Some(seed));
+            // The glass, diffuse and metal hero spheres come last. The glass
+            // one sits at the center of the scene, with the metal one in
+            // front of its middle from this camera, so aim halfway up it.
+            let ids = scene.ids();
+            let glass = ids[ids.len() - 3];
+            let center = Point3::new(0.0, 1.0, 0.0);
+            let (x, y) = pixel_of(&camera, &settings, Point3::new(0.0, 1.5, 0.0));
+            let renderer = Renderer::new(settings.clone());
+            let pick = |x, y| renderer.pick(&scene, &camera, x, y).unwrap();
+            let picked = pick(x, y).unwrap();
+            assert_eq!(picked.id, glass, "seed {}", seed);
+            assert!(((picked.point - center).len() - 1.0).abs() < 1e-9);
+            assert!(
+                picked.distance > 0.0
+                    && picked.distance < (camera.config().look_from - center).len()
+            );
+            // The sky in the top corner and pixels outside the image hold
+            // nothing.
+            assert!(pick(0, 0).is_none());
+            assert!(pick(0, settings.height).is_none());
+        }
+    }
+
+    #[test]
+    fn exposure_scales_the_linear_image() {
+        let gray = Scene::new(Box::new(ConstantBackground(Color::new(0.5, 0.5, 0.5))));
+        let (_, camera) = two_spheres();
+        for &(exposure, expected) in &[(2.0, 1.0), (0.5, 0.25), (0.0, 0.0)] {
+            let settings = RenderSettings {
+                exposure,
+                ..settings(8, 6)
+            };
+            let framebuffer = render(&gray, &camera, &settings).unwrap();
+            for color in &framebuffer.beauty {
+                assert_eq!(color.to_array(), [expected; 3]);
+            }
+        }
+
+        let (scene, camera) = two_spheres();
+        let plain = render(&scene, &camera, &settings(16, 12)).unwrap();
+        let doubled = RenderSettings {
+            exposure: 2.0,
+            ..settings(16, 12)
+        };
+        let doubled = render(&scene, &camera, &doubled).unwrap();
+        for (plain, doubled) in plain.beauty.iter().zip(&doubled.beauty) {
+            assert_eq!((*plain * 2.0).to_array(), doubled.to_array());
+        }
+    }
+}
@@ -0,0 +1,128 @@
+// The order tiles are handed to the render threads. It only changes which
+// parts of the image finish first: every pixel draws its samples from a
+// generator seeded by its position, so the image comes out the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOrder {
+    // Row by row from the top left.
+    Scanline,
+    // Outwards from the center, where the subject usually is.
+    Spiral,
+    // Along a Hilbert curve, so consecutive tiles are neighbours.
+    Hilbert,
+}
+
+impl TileOrder {
+    pub fn from_name(name: &str) -> Option<TileOrder> {
+        match name {
+            "scanline" => Some(TileOrder::Scanline),
+            "spiral" => Some(TileOrder::Spiral),
+            "hilbert" => Some(TileOrder::Hilbert),
+            _ => None,
+        }
+    }
+
+    // Column and row of every tile of a `columns` by `rows` grid, each once.
+    pub fn coordinates(
+        self,
+        columns: u32,
+        rows: u32,
+    ) -> Box<dyn Iterator<Item = (u32, u32)> + Send> {
+        match self {
+            TileOrder::Scanline => Box::new(
+                (0..rows).flat_map(move |row| (0..columns).map(move |column| (column, row))),
+            ),
+            TileOrder::Spiral => Box::new(Spiral::new(columns, rows)),
+            TileOrder::Hilbert => {
+                // The curve fills a power of two square; the part outside the
+                // grid is skipped.
+                let side = columns.max(rows).max(1).next_power_of_two();
+                Box::new(
+                    (0..side as u64 * side as u64)
+                        .map(move |distance| hilbert_point(side, distance))
+                        .filter(move |&(column, row)| column < columns && row < rows),
+                )
+            }
+        }
+    }
+}
+
+// Walks square rings around the center tile: one step right, one down, two
+// left, two up, three right and so on, skipping positions off the grid.
+struct Spiral {
+    columns: u32,
+    rows: u32,
+    position: (i64, i64),
+    direction: usize,
+    leg: u32,
+    step: u32,
+    remaining: u64,
+}
+
+const SPIRAL_DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+impl Spiral {
+    fn new(columns: u32, rows: u32) -> Spiral {
+        Spiral {
+            columns,
+            rows,
+            position: ((columns as i64 - 1) / 2, (rows as i64 - 1) / 2),
+            direction: 0,
+            leg: 1,
+            step: 0,
+            remaining: columns as u64 * rows as u64,
+        }
+    }
+
+    fn advance(&mut self) {
+        let (dx, dy) = SPIRAL_DIRECTIONS[self.direction];
+        self.position = (self.position.0 + dx, self.position.1 + dy);
+        self.step += 1;
+        if self.step == self.leg {
+            self.step = 0;
+            self.direction = (self.direction + 1) % 4;
+            if matches!(self.direction, 0 | 2) {
+                self.leg += 1;
+            }
+        }
+    }
+}
+
+impl Iterator for Spiral {
+    type Item = (u32, u32);
+
+    fn next(&mut self) -> Option<(u32, u32)> {
+        while self.remaining > 0 {
+            let (x, y) = self.position;
+            self.advance();
+            if (0..self.columns as i64).contains(&x) && (0..self.rows as i64).contains(&y) {
+                self.remaining -= 1;
+                return Some((x as u32, y as u32));
+            }
+        }
+        None
+    }
+}
+
+// The point at `distance` along the Hilbert curve through a `side` by `side`
+// square, `side` a power of two.
+fn hilbert_point(side: u32, distance: u64) -> (u32, u32) {
+    let (mut x, mut y) = (0u64, 0u64);
+    let mut rest = distance;
+    let mut size = 1u64;
+    while size < side as u64 {
+        let rx = 1 & (rest / 2);
+        let ry = 1 & (rest ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = size - 1 - x;
+                y = size - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += size * rx;
+        y += size * ry;
+        rest /= 4;
+        size *= 2;
+    }
+    (x as u32, y as u32)
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use crate::ray_tracing::NonFiniteBounce;
+use crate::vec_math::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleCheck {
+    // Accumulate every sample as is.
+    Off,
+    // Drop non-finite samples and average over the remaining ones.
+    Skip,
+    // Count non-finite samples as black.
+    Zero,
+    // Drop non-finite samples and record where they came from.
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NonFiniteSample {
+    pub pixel: (u32, u32),
+    pub sample: u32,
+    pub bounce: Option<NonFiniteBounce>,
+}
+
+#[derive(Default)]
+pub struct NonFiniteReport {
+    pub skipped: usize,
+    pub zeroed: usize,
+    pub samples: Vec<NonFiniteSample>,
+}
+
+impl NonFiniteReport {
+    pub fn is_empty(&self) -> bool {
+        self.skipped == 0 && self.zeroed == 0 && self.samples.is_empty()
+    }
+
+    pub fn append(&mut self, other: NonFiniteReport) {
+        self.skipped += other.skipped;
+        self.zeroed += other.zeroed;
+        self.samples.extend(other.samples);
+    }
+}
+
+impl fmt::Display for NonFiniteReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.skipped > 0 {
+            writeln!(f, "skipped {} non-finite samples", self.skipped)?;
+        }
+        if self.zeroed > 0 {
+            writeln!(f, "replaced {} non-finite samples with black", self.zeroed)?;
+        }
+        for sample in &self.samples {
+            write!(
+                f,
+                "non-finite sample {} at pixel ({}, {})",
+                sample.sample, sample.pixel.0, sample.pixel.1
+            )?;
+            match sample.bounce {
+                Some(bounce) => writeln!(f, ": bounce {} on {}", bounce.bounce, bounce.material)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct SampleChecker {
+    pub mode: SampleCheck,
+    pub report: NonFiniteReport,
+}
+
+impl SampleChecker {
+    pub fn new(mode: SampleCheck) -> Self {
+        SampleChecker {
+            mode,
+            report: NonFiniteReport::default(),
+        }
+    }
+
+    // Returns the color to accumulate, or None if the sample should not count.
+    pub fn check(
+        &mut self,
+        color: Color,
+        pixel: (u32, u32),
+        sample: u32,
+        bounce: Option<NonFiniteBounce>,
+    ) -> Option<Color> {
+        if self.mode == SampleCheck::Off || color.is_finite() {
+            return Some(color);
+        }
+        match self.mode {
+            SampleCheck::Off => Some(color),
+            SampleCheck::Skip => {
+                self.report.skipped += 1;
+                None
+            }
+            SampleCheck::Zero => {
+                self.report.zeroed += 1;
+                Some(Color::new(0.0, 0.0, 0.0))
+            }
+            SampleCheck::Strict => {
+                self.report.samples.push(NonFiniteSample {
+                    pixel,
+                    sample,
+                    bounce,
+                });
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,644 @@
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    error, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::background::{Background, ConstantBackground, Sky};
+pub use crate::material::MaterialDescription;
+use crate::material::{Material, MaterialLibrary};
+use crate::output::{OutputTransform, DEFAULT_OUTPUT};
+use crate::ray_tracing::{Camera, LensDistortion, Scene, Sphere, DEFAULT_RAY_EPSILON};
+use crate::renderer::{MultiOutput, RenderSettings, TileOrder};
+use crate::sample_check::SampleCheck;
+use crate::vec_math::{Color, Vec3};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub settings: SettingsDescription,
+    #[serde(default)]
+    pub background: BackgroundDescription,
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    pub objects: Vec<ObjectDescription>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraDescription {
+    pub look_from: [f64; 3],
+    pub look_at: [f64; 3],
+    pub vector_up: [f64; 3],
+    // Vertical field of view in degrees.
+    pub fov: f64,
+    pub aperture: f64,
+    pub focus_distance: f64,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub chromatic_aberration: f64,
+    #[serde(default, skip_serializing_if = "LensDistortion::is_none")]
+    pub distortion: LensDistortion,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SettingsDescription {
+    pub width: u32,
+    pub height: u32,
+    pub samples_per_pixel: u32,
+    pub max_depth: u32,
+    #[serde(skip_serializing_if = "is_default_ray_epsilon")]
+    pub ray_epsilon: f64,
+    #[serde(skip_serializing_if = "is_default_exposure")]
+    pub exposure: f64,
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+fn is_default_ray_epsilon(ray_epsilon: &f64) -> bool {
+    *ray_epsilon == DEFAULT_RAY_EPSILON
+}
+
+fn is_default_exposure(exposure: &f64) -> bool {
+    *exposure == 1.0
+}
+
+fn is_zero(value: &f64) -> bool {
+    *value == 0.0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl Default for SettingsDescription {
+    fn default() -> Self {
+        SettingsDescription {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            ray_epsilon: DEFAULT_RAY_EPSILON,
+            exposure: 1.0,
+            output: DEFAULT_OUTPUT.to_string(),
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum BackgroundDescription {
+    Sky {
+        zenith_color: [f64; 3],
+        horizon_color: [f64; 3],
+    },
+    Constant([f64; 3]),
+}
+
+impl Default for BackgroundDescription {
+    fn default() -> Self {
+        let sky = Sky::default();
+        BackgroundDescription::Sky {
+            zenith_color: sky.zenith_color.to_array(),
+            horizon_color: sky.horizon_color.to_array(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+        // Unique; `Scene::get_named` finds the object by it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        // For render masks; see `MultiOutput::masks`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        layer: Option<String>,
+        // See `Sphere::with_backface_culling`; not allowed for glass.
+        #[serde(default, skip_serializing_if = "is_false")]
+        cull_backfaces: bool,
+    },
+}
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnknownMaterial {
+        key: String,
+        material: String,
+    },
+    InvalidValue {
+        key: String,
+        message: String,
+    },
+    Unsupported {
+        key: String,
+        type_name: &'static str,
+    },
+    Serialize(String),
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io { path, source } => {
+                write!(f, "cannot access scene file {}: {}", path.display(), source)
+            }
+            SceneFileError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            SceneFileError::UnknownMaterial { key, material } => {
+                write!(f, "{}: unknown material \"{}\"", key, material)
+            }
+            SceneFileError::InvalidValue { key, message } => write!(f, "{}: {}", key, message),
+            SceneFileError::Unsupported { key, type_name } => {
+                write!(f, "{}: {} can't be stored in a scene file", key, type_name)
+            }
+            SceneFileError::Serialize(message) => write!(f, "cannot serialize scene: {}", message),
+        }
+    }
+}
+
+impl error::Error for SceneFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SceneFileError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(key: impl Into<String>, message: impl Into<String>) -> SceneFileError {
+    SceneFileError::InvalidValue {
+        key: key.into(),
+        message: message.into(),
+    }
+}
+
+fn describe_background(background: &dyn Background) -> Option<BackgroundDescription> {
+    let background = background as &dyn Any;
+    if let Some(sky) = background.downcast_ref::<Sky>() {
+        Some(BackgroundDescription::Sky {
+            zenith_color: sky.zenith_color.to_array(),
+            horizon_color: sky.horizon_color.to_array(),
+        })
+    } else {
+        background
+            .downcast_ref::<ConstantBackground>()
+            .map(|constant| BackgroundDescription::Constant(constant.0.to_array()))
+    }
+}
+
+// Names materials in order of first use, or by their name in the scene's
+// library. Objects sharing an `Arc` share the entry, so the file references
+// one material instead of repeating it.
+struct MaterialNames<'a> {
+    library: &'a MaterialLibrary,
+    names: HashMap<*const (), String>,
+    descriptions: BTreeMap<String, MaterialDescription>,
+}
+
+impl MaterialNames<'_> {
+    fn name_of(
+        &mut self,
+        material: &Arc<dyn Material>,
+        key: &str,
+    ) -> Result<String, SceneFileError> {
+        let pointer = Arc::as_ptr(material) as *const ();
+        if let Some(name) = self.names.get(&pointer) {
+            return Ok(name.clone());
+        }
+        let description = MaterialDescription::describe(material.as_ref()).ok_or_else(|| {
+            SceneFileError::Unsupported {
+                key: format!("{}.material", key),
+                type_name: material.name(),
+            }
+        })?;
+        // Generated names skip those the library uses.
+        let name = match self.library.name_of(material) {
+            Some(name) => name.to_string(),
+            None => (self.names.len()..)
+                .map(|index| format!("material_{}", index))
+                .find(|name| self.library.get(name).is_none())
+                .unwrap(),
+        };
+        self.names.insert(pointer, name.clone());
+        self.descriptions.insert(name.clone(), description);
+        Ok(name)
+    }
+}
+
+pub struct LoadedScene {
+    pub scene: Scene,
+    pub camera: Camera,
+    pub settings: RenderSettings,
+}
+
+impl SceneFile {
+    pub fn parse(source: &str) -> Result<SceneFile, SceneFileError> {
+        ron::from_str(source).map_err(|error| SceneFileError::Parse {
+            line: error.position.line,
+            column: error.position.col,
+            message: error.code.to_string(),
+        })
+    }
+
+    pub fn from_scene(
+        scene: &Scene,
+        camera: &Camera,
+        settings: &RenderSettings,
+    ) -> Result<SceneFile, SceneFileError> {
+        let mut materials = MaterialNames {
+            library: &scene.materials,
+            names: HashMap::new(),
+            descriptions: BTreeMap::new(),
+        };
+        let mut objects = Vec::with_capacity(scene.hittables().len());
+        for (index, (hittable, &id)) in scene.hittables().iter().zip(scene.ids()).enumerate() {
+            let key = format!("objects[{}]", index);
+            let sphere = (hittable.as_ref() as &dyn Any)
+                .downcast_ref::<Sphere>()
+                .ok_or_else(|| SceneFileError::Unsupported {
+                    key: key.clone(),
+                    type_name: hittable.name(),
+                })?;
+            objects.push(ObjectDescription::Sphere {
+                center: sphere.center().to_array(),
+                radius: sphere.radius(),
+                material: materials.name_of(sphere.material(), &key)?,
+                name: scene.name_of(id).map(str::to_string),
+                layer: scene.layer_of(id).map(str::to_string),
+                cull_backfaces: sphere.culls_backfaces(),
+            });
+        }
+        let background =
+            describe_background(scene.background.as_ref()).ok_or(SceneFileError::Unsupported {
+                key: "background".to_string(),
+                type_name: scene.background.name(),
+            })?;
+        let config = camera.config();
+        Ok(SceneFile {
+            camera: CameraDescription {
+                look_from: config.look_from.to_array(),
+                look_at: config.look_at.to_array(),
+                vector_up: config.vector_up.to_array(),
+                fov: config.fov.to_degrees(),
+                aperture: config.aperture,
+                focus_distance: config.focus_distance,
+                chromatic_aberration: config.chromatic_aberration,
+                distortion: config.distortion,
+            },
+            settings: SettingsDescription {
+                width: settings.width,
+                height: settings.height,
+                samples_per_pixel: settings.samples_per_pixel,
+                max_depth: settings.max_depth,
+                ray_epsilon: settings.ray_epsilon,
+                exposure: settings.exposure,
+                output: settings.outputs.beauty.to_string_lossy().into_owned(),
+                seed: settings.seed,
+            },
+            background,
+            materials: materials.descriptions,
+            objects,
+        })
+    }
+
+    pub fn to_ron(&self) -> Result<String, SceneFileError> {
+        let config = ron::ser::PrettyConfig::new().indentor("    ".to_string());
+        ron::ser::to_string_pretty(self, config)
+            .map_err(|error| SceneFileError::Serialize(error.to_string()))
+    }
+
+    pub fn build(&self) -> Result<LoadedScene, SceneFileError> {
+        let settings = &self.settings;
+        if settings.width == 0 || settings.height == 0 {
+            return Err(invalid(
+                "settings",
+                format!(
+                    "resolution {}x{} is too small",
+                    settings.width, settings.height
+                ),
+            ));
+        }
+        if settings.samples_per_pixel == 0 {
+            return Err(invalid("settings.samples_per_pixel", "must be at least 1"));
+        }
+        let camera = &self.camera;
+        if !(camera.fov > 0.0 && camera.fov < 180.0) {
+            return Err(invalid(
+                "camera.fov",
+                format!("{} is not between 0 and 180 degrees", camera.fov),
+            ));
+        }
+
+        let background: Box<dyn Background> = match self.background {
+            BackgroundDescription::Sky {
+                zenith_color,
+                horizon_color,
+            } => Box::new(Sky {
+                zenith_color: Color::from(zenith_color),
+                horizon_color: Color::from(horizon_color),
+            }),
+            BackgroundDescription::Constant(color) => {
+                Box::new(ConstantBackground(Color::from(color)))
+            }
+        };
+
+        let mut materials: BTreeMap<&str, Arc<dyn Material>> = BTreeMap::new();
+        for (name, description) in &self.materials {
+            if let MaterialDescription::Refractor { refr_coeff, .. }
+            | MaterialDescription::RoughRefractor { refr_coeff, .. } = *description
+            {
+                if refr_coeff <= 0.0 {
+                    return Err(invalid(
+                        format!("materials.{}.refr_coeff", name),
+                        "must be positive",
+                    ));
+                }
+            }
+            let material = description.build();
+            materials.insert(name, material);
+        }
+
+        let mut scene = Scene::new(background);
+        for (name, material) in &materials {
+            // The file's material names are unique map keys.
+            let _ = scene.materials.register(*name, Arc::clone(material));
+        }
+        for (index, object) in self.objects.iter().enumerate() {
+            match object {
+                ObjectDescription::Sphere {
+                    center,
+                    radius,
+                    material,
+                    name,
+                    layer,
+                    cull_backfaces,
+                } => {
+                    let key = format!("objects[{}]", index);
+                    if *radius <= 0.0 {
+                        return Err(invalid(key + ".radius", "must be positive"));
+                    }
+                    let material = materials.get(material.as_str()).ok_or_else(|| {
+                        SceneFileError::UnknownMaterial {
+                            key: key.clone() + ".material",
+                            material: material.clone(),
+                        }
+                    })?;
+                    if *cull_backfaces && material.transmits() {
+                        return Err(invalid(
+                            key + ".cull_backfaces",
+                            "light goes through the material, it needs its back faces",
+                        ));
+                    }
+                    let sphere = Box::new(
+                        Sphere::new(Vec3::from(*center), *radius, Arc::clone(material))
+                            .with_backface_culling(*cull_backfaces),
+                    );
+                    let id = match name {
+                        Some(name) => match scene.add_named(name.as_str(), sphere) {
+                            Ok(id) => id,
+                            Err(_) => {
+                                return Err(invalid(
+                                    key + ".name",
+                                    format!("\"{}\" is used by another object", name),
+                                ))
+                            }
+                        },
+                        None => scene.add(sphere),
+                    };
+                    scene.set_layer(id, layer.clone());
+                }
+            }
+        }
+        scene.build_bvh();
+
+        let camera = Camera::new(
+            Vec3::from(camera.look_from),
+            Vec3::from(camera.look_at),
+            Vec3::from(camera.vector_up),
+            camera.fov.to_radians(),
+            settings.width as f64 / settings.height as f64,
+            camera.aperture,
+            camera.focus_distance,
+        )
+        .with_chromatic_aberration(camera.chromatic_aberration)
+        .with_distortion(camera.distortion);
+        let settings = RenderSettings {
+            width: settings.width,
+            height: settings.height,
+            samples_per_pixel: settings.samples_per_pixel,
+            max_depth: settings.max_depth,
+            ray_epsilon: settings.ray_epsilon,
+            exposure: settings.exposure,
+            seed: settings.seed,
+            threads: None,
+            tile_order: TileOrder::Scanline,
+            sample_check: SampleCheck::Off,
+            regularization: None,
+            material_override: None,
+            denoiser: None,
+            output_transform: OutputTransform::default(),
+            outputs: MultiOutput::beauty_only(PathBuf::from(&settings.output)),
+        };
+        Ok(LoadedScene {
+            scene,
+            camera,
+            settings,
+        })
+    }
+}
+
+impl Scene {
+    pub fn from_file(path: &Path) -> Result<LoadedScene, SceneFileError> {
+        let source = fs::read_to_string(path).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        SceneFile::parse(&source)?.build()
+    }
+
+    pub fn save(
+        &self,
+        path: &Path,
+        camera: &Camera,
+        settings: &RenderSettings,
+    ) -> Result<(), SceneFileError> {
+        let source = SceneFile::from_scene(self, camera, settings)?.to_ron()?;
+        fs::write(path, source).map_err(|source| SceneFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::material::Diffusor;
+    use crate::presets::{self, RandomSpheresConfig};
+    use crate::ray_tracing::CameraConfig;
+    use crate::renderer::render;
+    use crate::vec_math::Point3;
+
+    fn beauty(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> Vec<[f64; 3]> {
+        let framebuffer = render(scene, camera, settings).unwrap();
+        framebuffer
+            .beauty
+            .iter()
+            .map(|color| color.to_array())
+            .collect()
+    }
+
+    #[test]
+    fn saved_random_scene_renders_the_same_after_loading() {
+        let (scene, camera, settings) = presets::random_spheres(&RandomSpheresConfig {
+            seed: Some(42),
+            ..RandomSpheresConfig::default()
+        });
+        let settings = RenderSettings {
+            width: 24,
+            height: 16,
+            samples_per_pixel: 2,
+            max_depth: 6,
+            ..settings
+        };
+        let path =
+            std::env::temp_dir().join(format!("raytacer-round-trip-{}.ron", std::process::id()));
+        scene.save(&path, &camera, &settings).unwrap();
+        let loaded = Scene::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.scene.hittables().len(), scene.hittables().len());
+        assert_eq!(loaded.settings.seed, Some(42));
+        assert_eq!(
+            beauty(&scene, &camera, &settings),
+            beauty(&loaded.scene, &loaded.camera, &loaded.settings)
+        );
+    }
+
+    #[test]
+    fn shared_materials_are_written_once() {
+        let (_, camera, settings) = presets::checkered();
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let gray: Arc<dyn Material> = Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        });
+        for x in 0..3 {
+            let center = Point3::new(x as f64, 0.0, 0.0);
+            scene.add(Box::new(Sphere::new(center, 0.4, Arc::clone(&gray))));
+        }
+        let file = SceneFile::from_scene(&scene, &camera, &settings).unwrap();
+        assert_eq!(file.materials.len(), 1);
+        let reparsed = SceneFile::parse(&file.to_ron().unwrap()).unwrap();
+        assert_eq!(reparsed.objects.len(), 3);
+        assert_eq!(reparsed.materials.len(), 1);
+    }
+
+    // Serializes `value` to JSON and RON and reads both back, comparing the
+    // debug output, which prints floats exactly.
+    fn assert_round_trips<T: Serialize + DeserializeOwned + fmt::Debug>(value: &T) {
+        let json = serde_json::to_string(value).unwrap();
+        let from_json: T = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            format!("{:?}", from_json),
+            format!("{:?}", value),
+            "{}",
+            json
+        );
+        let ron = ron::to_string(value).unwrap();
+        let from_ron: T = ron::from_str(&ron).unwrap();
+        assert_eq!(format!("{:?}", from_ron), format!("{:?}", value), "{}", ron);
+    }
+
+    #[test]
+    fn vectors_serialize_as_plain_arrays() {
+        assert_round_trips(&Vec3::new(1.0, -2.5, 1e-300));
+        let v = Vec3::new(1.0, -2.5, 0.125);
+        assert_eq!(serde_json::to_string(&v).unwrap(), "[1.0,-2.5,0.125]");
+        assert_eq!(ron::to_string(&v).unwrap(), "(1.0,-2.5,0.125)");
+        assert_round_trips(&Color::new(0.25, 0.5, 0.75));
+    }
+
+    #[test]
+    fn vectors_of_the_wrong_length_are_rejected() {
+        let error = serde_json::from_str::<Vec3>("[1.0, 2.0]").unwrap_err();
+        assert!(
+            error.to_string().contains("expected an array of length 3"),
+            "{}",
+            error
+        );
+        let error = ron::from_str::<Vec3>("(1.0, 2.0)").unwrap_err();
+        assert!(error.to_string().contains("length 3"), "{}", error);
+        assert!(serde_json::from_str::<Vec3>("[1.0, 2.0, 3.0, 4.0]").is_err());
+    }
+
+    #[test]
+    fn camera_configs_round_trip() {
+        let (_, camera, _) = presets::frosted_glass();
+        assert_round_trips(camera.config());
+        let distorted = camera.with_distortion(LensDistortion {
+            k1: -0.1,
+            k2: 0.01,
+            p1: 0.0,
+            p2: 0.002,
+        });
+        let config: &CameraConfig = distorted.config();
+        assert_round_trips(config);
+    }
+
+    #[test]
+    fn material_descriptions_round_trip() {
+        let color = Color::new(0.9, 0.1, 0.3);
+        let materials = [
+            MaterialDescription::Diffusor { color },
+            MaterialDescription::Emitter { color },
+            MaterialDescription::Checker {
+                even: color,
+                odd: Color::new(0.0, 0.0, 0.0),
+                scale: 0.5,
+            },
+            MaterialDescription::Reflector {
+                color,
+                fuzz_coeff: 0.3,
+            },
+            MaterialDescription::Refractor {
+                color,
+                fuzz_coeff: 0.0,
+                refr_coeff: 1.5,
+            },
+            MaterialDescription::RoughRefractor {
+                color,
+                roughness: 0.2,
+                refr_coeff: 1.33,
+            },
+        ];
+        for material in &materials {
+            assert_round_trips(material);
+        }
+    }
+}
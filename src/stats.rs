@@ -0,0 +1,209 @@
+use crate::material::Material;
+use std::fmt;
+
+// Counts from the innermost loops of a render. Each thread counts into its
+// own, the renderer takes them after every tile and adds them up. Without
+// the `stats` feature nothing is counted and the calls compile to nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Counters {
+    pub bvh_nodes: u64,
+    // Hit tests of single objects, after the BVH let them through.
+    pub object_tests: u64,
+    // `Material::scatter` calls by material type name; few enough types
+    // that a list beats a map.
+    pub scatters: Vec<(&'static str, u64)>,
+}
+
+#[cfg(feature = "stats")]
+thread_local! {
+    static COUNTERS: std::cell::RefCell<Counters> = std::cell::RefCell::new(Counters::default());
+}
+
+impl Counters {
+    pub fn is_empty(&self) -> bool {
+        *self == Counters::default()
+    }
+
+    pub fn merge(&mut self, other: &Counters) {
+        self.bvh_nodes += other.bvh_nodes;
+        self.object_tests += other.object_tests;
+        for &(material, count) in &other.scatters {
+            self.add_scatters(material, count);
+        }
+    }
+
+    fn add_scatters(&mut self, material: &'static str, count: u64) {
+        match self.scatters.iter_mut().find(|(name, _)| *name == material) {
+            Some((_, total)) => *total += count,
+            None => self.scatters.push((material, count)),
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn count_bvh_node() {
+    #[cfg(feature = "stats")]
+    COUNTERS.with(|counters| counters.borrow_mut().bvh_nodes += 1);
+}
+
+#[inline]
+pub(crate) fn count_object_test() {
+    #[cfg(feature = "stats")]
+    COUNTERS.with(|counters| counters.borrow_mut().object_tests += 1);
+}
+
+#[inline]
+pub(crate) fn count_scatter(material: &dyn Material) {
+    #[cfg(feature = "stats")]
+    COUNTERS.with(|counters| counters.borrow_mut().add_scatters(material.name(), 1));
+    #[cfg(not(feature = "stats"))]
+    let _ = material;
+}
+
+// What this thread counted since the last call.
+pub(crate) fn take() -> Counters {
+    #[cfg(feature = "stats")]
+    return COUNTERS.with(|counters| std::mem::take(&mut *counters.borrow_mut()));
+    #[cfg(not(feature = "stats"))]
+    return Counters::default();
+}
+
+// Per traced ray, given how many rays there were.
+pub struct PerRay<'a>(pub &'a Counters, pub u64);
+
+impl fmt::Display for PerRay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let PerRay(counters, rays) = *self;
+        let rays = rays.max(1) as f64;
+        write!(
+            f,
+            "{:.1} BVH nodes and {:.2} object tests per ray",
+            counters.bvh_nodes as f64 / rays,
+            counters.object_tests as f64 / rays
+        )?;
+        let mut scatters = counters.scatters.clone();
+        scatters.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        for (index, (material, count)) in scatters.iter().enumerate() {
+            let short = material.rsplit("::").next().unwrap_or(material);
+            let separator = if index == 0 { ", scatters: " } else { ", " };
+            write!(f, "{}{} {}", separator, short, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::background::Sky;
+    use crate::material::{Diffusor, Reflector};
+    use crate::ray_tracing::{Camera, Scene, Sphere};
+    use crate::renderer::{render, RenderSettings};
+    use crate::vec_math::{Color, Point3, Vec3};
+
+    fn ground() -> Arc<Diffusor> {
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.8, 0.0),
+        })
+    }
+
+    // A ball on the ground on one thread, with the stats the render reports.
+    fn render_stats() -> crate::renderer::RenderStats {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        let center = |x| Point3::new(x, 0.0, -1.0);
+        scene.add(Box::new(Sphere::new(
+            Point3::new(0.0, -100.5, -1.0),
+            100.0,
+            ground(),
+        )));
+        scene.add(Box::new(Sphere::new(
+            center(0.0),
+            0.5,
+            Arc::new(Reflector {
+                color: Color::new(0.8, 0.6, 0.2),
+                fuzz_coeff: 0.3,
+            }),
+        )));
+        scene.add(Box::new(Sphere::new(center(-1.0), 0.5, ground())));
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 1.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            1.5,
+            0.0,
+            2.0,
+        );
+        let settings = RenderSettings {
+            width: 48,
+            height: 32,
+            samples_per_pixel: 4,
+            max_depth: 8,
+            seed: Some(3),
+            threads: Some(1),
+            ..RenderSettings::default()
+        };
+        render(&scene, &camera, &settings).unwrap().stats
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn a_render_counts_bvh_visits_and_scatters_per_material() {
+        let stats = render_stats();
+        let counters = &stats.counters;
+        assert!(counters.bvh_nodes > stats.rays, "{:?}", counters);
+        assert!(counters.object_tests > 0, "{:?}", counters);
+        let scatters = |material: &str| {
+            counters
+                .scatters
+                .iter()
+                .find(|(name, _)| name.ends_with(material))
+                .map_or(0, |&(_, count)| count)
+        };
+        assert!(scatters("Diffusor") > 0 && scatters("Reflector") > 0);
+        // Every scatter sends out a ray, except at the depth limit.
+        let total: u64 = counters.scatters.iter().map(|&(_, count)| count).sum();
+        assert!(total >= stats.secondary_rays(), "{:?}", counters);
+    }
+
+    // Making all the counting calls of a render again, on their own, takes a
+    // small part of the time the render took.
+    #[cfg(feature = "stats")]
+    #[test]
+    fn counting_is_a_small_part_of_a_render() {
+        let stats = render_stats();
+        let counters = &stats.counters;
+        let material = ground();
+        let start = std::time::Instant::now();
+        for _ in 0..counters.bvh_nodes {
+            count_bvh_node();
+        }
+        for _ in 0..counters.object_tests {
+            count_object_test();
+        }
+        for &(_, count) in &counters.scatters {
+            for _ in 0..count {
+                count_scatter(material.as_ref());
+            }
+        }
+        let counting = start.elapsed();
+        assert_eq!(take().bvh_nodes, counters.bvh_nodes);
+        assert!(
+            counting * 4 < stats.elapsed,
+            "{:?} counting, {:?} rendering",
+            counting,
+            stats.elapsed
+        );
+    }
+
+    #[cfg(not(feature = "stats"))]
+    #[test]
+    fn without_the_feature_nothing_is_counted() {
+        let stats = render_stats();
+        assert!(stats.rays > 0);
+        assert_eq!(stats.counters, Counters::default());
+    }
+}
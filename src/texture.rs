@@ -0,0 +1,435 @@
+#[cfg(feature = "png-output")]
+mod cache;
+mod wood;
+mod worley;
+
+#[cfg(feature = "png-output")]
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+use crate::output::TransferFunction;
+use crate::ray_tracing::{HitRecord, Ray, UvFootprint};
+use crate::vec_math::{Color, Point3};
+
+#[cfg(feature = "png-output")]
+pub use cache::TextureCache;
+pub use wood::WoodTexture;
+pub use worley::{DistanceMetric, WorleyFeature, WorleyTexture};
+
+// Where a texture is looked up, how far from the ray's origin, and for camera
+// rays how much of the texture one pixel covers.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureLookup {
+    pub u: f64,
+    pub v: f64,
+    pub point: Point3,
+    pub distance: f64,
+    pub footprint: Option<UvFootprint>,
+}
+
+impl TextureLookup {
+    pub fn new(record: &HitRecord, ray: &Ray) -> Self {
+        TextureLookup {
+            u: record.u,
+            v: record.v,
+            point: record.point,
+            distance: record.t * ray.direction.len(),
+            footprint: record.footprint.as_deref().copied(),
+        }
+    }
+}
+
+pub trait Texture: Send + Sync {
+    fn value(&self, lookup: &TextureLookup) -> Color;
+}
+
+// A solid color is the simplest texture.
+impl Texture for Color {
+    fn value(&self, _lookup: &TextureLookup) -> Color {
+        *self
+    }
+}
+
+// Mixes two textures by the luminance of a third, e.g. noise: `low` where the
+// factor is 0, `high` where it is 1.
+pub struct Blend {
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+    pub factor: Arc<dyn Texture>,
+}
+
+impl Texture for Blend {
+    fn value(&self, lookup: &TextureLookup) -> Color {
+        let factor = self.factor.value(lookup).luminance().clamp(0.0, 1.0);
+        Color::lerp(self.low.value(lookup), self.high.value(lookup), factor)
+    }
+}
+
+// SplitMix64's finalizer; good enough to scatter points and noise, and the
+// same on every platform.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// In [0, 1).
+fn unit(x: u64) -> f64 {
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// For the integer lattice point `cell`.
+fn lattice_hash(seed: u64, cell: [f64; 3]) -> u64 {
+    cell.iter().fold(seed, |hash, &coordinate| {
+        mix(hash ^ (coordinate as i64 as u64))
+    })
+}
+
+// What lies past the edges of an image: more copies of it, or its edge texels
+// stretched out. Clamping keeps a black border black, where repeating blends
+// in the opposite edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+}
+
+struct MipLevel {
+    width: usize,
+    height: usize,
+    texels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn texel(&self, x: isize, y: isize, wrap: Wrap) -> Color {
+        let (width, height) = (self.width as isize, self.height as isize);
+        let (x, y) = match wrap {
+            Wrap::Repeat => (x.rem_euclid(width), y.rem_euclid(height)),
+            Wrap::Clamp => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+        };
+        self.texels[y as usize * self.width + x as usize]
+    }
+
+    // Bilinear between the four nearest texel centers. v = 0 is the bottom
+    // row.
+    fn sample(&self, u: f64, v: f64, wrap: Wrap) -> Color {
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let texel = |x, y| self.texel(x, y, wrap);
+        let top = Color::lerp(texel(x0, y0), texel(x0 + 1, y0), fx);
+        let bottom = Color::lerp(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), fx);
+        Color::lerp(top, bottom, fy)
+    }
+
+    // Half the size in both directions, each texel the average of the up to
+    // four it covers. Odd sizes repeat the last row or column.
+    fn downsample(&self) -> MipLevel {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut texels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (y0, y1) = (2 * y, (2 * y + 1).min(self.height - 1));
+            for x in 0..width {
+                let (x0, x1) = (2 * x, (2 * x + 1).min(self.width - 1));
+                let at = |x: usize, y: usize| self.texels[y * self.width + x];
+                texels.push((at(x0, y0) + at(x1, y0) + at(x0, y1) + at(x1, y1)) * 0.25);
+            }
+        }
+        MipLevel {
+            width,
+            height,
+            texels,
+        }
+    }
+}
+
+// The full image and its box-filtered reductions, each half the size of the
+// one before, down to 1x1. Takes about a third more memory than the image.
+pub struct MipPyramid {
+    levels: Vec<MipLevel>,
+}
+
+impl MipPyramid {
+    // Linear colors in rows from top to bottom.
+    pub fn from_colors(width: usize, height: usize, texels: Vec<Color>) -> Result<Self> {
+        if width == 0 || height == 0 || texels.len() != width * height {
+            return Err(Error::InvalidSettings(format!(
+                "{} texels don't make a {}x{} image",
+                texels.len(),
+                width,
+                height
+            )));
+        }
+        let mut levels = vec![MipLevel {
+            width,
+            height,
+            texels,
+        }];
+        while let Some(last) = levels.last().filter(|last| last.width * last.height > 1) {
+            let next = last.downsample();
+            levels.push(next);
+        }
+        Ok(MipPyramid { levels })
+    }
+
+    // 8-bit RGBA in rows from top to bottom, decoded with `transfer`; alpha
+    // is ignored.
+    pub fn from_rgba8(
+        width: usize,
+        height: usize,
+        data: &[u8],
+        transfer: TransferFunction,
+    ) -> Result<Self> {
+        let texels = data
+            .chunks_exact(4)
+            .map(|pixel| {
+                let encoded = Color::new(
+                    pixel[0] as f64 / 255.0,
+                    pixel[1] as f64 / 255.0,
+                    pixel[2] as f64 / 255.0,
+                );
+                transfer.decode_color(encoded)
+            })
+            .collect();
+        MipPyramid::from_colors(width, height, texels)
+    }
+
+    // An 8-bit sRGB PNG file.
+    #[cfg(feature = "png-output")]
+    pub fn open(path: &Path) -> Result<Self> {
+        let (width, height, data) = decode_png(path).map_err(|message| Error::ImageDecode {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        MipPyramid::from_rgba8(width, height, &data, TransferFunction::Srgb)
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        let texels: usize = self.levels.iter().map(|level| level.texels.len()).sum();
+        texels * std::mem::size_of::<Color>()
+    }
+
+    // `level` is fractional, from 0 for the full image, and blends between
+    // the two levels around it.
+    fn sample(&self, level: f64, u: f64, v: f64, wrap: Wrap) -> Color {
+        let lower = level.floor() as usize;
+        let color = self.levels[lower].sample(u, v, wrap);
+        let blend = level - lower as f64;
+        if blend > 0.0 {
+            let upper = self.levels[lower + 1].sample(u, v, wrap);
+            Color::lerp(color, upper, blend)
+        } else {
+            color
+        }
+    }
+}
+
+// The sizes of every level of a pyramid for a `width` x `height` image.
+fn level_sizes(width: usize, height: usize) -> impl Iterator<Item = (usize, usize)> {
+    let mut next = Some((width, height));
+    std::iter::from_fn(move || {
+        let (width, height) = next?;
+        next = (width * height > 1).then(|| ((width / 2).max(1), (height / 2).max(1)));
+        Some((width, height))
+    })
+}
+
+enum Source {
+    Resident(MipPyramid),
+    #[cfg(feature = "png-output")]
+    Cached {
+        path: std::path::PathBuf,
+        cache: Arc<TextureCache>,
+    },
+}
+
+// Shown where a cached image turned out not to decode.
+#[cfg(feature = "png-output")]
+const MISSING: Color = Color::new(1.0, 0.0, 1.0);
+
+// An image mapped onto the surface's u and v, repeating past [0, 1] unless
+// told to clamp, and either held in memory or loaded through a `TextureCache`
+// on first use. Lookups blend between the two levels of its `MipPyramid`
+// whose texels are nearest the size of a pixel, so distant surfaces don't
+// alias. That size comes from the pixel's footprint when the lookup has one,
+// and otherwise from the hit distance and `texel_distance`.
+pub struct ImageTexture {
+    source: Source,
+    width: usize,
+    height: usize,
+    texel_distance: f64,
+    footprints: bool,
+    wrap: Wrap,
+}
+
+impl ImageTexture {
+    pub fn new(pyramid: MipPyramid) -> Self {
+        let (width, height) = (pyramid.levels[0].width, pyramid.levels[0].height);
+        ImageTexture {
+            source: Source::Resident(pyramid),
+            width,
+            height,
+            texel_distance: f64::INFINITY,
+            footprints: true,
+            wrap: Wrap::Repeat,
+        }
+    }
+
+    // Linear colors in rows from top to bottom.
+    pub fn from_colors(width: usize, height: usize, texels: Vec<Color>) -> Result<Self> {
+        MipPyramid::from_colors(width, height, texels).map(ImageTexture::new)
+    }
+
+    // 8-bit RGBA in rows from top to bottom, decoded with `transfer`; alpha
+    // is ignored.
+    pub fn from_rgba8(
+        width: usize,
+        height: usize,
+        data: &[u8],
+        transfer: TransferFunction,
+    ) -> Result<Self> {
+        MipPyramid::from_rgba8(width, height, data, transfer).map(ImageTexture::new)
+    }
+
+    // An 8-bit sRGB PNG file, loaded now.
+    #[cfg(feature = "png-output")]
+    pub fn open(path: &Path) -> Result<Self> {
+        MipPyramid::open(path).map(ImageTexture::new)
+    }
+
+    // An 8-bit sRGB PNG file, loaded by `cache` when first looked up. Only
+    // the header is read now, so a missing or unreadable file fails here, but
+    // one that turns out not to decode shows up magenta.
+    #[cfg(feature = "png-output")]
+    pub fn lazy(path: &Path, cache: &Arc<TextureCache>) -> Result<Self> {
+        let (width, height) = png_size(path).map_err(|message| Error::ImageDecode {
+            path: path.to_path_buf(),
+            message,
+        })?;
+        Ok(ImageTexture {
+            source: Source::Cached {
+                path: path.to_path_buf(),
+                cache: Arc::clone(cache),
+            },
+            width,
+            height,
+            texel_distance: f64::INFINITY,
+            footprints: true,
+            wrap: Wrap::Repeat,
+        })
+    }
+
+    // The distance from the camera at which one texel of the full image
+    // covers about one pixel, for lookups without a footprint, such as after
+    // a bounce. Each doubling of the distance past it moves one level down
+    // the pyramid. Infinite by default, which reads the full image.
+    pub fn with_texel_distance(mut self, texel_distance: f64) -> Self {
+        self.texel_distance = texel_distance;
+        self
+    }
+
+    // Whether to use pixel footprints where lookups have them; on by default.
+    pub fn with_footprints(mut self, footprints: bool) -> Self {
+        self.footprints = footprints;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn level_count(&self) -> usize {
+        level_sizes(self.width, self.height).count()
+    }
+
+    // Of the whole pyramid, whether or not it is loaded right now.
+    pub fn memory_bytes(&self) -> usize {
+        let texels: usize = level_sizes(self.width, self.height)
+            .map(|(width, height)| width * height)
+            .sum();
+        texels * std::mem::size_of::<Color>()
+    }
+
+    // Fractional, from 0 for the full image. The wider of the two pixel steps
+    // decides, which blurs surfaces seen at a grazing angle a little more
+    // than needed.
+    fn level_at(&self, lookup: &TextureLookup) -> f64 {
+        let level = match lookup.footprint.filter(|_| self.footprints) {
+            Some(footprint) => {
+                let (width, height) = (self.width as f64, self.height as f64);
+                let dx = (footprint.du_dx * width).hypot(footprint.dv_dx * height);
+                let dy = (footprint.du_dy * width).hypot(footprint.dv_dy * height);
+                dx.max(dy).log2()
+            }
+            None => (lookup.distance / self.texel_distance).log2(),
+        };
+        if level > 0.0 {
+            level.min((self.level_count() - 1) as f64)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, lookup: &TextureLookup) -> Color {
+        let level = self.level_at(lookup);
+        match &self.source {
+            Source::Resident(pyramid) => pyramid.sample(level, lookup.u, lookup.v, self.wrap),
+            #[cfg(feature = "png-output")]
+            Source::Cached { path, cache } => match cache.get(path) {
+                Some(pyramid) => pyramid.sample(level, lookup.u, lookup.v, self.wrap),
+                None => MISSING,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "png-output")]
+fn decode_png(path: &Path) -> std::result::Result<(usize, usize, Vec<u8>), String> {
+    let file = std::fs::File::open(path).map_err(|error| error.to_string())?;
+    let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|error| error.to_string())?;
+    buffer.truncate(info.buffer_size());
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|pixel| [pixel[0], pixel[0], pixel[0], pixel[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer
+            .iter()
+            .flat_map(|&gray| [gray, gray, gray, 255])
+            .collect(),
+        color_type => return Err(format!("unsupported color type {:?}", color_type)),
+    };
+    Ok((info.width as usize, info.height as usize, rgba))
+}
+
+// Width and height from the header alone.
+#[cfg(feature = "png-output")]
+fn png_size(path: &Path) -> std::result::Result<(usize, usize), String> {
+    let file = std::fs::File::open(path).map_err(|error| error.to_string())?;
+    let decoder = png::Decoder::new(std::io::BufReader::new(file));
+    let reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let info = reader.info();
+    Ok((info.width as usize, info.height as usize))
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::MipPyramid;
+
+struct Entry {
+    // None when the file didn't decode; kept so it is only reported once.
+    pyramid: Option<Arc<MipPyramid>>,
+    bytes: usize,
+    last_used: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_path: HashMap<PathBuf, Entry>,
+    bytes: usize,
+}
+
+// Decoded images shared by every `ImageTexture::lazy` that uses it, loaded on
+// first lookup and dropped least recently used first once they would take
+// more than `budget` bytes. Lookups of loaded images only take the read lock.
+// A single image larger than the whole budget is still kept, alone. Images
+// that were dropped while a lookup was still reading them live on until it is
+// done, which the budget doesn't count.
+pub struct TextureCache {
+    budget: usize,
+    entries: RwLock<Entries>,
+    // One image is decoded at a time, so threads waiting on the same one
+    // don't each decode it and overshoot the budget together.
+    loading: Mutex<()>,
+    clock: AtomicU64,
+    peak: AtomicUsize,
+}
+
+impl TextureCache {
+    pub fn new(budget: usize) -> Self {
+        TextureCache {
+            budget,
+            entries: RwLock::new(Entries::default()),
+            loading: Mutex::new(()),
+            clock: AtomicU64::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    // Bytes of the images loaded right now.
+    pub fn used_bytes(&self) -> usize {
+        self.entries.read().unwrap().bytes
+    }
+
+    // The most `used_bytes` has been.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    // Loads the image at `path` unless it already is, evicting others to make
+    // room. None when it doesn't decode.
+    pub fn get(&self, path: &Path) -> Option<Arc<MipPyramid>> {
+        if let Some(pyramid) = self.find(path) {
+            return pyramid;
+        }
+        let _loading = self.loading.lock().unwrap();
+        if let Some(pyramid) = self.find(path) {
+            return pyramid;
+        }
+        let pyramid = match MipPyramid::open(path) {
+            Ok(pyramid) => Some(Arc::new(pyramid)),
+            Err(error) => {
+                log::error!("{}", error);
+                None
+            }
+        };
+        let bytes = pyramid.as_ref().map_or(0, |pyramid| pyramid.memory_bytes());
+        let mut entries = self.entries.write().unwrap();
+        while entries.bytes + bytes > self.budget {
+            let oldest = entries
+                .by_path
+                .iter()
+                .filter(|(_, entry)| entry.bytes > 0)
+                .min_by_key(|(_, entry)| entry.last_used.load(Ordering::Relaxed))
+                .map(|(path, _)| path.clone());
+            match oldest {
+                Some(oldest) => {
+                    let evicted = entries.by_path.remove(&oldest).unwrap();
+                    entries.bytes -= evicted.bytes;
+                    log::debug!("dropped texture {}", oldest.display());
+                }
+                None => break,
+            }
+        }
+        entries.bytes += bytes;
+        self.peak.fetch_max(entries.bytes, Ordering::Relaxed);
+        entries.by_path.insert(
+            path.to_path_buf(),
+            Entry {
+                pyramid: pyramid.clone(),
+                bytes,
+                last_used: AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed)),
+            },
+        );
+        pyramid
+    }
+
+    // Outer None on a miss.
+    fn find(&self, path: &Path) -> Option<Option<Arc<MipPyramid>>> {
+        let entries = self.entries.read().unwrap();
+        let entry = entries.by_path.get(path)?;
+        entry.last_used.store(
+            self.clock.fetch_add(1, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+        Some(entry.pyramid.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::background::Sky;
+    use crate::material::TexturedDiffusor;
+    use crate::output::{write_image, Rgba8Image, TransferFunction};
+    use crate::ray_tracing::{Camera, Scene, Sphere};
+    use crate::renderer::{render, RenderSettings};
+    use crate::texture::ImageTexture;
+    use crate::vec_math::{Point3, Vec3};
+
+    const TEXTURES: usize = 12;
+
+    // Stripes in a color of their own for each texture.
+    fn write_textures(dir: &Path) -> Vec<PathBuf> {
+        fs::create_dir_all(dir).unwrap();
+        (0..TEXTURES)
+            .map(|index| {
+                let size = 128;
+                let mut data = Vec::with_capacity(size * size * 4);
+                for y in 0..size {
+                    for x in 0..size {
+                        let stripe = ((x + y * index) / 8 % 2) as u8;
+                        data.extend_from_slice(&[
+                            (index * 20) as u8,
+                            stripe * 200,
+                            255 - (index * 20) as u8,
+                            255,
+                        ]);
+                    }
+                }
+                let path = dir.join(format!("texture{}.png", index));
+                let image = Rgba8Image {
+                    width: size as u32,
+                    height: size as u32,
+                    data,
+                    transfer: TransferFunction::Srgb,
+                };
+                write_image(&path, &image).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    // A 4 by 3 grid of spheres, each with one of the textures.
+    fn render_with(paths: &[PathBuf], cache: &Arc<TextureCache>) -> Vec<[f64; 3]> {
+        let mut scene = Scene::new(Box::new(Sky::default()));
+        for (index, path) in paths.iter().enumerate() {
+            let texture = ImageTexture::lazy(path, cache).unwrap();
+            let center = Point3::new(
+                (index % 4) as f64 * 1.1 - 1.65,
+                (index / 4) as f64 * 1.1 - 1.1,
+                -5.0,
+            );
+            scene.add(Box::new(Sphere::new(
+                center,
+                0.5,
+                Arc::new(TexturedDiffusor {
+                    texture: Arc::new(texture),
+                }),
+            )));
+        }
+        scene.build_bvh();
+        let camera = Camera::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0f64.to_radians(),
+            4.0 / 3.0,
+            0.0,
+            5.0,
+        );
+        let settings = RenderSettings {
+            width: 48,
+            height: 36,
+            samples_per_pixel: 4,
+            max_depth: 4,
+            seed: Some(8),
+            ..RenderSettings::default()
+        };
+        let framebuffer = render(&scene, &camera, &settings).unwrap();
+        framebuffer
+            .beauty
+            .iter()
+            .map(|color| color.to_array())
+            .collect()
+    }
+
+    #[test]
+    fn a_small_budget_stays_under_its_cap_and_renders_the_same_image() {
+        let dir = std::env::temp_dir().join(format!("raytacer-textures-{}", std::process::id()));
+        let paths = write_textures(&dir);
+        let one = MipPyramid::open(&paths[0]).unwrap().memory_bytes();
+
+        let unlimited = Arc::new(TextureCache::new(usize::MAX));
+        let expected = render_with(&paths, &unlimited);
+        assert_eq!(unlimited.used_bytes(), TEXTURES * one);
+
+        let budget = 3 * one;
+        let small = Arc::new(TextureCache::new(budget));
+        let image = render_with(&paths, &small);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            small.peak_bytes() <= budget,
+            "{} > {}",
+            small.peak_bytes(),
+            budget
+        );
+        assert!(small.used_bytes() >= one);
+        assert_eq!(image, expected);
+    }
+}
@@ -0,0 +1,61 @@
+use super::{lattice_hash, unit, Texture, TextureLookup};
+use crate::vec_math::{Color, Point3, Vec3};
+
+// Smoothly interpolated random values at the integer lattice, in [-1, 1].
+fn value_noise(point: Point3, seed: u64) -> f64 {
+    let cell = [point.x().floor(), point.y().floor(), point.z().floor()];
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let weights = [
+        smooth(point.x() - cell[0]),
+        smooth(point.y() - cell[1]),
+        smooth(point.z() - cell[2]),
+    ];
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let mut weight = 1.0;
+        let mut lattice = cell;
+        for axis in 0..3 {
+            if corner >> axis & 1 == 1 {
+                lattice[axis] += 1.0;
+                weight *= weights[axis];
+            } else {
+                weight *= 1.0 - weights[axis];
+            }
+        }
+        value += weight * (2.0 * unit(lattice_hash(seed, lattice)) - 1.0);
+    }
+    value
+}
+
+// Growth rings around the line through `center` along `axis`, evaluated at
+// the hit point so solid objects show end grain where they cut across the
+// rings and long grain along them. `rings` is the number of rings per unit
+// of distance from the axis, `sharpness` narrows the dark part of each ring
+// (1 is a plain sine), and `grain` is how far noise pushes the rings in and
+// out, in ring widths. The noise changes over about four rings across the
+// axis and five times slower along it, like the fibres of a trunk.
+pub struct WoodTexture {
+    pub center: Point3,
+    pub axis: Vec3,
+    pub rings: f64,
+    pub light: Color,
+    pub dark: Color,
+    pub sharpness: f64,
+    pub grain: f64,
+    pub seed: u64,
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, lookup: &TextureLookup) -> Color {
+        let axis = self.axis.to_unit_or(Vec3::new(0.0, 1.0, 0.0));
+        let offset = lookup.point - self.center;
+        let along = offset.dot(axis);
+        let across = offset - along * axis;
+        let stretched = across + (0.2 * along) * axis;
+        let noise = value_noise(stretched * (self.rings / 4.0), self.seed);
+        let phase = across.len() * self.rings + self.grain * noise;
+        let ring = 0.5 - 0.5 * (2.0 * std::f64::consts::PI * phase).cos();
+        let dark = ring.powf(self.sharpness.max(0.0));
+        Color::lerp(self.light, self.dark, dark)
+    }
+}
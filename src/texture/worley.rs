@@ -0,0 +1,183 @@
+use super::{lattice_hash, mix, unit, Texture, TextureLookup};
+use crate::vec_math::{Color, Point3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DistanceMetric {
+    Euclidean,
+    Manhattan,
+}
+
+impl DistanceMetric {
+    fn distance(self, a: Point3, b: Point3) -> f64 {
+        let d = a - b;
+        match self {
+            DistanceMetric::Euclidean => d.len(),
+            DistanceMetric::Manhattan => d.x().abs() + d.y().abs() + d.z().abs(),
+        }
+    }
+}
+
+// Which distance to the scattered feature points becomes the value: F1 to
+// the nearest gives round blobs, F2 to the second nearest bulging cells, and
+// F2 - F1 is zero along the borders between cells, for cracks and scales.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorleyFeature {
+    F1,
+    F2,
+    F2MinusF1,
+}
+
+// Cellular noise in 3D, evaluated at the hit point so it needs no u and v.
+// Space is cut into cubes `1 / scale` wide, each holding `points_per_cell`
+// feature points placed by `seed`. Gray, from black at a feature point (or a
+// border, for F2 - F1) up to white about one cell away, so it can drive a
+// `Blend`.
+pub struct WorleyTexture {
+    pub scale: f64,
+    pub points_per_cell: u32,
+    pub metric: DistanceMetric,
+    pub feature: WorleyFeature,
+    pub seed: u64,
+}
+
+impl WorleyTexture {
+    // The nearest and second nearest feature point, in cell units.
+    fn nearest_two(&self, point: Point3) -> (f64, f64) {
+        let cell = [point.x().floor(), point.y().floor(), point.z().floor()];
+        let (mut first, mut second) = (f64::INFINITY, f64::INFINITY);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let corner = [
+                        cell[0] + dx as f64,
+                        cell[1] + dy as f64,
+                        cell[2] + dz as f64,
+                    ];
+                    let mut hash = lattice_hash(self.seed, corner);
+                    for _ in 0..self.points_per_cell {
+                        let mut offset = [0.0; 3];
+                        for axis in offset.iter_mut() {
+                            hash = mix(hash.wrapping_add(0x9e37_79b9_7f4a_7c15));
+                            *axis = unit(hash);
+                        }
+                        let feature = Point3::new(
+                            corner[0] + offset[0],
+                            corner[1] + offset[1],
+                            corner[2] + offset[2],
+                        );
+                        let distance = self.metric.distance(point, feature);
+                        if distance < first {
+                            second = first;
+                            first = distance;
+                        } else if distance < second {
+                            second = distance;
+                        }
+                    }
+                }
+            }
+        }
+        (first, second)
+    }
+}
+
+impl Texture for WorleyTexture {
+    fn value(&self, lookup: &TextureLookup) -> Color {
+        let (first, second) = self.nearest_two(lookup.point * self.scale);
+        let distance = match self.feature {
+            WorleyFeature::F1 => first,
+            WorleyFeature::F2 => second,
+            WorleyFeature::F2MinusF1 => second - first,
+        };
+        // More points make the cells smaller; keep the same range of values.
+        let value = (distance * (self.points_per_cell.max(1) as f64).cbrt()).min(1.0);
+        Color::new(value, value, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec_math::Vec3;
+
+    fn worley(feature: WorleyFeature, points_per_cell: u32, seed: u64) -> WorleyTexture {
+        WorleyTexture {
+            scale: 1.0,
+            points_per_cell,
+            metric: DistanceMetric::Euclidean,
+            feature,
+            seed,
+        }
+    }
+
+    fn gray(texture: &WorleyTexture, point: Point3) -> f64 {
+        let lookup = TextureLookup {
+            u: 0.0,
+            v: 0.0,
+            point,
+            distance: 1.0,
+            footprint: None,
+        };
+        texture.value(&lookup).to_array()[0]
+    }
+
+    // The feature points come from integer hashing alone, so these are the
+    // same everywhere. Only the distances are compared exactly, as `value`
+    // also takes a cube root.
+    #[test]
+    fn a_fixed_seed_gives_fixed_distances() {
+        let texture = worley(WorleyFeature::F1, 2, 7);
+        let expected = [
+            (
+                Point3::new(0.25, 0.5, 0.75),
+                (0.30184320366938516, 0.35113035335980836),
+            ),
+            (
+                Point3::new(-3.1, 2.7, 10.4),
+                (0.567981881197697, 0.659763594399826),
+            ),
+            (
+                Point3::new(100.5, -42.25, 0.0),
+                (0.5200577549818195, 0.5880981803018902),
+            ),
+        ];
+        for &(point, distances) in &expected {
+            assert_eq!(texture.nearest_two(point), distances, "at {}", point);
+        }
+        let value = gray(&texture, expected[0].0);
+        assert!((value - 0.38029860607076377).abs() < 1e-12, "{}", value);
+        let other_seed = worley(WorleyFeature::F1, 2, 8);
+        assert_ne!(other_seed.nearest_two(expected[0].0), expected[0].1);
+    }
+
+    // F2 - F1 is never negative and drops to zero wherever the nearest
+    // feature point changes, which a line through a few cells crosses.
+    #[test]
+    fn f2_minus_f1_vanishes_on_cell_borders() {
+        let texture = worley(WorleyFeature::F2MinusF1, 1, 3);
+        let start = Point3::new(0.1, 0.2, 0.3);
+        let direction = Vec3::new(4.0, 1.0, 0.5);
+        const STEPS: usize = 100_000;
+        let mut smallest = f64::INFINITY;
+        for step in 0..=STEPS {
+            let point = start + direction * (step as f64 / STEPS as f64);
+            let (first, second) = texture.nearest_two(point);
+            assert!(second >= first);
+            smallest = smallest.min(second - first);
+        }
+        assert!(smallest < 1e-3, "{}", smallest);
+    }
+
+    // With no feature points every distance is infinite; every feature
+    // shows plain white rather than NaN.
+    #[test]
+    fn no_points_per_cell_is_white() {
+        for &feature in &[
+            WorleyFeature::F1,
+            WorleyFeature::F2,
+            WorleyFeature::F2MinusF1,
+        ] {
+            let texture = worley(feature, 0, 1);
+            assert_eq!(gray(&texture, Point3::new(0.5, -1.5, 2.5)), 1.0);
+        }
+    }
+}
@@ -1,3 +1,15 @@
+pub mod color;
+pub mod interval;
+pub mod mat4;
+pub mod quat;
 pub mod vec3;
+pub mod vec3f;
 
-pub use vec3::{random_double_in_interval, Color, Point3, Vec3};
+pub use color::Color;
+pub use interval::Interval;
+pub use mat4::{Mat4, SingularMatrix};
+pub use quat::Quat;
+pub use vec3::{
+    approx_eq, lerp, random_double_in_interval, ulps_eq, Point3, UnitVec3, Vec3, WrongLength,
+};
+pub use vec3f::Vec3f;
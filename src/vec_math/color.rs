@@ -0,0 +1,196 @@
+use rand::Rng;
+use std::{fmt, ops};
+
+use super::{Interval, Vec3};
+
+// Linear RGB. Kept apart from Vec3 so colors and positions can't be mixed up,
+// and so `*` between two colors is the component-wise product rather than the
+// dot product.
+#[derive(Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Color(pub Vec3);
+
+impl Color {
+    pub const BLACK: Color = Color(Vec3::new(0.0, 0.0, 0.0));
+    pub const WHITE: Color = Color(Vec3::new(1.0, 1.0, 1.0));
+
+    pub const fn new(r: f64, g: f64, b: f64) -> Color {
+        Color(Vec3::new(r, g, b))
+    }
+
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Color {
+        Color(Vec3::random(rng))
+    }
+
+    pub fn random_in_interval<R: Rng + ?Sized>(rng: &mut R, interval: (f64, f64)) -> Color {
+        Color(Vec3::random_in_interval(rng, interval))
+    }
+
+    pub fn r(&self) -> f64 {
+        self.0.x()
+    }
+
+    pub fn g(&self) -> f64 {
+        self.0.y()
+    }
+
+    pub fn b(&self) -> f64 {
+        self.0.z()
+    }
+
+    pub fn to_array(self) -> [f64; 3] {
+        self.0.to_array()
+    }
+
+    // Relative luminance with the Rec. 709 / sRGB primaries.
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r() + 0.7152 * self.g() + 0.0722 * self.b()
+    }
+
+    pub fn is_black(&self) -> bool {
+        self.r() == 0.0 && self.g() == 0.0 && self.b() == 0.0
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.0.is_finite()
+    }
+
+    pub fn lerp(a: Color, b: Color, t: f64) -> Color {
+        Color(Vec3::lerp(a.0, b.0, t))
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.0.max_component()
+    }
+
+    pub fn clamp(self, min: f64, max: f64) -> Color {
+        Color(self.0.clamp(min, max))
+    }
+
+    pub fn saturate(self, range: Interval) -> Color {
+        Color(self.0.saturate(range))
+    }
+
+    // Encodes linear values for display, raising each component to 1/gamma.
+    pub fn to_gamma(self, gamma: f64) -> Color {
+        // sqrt is exact and much cheaper than powf for the common case.
+        if gamma == 2.0 {
+            return Color::new(self.r().sqrt(), self.g().sqrt(), self.b().sqrt());
+        }
+        let exponent = 1.0 / gamma;
+        Color::new(
+            self.r().powf(exponent),
+            self.g().powf(exponent),
+            self.b().powf(exponent),
+        )
+    }
+
+    // The inverse of `to_gamma`.
+    pub fn to_linear(self, gamma: f64) -> Color {
+        Color::new(
+            self.r().powf(gamma),
+            self.g().powf(gamma),
+            self.b().powf(gamma),
+        )
+    }
+
+    pub fn distance_squared(self, other: Color) -> f64 {
+        (self.0 - other.0).len_squared()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Debug for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Color")
+            .field(&self.r())
+            .field(&self.g())
+            .field(&self.b())
+            .finish()
+    }
+}
+
+impl From<Vec3> for Color {
+    fn from(vector: Vec3) -> Color {
+        Color(vector)
+    }
+}
+
+impl From<Color> for Vec3 {
+    fn from(color: Color) -> Vec3 {
+        color.0
+    }
+}
+
+impl From<[f64; 3]> for Color {
+    fn from(data: [f64; 3]) -> Color {
+        Color(Vec3::from(data))
+    }
+}
+
+impl ops::Add for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Color {
+        Color(self.0 + rhs.0)
+    }
+}
+
+impl ops::AddAssign for Color {
+    fn add_assign(&mut self, rhs: Color) {
+        self.0 += rhs.0;
+    }
+}
+
+impl ops::Mul for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Color {
+        Color(self.0.hadamard(rhs.0))
+    }
+}
+
+impl ops::MulAssign for Color {
+    fn mul_assign(&mut self, rhs: Color) {
+        *self = *self * rhs;
+    }
+}
+
+impl ops::Mul<f64> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: f64) -> Color {
+        Color(self.0 * rhs)
+    }
+}
+
+impl ops::Mul<Color> for f64 {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Color {
+        Color(self * rhs.0)
+    }
+}
+
+impl ops::Div<f64> for Color {
+    type Output = Color;
+
+    fn div(self, rhs: f64) -> Color {
+        Color(self.0 / rhs)
+    }
+}
+
+impl ops::DivAssign<f64> for Color {
+    fn div_assign(&mut self, rhs: f64) {
+        self.0 /= rhs;
+    }
+}
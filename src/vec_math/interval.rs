@@ -0,0 +1,140 @@
+// A closed range of reals. Intervals with min > max are empty.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Interval {
+    pub const EMPTY: Interval = Interval {
+        min: f64::INFINITY,
+        max: f64::NEG_INFINITY,
+    };
+    pub const UNIVERSE: Interval = Interval {
+        min: f64::NEG_INFINITY,
+        max: f64::INFINITY,
+    };
+
+    pub const fn new(min: f64, max: f64) -> Self {
+        Interval { min, max }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max || self.min.is_nan() || self.max.is_nan()
+    }
+
+    // Includes both ends.
+    pub fn contains(&self, x: f64) -> bool {
+        self.min <= x && x <= self.max
+    }
+
+    // Excludes both ends.
+    pub fn surrounds(&self, x: f64) -> bool {
+        self.min < x && x < self.max
+    }
+
+    // Like f64::clamp, but doesn't panic on empty intervals; NaN stays NaN.
+    pub fn clamp(&self, x: f64) -> f64 {
+        if x < self.min {
+            self.min
+        } else if x > self.max {
+            self.max
+        } else {
+            x
+        }
+    }
+
+    // Like `clamp`, but NaN maps to `min`, so the result is always inside
+    // the interval. For output, where a NaN must not become garbage.
+    pub fn saturate(&self, x: f64) -> f64 {
+        if x.is_nan() {
+            self.min
+        } else {
+            self.clamp(x)
+        }
+    }
+
+    // None for NaN, for callers that want to report it rather than hide it.
+    pub fn try_clamp(&self, x: f64) -> Option<f64> {
+        (!x.is_nan()).then(|| self.clamp(x))
+    }
+
+    // Grows the interval by `delta` in total, half on each side.
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval::new(self.min - padding, self.max + padding)
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Interval {
+        Interval::new(self.min.max(other.min), self.max.min(other.max))
+    }
+
+    // The smallest interval containing both.
+    pub fn union(&self, other: &Interval) -> Interval {
+        Interval::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn with_max(&self, max: f64) -> Interval {
+        Interval::new(self.min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIT: Interval = Interval::new(0.0, 1.0);
+
+    #[test]
+    fn saturate_maps_nan_to_the_minimum() {
+        assert_eq!(UNIT.saturate(f64::NAN), 0.0);
+        assert_eq!(UNIT.saturate(-f64::NAN), 0.0);
+        assert_eq!(Interval::new(-2.0, 3.0).saturate(f64::NAN), -2.0);
+        assert!(UNIT.clamp(f64::NAN).is_nan());
+        assert_eq!(UNIT.try_clamp(f64::NAN), None);
+    }
+
+    #[test]
+    fn infinities_clamp_to_the_nearest_bound() {
+        for &clamp in &[Interval::clamp, Interval::saturate] {
+            assert_eq!(clamp(&UNIT, f64::INFINITY), 1.0);
+            assert_eq!(clamp(&UNIT, f64::NEG_INFINITY), 0.0);
+            assert_eq!(clamp(&UNIT, f64::MAX), 1.0);
+            assert_eq!(clamp(&UNIT, -f64::MAX), 0.0);
+        }
+        assert_eq!(UNIT.try_clamp(f64::INFINITY), Some(1.0));
+        assert_eq!(UNIT.try_clamp(f64::NEG_INFINITY), Some(0.0));
+        // An unbounded interval lets them through.
+        assert_eq!(Interval::UNIVERSE.saturate(f64::INFINITY), f64::INFINITY);
+        assert_eq!(
+            Interval::UNIVERSE.saturate(f64::NEG_INFINITY),
+            f64::NEG_INFINITY
+        );
+    }
+
+    #[test]
+    fn values_at_the_bounds_are_kept_exactly() {
+        let range = Interval::new(0.25, 0.999);
+        for &x in &[0.25, 0.999] {
+            assert_eq!(range.clamp(x), x);
+            assert_eq!(range.saturate(x), x);
+            assert_eq!(range.try_clamp(x), Some(x));
+        }
+        let below = 0.25f64 - f64::EPSILON;
+        let above = 0.999f64 + f64::EPSILON;
+        assert_eq!(range.saturate(below), 0.25);
+        assert_eq!(range.saturate(above), 0.999);
+        // -0.0 is inside [0, 1] and keeps its sign.
+        assert!(UNIT.saturate(-0.0).is_sign_negative());
+    }
+
+    #[test]
+    fn empty_intervals_clamp_without_panicking() {
+        assert_eq!(Interval::new(1.0, 0.0).clamp(2.0), 0.0);
+        assert_eq!(Interval::new(1.0, 0.0).clamp(-2.0), 1.0);
+    }
+}
@@ -0,0 +1,283 @@
+use std::{error, fmt, ops};
+
+use super::Vec3;
+
+// Returned when inverting a matrix that has no inverse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SingularMatrix;
+
+impl fmt::Display for SingularMatrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("matrix is singular and can't be inverted")
+    }
+}
+
+impl error::Error for SingularMatrix {}
+
+// A 4x4 matrix for affine transforms, stored row by row. Points and vectors
+// are columns, so `a * b` applies b first, then a.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub rows: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        rows: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub const fn new(rows: [[f64; 4]; 4]) -> Mat4 {
+        Mat4 { rows }
+    }
+
+    pub fn translation(offset: Vec3) -> Mat4 {
+        Mat4::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn scaling(factors: Vec3) -> Mat4 {
+        Mat4::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Counterclockwise by `angle` radians when looking down `axis` towards
+    // the origin.
+    pub fn rotation(axis: Vec3, angle: f64) -> Mat4 {
+        let axis = axis.to_unit();
+        let (x, y, z) = (axis.x(), axis.y(), axis.z());
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Mat4::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Maps camera space to world space, with the same basis the Camera builds:
+    // the camera looks down -z, with +y as close to `up` as possible.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        let w = (eye - target).to_unit();
+        let u = up.cross_product(w).to_unit();
+        let v = w.cross_product(u);
+        Mat4::new([
+            [u.x(), v.x(), w.x(), eye.x()],
+            [u.y(), v.y(), w.y(), eye.y()],
+            [u.z(), v.z(), w.z(), eye.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Mat4::new(rows)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting.
+    pub fn inverse(&self) -> Result<Mat4, SingularMatrix> {
+        let mut left = self.rows;
+        let mut right = Mat4::IDENTITY.rows;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&a, &b| left[a][column].abs().total_cmp(&left[b][column].abs()))
+                .unwrap_or(column);
+            let pivot_value = left[pivot][column];
+            if pivot_value.abs() <= 1e-12 || pivot_value.is_nan() {
+                return Err(SingularMatrix);
+            }
+            left.swap(column, pivot);
+            right.swap(column, pivot);
+            for j in 0..4 {
+                left[column][j] /= pivot_value;
+                right[column][j] /= pivot_value;
+            }
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = left[row][column];
+                for j in 0..4 {
+                    left[row][j] -= factor * left[column][j];
+                    right[row][j] -= factor * right[column][j];
+                }
+            }
+        }
+        Ok(Mat4::new(right))
+    }
+
+    // Applies the full transform, translation included.
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let m = &self.rows;
+        let transformed = self.transform_vector(point) + Vec3::new(m[0][3], m[1][3], m[2][3]);
+        let w = m[3][0] * point.x() + m[3][1] * point.y() + m[3][2] * point.z() + m[3][3];
+        if w == 1.0 {
+            transformed
+        } else {
+            transformed / w
+        }
+    }
+
+    // Ignores translation, for directions and offsets.
+    pub fn transform_vector(&self, vector: Vec3) -> Vec3 {
+        let m = &self.rows;
+        Vec3::new(
+            m[0][0] * vector.x() + m[0][1] * vector.y() + m[0][2] * vector.z(),
+            m[1][0] * vector.x() + m[1][1] * vector.y() + m[1][2] * vector.z(),
+            m[2][0] * vector.x() + m[2][1] * vector.y() + m[2][2] * vector.z(),
+        )
+    }
+
+    // Normals stay perpendicular to the surface only under the inverse
+    // transpose. The result isn't normalized. Callers transforming many
+    // normals should keep `inverse()?.transpose()` around and use
+    // `transform_vector` instead.
+    pub fn transform_normal(&self, normal: Vec3) -> Result<Vec3, SingularMatrix> {
+        Ok(self.inverse()?.transpose().transform_vector(normal))
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::IDENTITY
+    }
+}
+
+impl ops::Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+            }
+        }
+        Mat4::new(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assert_vec_eq;
+
+    fn assert_mat_eq(left: &Mat4, right: &Mat4) {
+        let close =
+            (0..4).all(|i| (0..4).all(|j| (left.rows[i][j] - right.rows[i][j]).abs() < 1e-9));
+        assert!(close, "{:?}\nis not close to\n{:?}", left, right);
+    }
+
+    fn composed() -> Mat4 {
+        Mat4::translation(Vec3::new(1.0, -2.0, 3.5))
+            * Mat4::rotation(Vec3::new(1.0, 1.0, 0.0), 0.7)
+            * Mat4::scaling(Vec3::new(2.0, 0.5, 3.0))
+    }
+
+    #[test]
+    fn matrices_times_their_inverse_are_the_identity() {
+        let look_at = Mat4::look_at(
+            Vec3::new(3.0, 2.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        for matrix in [
+            composed(),
+            look_at,
+            Mat4::IDENTITY,
+            Mat4::scaling(Vec3::new(1e-3, 1e3, 1.0)),
+        ] {
+            let inverse = matrix.inverse().unwrap();
+            assert_mat_eq(&(matrix * inverse), &Mat4::IDENTITY);
+            assert_mat_eq(&(inverse * matrix), &Mat4::IDENTITY);
+        }
+        let point = Vec3::new(0.3, -4.0, 2.0);
+        let matrix = composed();
+        assert_vec_eq!(
+            matrix
+                .inverse()
+                .unwrap()
+                .transform_point(matrix.transform_point(point)),
+            point
+        );
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        let flattened = Mat4::scaling(Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(flattened.inverse(), Err(SingularMatrix));
+        assert_eq!((composed() * flattened).inverse(), Err(SingularMatrix));
+        let repeated_row = Mat4::new([
+            [1.0, 2.0, 3.0, 4.0],
+            [2.0, 4.0, 6.0, 8.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(repeated_row.inverse(), Err(SingularMatrix));
+        assert_eq!(Mat4::new([[f64::NAN; 4]; 4]).inverse(), Err(SingularMatrix));
+        assert!(flattened
+            .transform_normal(Vec3::new(0.0, 1.0, 0.0))
+            .is_err());
+    }
+
+    #[test]
+    fn transformed_normals_stay_perpendicular() {
+        let matrix = composed();
+        let (tangent, normal) = (Vec3::new(1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let transformed = matrix.transform_normal(normal).unwrap();
+        assert!(matrix.transform_vector(tangent).dot(transformed).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rotation_and_look_at_follow_the_conventions() {
+        let quarter = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), std::f64::consts::FRAC_PI_2);
+        assert_vec_eq!(
+            quarter.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+        let eye = Vec3::new(3.0, 2.0, 1.0);
+        let look_at = Mat4::look_at(eye, Vec3::new(0.0, 2.0, 1.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_eq!(look_at.transform_point(Vec3::new(0.0, 0.0, 0.0)), eye);
+        assert_vec_eq!(
+            look_at.transform_vector(Vec3::new(0.0, 0.0, -1.0)),
+            Vec3::new(-1.0, 0.0, 0.0)
+        );
+        assert_vec_eq!(
+            look_at.transform_vector(Vec3::new(0.0, 1.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+    }
+}
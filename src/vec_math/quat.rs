@@ -0,0 +1,247 @@
+use std::ops;
+
+use super::{Mat4, Vec3};
+
+// A rotation as a unit quaternion w + xi + yj + zk. q and -q are the same
+// rotation. Rotations follow Mat4::rotation: counterclockwise when looking
+// down the axis towards the origin, so `a * b` rotates by b, then a.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Quat {
+    pub const IDENTITY: Quat = Quat::new(1.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(w: f64, x: f64, y: f64, z: f64) -> Quat {
+        Quat { w, x, y, z }
+    }
+
+    pub fn from_axis_angle(axis: Vec3, angle: f64) -> Quat {
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let axis = axis.to_unit() * sin;
+        Quat::new(cos, axis.x(), axis.y(), axis.z())
+    }
+
+    // The shortest rotation taking the direction of `from` to that of `to`.
+    pub fn from_rotation_arc(from: Vec3, to: Vec3) -> Quat {
+        let from = from.to_unit();
+        let to = to.to_unit();
+        let cos = from.dot(to);
+        if cos < -1.0 + 1e-12 {
+            // Opposite directions: any axis perpendicular to `from` works.
+            let helper = if from.x().abs() < 0.9 {
+                Vec3::new(1.0, 0.0, 0.0)
+            } else {
+                Vec3::new(0.0, 1.0, 0.0)
+            };
+            return Quat::from_axis_angle(from.cross_product(helper), std::f64::consts::PI);
+        }
+        // Half-way quaternion: (1 + cos, from × to) has twice the half angle's
+        // cosine and sine, so normalizing it gives the rotation directly.
+        let axis = from.cross_product(to);
+        Quat::new(1.0 + cos, axis.x(), axis.y(), axis.z()).normalized()
+    }
+
+    pub fn vector(&self) -> Vec3 {
+        Vec3::new(self.x, self.y, self.z)
+    }
+
+    pub fn dot(&self, other: Quat) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn len(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    // Rounding drifts repeated products away from unit length.
+    pub fn normalized(self) -> Quat {
+        let len = self.len();
+        Quat::new(self.w / len, self.x / len, self.y / len, self.z / len)
+    }
+
+    // The inverse rotation, for unit quaternions.
+    pub fn conjugate(self) -> Quat {
+        Quat::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn rotate(&self, vector: Vec3) -> Vec3 {
+        // v + 2w(q × v) + 2q × (q × v), which skips building the full product.
+        let q = self.vector();
+        let t = 2.0 * q.cross_product(vector);
+        vector + self.w * t + q.cross_product(t)
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        let Quat { w, x, y, z } = *self;
+        Mat4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // Constant angular speed from a (t = 0) to b (t = 1), along the shorter
+    // of the two arcs.
+    pub fn slerp(a: Quat, b: Quat, t: f64) -> Quat {
+        let mut cos = a.dot(b);
+        // b and -b are the same rotation, but interpolating towards the one on
+        // the far side of the sphere takes the long way round.
+        let b = if cos < 0.0 {
+            cos = -cos;
+            -b
+        } else {
+            b
+        };
+        // Nearly parallel: the sine below vanishes, and a normalized lerp is
+        // indistinguishable.
+        if cos > 1.0 - 1e-9 {
+            return Quat::new(
+                a.w + (b.w - a.w) * t,
+                a.x + (b.x - a.x) * t,
+                a.y + (b.y - a.y) * t,
+                a.z + (b.z - a.z) * t,
+            )
+            .normalized();
+        }
+        let angle = cos.acos();
+        let sin = angle.sin();
+        let weight_a = ((1.0 - t) * angle).sin() / sin;
+        let weight_b = (t * angle).sin() / sin;
+        Quat::new(
+            weight_a * a.w + weight_b * b.w,
+            weight_a * a.x + weight_b * b.x,
+            weight_a * a.y + weight_b * b.y,
+            weight_a * a.z + weight_b * b.z,
+        )
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::IDENTITY
+    }
+}
+
+impl From<Quat> for Mat4 {
+    fn from(rotation: Quat) -> Mat4 {
+        rotation.to_mat4()
+    }
+}
+
+impl ops::Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat::new(-self.w, -self.x, -self.y, -self.z)
+    }
+}
+
+impl ops::Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, rhs: Quat) -> Quat {
+        Quat::new(
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+    use crate::assert_vec_eq;
+
+    // The angle between two rotations, whichever sign either one has.
+    fn angle_between(a: Quat, b: Quat) -> f64 {
+        2.0 * a.dot(b).abs().min(1.0).acos()
+    }
+
+    #[test]
+    fn quarter_turn_about_z_takes_x_to_y() {
+        let quarter = Quat::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        assert_vec_eq!(
+            quarter.rotate(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+        let matrix = quarter.to_mat4();
+        assert_vec_eq!(
+            matrix.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0)
+        );
+        let reference = Mat4::rotation(Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2);
+        let v = Vec3::new(0.3, -1.2, 2.0);
+        assert_vec_eq!(matrix.transform_vector(v), reference.transform_vector(v));
+        // Composition applies the right-hand rotation first.
+        let about_x = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), FRAC_PI_2);
+        assert_vec_eq!(
+            (quarter * about_x).rotate(v),
+            quarter.rotate(about_x.rotate(v))
+        );
+    }
+
+    #[test]
+    fn rotation_arc_takes_one_direction_to_the_other() {
+        let cases = [
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)),
+            (Vec3::new(1.0, 2.0, 3.0), Vec3::new(-3.0, 0.5, 1.0)),
+            (Vec3::new(0.0, 0.0, 2.0), Vec3::new(0.0, 0.0, -5.0)),
+            (Vec3::new(1.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)),
+        ];
+        for (from, to) in cases {
+            let arc = Quat::from_rotation_arc(from, to);
+            assert!((arc.len() - 1.0).abs() < 1e-12);
+            assert_vec_eq!(arc.rotate(from.to_unit()), to.to_unit());
+        }
+    }
+
+    #[test]
+    fn slerp_takes_the_short_arc() {
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let start = Quat::from_axis_angle(axis, 0.0);
+        let end = Quat::from_axis_angle(axis, 0.5);
+        // -end is the same rotation, half way round the quaternion sphere.
+        for target in [end, -end] {
+            let middle = Quat::slerp(start, target, 0.5);
+            assert!(angle_between(middle, Quat::from_axis_angle(axis, 0.25)) < 1e-9);
+            for step in 0..=10 {
+                let t = step as f64 / 10.0;
+                let between = Quat::slerp(start, target, t);
+                assert!((between.len() - 1.0).abs() < 1e-12);
+                assert!((angle_between(start, between) - 0.5 * t).abs() < 1e-9);
+            }
+        }
+        // Across the half-turn mark the short way goes backwards through
+        // 180 degrees rather than forwards through 0.
+        let a = Quat::from_axis_angle(axis, PI - 0.1);
+        let b = Quat::from_axis_angle(axis, -PI + 0.1);
+        let middle = Quat::slerp(a, b, 0.5);
+        assert!(angle_between(middle, Quat::from_axis_angle(axis, PI)) < 1e-9);
+        assert_vec_eq!(Quat::slerp(a, b, 0.0).rotate(axis), axis);
+    }
+}
@@ -1,28 +1,110 @@
-use rand::prelude::ThreadRng;
 use rand::Rng;
-use std::{cmp::Ordering, ops};
+use std::convert::TryFrom;
+use std::iter::FromIterator;
+use std::{error, fmt, ops, slice};
 
-#[derive(Debug, Clone, Copy)]
+use super::Interval;
+
+// With the serde feature, serialized as a plain `[x, y, z]` array.
+#[derive(Clone, Copy)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "[f64; 3]", into = "[f64; 3]")
+)]
 pub struct Vec3 {
-    pub data: [f64; 3],
+    data: [f64; 3],
 }
 
-pub fn random_double_in_interval(rng: &mut ThreadRng, interval: (f64, f64)) -> f64 {
+pub fn random_double_in_interval<R: Rng + ?Sized>(rng: &mut R, interval: (f64, f64)) -> f64 {
     interval.0 + (interval.1 - interval.0) * rng.gen::<f64>()
 }
 
+// Exact at both ends: t = 0 gives a and t = 1 gives b.
+pub fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    (1.0 - t) * a + t * b
+}
+
+// |a - b| <= epsilon. Infinities only equal themselves; NaN equals nothing.
+pub fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
+    a == b || (a - b).abs() <= epsilon
+}
+
+// Maps floats onto integers so that adjacent floats are adjacent integers,
+// with -0.0 and 0.0 both at zero.
+fn ordered_bits(x: f64) -> i64 {
+    let bits = x.to_bits() as i64;
+    if bits < 0 {
+        i64::MIN - bits
+    } else {
+        bits
+    }
+}
+
+// True when a and b are at most `max_ulps` representable floats apart, which
+// scales with their magnitude, unlike `approx_eq`.
+pub fn ulps_eq(a: f64, b: f64, max_ulps: u64) -> bool {
+    if a.is_nan() || b.is_nan() {
+        return false;
+    }
+    if a == b {
+        return true;
+    }
+    let distance = (ordered_bits(a) as i128 - ordered_bits(b) as i128).unsigned_abs();
+    distance <= max_ulps as u128
+}
+
+// Compares two vectors with `Vec3::approx_eq`, which defaults to an epsilon
+// of 1e-9, and prints both on failure.
+#[macro_export]
+macro_rules! assert_vec_eq {
+    ($left:expr, $right:expr) => {
+        $crate::assert_vec_eq!($left, $right, 1e-9)
+    };
+    ($left:expr, $right:expr, $epsilon:expr) => {{
+        let (left, right): ($crate::vec_math::Vec3, $crate::vec_math::Vec3) = ($left, $right);
+        let epsilon: f64 = $epsilon;
+        if !left.approx_eq(&right, epsilon) {
+            panic!(
+                "assertion failed: `left ~= right` (epsilon {})\n  left: {}\n right: {}",
+                epsilon, left, right
+            );
+        }
+    }};
+}
+
 impl Vec3 {
-    pub fn new(x: f64, y: f64, z: f64) -> Vec3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3 {
         Vec3 { data: [x, y, z] }
     }
 
-    pub fn random(rng: &mut ThreadRng) -> Vec3 {
+    pub fn x(&self) -> f64 {
+        self.data[0]
+    }
+
+    pub fn y(&self) -> f64 {
+        self.data[1]
+    }
+
+    pub fn z(&self) -> f64 {
+        self.data[2]
+    }
+
+    pub fn to_array(self) -> [f64; 3] {
+        self.data
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, f64> {
+        self.data.iter()
+    }
+
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         Vec3 {
             data: [rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()],
         }
     }
 
-    pub fn random_in_interval(rng: &mut ThreadRng, interval: (f64, f64)) -> Vec3 {
+    pub fn random_in_interval<R: Rng + ?Sized>(rng: &mut R, interval: (f64, f64)) -> Vec3 {
         Vec3 {
             data: [
                 random_double_in_interval(rng, interval),
@@ -32,16 +114,16 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_sphere(rng: &mut ThreadRng) -> Vec3 {
+    pub fn random_in_unit_sphere<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
             let random_vector = Vec3::random_in_interval(rng, (-1.0, 1.0));
-            if random_vector.len_squared().partial_cmp(&1.0).unwrap() == std::cmp::Ordering::Less {
+            if random_vector.len_squared() < 1.0 {
                 return random_vector;
             }
         }
     }
 
-    pub fn random_in_unit_disk(rng: &mut ThreadRng) -> Vec3 {
+    pub fn random_in_unit_disk<R: Rng + ?Sized>(rng: &mut R) -> Vec3 {
         loop {
             let random_vector = Vec3::new(
                 random_double_in_interval(rng, (-1.0, 1.0)),
@@ -54,20 +136,110 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_hemisphere(rng: &mut ThreadRng, normal: Vec3) -> Vec3 {
+    pub fn random_in_hemisphere<R: Rng + ?Sized>(rng: &mut R, normal: Vec3) -> Vec3 {
         let random_in_unit_sphere = Vec3::random_in_unit_sphere(rng);
-        if (random_in_unit_sphere * normal).partial_cmp(&0.0).unwrap() == Ordering::Greater {
+        if random_in_unit_sphere * normal > 0.0 {
             random_in_unit_sphere
         } else {
-            return -random_in_unit_sphere;
+            -random_in_unit_sphere
         }
     }
 
+    // Samples a microfacet normal from the GGX distribution of normals visible
+    // from `v`, with spherical caps (Dupuy and Benyoub 2023, "Sampling Visible
+    // GGX Normals with Spherical Caps"). Works in the local frame where the
+    // surface normal is +z, with `v` pointing away from the point seen;
+    // `roughness` is the GGX alpha. `v` may come from below the surface, as
+    // it does for a ray bouncing between microfacets. Reflecting -v about the
+    // result gives the scattered direction.
+    pub fn sample_ggx_vndf<R: Rng + ?Sized>(v: Vec3, roughness: f64, rng: &mut R) -> Vec3 {
+        // Stretch the view vector so the distribution becomes a hemisphere.
+        // A grazing view with zero roughness stretches to nothing; treat it
+        // like a view along the normal.
+        let stretched = Vec3::new(roughness * v.x(), roughness * v.y(), v.z())
+            .to_unit_or(Vec3::new(0.0, 0.0, 1.0));
+
+        // A direction on the cap of the unit sphere above -stretched.z; added
+        // to the view, it gives a visible normal of the hemisphere.
+        let phi = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+        let z = (1.0 - rng.gen::<f64>()) * (1.0 + stretched.z()) - stretched.z();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        let normal = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), z) + stretched;
+
+        // Unstretch back to the ellipsoid.
+        Vec3::new(
+            roughness * normal.x(),
+            roughness * normal.y(),
+            normal.z().max(0.0),
+        )
+        .to_unit_or(Vec3::new(0.0, 0.0, 1.0))
+    }
+
     pub fn near_zero(&self) -> bool {
         let sigma = 1e-8;
-        return self.data[0].abs().partial_cmp(&sigma).unwrap() == Ordering::Less
-            && self.data[1].abs().partial_cmp(&sigma).unwrap() == Ordering::Less
-            && self.data[2].abs().partial_cmp(&sigma).unwrap() == Ordering::Less;
+        // NaN components compare false, so a NaN vector is never near zero.
+        self.data.iter().all(|component| component.abs() < sigma)
+    }
+
+    pub fn approx_eq(&self, other: &Vec3, epsilon: f64) -> bool {
+        (0..3).all(|axis| approx_eq(self[axis], other[axis], epsilon))
+    }
+
+    pub fn ulps_eq(&self, other: &Vec3, max_ulps: u64) -> bool {
+        (0..3).all(|axis| ulps_eq(self[axis], other[axis], max_ulps))
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.data.iter().all(|component| component.is_finite())
+    }
+
+    pub fn clamp(self, min: f64, max: f64) -> Vec3 {
+        Vec3::new(
+            self.data[0].clamp(min, max),
+            self.data[1].clamp(min, max),
+            self.data[2].clamp(min, max),
+        )
+    }
+
+    // Component-wise `Interval::saturate`: NaN components map to the minimum.
+    pub fn saturate(self, range: Interval) -> Vec3 {
+        Vec3::new(
+            range.saturate(self.data[0]),
+            range.saturate(self.data[1]),
+            range.saturate(self.data[2]),
+        )
+    }
+
+    pub fn min(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.x().min(other.x()),
+            self.y().min(other.y()),
+            self.z().min(other.z()),
+        )
+    }
+
+    pub fn max(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.x().max(other.x()),
+            self.y().max(other.y()),
+            self.z().max(other.z()),
+        )
+    }
+
+    pub fn abs(self) -> Vec3 {
+        Vec3::new(self.x().abs(), self.y().abs(), self.z().abs())
+    }
+
+    pub fn min_component(&self) -> f64 {
+        self.x().min(self.y()).min(self.z())
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.x().max(self.y()).max(self.z())
+    }
+
+    pub fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+        (1.0 - t) * a + t * b
     }
 
     pub fn len(&self) -> f64 {
@@ -78,6 +250,11 @@ impl Vec3 {
         self.data[0] * self.data[0] + self.data[1] * self.data[1] + self.data[2] * self.data[2]
     }
 
+    // Same as `*` between two vectors, but harder to mistake for scaling.
+    pub fn dot(&self, rhs: Vec3) -> f64 {
+        *self * rhs
+    }
+
     pub fn cross_product(&self, rhs: Vec3) -> Vec3 {
         Vec3::new(
             self.data[1] * rhs.data[2] - self.data[2] * rhs.data[1],
@@ -86,23 +263,261 @@ impl Vec3 {
         )
     }
 
-    pub fn to_unit(&self) -> Vec3 {
+    // Component-wise product, e.g. for tinting one color by another. `*`
+    // between two vectors is the dot product.
+    pub fn hadamard(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x() * rhs.x(), self.y() * rhs.y(), self.z() * rhs.z())
+    }
+
+    // Component-wise quotient. Zero components in `rhs` give inf or NaN like
+    // scalar division does; callers that care should check `is_finite`.
+    pub fn hadamard_div(self, rhs: Vec3) -> Vec3 {
+        Vec3::new(self.x() / rhs.x(), self.y() / rhs.y(), self.z() / rhs.z())
+    }
+
+    // The component of `self` along `onto`. `onto` doesn't need to be unit
+    // length, but a zero vector gives NaN.
+    pub fn project(self, onto: Vec3) -> Vec3 {
+        (self * onto / (onto * onto)) * onto
+    }
+
+    // The component of `self` perpendicular to `from`.
+    pub fn reject(self, from: Vec3) -> Vec3 {
+        self - self.project(from)
+    }
+
+    // For vectors known to have a direction. A zero vector, or one so short
+    // its length underflows, would come out as NaN; debug builds catch that.
+    pub fn to_unit(self) -> Vec3 {
         let len = self.len();
-        *self / len
+        let unit = self / len;
+        debug_assert!(
+            unit.is_finite() || !self.is_finite(),
+            "{} has no direction",
+            self
+        );
+        unit
+    }
+
+    // None for zero and non-finite vectors. Vectors too short or too long for
+    // `len` are scaled by their largest component first, so e.g. 1e-200
+    // components still give a direction.
+    pub fn try_to_unit(self) -> Option<Vec3> {
+        if !self.is_finite() {
+            return None;
+        }
+        if self.len_squared().is_normal() {
+            return Some(self / self.len());
+        }
+        let largest = self.abs().max_component();
+        if largest == 0.0 {
+            return None;
+        }
+        let scaled = self / largest;
+        Some(scaled / scaled.len())
     }
 
-    pub fn reflect(&self, normal: &Vec3) -> Vec3 {
-        *self - (2.0 * (*self) * (*normal)) * (*normal)
+    pub fn to_unit_or(self, fallback: Vec3) -> Vec3 {
+        self.try_to_unit().unwrap_or(fallback)
     }
 
-    pub fn refract(&self, normal: &Vec3, refraction_ratio: f64) -> Vec3 {
-        let cos_theta = (-*self * (*normal)).min(1.0);
-        let r_out_perp = refraction_ratio * (*self + cos_theta * (*normal));
-        let r_out_parallel = -((1.0 - r_out_perp.len_squared()).abs().sqrt()) * (*normal);
+    // `to_unit` as a `UnitVec3`, with the same debug assertion for vectors
+    // without a direction.
+    pub fn normalized(self) -> UnitVec3 {
+        UnitVec3::new_unchecked(self.to_unit())
+    }
+
+    pub fn reflect(&self, normal: &UnitVec3) -> Vec3 {
+        *self - (2.0 * self.dot(**normal)) * **normal
+    }
+}
+
+// A vector of length one, for the places that assume normalized input.
+#[derive(Debug, Clone, Copy)]
+pub struct UnitVec3(Vec3);
+
+impl UnitVec3 {
+    // For vectors that are unit length by construction, like a sphere's
+    // surface offset divided by its radius. Non-finite vectors are let through
+    // so degenerate geometry can still be reported later.
+    pub fn new_unchecked(vector: Vec3) -> UnitVec3 {
+        debug_assert!(
+            !vector.is_finite() || (vector.len_squared() - 1.0).abs() < 1e-6,
+            "{} is not unit length",
+            vector
+        );
+        UnitVec3(vector)
+    }
+
+    // For geometry that may be degenerate, like the normal of edges that span
+    // no area: a vector without a direction comes out as NaN, which the
+    // `validate` methods report, rather than tripping `normalized`.
+    pub fn or_nan(vector: Vec3) -> UnitVec3 {
+        UnitVec3(vector.to_unit_or(Vec3::new(f64::NAN, f64::NAN, f64::NAN)))
+    }
+
+    pub fn get(self) -> Vec3 {
+        self.0
+    }
+
+    // Both `self` and `normal` must point into the surface and out of it
+    // respectively, as in `Refractor::scatter`.
+    pub fn refract(&self, normal: &UnitVec3, refraction_ratio: f64) -> Vec3 {
+        let (direction, normal) = (self.0, normal.0);
+        let cos_theta = (-direction).dot(normal).min(1.0);
+        let r_out_perp = refraction_ratio * (direction + cos_theta * normal);
+        let r_out_parallel = -((1.0 - r_out_perp.len_squared()).abs().sqrt()) * normal;
         r_out_perp + r_out_parallel
     }
 }
 
+impl ops::Deref for UnitVec3 {
+    type Target = Vec3;
+
+    fn deref(&self) -> &Vec3 {
+        &self.0
+    }
+}
+
+impl ops::Neg for UnitVec3 {
+    type Output = UnitVec3;
+
+    fn neg(self) -> UnitVec3 {
+        UnitVec3(-self.0)
+    }
+}
+
+impl From<UnitVec3> for Vec3 {
+    fn from(unit: UnitVec3) -> Vec3 {
+        unit.0
+    }
+}
+
+// Prints `(x, y, z)`; a precision like `{:.3}` applies to every component.
+impl fmt::Display for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.precision() {
+            Some(precision) => write!(
+                f,
+                "({:.*}, {:.*}, {:.*})",
+                precision,
+                self.x(),
+                precision,
+                self.y(),
+                precision,
+                self.z()
+            ),
+            None => write!(f, "({}, {}, {})", self.x(), self.y(), self.z()),
+        }
+    }
+}
+
+impl fmt::Debug for Vec3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Vec3")
+            .field(&self.x())
+            .field(&self.y())
+            .field(&self.z())
+            .finish()
+    }
+}
+
+impl From<[f64; 3]> for Vec3 {
+    fn from(data: [f64; 3]) -> Vec3 {
+        Vec3 { data }
+    }
+}
+
+impl From<(f64, f64, f64)> for Vec3 {
+    fn from((x, y, z): (f64, f64, f64)) -> Vec3 {
+        Vec3::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(vector: Vec3) -> [f64; 3] {
+        vector.data
+    }
+}
+
+impl From<Vec3> for (f64, f64, f64) {
+    fn from(vector: Vec3) -> (f64, f64, f64) {
+        (vector.x(), vector.y(), vector.z())
+    }
+}
+
+// Returned when converting a slice whose length isn't 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WrongLength(pub usize);
+
+impl fmt::Display for WrongLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected 3 components, got {}", self.0)
+    }
+}
+
+impl error::Error for WrongLength {}
+
+impl TryFrom<&[f64]> for Vec3 {
+    type Error = WrongLength;
+
+    fn try_from(slice: &[f64]) -> Result<Vec3, WrongLength> {
+        match *slice {
+            [x, y, z] => Ok(Vec3::new(x, y, z)),
+            _ => Err(WrongLength(slice.len())),
+        }
+    }
+}
+
+// Takes the first three items; panics if there are fewer.
+impl FromIterator<f64> for Vec3 {
+    fn from_iter<I: IntoIterator<Item = f64>>(iter: I) -> Vec3 {
+        let mut iter = iter.into_iter();
+        let mut data = [0.0; 3];
+        for (index, component) in data.iter_mut().enumerate() {
+            *component = iter.next().unwrap_or_else(|| {
+                panic!("Vec3 needs 3 components, the iterator yielded {}", index)
+            });
+        }
+        Vec3 { data }
+    }
+}
+
+impl<'a> IntoIterator for &'a Vec3 {
+    type Item = &'a f64;
+    type IntoIter = slice::Iter<'a, f64>;
+
+    fn into_iter(self) -> slice::Iter<'a, f64> {
+        self.iter()
+    }
+}
+
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &f64 {
+        match self.data.get(index) {
+            Some(component) => component,
+            None => panic!(
+                "Vec3 index out of range: the index is {} but a Vec3 has 3 components",
+                index
+            ),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, index: usize) -> &mut f64 {
+        match self.data.get_mut(index) {
+            Some(component) => component,
+            None => panic!(
+                "Vec3 index out of range: the index is {} but a Vec3 has 3 components",
+                index
+            ),
+        }
+    }
+}
+
 impl ops::Add<Vec3> for Vec3 {
     type Output = Self;
 
@@ -192,4 +607,449 @@ impl ops::Neg for Vec3 {
 }
 
 pub type Point3 = Vec3;
-pub type Color = Vec3;
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn nan_and_infinite_vectors_are_not_near_zero() {
+        assert!(Vec3::new(1e-9, -1e-9, 0.0).near_zero());
+        assert!(!Vec3::new(f64::NAN, 0.0, 0.0).near_zero());
+        assert!(!Vec3::new(0.0, f64::INFINITY, 0.0).near_zero());
+        assert!(!Vec3::new(0.0, 0.0, f64::NEG_INFINITY).near_zero());
+    }
+
+    #[test]
+    fn saturate_clamps_each_component_and_zeroes_nan() {
+        let unit = Interval::new(0.0, 1.0);
+        let saturated = Vec3::new(f64::NAN, f64::INFINITY, f64::NEG_INFINITY).saturate(unit);
+        assert_eq!(saturated.to_array(), [0.0, 1.0, 0.0]);
+        let bounds = Vec3::new(0.0, 1.0, 0.5).saturate(unit);
+        assert_eq!(bounds.to_array(), [0.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn hemisphere_sampling_survives_a_nan_normal() {
+        let rng = &mut StdRng::seed_from_u64(1);
+        let normal = Vec3::new(f64::NAN, 0.0, 1.0);
+        for _ in 0..100 {
+            let sample = Vec3::random_in_hemisphere(rng, normal);
+            assert!(sample.is_finite() && sample.len() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let normal = UnitVec3::new_unchecked(Vec3::new(0.0, 1.0, 0.0));
+        for &ratio in &[1.0 / 1.5, 1.0 / 1.33, 0.9, 1.2] {
+            for step in 0..9 {
+                let theta_i: f64 = step as f64 * 10.0f64.to_radians();
+                let sin_t = ratio * theta_i.sin();
+                if sin_t > 1.0 {
+                    continue;
+                }
+                let incoming = Vec3::new(theta_i.sin(), -theta_i.cos(), 0.0).normalized();
+                let refracted = incoming.refract(&normal, ratio);
+                let expected = Vec3::new(sin_t, -(1.0 - sin_t * sin_t).sqrt(), 0.0);
+                assert!(
+                    refracted.approx_eq(&expected, 1e-12),
+                    "ratio {}, {} degrees: {} instead of {}",
+                    ratio,
+                    theta_i.to_degrees(),
+                    refracted,
+                    expected
+                );
+            }
+        }
+    }
+
+    // The GGX distribution of normals, for a normal in the frame where the
+    // surface normal is +z.
+    fn ggx_d(h: Vec3, alpha: f64) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = alpha * alpha;
+        let denominator = (alpha2 - 1.0) * h.z() * h.z() + 1.0;
+        alpha2 / (std::f64::consts::PI * denominator * denominator)
+    }
+
+    // The density of the direction `sample_ggx_vndf` reflects -v into:
+    // D_v(h) / (4 v.h), with D_v(h) = G1(v) max(0, v.h) D(h) / v.z.
+    fn reflected_pdf(v: Vec3, l: Vec3, alpha: f64) -> f64 {
+        let h = (v + l).to_unit();
+        let g1 =
+            2.0 * v.z() / (v.z() + (alpha * alpha * (1.0 - v.z() * v.z()) + v.z() * v.z()).sqrt());
+        let v_dot_h = v.dot(h);
+        if v_dot_h <= 0.0 {
+            return 0.0;
+        }
+        g1 * v_dot_h * ggx_d(h, alpha) / v.z() / (4.0 * v_dot_h)
+    }
+
+    fn reflect_about(v: Vec3, h: Vec3) -> Vec3 {
+        2.0 * v.dot(h) * h - v
+    }
+
+    // Which of 8 bins a direction falls into: 4 bands of height above or
+    // below the surface, on the side of the view or away from it.
+    fn bin(l: Vec3) -> usize {
+        let band = ((l.z() + 1.0) * 2.0).clamp(0.0, 3.999) as usize;
+        band * 2 + (l.x() > 0.0) as usize
+    }
+
+    #[test]
+    fn ggx_vndf_samples_follow_the_visible_normal_pdf() {
+        const SAMPLES: usize = 10_000;
+        let rng = &mut StdRng::seed_from_u64(5);
+        for &(alpha, theta) in &[(0.5f64, 45.0f64), (0.3, 70.0), (0.8, 20.0)] {
+            let theta = theta.to_radians();
+            let v = Vec3::new(theta.sin(), 0.0, theta.cos());
+            let mut sampled = [0.0; 8];
+            for _ in 0..SAMPLES {
+                let h = Vec3::sample_ggx_vndf(v, alpha, rng);
+                assert!(h.z() >= 0.0 && (h.len() - 1.0).abs() < 1e-9);
+                assert!(v.dot(h) >= -1e-12, "{} is not visible from {}", h, v);
+                sampled[bin(reflect_about(v, h))] += 1.0 / SAMPLES as f64;
+            }
+            // The same bins, integrated over the sphere from uniform samples.
+            let mut integrated = [0.0; 8];
+            let mut total = 0.0;
+            const UNIFORM: usize = 200_000;
+            for _ in 0..UNIFORM {
+                let l = Vec3::random_in_unit_sphere(rng).to_unit();
+                let weight =
+                    reflected_pdf(v, l, alpha) * 4.0 * std::f64::consts::PI / UNIFORM as f64;
+                integrated[bin(l)] += weight;
+                total += weight;
+            }
+            assert!(
+                (total - 1.0).abs() < 0.05,
+                "alpha {}: pdf integrates to {}",
+                alpha,
+                total
+            );
+            for (bin, (&sampled, &integrated)) in sampled.iter().zip(&integrated).enumerate() {
+                let tolerance = 0.05 * integrated.max(0.1);
+                assert!(
+                    (sampled - integrated).abs() < tolerance,
+                    "alpha {}, bin {}: sampled {} against {}",
+                    alpha,
+                    bin,
+                    sampled,
+                    integrated
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn components_read_and_write_by_index_and_accessor() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!((v[0], v[1], v[2]), (v.x(), v.y(), v.z()));
+        assert_eq!((v.x(), v.y(), v.z()), (1.0, 2.0, 3.0));
+        v[1] = -4.0;
+        v[2] *= 2.0;
+        assert_eq!(v.to_array(), [1.0, -4.0, 6.0]);
+    }
+
+    #[test]
+    fn vectors_without_a_direction_have_no_unit_vector() {
+        let zero = Vec3::new(0.0, 0.0, 0.0);
+        assert!(zero.try_to_unit().is_none());
+        assert!((-zero).try_to_unit().is_none());
+        assert!(Vec3::new(f64::NAN, 0.0, 0.0).try_to_unit().is_none());
+        assert!(Vec3::new(0.0, f64::INFINITY, 0.0).try_to_unit().is_none());
+        let fallback = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(zero.to_unit_or(fallback).to_array(), [0.0, 0.0, 1.0]);
+        assert_eq!(
+            Vec3::new(0.0, -2.0, 0.0).to_unit_or(fallback).to_array(),
+            [0.0, -1.0, 0.0]
+        );
+        // The smallest subnormal still has a direction.
+        assert_eq!(
+            Vec3::new(f64::from_bits(1), 0.0, 0.0)
+                .try_to_unit()
+                .map(Vec3::to_array),
+            Some([1.0, 0.0, 0.0])
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "has no direction")]
+    fn normalizing_a_zero_vector_fails_the_debug_assertion() {
+        Vec3::new(0.0, 0.0, 0.0).normalized();
+    }
+
+    #[test]
+    fn or_nan_keeps_degenerate_vectors_visible() {
+        assert!(!UnitVec3::or_nan(Vec3::new(0.0, 0.0, 0.0)).is_finite());
+        assert_vec_eq!(
+            UnitVec3::or_nan(Vec3::new(0.0, 3.0, 4.0)).get(),
+            Vec3::new(0.0, 0.6, 0.8)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "the index is 3 but a Vec3 has 3 components")]
+    fn index_out_of_range_panics() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        let _ = v[3];
+    }
+
+    #[test]
+    #[should_panic(expected = "the index is 7 but a Vec3 has 3 components")]
+    fn mutable_index_out_of_range_panics() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        v[7] = 0.0;
+    }
+
+    fn bits(components: [f64; 3]) -> [u64; 3] {
+        components.map(f64::to_bits)
+    }
+
+    proptest! {
+        #[test]
+        fn arrays_and_tuples_round_trip(x in any::<f64>(), y in any::<f64>(), z in any::<f64>()) {
+            let v = Vec3::from([x, y, z]);
+            prop_assert_eq!(bits(v.into()), bits([x, y, z]));
+            let (a, b, c): (f64, f64, f64) = Vec3::from((x, y, z)).into();
+            prop_assert_eq!(bits([a, b, c]), bits([x, y, z]));
+            prop_assert_eq!(bits(v.to_array()), bits([v.x(), v.y(), v.z()]));
+        }
+
+        #[test]
+        fn slices_and_iterators_round_trip(components in prop::array::uniform3(any::<f64>())) {
+            let v = Vec3::try_from(&components[..]).unwrap();
+            prop_assert_eq!(bits(v.to_array()), bits(components));
+            let collected: Vec3 = v.iter().copied().collect();
+            prop_assert_eq!(bits(collected.to_array()), bits(components));
+            let chained: Vec3 = components.iter().copied().chain([1.0, 2.0]).collect();
+            prop_assert_eq!(bits(chained.to_array()), bits(components));
+        }
+
+        #[test]
+        fn slices_of_other_lengths_are_rejected(components in prop::collection::vec(any::<f64>(), 0..8)) {
+            let result = Vec3::try_from(&components[..]);
+            if components.len() == 3 {
+                prop_assert!(result.is_ok());
+            } else {
+                prop_assert_eq!(result.unwrap_err(), WrongLength(components.len()));
+            }
+        }
+    }
+
+    fn finite() -> impl Strategy<Value = [f64; 3]> {
+        prop::array::uniform3(-1e300..1e300f64)
+    }
+
+    // The component-wise helpers against the same operation done one
+    // component at a time.
+    proptest! {
+        #[test]
+        fn min_max_and_abs_match_the_scalar_loops(a in finite(), b in finite()) {
+            let (u, v) = (Vec3::from(a), Vec3::from(b));
+            let mut min = [0.0; 3];
+            let mut max = [0.0; 3];
+            let mut abs = [0.0; 3];
+            for i in 0..3 {
+                min[i] = a[i].min(b[i]);
+                max[i] = a[i].max(b[i]);
+                abs[i] = a[i].abs();
+            }
+            prop_assert_eq!(bits(u.min(v).to_array()), bits(min));
+            prop_assert_eq!(bits(u.max(v).to_array()), bits(max));
+            prop_assert_eq!(bits(u.abs().to_array()), bits(abs));
+        }
+
+        #[test]
+        fn min_and_max_component_match_the_scalar_loops(a in finite()) {
+            let v = Vec3::from(a);
+            let mut min = f64::INFINITY;
+            let mut max = f64::NEG_INFINITY;
+            for &component in &a {
+                min = min.min(component);
+                max = max.max(component);
+            }
+            prop_assert_eq!(v.min_component(), min);
+            prop_assert_eq!(v.max_component(), max);
+        }
+
+        #[test]
+        fn lerp_matches_the_scalar_loop(a in finite(), b in finite(), t in 0.0..=1.0f64) {
+            let mut lerp = [0.0; 3];
+            for i in 0..3 {
+                lerp[i] = (1.0 - t) * a[i] + t * b[i];
+            }
+            let (u, v) = (Vec3::from(a), Vec3::from(b));
+            prop_assert_eq!(bits(Vec3::lerp(u, v, t).to_array()), bits(lerp));
+            prop_assert_eq!(Vec3::lerp(u, v, 0.0).to_array(), a);
+            prop_assert_eq!(Vec3::lerp(u, v, 1.0).to_array(), b);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "the iterator yielded 2")]
+    fn collecting_fewer_than_three_components_panics() {
+        let _: Vec3 = [1.0, 2.0].iter().copied().collect();
+    }
+
+    #[test]
+    fn approx_eq_takes_an_absolute_tolerance() {
+        assert!(approx_eq(1.0, 1.0 + 1e-10, 1e-9));
+        assert!(!approx_eq(1.0, 1.0 + 1e-8, 1e-9));
+        assert!(approx_eq(f64::INFINITY, f64::INFINITY, 0.0));
+        assert!(!approx_eq(f64::INFINITY, f64::NEG_INFINITY, 1e300));
+        assert!(!approx_eq(f64::NAN, f64::NAN, 1.0));
+        let v = Vec3::new(1.0, -2.0, 3.0);
+        assert!(v.approx_eq(&(v + Vec3::new(1e-10, 0.0, -1e-10)), 1e-9));
+        assert!(!v.approx_eq(&(v + Vec3::new(0.0, 1e-6, 0.0)), 1e-9));
+        crate::assert_vec_eq!(v, v + Vec3::new(5e-10, 0.0, 0.0));
+    }
+
+    #[test]
+    fn ulps_eq_scales_with_magnitude() {
+        let next = |x: f64| f64::from_bits(x.to_bits() + 1);
+        assert!(ulps_eq(1.0, next(next(1.0)), 2));
+        assert!(!ulps_eq(1.0, next(next(1.0)), 1));
+        assert!(ulps_eq(1e20, next(1e20), 1) && !approx_eq(1e20, next(1e20), 1e-9));
+        assert!(ulps_eq(0.0, -0.0, 0));
+        assert!(ulps_eq(f64::from_bits(1), -f64::from_bits(1), 2));
+        assert!(!ulps_eq(f64::NAN, f64::NAN, u64::MAX));
+        let v = Vec3::new(1.0, 1e10, -1e-10);
+        assert!(v.ulps_eq(&Vec3::new(next(1.0), 1e10, -1e-10), 1));
+        assert!(!v.ulps_eq(&Vec3::new(1.0, 1e10, -1e-10 * 1.000001), 4));
+    }
+
+    #[test]
+    #[should_panic(expected = "left ~= right")]
+    fn assert_vec_eq_panics_on_a_difference() {
+        crate::assert_vec_eq!(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1e-6, 0.0));
+    }
+
+    fn vector() -> impl Strategy<Value = Vec3> {
+        prop::array::uniform3(-100.0..100.0f64).prop_map(Vec3::from)
+    }
+
+    fn unit_vector() -> impl Strategy<Value = UnitVec3> {
+        vector()
+            .prop_filter("too short for a direction", |v| v.len() > 1e-3)
+            .prop_map(Vec3::normalized)
+    }
+
+    proptest! {
+        #[test]
+        fn reflecting_twice_gives_the_vector_back(v in vector(), normal in unit_vector()) {
+            let twice = v.reflect(&normal).reflect(&normal);
+            prop_assert!(twice.approx_eq(&v, 1e-9 * v.len().max(1.0)), "{} became {}", v, twice);
+        }
+
+        #[test]
+        fn refracting_with_ratio_one_goes_straight_through(
+            direction in unit_vector(),
+            normal in unit_vector(),
+        ) {
+            // `refract` expects the direction to point into the surface.
+            let normal = if direction.dot(*normal) > 0.0 { -normal } else { normal };
+            let refracted = direction.refract(&normal, 1.0);
+            prop_assert!(refracted.approx_eq(&direction, 1e-7), "{} became {}", *direction, refracted);
+        }
+
+        #[test]
+        fn projection_and_rejection_add_up_to_the_vector(v in vector(), normal in unit_vector()) {
+            let (along, across) = (v.project(*normal), v.reject(*normal));
+            let tolerance = 1e-9 * v.len().max(1.0);
+            prop_assert!((along + across).approx_eq(&v, tolerance));
+            prop_assert!(across.dot(*normal).abs() < tolerance);
+            prop_assert!(along.cross_product(*normal).len() < tolerance);
+        }
+
+        #[test]
+        fn cross_product_is_orthogonal_to_both_factors(a in vector(), b in vector()) {
+            let cross = a.cross_product(b);
+            let tolerance = 1e-12 * a.len() * b.len() * a.len().max(b.len()).max(1.0);
+            prop_assert!(cross.dot(a).abs() <= tolerance, "{} . {}", cross, a);
+            prop_assert!(cross.dot(b).abs() <= tolerance, "{} . {}", cross, b);
+        }
+
+        #[test]
+        fn cross_product_is_anticommutative(a in vector(), b in vector()) {
+            prop_assert_eq!(a.cross_product(b).to_array(), (-b.cross_product(a)).to_array());
+        }
+
+        #[test]
+        fn reflection_keeps_the_length(v in vector(), normal in unit_vector()) {
+            let reflected = v.reflect(&normal);
+            prop_assert!((reflected.len() - v.len()).abs() <= 1e-12 * v.len().max(1.0));
+        }
+
+        #[test]
+        fn unit_vectors_have_length_one(v in vector(), exponent in -300i32..300) {
+            prop_assume!(v.len() > 1e-3);
+            prop_assert!((v.to_unit().len() - 1.0).abs() < 1e-15);
+            prop_assert!((v.normalized().len() - 1.0).abs() < 1e-15);
+            // try_to_unit also copes with lengths that over- or underflow.
+            let scaled = v * 10f64.powi(exponent);
+            let unit = scaled.try_to_unit().unwrap();
+            prop_assert!((unit.len() - 1.0).abs() < 1e-15, "{} from {}", unit, scaled);
+        }
+    }
+
+    // Also checks the spread: uniform samples have a mean squared length of
+    // 3/5 in the ball and 1/2 in the disk.
+    #[test]
+    fn unit_ball_and_disk_samples_stay_inside_and_center_on_zero() {
+        const SAMPLES: usize = 20_000;
+        for seed in 0..4 {
+            let rng = &mut StdRng::seed_from_u64(seed);
+            let zero = Vec3::new(0.0, 0.0, 0.0);
+            let (mut ball, mut disk) = (zero, zero);
+            let (mut ball_squared, mut disk_squared) = (0.0, 0.0);
+            for _ in 0..SAMPLES {
+                let sample = Vec3::random_in_unit_sphere(rng);
+                assert!(sample.len_squared() < 1.0, "{}", sample);
+                ball += sample;
+                ball_squared += sample.len_squared();
+                let sample = Vec3::random_in_unit_disk(rng);
+                assert!(
+                    sample.len_squared() < 1.0 && sample.z() == 0.0,
+                    "{}",
+                    sample
+                );
+                disk += sample;
+                disk_squared += sample.len_squared();
+            }
+            let n = SAMPLES as f64;
+            crate::assert_vec_eq!(ball / n, zero, 0.02);
+            crate::assert_vec_eq!(disk / n, zero, 0.02);
+            assert!(
+                (ball_squared / n - 0.6).abs() < 0.01,
+                "{}",
+                ball_squared / n
+            );
+            assert!(
+                (disk_squared / n - 0.5).abs() < 0.01,
+                "{}",
+                disk_squared / n
+            );
+        }
+    }
+
+    #[test]
+    fn projection_onto_a_longer_vector_ignores_its_length() {
+        let v = Vec3::new(3.0, 4.0, 5.0);
+        crate::assert_vec_eq!(
+            v.project(Vec3::new(0.0, 10.0, 0.0)),
+            Vec3::new(0.0, 4.0, 0.0)
+        );
+        crate::assert_vec_eq!(
+            v.reject(Vec3::new(0.0, 10.0, 0.0)),
+            Vec3::new(3.0, 0.0, 5.0)
+        );
+    }
+}
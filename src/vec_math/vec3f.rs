@@ -0,0 +1,209 @@
+use std::{fmt, ops};
+
+use super::Vec3;
+
+// Single precision counterpart of Vec3, half the size, for storing large
+// amounts of geometry. Convert to Vec3 before doing intersection math.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Vec3f {
+    pub data: [f32; 3],
+}
+
+impl Vec3f {
+    pub const fn new(x: f32, y: f32, z: f32) -> Vec3f {
+        Vec3f { data: [x, y, z] }
+    }
+
+    pub fn x(&self) -> f32 {
+        self.data[0]
+    }
+
+    pub fn y(&self) -> f32 {
+        self.data[1]
+    }
+
+    pub fn z(&self) -> f32 {
+        self.data[2]
+    }
+
+    pub fn to_array(self) -> [f32; 3] {
+        self.data
+    }
+
+    pub fn near_zero(&self) -> bool {
+        let s = 1e-6;
+        self.data.iter().all(|component| component.abs() < s)
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.data.iter().all(|component| component.is_finite())
+    }
+
+    pub fn min(self, other: Vec3f) -> Vec3f {
+        Vec3f::new(
+            self.x().min(other.x()),
+            self.y().min(other.y()),
+            self.z().min(other.z()),
+        )
+    }
+
+    pub fn max(self, other: Vec3f) -> Vec3f {
+        Vec3f::new(
+            self.x().max(other.x()),
+            self.y().max(other.y()),
+            self.z().max(other.z()),
+        )
+    }
+
+    pub fn len(&self) -> f32 {
+        self.len_squared().sqrt()
+    }
+
+    pub fn len_squared(&self) -> f32 {
+        self.dot(*self)
+    }
+
+    pub fn dot(&self, rhs: Vec3f) -> f32 {
+        self.x() * rhs.x() + self.y() * rhs.y() + self.z() * rhs.z()
+    }
+
+    pub fn cross_product(&self, rhs: Vec3f) -> Vec3f {
+        Vec3f::new(
+            self.y() * rhs.z() - self.z() * rhs.y(),
+            self.z() * rhs.x() - self.x() * rhs.z(),
+            self.x() * rhs.y() - self.y() * rhs.x(),
+        )
+    }
+
+    pub fn to_unit(self) -> Vec3f {
+        self / self.len()
+    }
+}
+
+impl fmt::Display for Vec3f {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&Vec3::from(*self), f)
+    }
+}
+
+impl fmt::Debug for Vec3f {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Vec3f")
+            .field(&self.x())
+            .field(&self.y())
+            .field(&self.z())
+            .finish()
+    }
+}
+
+impl From<[f32; 3]> for Vec3f {
+    fn from(data: [f32; 3]) -> Vec3f {
+        Vec3f { data }
+    }
+}
+
+// Widening is exact.
+impl From<Vec3f> for Vec3 {
+    fn from(vector: Vec3f) -> Vec3 {
+        Vec3::new(vector.x() as f64, vector.y() as f64, vector.z() as f64)
+    }
+}
+
+// Rounds to the nearest f32.
+impl From<Vec3> for Vec3f {
+    fn from(vector: Vec3) -> Vec3f {
+        Vec3f::new(vector.x() as f32, vector.y() as f32, vector.z() as f32)
+    }
+}
+
+impl ops::Index<usize> for Vec3f {
+    type Output = f32;
+
+    fn index(&self, index: usize) -> &f32 {
+        match self.data.get(index) {
+            Some(component) => component,
+            None => panic!(
+                "Vec3f index out of range: the index is {} but a Vec3f has 3 components",
+                index
+            ),
+        }
+    }
+}
+
+impl ops::IndexMut<usize> for Vec3f {
+    fn index_mut(&mut self, index: usize) -> &mut f32 {
+        match self.data.get_mut(index) {
+            Some(component) => component,
+            None => panic!(
+                "Vec3f index out of range: the index is {} but a Vec3f has 3 components",
+                index
+            ),
+        }
+    }
+}
+
+impl ops::Add for Vec3f {
+    type Output = Vec3f;
+
+    fn add(self, rhs: Vec3f) -> Vec3f {
+        Vec3f::new(self.x() + rhs.x(), self.y() + rhs.y(), self.z() + rhs.z())
+    }
+}
+
+impl ops::AddAssign for Vec3f {
+    fn add_assign(&mut self, rhs: Vec3f) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Sub for Vec3f {
+    type Output = Vec3f;
+
+    fn sub(self, rhs: Vec3f) -> Vec3f {
+        Vec3f::new(self.x() - rhs.x(), self.y() - rhs.y(), self.z() - rhs.z())
+    }
+}
+
+impl ops::Mul<f32> for Vec3f {
+    type Output = Vec3f;
+
+    fn mul(self, rhs: f32) -> Vec3f {
+        Vec3f::new(self.x() * rhs, self.y() * rhs, self.z() * rhs)
+    }
+}
+
+impl ops::Mul<Vec3f> for f32 {
+    type Output = Vec3f;
+
+    fn mul(self, rhs: Vec3f) -> Vec3f {
+        rhs * self
+    }
+}
+
+impl ops::MulAssign<f32> for Vec3f {
+    fn mul_assign(&mut self, rhs: f32) {
+        *self = *self * rhs;
+    }
+}
+
+impl ops::Div<f32> for Vec3f {
+    type Output = Vec3f;
+
+    fn div(self, rhs: f32) -> Vec3f {
+        Vec3f::new(self.x() / rhs, self.y() / rhs, self.z() / rhs)
+    }
+}
+
+impl ops::DivAssign<f32> for Vec3f {
+    fn div_assign(&mut self, rhs: f32) {
+        *self = *self / rhs;
+    }
+}
+
+impl ops::Neg for Vec3f {
+    type Output = Vec3f;
+
+    fn neg(self) -> Vec3f {
+        Vec3f::new(-self.x(), -self.y(), -self.z())
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+
+use raytacer::animation::frame_seed;
+use raytacer::output::{self, OutputTransform, Rgba8Image};
+use raytacer::progress::ProgressControl;
+use raytacer::ray_tracing::{Camera, Scene};
+use raytacer::renderer::{self, MultiOutput};
+use raytacer::vec_math::Color;
+use raytacer::{Error, RenderSettings};
+
+// Passes start at one sample per pixel for a quick first look and double up
+// to this many.
+const MAX_PASS_SAMPLES: u32 = 16;
+// +/- change the exposure by half a stop.
+const EXPOSURE_STEP: f64 = std::f64::consts::SQRT_2;
+
+// The sum of every finished pass, weighted by its samples per pixel.
+struct Accumulation {
+    sum: Vec<Color>,
+    samples: u32,
+}
+
+// Encoded 8-bit RGB of the current average, or None before the first pass
+// is done.
+fn encode(
+    accumulation: &Accumulation,
+    exposure: f64,
+    transform: OutputTransform,
+) -> Option<Vec<[u8; 3]>> {
+    if accumulation.samples == 0 {
+        return None;
+    }
+    let scale = exposure / accumulation.samples as f64;
+    let pixels = accumulation
+        .sum
+        .iter()
+        .map(|&sum| transform.encode(sum * scale));
+    Some(pixels.collect())
+}
+
+fn render_passes(
+    scene: &Scene,
+    camera: &Camera,
+    settings: &RenderSettings,
+    accumulation: &Mutex<Accumulation>,
+    stop: &AtomicBool,
+) -> raytacer::Result<()> {
+    let seed = settings.seed.unwrap_or_else(renderer::fresh_seed);
+    let mut pass_samples = 1;
+    let mut done = 0;
+    let mut pass = 0;
+    while done < settings.samples_per_pixel && !stop.load(Ordering::Relaxed) {
+        let samples = pass_samples.min(settings.samples_per_pixel - done);
+        let pass_settings = RenderSettings {
+            samples_per_pixel: samples,
+            exposure: 1.0,
+            seed: Some(frame_seed(seed, pass)),
+            denoiser: None,
+            outputs: MultiOutput::beauty_only(settings.outputs.beauty.clone()),
+            ..settings.clone()
+        };
+        let framebuffer = renderer::render_with_progress(scene, camera, &pass_settings, &|_| {
+            if stop.load(Ordering::Relaxed) {
+                ProgressControl::Cancel
+            } else {
+                ProgressControl::Continue
+            }
+        })?;
+        if framebuffer.cancelled {
+            break;
+        }
+        let mut accumulation = accumulation.lock().unwrap();
+        for (sum, color) in accumulation.sum.iter_mut().zip(framebuffer.beauty) {
+            *sum += color * samples as f64;
+        }
+        accumulation.samples += samples;
+        done += samples;
+        pass += 1;
+        pass_samples = (pass_samples * 2).min(MAX_PASS_SAMPLES);
+    }
+    Ok(())
+}
+
+fn save(settings: &RenderSettings, width: u32, height: u32, pixels: &[[u8; 3]]) {
+    let image = Rgba8Image {
+        width,
+        height,
+        data: pixels
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 255])
+            .collect(),
+        transfer: settings.output_transform.transfer,
+    };
+    let path = &settings.outputs.beauty;
+    match output::write_image(path, &image) {
+        Ok(()) => log::info!("saved {}", path.display()),
+        Err(error) => log::error!("{}", error),
+    }
+}
+
+// Renders progressively on a background thread and shows the running average
+// until the window is closed. Esc quits, S saves the image as it is now to
+// the beauty output, + and - change the exposure.
+pub fn run(scene: &Scene, camera: &Camera, settings: &RenderSettings) -> raytacer::Result<()> {
+    settings.validate()?;
+    camera.validate()?;
+    settings.check_camera(camera)?;
+    let (width, height) = (settings.width, settings.height);
+    let mut window = Window::new(
+        "raytacer",
+        width as usize,
+        height as usize,
+        WindowOptions::default(),
+    )
+    .map_err(|error| Error::Window(error.to_string()))?;
+    window.set_target_fps(30);
+
+    let accumulation = Mutex::new(Accumulation {
+        sum: vec![Color::BLACK; width as usize * height as usize],
+        samples: 0,
+    });
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let worker = scope.spawn(|| render_passes(scene, camera, settings, &accumulation, &stop));
+        let mut exposure = settings.exposure;
+        let mut shown = None;
+        let mut pixels = vec![];
+        let mut buffer = vec![0u32; width as usize * height as usize];
+        while window.is_open() && !window.is_key_down(Key::Escape) {
+            if window.is_key_pressed(Key::Equal, KeyRepeat::Yes)
+                || window.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes)
+            {
+                exposure *= EXPOSURE_STEP;
+            }
+            if window.is_key_pressed(Key::Minus, KeyRepeat::Yes)
+                || window.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes)
+            {
+                exposure /= EXPOSURE_STEP;
+            }
+            // Encoding takes the lock, so only do it when there's something new.
+            let samples = accumulation.lock().unwrap().samples;
+            if shown != Some((samples, exposure)) {
+                if let Some(encoded) = encode(
+                    &accumulation.lock().unwrap(),
+                    exposure,
+                    settings.output_transform,
+                ) {
+                    pixels = encoded;
+                    for (pixel, &[r, g, b]) in buffer.iter_mut().zip(&pixels) {
+                        *pixel = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+                    }
+                }
+                shown = Some((samples, exposure));
+                window.set_title(&format!(
+                    "raytacer - {}/{} samples, exposure {:.2}",
+                    samples, settings.samples_per_pixel, exposure
+                ));
+            }
+            if window.is_key_pressed(Key::S, KeyRepeat::No) && !pixels.is_empty() {
+                save(settings, width, height, &pixels);
+            }
+            if window
+                .update_with_buffer(&buffer, width as usize, height as usize)
+                .is_err()
+            {
+                break;
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        worker.join().unwrap()
+    })
+}
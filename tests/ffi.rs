@@ -0,0 +1,213 @@
+// Drives the C interface the way a C caller would, through raw pointers and
+// the exported functions only.
+
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use raytacer::ffi::*;
+
+const WIDTH: u32 = 16;
+const HEIGHT: u32 = 12;
+
+fn diffuse(color: [f64; 3]) -> RaytacerMaterial {
+    RaytacerMaterial {
+        kind: RAYTACER_DIFFUSE,
+        color,
+        fuzz: 0.0,
+        refraction_index: 0.0,
+    }
+}
+
+fn camera() -> RaytacerCamera {
+    RaytacerCamera {
+        look_from: [0.0, 0.0, 1.0],
+        look_at: [0.0, 0.0, -1.0],
+        vector_up: [0.0, 1.0, 0.0],
+        fov: 60.0,
+        aperture: 0.0,
+        focus_distance: 2.0,
+    }
+}
+
+// A ground and a glass sphere in front of the camera, which the caller frees.
+fn two_spheres() -> *mut RaytacerScene {
+    let scene = raytacer_scene_new();
+    assert!(!scene.is_null());
+    let glass = RaytacerMaterial {
+        kind: RAYTACER_GLASS,
+        color: [1.0, 1.0, 1.0],
+        fuzz: 0.0,
+        refraction_index: 1.5,
+    };
+    unsafe {
+        let ground = diffuse([0.8, 0.8, 0.0]);
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &[0.0, -100.5, -1.0], 100.0, &ground),
+            RAYTACER_OK
+        );
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &[0.0, 0.0, -1.0], 0.5, &glass),
+            RAYTACER_OK
+        );
+        assert_eq!(raytacer_scene_set_camera(scene, &camera()), RAYTACER_OK);
+    }
+    scene
+}
+
+unsafe fn render(
+    scene: *mut RaytacerScene,
+    seed: u64,
+    pixels: &mut [u8],
+    progress: RaytacerProgress,
+    user_data: *mut c_void,
+) -> c_int {
+    raytacer_render(
+        scene,
+        WIDTH,
+        HEIGHT,
+        4,
+        8,
+        seed,
+        pixels.as_mut_ptr(),
+        pixels.len(),
+        progress,
+        user_data,
+    )
+}
+
+extern "C" fn count_tiles(completed: usize, total: usize, user_data: *mut c_void) -> c_int {
+    let calls = unsafe { &mut *(user_data as *mut Vec<(usize, usize)>) };
+    calls.push((completed, total));
+    0
+}
+
+extern "C" fn cancel(_completed: usize, _total: usize, _user_data: *mut c_void) -> c_int {
+    1
+}
+
+#[test]
+fn renders_a_tiny_scene_through_the_c_interface() {
+    let scene = two_spheres();
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    let user_data = &mut calls as *mut Vec<(usize, usize)> as *mut c_void;
+    let result = unsafe { render(scene, 7, &mut pixels, Some(count_tiles), user_data) };
+    assert_eq!(result, RAYTACER_OK);
+    assert!(pixels.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    assert!(pixels.chunks_exact(4).any(|pixel| pixel[..3] != [0, 0, 0]));
+    // One 32 pixel tile covers the whole image.
+    assert_eq!(calls, [(1, 1)]);
+
+    let mut again = vec![0u8; pixels.len()];
+    assert_eq!(
+        unsafe { render(scene, 7, &mut again, None, ptr::null_mut()) },
+        RAYTACER_OK
+    );
+    assert_eq!(again, pixels);
+    unsafe { raytacer_scene_free(scene) };
+}
+
+#[test]
+fn a_progress_callback_can_cancel() {
+    let scene = two_spheres();
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    let result = unsafe { render(scene, 7, &mut pixels, Some(cancel), ptr::null_mut()) };
+    assert_eq!(result, RAYTACER_CANCELLED);
+    unsafe { raytacer_scene_free(scene) };
+}
+
+#[test]
+fn null_pointers_are_reported_rather_than_dereferenced() {
+    let material = diffuse([0.5, 0.5, 0.5]);
+    let center = [0.0, 0.0, -1.0];
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    let scene = two_spheres();
+    unsafe {
+        raytacer_scene_free(ptr::null_mut());
+        let null_scene = ptr::null_mut();
+        assert_eq!(
+            raytacer_scene_add_sphere(null_scene, &center, 1.0, &material),
+            RAYTACER_NULL_POINTER
+        );
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, ptr::null(), 1.0, &material),
+            RAYTACER_NULL_POINTER
+        );
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &center, 1.0, ptr::null()),
+            RAYTACER_NULL_POINTER
+        );
+        assert_eq!(
+            raytacer_scene_set_camera(null_scene, &camera()),
+            RAYTACER_NULL_POINTER
+        );
+        assert_eq!(
+            raytacer_scene_set_camera(scene, ptr::null()),
+            RAYTACER_NULL_POINTER
+        );
+        assert_eq!(
+            render(null_scene, 7, &mut pixels, None, ptr::null_mut()),
+            RAYTACER_NULL_POINTER
+        );
+        let result = raytacer_render(
+            scene,
+            WIDTH,
+            HEIGHT,
+            4,
+            8,
+            7,
+            ptr::null_mut(),
+            pixels.len(),
+            None,
+            ptr::null_mut(),
+        );
+        assert_eq!(result, RAYTACER_NULL_POINTER);
+        raytacer_scene_free(scene);
+    }
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let center = [0.0, 0.0, -1.0];
+    let scene = raytacer_scene_new();
+    unsafe {
+        let unknown = RaytacerMaterial {
+            kind: 42,
+            ..diffuse([0.5, 0.5, 0.5])
+        };
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &center, 1.0, &unknown),
+            RAYTACER_INVALID_ARGUMENT
+        );
+        let material = diffuse([0.5, 0.5, 0.5]);
+        for &radius in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert_eq!(
+                raytacer_scene_add_sphere(scene, &center, radius, &material),
+                RAYTACER_INVALID_ARGUMENT
+            );
+        }
+        let nan = diffuse([f64::NAN, 0.5, 0.5]);
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &center, 1.0, &nan),
+            RAYTACER_INVALID_ARGUMENT
+        );
+        assert_eq!(
+            raytacer_scene_add_sphere(scene, &center, 1.0, &material),
+            RAYTACER_OK
+        );
+
+        // No camera yet, then a buffer too small for the image.
+        let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+        assert_eq!(
+            render(scene, 7, &mut pixels, None, ptr::null_mut()),
+            RAYTACER_INVALID_ARGUMENT
+        );
+        assert_eq!(raytacer_scene_set_camera(scene, &camera()), RAYTACER_OK);
+        let mut short = vec![0u8; pixels.len() - 1];
+        assert_eq!(
+            render(scene, 7, &mut short, None, ptr::null_mut()),
+            RAYTACER_INVALID_ARGUMENT
+        );
+        raytacer_scene_free(scene);
+    }
+}
@@ -0,0 +1,268 @@
+// Renders tiny scenes with a fixed seed and compares them with the reference
+// images in tests/golden. By default an image passes when its RMSE against
+// the reference is small, which leaves room for floating point differences
+// between platforms. `RAYTACER_GOLDEN=exact` also requires the same bytes, for
+// refactors that mustn't change a single pixel, and `RAYTACER_GOLDEN=update`
+// writes the current renders as the new references:
+//
+//     RAYTACER_GOLDEN=update cargo test --test golden
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use raytacer::background::Sky;
+use raytacer::material::{Checker, Diffusor, Reflector, Refractor};
+use raytacer::ray_tracing::{Camera, Scene, Sphere};
+use raytacer::vec_math::{Color, Point3, Vec3};
+use raytacer::{render, RenderSettings};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+// In 8-bit steps.
+const MAX_RMSE: f64 = 2.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Tolerant,
+    Exact,
+    Update,
+}
+
+fn mode() -> Mode {
+    match std::env::var("RAYTACER_GOLDEN").as_deref() {
+        Ok("exact") => Mode::Exact,
+        Ok("update") => Mode::Update,
+        _ => Mode::Tolerant,
+    }
+}
+
+fn camera(look_from: Point3, look_at: Point3, fov: f64) -> Camera {
+    Camera::new(
+        look_from,
+        look_at,
+        Vec3::new(0.0, 1.0, 0.0),
+        fov.to_radians(),
+        WIDTH as f64 / HEIGHT as f64,
+        0.0,
+        (look_at - look_from).len(),
+    )
+}
+
+// Packed RGB, the alpha channel is always opaque.
+fn render_rgb(scene: &Scene, camera: &Camera) -> Vec<u8> {
+    let settings = RenderSettings {
+        width: WIDTH,
+        height: HEIGHT,
+        samples_per_pixel: 8,
+        max_depth: 8,
+        seed: Some(2024),
+        ..RenderSettings::default()
+    };
+    render(scene, camera, &settings)
+        .unwrap()
+        .beauty_image()
+        .data
+        .chunks_exact(4)
+        .flat_map(|pixel| pixel[..3].to_vec())
+        .collect()
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.ppm", name))
+}
+
+// Binary PPM, which any image viewer opens and which takes no decoder here.
+fn write_ppm(path: &PathBuf, rgb: &[u8]) {
+    let mut file = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    file.extend_from_slice(rgb);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, file).unwrap();
+}
+
+fn read_ppm(path: &PathBuf) -> Vec<u8> {
+    let file = fs::read(path).unwrap_or_else(|error| {
+        panic!(
+            "{}: {}; RAYTACER_GOLDEN=update writes the references",
+            path.display(),
+            error
+        )
+    });
+    let header = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    assert!(
+        file.starts_with(&header),
+        "{} is not a {}x{} binary PPM",
+        path.display(),
+        WIDTH,
+        HEIGHT
+    );
+    file[header.len()..].to_vec()
+}
+
+// FNV-1a, short enough to compare by eye in a failure message.
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn rmse(a: &[u8], b: &[u8]) -> f64 {
+    let sum: f64 = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    (sum / a.len() as f64).sqrt()
+}
+
+fn check(name: &str, scene: &Scene, camera: &Camera) {
+    let rendered = render_rgb(scene, camera);
+    let path = reference_path(name);
+    let mode = mode();
+    if mode == Mode::Update {
+        write_ppm(&path, &rendered);
+        return;
+    }
+    let reference = read_ppm(&path);
+    assert_eq!(rendered.len(), reference.len(), "{}", name);
+    if mode == Mode::Exact {
+        assert_eq!(
+            hash(&rendered),
+            hash(&reference),
+            "{} differs from {}",
+            name,
+            path.display()
+        );
+    }
+    let error = rmse(&rendered, &reference);
+    assert!(
+        error <= MAX_RMSE,
+        "{} is {:.3} RMSE away from {}",
+        name,
+        error,
+        path.display()
+    );
+}
+
+#[test]
+fn two_spheres() {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.8, 0.0),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Reflector {
+            color: Color::new(0.8, 0.6, 0.2),
+            fuzz_coeff: 0.1,
+        }),
+    )));
+    scene.build_bvh();
+    let camera = camera(
+        Point3::new(0.0, 0.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+        60.0,
+    );
+    check("two_spheres", &scene, &camera);
+}
+
+// A glass sphere filling most of the frame, with a colored sphere behind it
+// to refract.
+#[test]
+fn glass_close_up() {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.5, 0.5, 0.5),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Refractor {
+            color: Color::WHITE,
+            fuzz_coeff: 0.0,
+            refr_coeff: 1.5,
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.6, 0.1, -3.0),
+        0.6,
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.1, 0.1),
+        }),
+    )));
+    scene.build_bvh();
+    let camera = camera(
+        Point3::new(0.0, 0.1, 0.3),
+        Point3::new(0.0, 0.0, -1.0),
+        50.0,
+    );
+    check("glass_close_up", &scene, &camera);
+}
+
+#[test]
+fn checker_floor() {
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Checker {
+            even: Color::new(0.9, 0.9, 0.9),
+            odd: Color::new(0.2, 0.3, 0.1),
+            scale: 1.0,
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Reflector {
+            color: Color::new(0.7, 0.7, 0.7),
+            fuzz_coeff: 0.0,
+        }),
+    )));
+    scene.build_bvh();
+    let camera = camera(Point3::new(6.0, 2.0, 4.0), Point3::new(0.0, 0.5, 0.0), 40.0);
+    check("checker_floor", &scene, &camera);
+}
+
+#[test]
+fn the_comparison_catches_a_changed_material() {
+    if mode() == Mode::Update {
+        return;
+    }
+    let mut scene = Scene::new(Box::new(Sky::default()));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        Arc::new(Diffusor {
+            color: Color::new(0.8, 0.8, 0.0),
+        }),
+    )));
+    scene.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.0, -1.0),
+        0.5,
+        Arc::new(Reflector {
+            color: Color::new(0.6, 0.6, 0.8),
+            fuzz_coeff: 0.1,
+        }),
+    )));
+    scene.build_bvh();
+    let camera = camera(
+        Point3::new(0.0, 0.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+        60.0,
+    );
+    let reference = read_ppm(&reference_path("two_spheres"));
+    assert!(rmse(&render_rgb(&scene, &camera), &reference) > MAX_RMSE);
+}